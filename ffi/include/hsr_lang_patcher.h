@@ -0,0 +1,44 @@
+/* C API of hsr-lang-patcher, see ffi/src/lib.rs */
+#ifndef HSR_LANG_PATCHER_H
+#define HSR_LANG_PATCHER_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Return codes, the same as the exit codes of hsr-lang-patcher.exe */
+#define HSR_OK 0
+#define HSR_ERR_OTHER 1
+#define HSR_ERR_GAME_NOT_FOUND 2
+#define HSR_ERR_UNSUPPORTED_VERSION 3
+#define HSR_ERR_FILE_LOCKED 4
+#define HSR_ERR_WRITE_FAILED 5
+#define HSR_ERR_INVALID_ARGUMENTS 6
+#define HSR_ERR_CANCELLED 7
+
+typedef struct HsrStatus {
+    /* NUL terminated language codes, empty when the row is missing */
+    char text_language[8];
+    char voice_language[8];
+} HsrStatus;
+
+/* Sets the text and voice language. path is the game folder or its DesignData folder.
+ * The text can be "cn", "en", "kr", "jp", "cht", "de", "es", "fr", "id", "pt", "ru",
+ * "th" or "vi", the voice only "cn", "en", "kr" or "jp". All strings are UTF-8.
+ * Returns HSR_ERR_FILE_LOCKED without waiting while another run changes the install. */
+int32_t hsr_patch_apply(const char *path, const char *text, const char *voice);
+
+/* Reads the languages currently set in the game files into status. */
+int32_t hsr_patch_status(const char *path, HsrStatus *status);
+
+/* Description of the last failure on this thread, or NULL after a success.
+ * Valid until the next call on the same thread. */
+const char *hsr_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,163 @@
+//! C API for launchers that want to patch in-process instead of running the .exe and
+//! parsing its console output. The declarations are in `include/hsr_lang_patcher.h`.
+//!
+//! Functions return `HSR_OK` or one of the exit codes of the .exe, and store a
+//! description of the failure for [`hsr_last_error`].
+
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString, c_char},
+    path::Path,
+    ptr,
+};
+
+use hsr_lang_patcher::{
+    ErrorKind, PatcherError,
+    game::{Game, get_design_data_path},
+    language::Language,
+};
+
+pub const HSR_OK: i32 = 0;
+
+/// Languages currently set in the game files.
+#[repr(C)]
+pub struct HsrStatus {
+    /// NUL terminated language codes, empty when the row is missing.
+    pub text_language: [c_char; 8],
+    pub voice_language: [c_char; 8],
+}
+
+struct Error {
+    kind: ErrorKind,
+    message: String,
+}
+
+impl From<PatcherError> for Error {
+    fn from(error: PatcherError) -> Self {
+        // Include the causes, e.g. the OS error behind a failed write
+        let mut message = error.to_string();
+        let mut source = std::error::Error::source(&error);
+        while let Some(cause) = source {
+            message.push_str(&format!(": {cause}"));
+            source = cause.source();
+        }
+        Self {
+            kind: error.kind(),
+            message,
+        }
+    }
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn run(f: impl FnOnce() -> Result<(), Error>) -> i32 {
+    let (code, message) = match f() {
+        Ok(()) => (HSR_OK, None),
+        Err(e) => (
+            e.kind.exit_code(),
+            // Messages never contain NUL, but don't lose the error if one does
+            Some(CString::new(e.message.replace('\0', " ")).unwrap_or_default()),
+        ),
+    };
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    code
+}
+
+/// # Safety
+/// `ptr` must be null or point to a NUL terminated string.
+unsafe fn str_arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str, Error> {
+    let invalid = |message: String| Error {
+        kind: ErrorKind::InvalidArguments,
+        message,
+    };
+    if ptr.is_null() {
+        return Err(invalid(format!("{name} is NULL")));
+    }
+    unsafe { CStr::from_ptr(ptr) }
+        .to_str()
+        .map_err(|_| invalid(format!("{name} is not valid UTF-8")))
+}
+
+/// # Safety
+/// `path` must be null or point to a NUL terminated string.
+unsafe fn load(path: *const c_char) -> Result<Game, Error> {
+    let path = unsafe { str_arg(path, "path") }?;
+    Ok(Game::load(get_design_data_path(Path::new(path))?)?)
+}
+
+/// Sets the text and voice language. `path` is the game folder or its `DesignData`
+/// folder. The text can be `cn`, `en`, `kr`, `jp`, `cht`, `de`, `es`, `fr`, `id`, `pt`,
+/// `ru`, `th` or `vi`, the voice only `cn`, `en`, `kr` or `jp`. Returns
+/// `HSR_ERR_FILE_LOCKED` without waiting while another run changes the install.
+///
+/// # Safety
+/// All arguments must be null or point to NUL terminated UTF-8 strings.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hsr_patch_apply(
+    path: *const c_char,
+    text: *const c_char,
+    voice: *const c_char,
+) -> i32 {
+    run(|| {
+        let text = Language::parse_text(unsafe { str_arg(text, "text") }?)?;
+        let voice = Language::parse_voice(unsafe { str_arg(voice, "voice") }?)?;
+        let game = unsafe { load(path) }?;
+        // Like the .exe, never write while another run changes the install
+        let _lock = game.try_lock()?;
+        game.apply(text, voice)?;
+        Ok(())
+    })
+}
+
+/// Reads the languages currently set in the game files into `status`.
+///
+/// # Safety
+/// `path` must be null or point to a NUL terminated UTF-8 string, `status` must be null
+/// or point to writable memory for an `HsrStatus`.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn hsr_patch_status(path: *const c_char, status: *mut HsrStatus) -> i32 {
+    run(|| {
+        if status.is_null() {
+            return Err(Error {
+                kind: ErrorKind::InvalidArguments,
+                message: "status is NULL".to_string(),
+            });
+        }
+        let rows = unsafe { load(path) }?.allowed_language().parse()?;
+        let language = |voice: bool| {
+            let mut buffer = [0; 8];
+            let lang = rows
+                .iter()
+                .find(|row| {
+                    row.area() == Some("os") && if voice { row.is_voice() } else { row.is_text() }
+                })
+                .and_then(|row| row.default_language())
+                .unwrap_or_default();
+            // Leave room for the NUL terminator
+            for (dst, &src) in buffer.iter_mut().zip(&lang.as_bytes()[..lang.len().min(7)]) {
+                *dst = src as c_char;
+            }
+            buffer
+        };
+
+        unsafe {
+            ptr::write(
+                status,
+                HsrStatus {
+                    text_language: language(false),
+                    voice_language: language(true),
+                },
+            )
+        };
+        Ok(())
+    })
+}
+
+/// Description of the last failure on this thread, or NULL after a success. The string
+/// stays valid until the next call on the same thread.
+#[unsafe(no_mangle)]
+pub extern "C" fn hsr_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |s| s.as_ptr()))
+}
@@ -0,0 +1,21 @@
+#![no_main]
+
+use std::path::Path;
+
+use hsr_lang_patcher::{allowed_language::AllowedLanguage, design_index::DataEntry};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let entry = DataEntry {
+        size: data.len() as i32,
+        ..Default::default()
+    };
+    let allowed_language = AllowedLanguage::new(&entry, Path::new("fuzz.bytes"));
+
+    // Anything that decodes has to survive a round trip
+    if let Ok(rows) = allowed_language.parse_raw(data) {
+        allowed_language
+            .serialize_rows(rows)
+            .expect("decoded rows failed to serialize");
+    }
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use hsr_lang_patcher::design_index::DesignIndex;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = DesignIndex::parse(data);
+});
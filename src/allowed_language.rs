@@ -1,14 +1,47 @@
 use std::{
     fs::File,
-    io::{BufReader, Cursor, Read, Seek, SeekFrom, Write},
+    io::{self, BufReader, Cursor, Read, Seek, SeekFrom, Write},
     path::Path,
 };
 
-use crate::Result;
+use crate::{PatcherError, Result};
 use byteorder::{ReadBytesExt, WriteBytesExt};
-use varint_rs::{VarintReader, VarintWriter};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    design_index::DataEntry,
+    language::Language,
+    schema::{
+        read_count, read_string, read_string_array, write_count, write_string, write_string_array,
+    },
+};
+
+pub const ALLOWED_LANGUAGE_HASH: i32 = -515329346;
+
+/// Global (`os`) or Chinese (`cn`) client, named like the AllowedLanguage areas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClientRegion {
+    Os,
+    Cn,
+}
+
+impl ClientRegion {
+    pub fn area(self) -> &'static str {
+        match self {
+            Self::Os => "os",
+            Self::Cn => "cn",
+        }
+    }
 
-use crate::design_index::DataEntry;
+    pub fn from_area(area: &str) -> Option<Self> {
+        match area {
+            "os" => Some(Self::Os),
+            "cn" => Some(Self::Cn),
+            _ => None,
+        }
+    }
+}
 
 pub struct AllowedLanguage<'a> {
     data_entry: &'a DataEntry,
@@ -16,8 +49,6 @@ pub struct AllowedLanguage<'a> {
 }
 
 impl<'a> AllowedLanguage<'a> {
-    pub const VALID_LANGUAGES: [&'static str; 4] = ["cn", "en", "kr", "jp"];
-
     pub fn new(data_entry: &'a DataEntry, bytes_path: &'a Path) -> Self {
         Self {
             data_entry,
@@ -25,12 +56,39 @@ impl<'a> AllowedLanguage<'a> {
         }
     }
 
+    /// Serializes the rows and checks that the result decodes back to the same rows
+    /// and fits the region, so a codec bug never reaches the game files.
     pub fn serialize_rows(&self, rows: Vec<AllowedLanguageRow>) -> Result<Vec<u8>> {
+        let data = Self::write_rows(&rows).map_err(|source| PatcherError::Codec {
+            path: self.bytes_path.to_path_buf(),
+            offset: self.data_entry.offset as u64,
+            source,
+        })?;
+
+        if data.len() > self.data_entry.size as usize {
+            return Err(PatcherError::SelfCheck(format!(
+                "is {} bytes, larger than the {} byte region",
+                data.len(),
+                self.data_entry.size
+            )));
+        }
+        match self.parse_raw(&data) {
+            Ok(decoded) if decoded == rows => Ok(data),
+            Ok(_) => Err(PatcherError::SelfCheck(
+                "decodes to different rows".to_string(),
+            )),
+            Err(e) => Err(PatcherError::SelfCheck(format!("can't be decoded: {e}"))),
+        }
+    }
+
+    /// Encodes rows without the checks of [`Self::serialize_rows`], e.g. for an excel
+    /// held in memory.
+    pub fn write_rows(rows: &[AllowedLanguageRow]) -> io::Result<Vec<u8>> {
         let mut buffer = Vec::new();
         let mut cursor = Cursor::new(&mut buffer);
 
         cursor.write_u8(0)?;
-        cursor.write_i8_varint(rows.len() as i8)?;
+        write_count(&mut cursor, rows.len(), "row count")?;
 
         for row in rows {
             let row_data = row.serialize()?;
@@ -41,17 +99,77 @@ impl<'a> AllowedLanguage<'a> {
     }
 
     pub fn parse(&self) -> Result<Vec<AllowedLanguageRow>> {
+        self.parse_raw(&self.read_raw()?)
+    }
+
+    /// Reads the raw excel region, including the zero padding after the rows.
+    pub fn read_raw(&self) -> Result<Vec<u8>> {
+        self.read_entry().map_err(|source| PatcherError::Read {
+            path: self.bytes_path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Decodes rows from a region previously returned by [`Self::read_raw`].
+    pub fn parse_raw(&self, data: &[u8]) -> Result<Vec<AllowedLanguageRow>> {
+        let mut cursor = Cursor::new(data);
+        Self::read_rows(&mut cursor).map_err(|source| PatcherError::Codec {
+            path: self.bytes_path.to_path_buf(),
+            offset: self.data_entry.offset as u64 + cursor.position(),
+            source,
+        })
+    }
+
+    /// Decodes rows from excel data held in memory, without a file behind it.
+    pub fn decode(data: &[u8]) -> io::Result<Vec<AllowedLanguageRow>> {
+        Self::read_rows(&mut Cursor::new(data))
+    }
+
+    /// What in a region from [`Self::read_raw`] this version doesn't know: bitmask bits
+    /// past the four fields and anything but zero padding after the rows.
+    pub fn format_drift(&self, data: &[u8]) -> Result<Vec<FormatDrift>> {
+        let mut cursor = Cursor::new(data);
+        let rows = Self::read_rows(&mut cursor).map_err(|source| PatcherError::Codec {
+            path: self.bytes_path.to_path_buf(),
+            offset: self.data_entry.offset as u64 + cursor.position(),
+            source,
+        })?;
+
+        let mut drift: Vec<FormatDrift> = rows
+            .iter()
+            .enumerate()
+            .filter(|(_, row)| row.unknown_bits != 0)
+            .map(|(row, r)| FormatDrift::UnknownBits {
+                row,
+                bits: r.unknown_bits,
+            })
+            .collect();
+        let end = cursor.position() as usize;
+        let trailing = data[end..].iter().filter(|&&byte| byte != 0).count();
+        if trailing > 0 {
+            drift.push(FormatDrift::TrailingBytes {
+                offset: end,
+                count: trailing,
+            });
+        }
+        Ok(drift)
+    }
+
+    fn read_entry(&self) -> io::Result<Vec<u8>> {
         let mut excel_file = BufReader::new(File::open(self.bytes_path)?);
         excel_file.seek(SeekFrom::Start(self.data_entry.offset as u64))?;
 
         let mut buffer = vec![0u8; self.data_entry.size as usize];
         excel_file.read_exact(&mut buffer)?;
 
-        let mut cursor = Cursor::new(buffer);
+        Ok(buffer)
+    }
 
+    fn read_rows(cursor: &mut Cursor<&[u8]>) -> io::Result<Vec<AllowedLanguageRow>> {
         cursor.read_u8()?;
 
-        let count = cursor.read_i8_varint()? as usize;
+        // Every row takes at least its bitmask byte
+        let count = read_count(cursor, 1, "row count")?;
         let mut rows = Vec::with_capacity(count);
 
         for _ in 0..count {
@@ -59,57 +177,58 @@ impl<'a> AllowedLanguage<'a> {
             let mut row = AllowedLanguageRow::default();
 
             if bitmask & 1 << 0 != 0 {
-                row.area = Some(Self::read_string(&mut cursor)?);
+                row.area = Some(read_string(cursor)?);
             }
             if bitmask & 1 << 1 != 0 {
                 row.row_type = Some(cursor.read_u8()?);
             }
             if bitmask & 1 << 2 != 0 {
-                row.language_list = Some(Self::read_string_array(&mut cursor)?);
+                row.language_list = Some(read_string_array(cursor)?);
             }
             if bitmask & 1 << 3 != 0 {
-                row.default_language = Some(Self::read_string(&mut cursor)?);
+                row.default_language = Some(read_string(cursor)?);
             }
+            row.unknown_bits = bitmask & !0b1111;
 
             rows.push(row);
         }
 
         Ok(rows)
     }
-
-    #[inline]
-    fn read_string(cursor: &mut Cursor<Vec<u8>>) -> Result<String> {
-        let length = cursor.read_u8()? as usize;
-        let mut buffer = vec![0u8; length];
-        Read::read_exact(cursor, &mut buffer)?;
-        String::from_utf8(buffer).map_err(|e| anyhow::anyhow!(e))
-    }
-
-    #[inline]
-    fn read_string_array(cursor: &mut Cursor<Vec<u8>>) -> Result<Vec<String>> {
-        let length = cursor.read_i8_varint()? as usize;
-        let mut strings = Vec::with_capacity(length);
-        for _ in 0..length {
-            strings.push(Self::read_string(cursor)?);
-        }
-        Ok(strings)
-    }
 } // HI
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize)]
 pub struct AllowedLanguageRow {
     area: Option<String>,
+    #[serde(rename = "type")]
     row_type: Option<u8>,
     language_list: Option<Vec<String>>,
     default_language: Option<String>,
+    /// Bitmask bits without a known field, written back as they were read.
+    #[serde(skip)]
+    unknown_bits: u8,
 }
 
 impl AllowedLanguageRow {
-    pub fn serialize(&self) -> Result<Vec<u8>> {
-        let mut buffer = Vec::new();
-        let mut cursor = Cursor::new(&mut buffer);
+    pub fn new(
+        area: Option<&str>,
+        row_type: Option<u8>,
+        language_list: Option<&[&str]>,
+        default_language: Option<&str>,
+    ) -> Self {
+        Self {
+            area: area.map(str::to_string),
+            row_type,
+            language_list: language_list
+                .map(|list| list.iter().map(|lang| lang.to_string()).collect()),
+            default_language: default_language.map(str::to_string),
+            unknown_bits: 0,
+        }
+    }
 
-        let bitmask = [
+    /// Which fields the row sets, plus the unknown bits it was read with.
+    pub fn bitmask(&self) -> u8 {
+        [
             self.area.is_some(),
             self.row_type.is_some(),
             self.language_list.is_some(),
@@ -117,52 +236,64 @@ impl AllowedLanguageRow {
         ]
         .iter()
         .enumerate()
-        .fold(0u8, |acc, (i, &set)| acc | ((set as u8) << i));
+        .fold(self.unknown_bits, |acc, (i, &set)| acc | ((set as u8) << i))
+    }
 
-        cursor.write_u8(bitmask)?;
+    pub fn serialize(&self) -> io::Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        let mut cursor = Cursor::new(&mut buffer);
+
+        cursor.write_u8(self.bitmask())?;
 
         if let Some(ref area) = self.area {
-            Self::write_string(&mut cursor, area)?;
+            write_string(&mut cursor, area)?;
         }
         if let Some(row_type) = self.row_type {
             cursor.write_u8(row_type)?;
         }
         if let Some(ref language_list) = self.language_list {
-            Self::write_string_array(&mut cursor, language_list)?;
+            write_string_array(&mut cursor, language_list)?;
         }
         if let Some(ref default_language) = self.default_language {
-            Self::write_string(&mut cursor, default_language)?;
+            write_string(&mut cursor, default_language)?;
         }
 
         Ok(buffer)
     }
 
-    #[inline]
-    fn write_string(cursor: &mut Cursor<&mut Vec<u8>>, s: &str) -> Result<()> {
-        let bytes = s.as_bytes();
-        cursor.write_u8(bytes.len() as u8)?;
-        cursor.write_all(bytes)?;
-        Ok(())
-    }
-
-    #[inline]
-    fn write_string_array(cursor: &mut Cursor<&mut Vec<u8>>, strings: &[String]) -> Result<()> {
-        cursor.write_i8_varint(strings.len() as i8)?;
-        for s in strings {
-            Self::write_string(cursor, s)?;
-        }
-        Ok(())
-    }
-
-    pub fn update_language(&mut self, lang: &str) {
+    pub fn update_language(&mut self, lang: Language) {
         self.default_language = Some(lang.to_string());
         self.language_list = Some(vec![lang.to_string()]);
     }
 
+    /// Whether the list allows `lang`. Rows without a list allow any language.
+    pub fn lists(&self, lang: &str) -> bool {
+        self.language_list
+            .as_ref()
+            .is_none_or(|list| list.iter().any(|listed| listed == lang))
+    }
+
     pub fn area(&self) -> Option<&str> {
         self.area.as_deref()
     }
 
+    pub fn row_type(&self) -> Option<u8> {
+        self.row_type
+    }
+
+    pub fn language_list(&self) -> Option<&[String]> {
+        self.language_list.as_deref()
+    }
+
+    pub fn default_language(&self) -> Option<&str> {
+        self.default_language.as_deref()
+    }
+
+    /// The default language, if it is one the patcher knows.
+    pub fn language(&self) -> Option<Language> {
+        Language::from_code(self.default_language()?)
+    }
+
     pub fn is_text(&self) -> bool {
         self.row_type.is_none()
     }
@@ -171,3 +302,481 @@ impl AllowedLanguageRow {
         self.row_type == Some(1)
     }
 }
+
+/// What [`patch_languages_with`] does with the language lists of the patched rows.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ListMode {
+    /// Narrow every list to the chosen language, so the game can't switch back.
+    #[default]
+    Replace,
+    /// Only change the defaults, so the other languages stay selectable in the game
+    /// settings. A default the list doesn't allow is refused.
+    Keep,
+    /// Like [`ListMode::Keep`], but a missing language is appended to the list.
+    Append,
+}
+
+/// Whose rows [`patch_languages_with`] changes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Areas {
+    /// The rows of the global and the CN client.
+    #[default]
+    All,
+    /// Only the rows the given client reads, the others are left as they are.
+    Only(ClientRegion),
+}
+
+impl Areas {
+    pub fn includes(self, area: &str) -> bool {
+        match self {
+            Self::All => true,
+            Self::Only(region) => region.area() == area,
+        }
+    }
+}
+
+/// Voice language of the global and the CN client rows. Both are the same unless told
+/// otherwise, users of dual-region data can give each client its own dub.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Voices {
+    pub os: Language,
+    pub cn: Language,
+}
+
+impl Voices {
+    pub fn of(self, region: ClientRegion) -> Language {
+        match region {
+            ClientRegion::Os => self.os,
+            ClientRegion::Cn => self.cn,
+        }
+    }
+
+    /// The CN voice when it differs from the global one.
+    pub fn cn_override(self) -> Option<Language> {
+        (self.cn != self.os).then_some(self.cn)
+    }
+
+    /// Each voice once.
+    pub fn languages(self) -> impl Iterator<Item = Language> {
+        [Some(self.os), self.cn_override()].into_iter().flatten()
+    }
+}
+
+impl From<Language> for Voices {
+    fn from(lang: Language) -> Self {
+        Self { os: lang, cn: lang }
+    }
+}
+
+/// The code, or both with their area when they differ.
+impl std::fmt::Display for Voices {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.cn_override() {
+            Some(cn) => write!(f, "os {}, cn {cn}", self.os),
+            None => write!(f, "{}", self.os),
+        }
+    }
+}
+
+/// The rows every client has.
+fn patch_targets(areas: Areas) -> impl Iterator<Item = (&'static str, bool)> {
+    [("os", false), ("cn", true), ("os", true), ("cn", false)]
+        .into_iter()
+        .filter(move |(area, _)| areas.includes(area))
+}
+
+/// A row [`patch_languages`] left alone since it doesn't know what it is for, or one it
+/// expected but didn't find. Rows are numbered from 0.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "reason", rename_all = "snake_case")]
+pub enum SkippedRow {
+    /// An area other than `os` and `cn`, e.g. a new region or channel.
+    UnknownArea { row: usize, area: String },
+    /// Neither a text (no type) nor a voice (type 1) row.
+    UnknownType { row: usize, value: u8 },
+    /// No text or voice row for the area, e.g. in an unusual client build.
+    Missing { area: String, voice: bool },
+}
+
+impl std::fmt::Display for SkippedRow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownArea { row, area } => {
+                write!(f, "Skipped row {row} with the unknown area '{area}'")
+            }
+            Self::UnknownType { row, value } => {
+                write!(f, "Skipped row {row} with the unknown type {value}")
+            }
+            Self::Missing { area, voice } => {
+                let kind = if *voice { "voice" } else { "text" };
+                write!(
+                    f,
+                    "No {} {kind} row, patched the others",
+                    area.to_uppercase()
+                )
+            }
+        }
+    }
+}
+
+/// A sign of a changed excel format after a game update, see
+/// [`AllowedLanguage::format_drift`]. Rows are numbered from 0.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum FormatDrift {
+    UnknownBits {
+        row: usize,
+        bits: u8,
+    },
+    /// Non-zero bytes after the rows, `offset` is relative to the region.
+    TrailingBytes {
+        offset: usize,
+        count: usize,
+    },
+}
+
+impl std::fmt::Display for FormatDrift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownBits { row, bits } => {
+                write!(f, "Row {row} sets the unknown bitmask bits 0x{bits:02X}")
+            }
+            Self::TrailingBytes { offset, count } => write!(
+                f,
+                "{count} non-zero bytes follow the rows at offset 0x{offset:X}"
+            ),
+        }
+    }
+}
+
+/// The client a row belongs to and whether it is its voice row.
+fn row_kind(index: usize, row: &AllowedLanguageRow) -> Result<(ClientRegion, bool), SkippedRow> {
+    let area = row.area().unwrap_or_default();
+    let region = ClientRegion::from_area(area).ok_or_else(|| SkippedRow::UnknownArea {
+        row: index,
+        area: area.to_string(),
+    })?;
+    match row.row_type() {
+        None => Ok((region, false)),
+        Some(1) => Ok((region, true)),
+        Some(value) => Err(SkippedRow::UnknownType { row: index, value }),
+    }
+}
+
+pub fn patch_languages(
+    rows: &mut [AllowedLanguageRow],
+    text_lang: Language,
+    voices: impl Into<Voices>,
+) -> Result<Vec<SkippedRow>> {
+    patch_languages_with(
+        rows,
+        text_lang,
+        voices,
+        ListMode::Replace,
+        Areas::All,
+        false,
+    )
+}
+
+/// Sets the language of every text and voice row in `areas`, returns the rows it
+/// couldn't place. A missing row only fails when `strict` is set or no row is left to
+/// patch, odd client builds still get the rows they have. Rows of an unknown area or
+/// type fail with `strict` too.
+pub fn patch_languages_with(
+    rows: &mut [AllowedLanguageRow],
+    text_lang: Language,
+    voices: impl Into<Voices>,
+    mode: ListMode,
+    areas: Areas,
+    strict: bool,
+) -> Result<Vec<SkippedRow>> {
+    let voices = voices.into();
+    let missing: Vec<_> = patch_targets(areas)
+        .filter(|&(area, voice)| {
+            !rows.iter().enumerate().any(|(i, row)| {
+                row_kind(i, row)
+                    .is_ok_and(|(region, is_voice)| region.area() == area && is_voice == voice)
+            })
+        })
+        .collect();
+    if let Some(&(area, voice)) = missing.first()
+        && (strict || missing.len() == patch_targets(areas).count())
+    {
+        return Err(PatcherError::RowNotFound {
+            area: area.to_string(),
+            voice,
+        });
+    }
+
+    let mut skipped: Vec<SkippedRow> = missing
+        .into_iter()
+        .map(|(area, voice)| SkippedRow::Missing {
+            area: area.to_string(),
+            voice,
+        })
+        .collect();
+    for (i, row) in rows.iter_mut().enumerate() {
+        let (region, voice) = match row_kind(i, row) {
+            Ok(kind) => kind,
+            Err(skip) if strict => return Err(PatcherError::Strict(skip.to_string())),
+            Err(skip) => {
+                skipped.push(skip);
+                continue;
+            }
+        };
+        if !areas.includes(region.area()) {
+            continue;
+        }
+        let lang = if voice { voices.of(region) } else { text_lang };
+        match mode {
+            ListMode::Replace => row.update_language(lang),
+            ListMode::Keep if !row.lists(lang.code()) => {
+                return Err(PatcherError::LanguageNotListed {
+                    area: region.area().to_string(),
+                    voice,
+                    language: lang.to_string(),
+                });
+            }
+            ListMode::Keep | ListMode::Append => {
+                if let Some(list) = row
+                    .language_list
+                    .as_mut()
+                    .filter(|list| !list.iter().any(|l| l == lang.code()))
+                {
+                    list.push(lang.to_string());
+                }
+                row.default_language = Some(lang.to_string());
+            }
+        }
+    }
+
+    Ok(skipped)
+}
+
+/// Rows (area and whether it is the voice row) whose list lacks the language they
+/// would get, i.e. the ones [`ListMode::Keep`] refuses.
+pub fn unlisted_languages(
+    rows: &[AllowedLanguageRow],
+    text_lang: Language,
+    voices: impl Into<Voices>,
+    areas: Areas,
+) -> Vec<(&'static str, bool, Language)> {
+    let voices = voices.into();
+    let mut unlisted = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        let Ok((region, voice)) = row_kind(i, row) else {
+            continue;
+        };
+        let lang = if voice { voices.of(region) } else { text_lang };
+        let entry = (region.area(), voice, lang);
+        if areas.includes(region.area()) && !row.lists(lang.code()) && !unlisted.contains(&entry) {
+            unlisted.push(entry);
+        }
+    }
+    unlisted
+}
+
+/// Why a language no row lists breaks, for text and voice. Players report these as
+/// game bugs, so they are explained up front.
+const MISSING_LANGUAGE_REASONS: &[(bool, &str)] = &[
+    (
+        false,
+        "the client doesn't ship its text, menus and dialogue show placeholder keys",
+    ),
+    (
+        true,
+        "the launcher offers no voice pack for it, voiced lines and cutscenes stay silent",
+    ),
+];
+
+/// A combination one client is known to handle badly, even where its rows list the
+/// languages.
+struct CombinationRule {
+    client: ClientRegion,
+    /// The language that breaks for text and voices, and whether it is a voice.
+    check: fn(Language, Voices) -> Option<(bool, Language)>,
+    reason: &'static str,
+}
+
+const COMBINATION_RULES: &[CombinationRule] = &[
+    CombinationRule {
+        client: ClientRegion::Cn,
+        check: |text, _| {
+            (!matches!(
+                text,
+                Language::Cn | Language::Cht | Language::En | Language::Jp | Language::Kr
+            ))
+            .then_some((false, text))
+        },
+        reason: "the CN client only ships Chinese, English, Japanese and Korean text, menus \
+            and dialogue show placeholder keys",
+    },
+    CombinationRule {
+        client: ClientRegion::Os,
+        check: |_, voices| voices.cn_override().map(|lang| (true, lang)),
+        reason: "the global client only reads the os voice rows, the CN voice has no effect",
+    },
+];
+
+/// A language set by [`patch_languages`] that the game doesn't offer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CombinationWarning {
+    pub voice: bool,
+    pub language: Language,
+    /// The client a rule of this client found it for, `None` if no row lists it.
+    pub client: Option<ClientRegion>,
+    pub reason: &'static str,
+}
+
+impl std::fmt::Display for CombinationWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = if self.voice { "voice" } else { "text" };
+        match self.client {
+            Some(client) => write!(
+                f,
+                "{} client with {kind} '{}': {}",
+                client.area(),
+                self.language,
+                self.reason
+            ),
+            None => write!(
+                f,
+                "no {kind} row lists '{}': {}",
+                self.language, self.reason
+            ),
+        }
+    }
+}
+
+/// Checks the languages against the lists of the unpatched `rows`, before
+/// [`patch_languages`] narrows every list to the chosen language, and against the rules
+/// for the detected `client`. The CN rows of the global client only list `cn`, so a
+/// language counts as shipped if any row of its kind lists it.
+pub fn check_combination(
+    rows: &[AllowedLanguageRow],
+    client: Option<ClientRegion>,
+    text_lang: Language,
+    voices: impl Into<Voices>,
+) -> Vec<CombinationWarning> {
+    let voices = voices.into();
+    let unlisted = MISSING_LANGUAGE_REASONS
+        .iter()
+        .flat_map(|&(voice, reason)| {
+            let langs: Vec<_> = if voice {
+                voices.languages().collect()
+            } else {
+                vec![text_lang]
+            };
+            langs.into_iter().map(move |lang| (voice, reason, lang))
+        })
+        .filter_map(|(voice, reason, lang)| {
+            let mut lists = rows
+                .iter()
+                .filter(|row| if voice { row.is_voice() } else { row.is_text() })
+                .filter_map(|row| row.language_list())
+                .peekable();
+            // Nothing to compare against
+            lists.peek()?;
+            let listed = lists.flatten().any(|listed| listed == lang.code());
+            (!listed).then_some(CombinationWarning {
+                voice,
+                language: lang,
+                client: None,
+                reason,
+            })
+        });
+    let ruled = COMBINATION_RULES
+        .iter()
+        .filter(|rule| Some(rule.client) == client)
+        .filter_map(|rule| {
+            let (voice, language) = (rule.check)(text_lang, voices)?;
+            Some(CombinationWarning {
+                voice,
+                language,
+                client,
+                reason: rule.reason,
+            })
+        });
+    unlisted.chain(ruled).collect()
+}
+
+/// Something off in the parsed rows, from a format change in a game update or an earlier
+/// broken write. Rows are numbered from 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RowAnomaly {
+    EmptyArea {
+        row: usize,
+    },
+    /// Only text rows (no type) and voice rows (type 1) are known.
+    UnknownType {
+        row: usize,
+        value: u8,
+    },
+    DefaultNotListed {
+        row: usize,
+        language: String,
+    },
+    /// A second row for the same area and type, both get patched.
+    Duplicate {
+        row: usize,
+        first: usize,
+    },
+}
+
+impl std::fmt::Display for RowAnomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::EmptyArea { row } => write!(f, "Row {row} has no area"),
+            Self::UnknownType { row, value } => write!(f, "Row {row} has the unknown type {value}"),
+            Self::DefaultNotListed { row, language } => write!(
+                f,
+                "Row {row} defaults to '{language}', which its language list doesn't contain"
+            ),
+            Self::Duplicate { row, first } => {
+                write!(f, "Row {row} has the same area and type as row {first}")
+            }
+        }
+    }
+}
+
+/// Checks the rows for anything a patch would trip over or make worse.
+pub fn validate_rows(rows: &[AllowedLanguageRow]) -> Vec<RowAnomaly> {
+    let mut anomalies = Vec::new();
+    for (i, row) in rows.iter().enumerate() {
+        if row.area().is_none_or(str::is_empty) {
+            anomalies.push(RowAnomaly::EmptyArea { row: i });
+        }
+        if let Some(value) = row.row_type().filter(|&value| value != 1) {
+            anomalies.push(RowAnomaly::UnknownType { row: i, value });
+        }
+        if let Some(language) = row.default_language().filter(|&lang| !row.lists(lang)) {
+            anomalies.push(RowAnomaly::DefaultNotListed {
+                row: i,
+                language: language.to_string(),
+            });
+        }
+        let first = rows[..i]
+            .iter()
+            .position(|other| other.area() == row.area() && other.row_type() == row.row_type());
+        if let Some(first) = first {
+            anomalies.push(RowAnomaly::Duplicate { row: i, first });
+        }
+    }
+    anomalies
+}
+
+/// Default language of the OS text or voice row, the one the game shows.
+pub fn current_language(rows: &[AllowedLanguageRow], voice: bool) -> Option<&str> {
+    area_language(rows, "os", voice)
+}
+
+/// Default language of the text or voice row of an area.
+pub fn area_language<'a>(
+    rows: &'a [AllowedLanguageRow],
+    area: &str,
+    voice: bool,
+) -> Option<&'a str> {
+    rows.iter()
+        .find(|row| row.area() == Some(area) && if voice { row.is_voice() } else { row.is_text() })
+        .and_then(|row| row.default_language())
+}
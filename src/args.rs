@@ -1,77 +1,617 @@
-use std::env;
+use std::{
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
 
-use anyhow::anyhow;
+use anyhow::{Context, anyhow};
 use inquire::Select;
 
-use crate::{Result, allowed_language::AllowedLanguage};
+use hsr_lang_patcher::{
+    ErrorKind,
+    allowed_language::Voices,
+    design_index::{EntryQuery, excel_name_hash},
+    game::Platform,
+    language::Language,
+    recipe::{self, PatchRecipe},
+    schema::{LANGUAGE_EXCELS, Schema},
+};
+
+use crate::{
+    Result,
+    i18n::{self, Msg},
+};
+
+// Read when the command line doesn't give the value, which still wins over them
+const GAME_PATH_VAR: &str = "HSR_GAME_PATH";
+const TEXT_LANG_VAR: &str = "HSR_TEXT_LANG";
+const VOICE_LANG_VAR: &str = "HSR_VOICE_LANG";
+const NONINTERACTIVE_VAR: &str = "HSR_NONINTERACTIVE";
 
 pub struct Languages {
-    pub text: &'static str,
-    pub voice: &'static str,
+    pub text: Language,
+    pub voice: Language,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Patch,
+    Tray,
+    Daemon,
+    InstallTask,
+    UninstallTask,
+    Install,
+    Repair,
+    FixPermissions,
+    ExportPatch,
+    ApplyPatch,
+    Diff,
+    Rebuild,
+    AddEntry,
+    Checksums,
+    Find,
+    DumpIndex,
+    DiffIndex,
+    Hexdump,
+    Infer,
+    Template,
+    Extract,
+    Inject,
+    Backups(BackupAction),
+    Status,
+    SetField,
+    Browse,
+    Batch,
+    ReportFormat,
+    SnapshotLangdata,
+    VerifyRoundtrip,
+    #[cfg(feature = "scripting")]
+    RunScript,
+}
+
+/// `backups list`, `backups prune` or `backups restore ID`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackupAction {
+    List,
+    Prune,
+    Restore,
+}
+
+impl BackupAction {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "list" => Some(Self::List),
+            "prune" => Some(Self::Prune),
+            "restore" => Some(Self::Restore),
+            _ => None,
+        }
+    }
+}
+
+impl Command {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "tray" => Some(Self::Tray),
+            "daemon" => Some(Self::Daemon),
+            "install-task" => Some(Self::InstallTask),
+            "uninstall-task" => Some(Self::UninstallTask),
+            "install" => Some(Self::Install),
+            "repair" => Some(Self::Repair),
+            "fix-permissions" => Some(Self::FixPermissions),
+            "export-patch" => Some(Self::ExportPatch),
+            "apply-patch" => Some(Self::ApplyPatch),
+            "diff" => Some(Self::Diff),
+            "rebuild" => Some(Self::Rebuild),
+            "add-entry" => Some(Self::AddEntry),
+            "checksums" => Some(Self::Checksums),
+            "find" => Some(Self::Find),
+            "dump-index" => Some(Self::DumpIndex),
+            "diff-index" => Some(Self::DiffIndex),
+            "hexdump" => Some(Self::Hexdump),
+            "infer" => Some(Self::Infer),
+            "template" => Some(Self::Template),
+            "extract" => Some(Self::Extract),
+            "inject" => Some(Self::Inject),
+            "backups" => Some(Self::Backups(BackupAction::List)),
+            "status" => Some(Self::Status),
+            "set-field" => Some(Self::SetField),
+            "browse" => Some(Self::Browse),
+            "batch" => Some(Self::Batch),
+            "report-format" => Some(Self::ReportFormat),
+            "snapshot-langdata" => Some(Self::SnapshotLangdata),
+            "verify-roundtrip" => Some(Self::VerifyRoundtrip),
+            #[cfg(feature = "scripting")]
+            "run-script" => Some(Self::RunScript),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Json,
+    /// Kaitai Struct definition.
+    Ksy,
+    /// 010 Editor binary template.
+    Bt,
+}
+
+impl Format {
+    /// Also used to guess the format from a file extension.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "csv" => Some(Self::Csv),
+            "json" => Some(Self::Json),
+            "ksy" | "kaitai" => Some(Self::Ksy),
+            "bt" | "010" => Some(Self::Bt),
+            _ => None,
+        }
+    }
 }
 
 pub struct Args {
-    pub game_path: Option<String>,
+    pub command: Command,
+    pub game_path: Option<PathBuf>,
+    /// Second game for commands comparing two installs.
+    pub other_game_path: Option<PathBuf>,
     pub languages: Option<Languages>,
+    /// Voice of the global or CN client rows instead of the chosen voice.
+    pub os_voice: Option<Language>,
+    pub cn_voice: Option<Language>,
+    pub tui: bool,
+    pub launch: bool,
+    /// Waits for a running game to exit before patching.
+    pub wait_for_game_exit: bool,
+    pub game_args: Vec<OsString>,
+    pub last: bool,
+    pub from_process: bool,
+    pub verify: bool,
+    /// Answer JSON-RPC requests on stdin instead of patching once.
+    pub serve: bool,
+    /// Only print a single result line, implies `yes`.
+    pub quiet: bool,
+    /// Never prompt, fail instead when information is missing. Also set by
+    /// `HSR_NONINTERACTIVE`.
+    pub yes: bool,
+    /// `--pause` or `--no-pause`, whether to wait for Enter before exiting. By default
+    /// only a console opened for this run waits.
+    pub pause: Option<bool>,
+    pub json: bool,
+    pub no_color: bool,
+    /// Print how long each stage took.
+    pub timings: bool,
+    /// Parse the index instead of using the cached one, and don't cache it.
+    pub no_cache: bool,
+    /// Skip the `pre_patch` and `post_patch` commands of the config.
+    pub no_hooks: bool,
+    /// Back up whole containers instead of only the overwritten bytes.
+    pub full_backup: bool,
+    /// Apply languages the game doesn't list for a row.
+    pub force: bool,
+    /// Only change the default languages and keep the rows' language lists.
+    pub keep_lists: bool,
+    /// Patch the rows of both clients instead of the detected one's.
+    pub all_regions: bool,
+    /// Fail on anything a newer excel format might have changed.
+    pub strict: bool,
+    /// Only warn about it, overrides `strict` in the config.
+    pub lenient: bool,
+    /// Where to keep backups instead of next to the game.
+    pub backup_dir: Option<PathBuf>,
+    pub schedule: Option<String>,
+    /// Register `daemon` as the scheduled task instead of a periodic `--last` run.
+    pub daemon: bool,
+    /// Show a desktop notification when `daemon` re-applies the profile.
+    pub notify: bool,
+    /// Patch file written by `export-patch` and read by `apply-patch`.
+    pub file: Option<PathBuf>,
+    /// Where `extract` writes the excel, `-` for stdout.
+    pub output: Option<PathBuf>,
+    /// Extract compressed entries as stored instead of decompressing them.
+    pub raw: bool,
+    /// Where `inject` reads the excel from, `-` for stdin.
+    pub input: Option<PathBuf>,
+    pub platform: Option<Platform>,
+    /// Name hash of the entry created by `add-entry`.
+    pub name_hash: Option<i32>,
+    /// Container file hash for `add-entry`, defaults to the AllowedLanguage container.
+    pub container: Option<String>,
+    /// Index entry filters for `find`.
+    pub query: EntryQuery,
+    /// Entry shown by `hexdump`, probed by `infer` or opened by `browse`, defaults to
+    /// AllowedLanguage. Given as a name hash or the name of a built-in schema.
+    pub excel: Option<i32>,
+    /// `name=value` row filters of `set-field`.
+    pub filters: Vec<String>,
+    /// `name=value` assignments of `set-field`.
+    pub assignments: Vec<String>,
+    /// Number of bytes shown by `hexdump`.
+    pub len: Option<u64>,
+    /// Output format of `dump-index` and `template`, guessed from `--file` when unset.
+    pub format: Option<Format>,
+    /// User supplied schema for `template`, `set-field` and `browse`, defaults to
+    /// AllowedLanguage.
+    pub schema: Option<PathBuf>,
+    /// Backup picked by `backups restore`.
+    pub backup_id: Option<String>,
+    /// Built-in tweaks applied after the language patch.
+    pub tweaks: Vec<&'static dyn PatchRecipe>,
+    /// Interface language of the tool, detected from the system locale when unset.
+    pub ui_lang: Option<String>,
 }
 
 impl Args {
     pub fn parse() -> Result<Self> {
-        let args: Vec<String> = env::args().skip(1).collect();
+        let mut args = env::args_os().skip(1);
 
+        let mut command = Command::Patch;
         let mut game_path = None;
+        let mut other_game_path = None;
         let mut languages = None;
+        let mut tui = false;
+        let mut os_voice = None;
+        let mut cn_voice = None;
+        let mut launch = false;
+        let mut wait_for_game_exit = false;
+        let mut game_args = Vec::new();
+        let mut last = false;
+        let mut from_process = false;
+        let mut verify = false;
+        let mut serve = false;
+        let mut quiet = false;
+        let mut yes = false;
+        let mut pause = None;
+        let mut json = false;
+        let mut no_color = false;
+        let mut timings = false;
+        let mut no_cache = false;
+        let mut no_hooks = false;
+        let mut full_backup = false;
+        let mut force = false;
+        let mut keep_lists = false;
+        let mut all_regions = false;
+        let mut strict = false;
+        let mut lenient = false;
+        let mut backup_dir = None;
+        let mut schedule = None;
+        let mut daemon = false;
+        let mut notify = false;
+        let mut file = None;
+        let mut output = None;
+        let mut raw = false;
+        let mut input = None;
+        let mut platform = None;
+        let mut name_hash = None;
+        let mut container = None;
+        let mut query = EntryQuery::default();
+        let mut excel = None;
+        let mut filters = Vec::new();
+        let mut assignments = Vec::new();
+        let mut len = None;
+        let mut format = None;
+        let mut schema = None;
+        let mut tweaks = Vec::new();
+        let mut backup_action = None;
+        let mut backup_id = None;
+        let mut ui_lang = None;
+
+        while let Some(arg) = args.next() {
+            // Flags are always ASCII, so anything that isn't valid UTF-8 can only be a path
+            let Some(text) = arg.to_str() else {
+                if game_path.is_none() {
+                    game_path = Some(PathBuf::from(arg));
+                } else if other_game_path.is_none() {
+                    other_game_path = Some(PathBuf::from(arg));
+                }
+                continue;
+            };
 
-        for arg in &args {
-            if let Some(stripped) = arg.strip_prefix('-') {
-                if stripped.starts_with("lang:") {
-                    languages = Some(Languages::from_arg(arg)?)
-                } else {
-                    return Err(anyhow!("Unknown argument: '{arg}'"));
+            if let Some(stripped) = text.strip_prefix('-') {
+                match stripped {
+                    _ if stripped.starts_with("lang:") => {
+                        languages = Some(Languages::from_arg(text)?)
+                    }
+                    "-lang" => {
+                        let (text, voice) = Language::parse_pair(&next_value(&mut args, text)?)?;
+                        languages = Some(Languages { text, voice })
+                    }
+                    "-os-voice" => {
+                        os_voice = Some(Language::parse_voice(&next_value(&mut args, text)?)?)
+                    }
+                    "-cn-voice" => {
+                        cn_voice = Some(Language::parse_voice(&next_value(&mut args, text)?)?)
+                    }
+                    "-tui" => tui = true,
+                    "-launch" => launch = true,
+                    "-wait-for-game-exit" => wait_for_game_exit = true,
+                    "-last" => last = true,
+                    "-from-process" => from_process = true,
+                    "-verify" => verify = true,
+                    "-serve" => serve = true,
+                    "-quiet" | "q" => quiet = true,
+                    "-yes" | "y" => yes = true,
+                    "-pause" => pause = Some(true),
+                    "-no-pause" => pause = Some(false),
+                    "-json" => json = true,
+                    "-no-color" => no_color = true,
+                    "-timings" => timings = true,
+                    "-no-cache" => no_cache = true,
+                    "-no-hooks" => no_hooks = true,
+                    "-full-backup" => full_backup = true,
+                    "-force" => force = true,
+                    "-keep-lists" => keep_lists = true,
+                    "-raw" => raw = true,
+                    "-all-regions" => all_regions = true,
+                    "-strict" => strict = true,
+                    "-lenient" => lenient = true,
+                    "-schedule" => schedule = Some(next_value(&mut args, text)?),
+                    "-daemon" => daemon = true,
+                    "-notify" => notify = true,
+                    "-backup-dir" => {
+                        backup_dir = Some(PathBuf::from(
+                            args.next()
+                                .context("Expected a directory after '--backup-dir'")?,
+                        ))
+                    }
+                    "-file" => {
+                        file = Some(PathBuf::from(
+                            args.next().context("Expected a value after '--file'")?,
+                        ))
+                    }
+                    "-output" | "o" => {
+                        output =
+                            Some(PathBuf::from(args.next().with_context(|| {
+                                format!("Expected a value after '{text}'")
+                            })?))
+                    }
+                    "-input" | "i" => {
+                        input =
+                            Some(PathBuf::from(args.next().with_context(|| {
+                                format!("Expected a value after '{text}'")
+                            })?))
+                    }
+                    "-platform" => {
+                        let name = next_value(&mut args, text)?;
+                        platform = Some(Platform::from_name(&name).with_context(|| {
+                            format!("Invalid platform '{name}'. Must be windows, android, or ios")
+                        })?)
+                    }
+                    "-name-hash" => {
+                        let value = next_value(&mut args, text)?;
+                        name_hash = Some(
+                            value
+                                .parse()
+                                .with_context(|| format!("Invalid name hash '{value}'"))?,
+                        )
+                    }
+                    "-container" => container = Some(next_value(&mut args, text)?),
+                    "-hash" => {
+                        let value = next_value(&mut args, text)?;
+                        query.name_hash = Some(
+                            value
+                                .parse()
+                                .with_context(|| format!("Invalid name hash '{value}'"))?,
+                        )
+                    }
+                    "-offset" => query.offset = Some(parse_number(&next_value(&mut args, text)?)?),
+                    "-size-range" => {
+                        let value = next_value(&mut args, text)?;
+                        let (min, max) = value.split_once("..").with_context(|| {
+                            format!("Invalid size range '{value}'. Expected MIN..MAX")
+                        })?;
+                        let bound = |bound: &str| {
+                            (!bound.is_empty()).then(|| parse_number(bound)).transpose()
+                        };
+                        query.min_size = bound(min)?;
+                        query.max_size = bound(max.trim_start_matches('='))?;
+                    }
+                    "-excel" => excel = Some(parse_excel(&next_value(&mut args, text)?)?),
+                    "-where" => filters.push(next_value(&mut args, text)?),
+                    "-set" => assignments.push(next_value(&mut args, text)?),
+                    "-len" => len = Some(parse_number(&next_value(&mut args, text)?)?),
+                    "-format" => {
+                        let name = next_value(&mut args, text)?;
+                        format = Some(Format::from_name(&name).with_context(|| {
+                            format!("Invalid format '{name}'. Must be csv, json, ksy or 010")
+                        })?)
+                    }
+                    "-schema" => {
+                        schema = Some(PathBuf::from(
+                            args.next().context("Expected a value after '--schema'")?,
+                        ))
+                    }
+                    "-tweak" => tweaks.push(recipe::find_recipe(&next_value(&mut args, text)?)?),
+                    "-ui-lang" => ui_lang = Some(next_value(&mut args, text)?),
+                    // Everything after `--` is passed through to the game
+                    "-" => game_args = args.by_ref().collect(),
+                    _ => return Err(anyhow!("Unknown argument: '{text}'")),
                 }
+            } else if let (Command::Patch, Some(cmd)) = (&command, Command::from_name(text)) {
+                command = cmd;
+            } else if let (Command::Backups(_), None, Some(action)) =
+                (&command, backup_action, BackupAction::from_name(text))
+            {
+                backup_action = Some(action);
+                command = Command::Backups(action);
+            } else if command == Command::Backups(BackupAction::Restore)
+                && backup_id.is_none()
+                && is_backup_id(text)
+            {
+                backup_id = Some(text.to_string());
             } else if game_path.is_none() {
-                game_path = Some(arg.clone());
+                game_path = Some(PathBuf::from(arg));
+            } else if other_game_path.is_none() {
+                other_game_path = Some(PathBuf::from(arg));
             }
         }
 
+        if game_path.is_none() && !from_process {
+            game_path = env::var_os(GAME_PATH_VAR)
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from);
+        }
+        if languages.is_none() {
+            languages = Languages::from_env()?;
+        }
+
+        let to_stdout = output.as_deref() == Some(Path::new("-"));
         Ok(Self {
+            command,
             game_path,
+            other_game_path,
             languages,
+            os_voice,
+            cn_voice,
+            tui,
+            launch,
+            wait_for_game_exit,
+            game_args,
+            last,
+            from_process,
+            verify,
+            serve,
+            // JSON output must not be mixed with the banner or prompts to press enter,
+            // and neither must excel data written to stdout
+            quiet: quiet || json || serve || to_stdout,
+            yes: yes || noninteractive() || quiet || json || serve || to_stdout,
+            pause,
+            json,
+            no_color,
+            timings,
+            no_cache,
+            no_hooks,
+            full_backup,
+            force,
+            keep_lists,
+            all_regions,
+            strict,
+            lenient,
+            backup_dir,
+            schedule,
+            daemon,
+            notify,
+            file,
+            output,
+            raw,
+            input,
+            platform,
+            name_hash,
+            container,
+            query,
+            excel,
+            filters,
+            assignments,
+            len,
+            format,
+            schema,
+            tweaks,
+            backup_id,
+            ui_lang,
         })
     }
 
-    pub fn get_or_prompt_languages(&self) -> Result<(&'static str, &'static str)> {
+    /// `voices`, unless `--os-voice` or `--cn-voice` gives a client its own.
+    pub fn voices(&self, voices: Voices) -> Voices {
+        Voices {
+            os: self.os_voice.unwrap_or(voices.os),
+            cn: self.cn_voice.unwrap_or(voices.cn),
+        }
+    }
+
+    pub fn get_or_prompt_languages(&self) -> Result<(Language, Language)> {
         if let Some(langs) = &self.languages {
             return Ok((langs.text, langs.voice));
         }
 
-        let voice = Select::new(
-            "What language should be used for voice?",
-            AllowedLanguage::VALID_LANGUAGES.to_vec(),
-        )
-        .prompt()?;
-        let text = Select::new(
-            "What language should be used for text?",
-            AllowedLanguage::VALID_LANGUAGES.to_vec(),
-        )
-        .prompt()?;
-
-        Ok((
-            Self::validate_language(text)?,
-            Self::validate_language(voice)?,
-        ))
+        if self.yes {
+            return Err(anyhow!(i18n::tr(Msg::NoLanguages))).context(ErrorKind::InvalidArguments);
+        }
+
+        let voice = prompt_language(i18n::tr(Msg::PromptVoice), Language::voice_languages())?;
+        let text = prompt_language(i18n::tr(Msg::PromptText), Language::ALL.into_iter())?;
+        Ok((text, voice))
     }
+}
+
+/// Lists the languages with their names, e.g. `en (English)`.
+fn prompt_language(message: &str, languages: impl Iterator<Item = Language>) -> Result<Language> {
+    let languages: Vec<_> = languages.collect();
+    let options = languages
+        .iter()
+        .map(|lang| format!("{lang} ({})", lang.display_name()))
+        .collect();
+    let choice = Select::new(message, options).raw_prompt()?;
+    Ok(languages[choice.index])
+}
+
+/// `HSR_NONINTERACTIVE`, the same as `--yes`. Set to anything but `0` or `false`.
+pub fn noninteractive() -> bool {
+    env_var(NONINTERACTIVE_VAR)
+        .is_some_and(|value| value != "0" && !value.eq_ignore_ascii_case("false"))
+}
+
+/// An empty variable counts as unset.
+fn env_var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+fn next_value(args: &mut impl Iterator<Item = OsString>, flag: &str) -> Result<String> {
+    args.next()
+        .and_then(|value| value.into_string().ok())
+        .with_context(|| format!("Expected a value after '{flag}'"))
+}
+
+/// Backup IDs are `HASH-TIMESTAMP`, which tells them apart from a game path.
+fn is_backup_id(text: &str) -> bool {
+    text.rsplit_once('-').is_some_and(|(hash, created)| {
+        hash.chars().all(|c| c.is_ascii_hexdigit()) && created.parse::<u64>().is_ok()
+    })
+}
+
+/// A name hash, or the name of a built-in schema or a language excel.
+pub fn parse_excel(text: &str) -> Result<i32> {
+    text.parse()
+        .ok()
+        .or_else(|| Schema::find_known(text)?.name_hash)
+        .or_else(|| {
+            LANGUAGE_EXCELS
+                .iter()
+                .find(|name| name.eq_ignore_ascii_case(text))
+                .map(|name| excel_name_hash(name))
+        })
+        .with_context(|| format!("Invalid excel hash or name '{text}'"))
+}
 
-    fn validate_language(lang: &str) -> Result<&'static str> {
-        AllowedLanguage::VALID_LANGUAGES
-            .iter()
-            .find(|&&l| l == lang)
-            .ok_or_else(|| anyhow!("Invalid language '{lang}'. Must be cn, en, kr, or jp"))
-            .copied()
+/// Parses a decimal or `0x` prefixed hexadecimal number.
+fn parse_number(text: &str) -> Result<u64> {
+    match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
     }
+    .with_context(|| format!("Invalid number '{text}'"))
 }
 
 impl Languages {
+    /// `HSR_TEXT_LANG` and `HSR_VOICE_LANG`, which only work together like `-lang:`.
+    fn from_env() -> Result<Option<Self>> {
+        let (text, voice) = match (env_var(TEXT_LANG_VAR), env_var(VOICE_LANG_VAR)) {
+            (None, None) => return Ok(None),
+            (Some(text), Some(voice)) => (text, voice),
+            _ => {
+                return Err(anyhow!(
+                    "Set both {TEXT_LANG_VAR} and {VOICE_LANG_VAR}, or neither"
+                ));
+            }
+        };
+        Ok(Some(Self {
+            text: Language::parse_text(&text)
+                .with_context(|| format!("Invalid {TEXT_LANG_VAR}"))?,
+            voice: Language::parse_voice(&voice)
+                .with_context(|| format!("Invalid {VOICE_LANG_VAR}"))?,
+        }))
+    }
+
     fn from_arg(arg: &str) -> Result<Self> {
         let lang_part = arg
             .strip_prefix("-lang:")
@@ -91,22 +631,16 @@ impl Languages {
             }
 
             let (type_char, lang) = part.split_at(1);
-            Args::validate_language(lang)?;
-
             match type_char {
-                "0" => text = Some(lang),
-                "1" => voice = Some(lang),
+                "0" => text = Some(Language::parse_text(lang)?),
+                "1" => voice = Some(Language::parse_voice(lang)?),
                 _ => return Err(anyhow!("Language type must be 0 (text) or 1 (voice)")),
             }
         }
 
         Ok(Self {
-            text: Args::validate_language(
-                text.ok_or_else(|| anyhow!("Missing text language (0)"))?,
-            )?,
-            voice: Args::validate_language(
-                voice.ok_or_else(|| anyhow!("Missing voice language (1)"))?,
-            )?,
+            text: text.ok_or_else(|| anyhow!("Missing text language (0)"))?,
+            voice: voice.ok_or_else(|| anyhow!("Missing voice language (1)"))?,
         })
     }
 }
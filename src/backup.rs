@@ -0,0 +1,650 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use md5::{Digest, Md5};
+use ruzstd::{
+    decoding::StreamingDecoder,
+    encoding::{CompressionLevel, compress_to_vec},
+};
+
+use crate::{
+    PatcherError, Result,
+    allowed_language::AllowedLanguageRow,
+    game::{Game, ensure_space, get_game_executable, read, write_error},
+    integrity::hash_file,
+    paths::{canonical_path, long_path},
+};
+
+const BACKUP_DIR: &str = "hsr-lang-patcher-backups";
+
+/// Backups taken before compression was added are plain copies.
+const RAW_EXTENSION: &str = ".bytes";
+const COMPRESSED_EXTENSION: &str = ".bytes.zst";
+const RANGE_EXTENSION: &str = ".range";
+
+const RANGE_MAGIC: &[u8; 8] = b"HLPRANGE";
+/// Bytes hashed on each side of a backed up range, to tell whether the file around it
+/// is still the one the range was taken from.
+const RANGE_CONTEXT: u64 = 4096;
+
+/// Compressed backups start with a zstd skippable frame holding the MD5 and size of the
+/// container, so they stay readable by any zstd tool.
+const HEADER_MAGIC: u32 = 0x184D_2A5C;
+const HEADER_DATA_SIZE: usize = 16 + 8;
+const HEADER_SIZE: usize = 8 + HEADER_DATA_SIZE;
+
+/// A copy of an excel container taken before the first write to it.
+#[derive(Debug, Clone)]
+pub struct Backup {
+    pub path: PathBuf,
+    pub file_hash: String,
+    /// Unix timestamp of when the backup was taken.
+    pub created: u64,
+    pub size: u64,
+}
+
+/// Limits for [`Game::prune_backups`]. `None` means unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct Retention {
+    pub max_count: Option<usize>,
+    pub max_age: Option<Duration>,
+    /// Total size of all backups in bytes.
+    pub max_size: Option<u64>,
+}
+
+/// Original bytes of the regions a patch overwrote, instead of a copy of the whole
+/// container. Only valid for the container they were taken from, so each range also
+/// stores a hash of the bytes around it.
+#[derive(Debug, Clone, Default)]
+struct RangeBackup {
+    /// Size of the container when the first range was taken.
+    file_size: u64,
+    /// In the order they were taken.
+    ranges: Vec<Range>,
+}
+
+#[derive(Debug, Clone)]
+struct Range {
+    offset: u64,
+    data: Vec<u8>,
+    context_md5: [u8; 16],
+}
+
+impl Range {
+    fn end(&self) -> u64 {
+        self.offset + self.data.len() as u64
+    }
+
+    fn covers(&self, offset: u64, len: usize) -> bool {
+        self.offset <= offset && offset + len as u64 <= self.end()
+    }
+}
+
+impl RangeBackup {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let rest = data.strip_prefix(RANGE_MAGIC)?;
+        let (file_size, rest) = rest.split_first_chunk::<8>()?;
+        let (count, mut rest) = rest.split_first_chunk::<4>()?;
+
+        let mut ranges = Vec::new();
+        for _ in 0..u32::from_le_bytes(*count) {
+            let (offset, tail) = rest.split_first_chunk::<8>()?;
+            let (len, tail) = tail.split_first_chunk::<8>()?;
+            let (context_md5, tail) = tail.split_first_chunk::<16>()?;
+            let len = usize::try_from(u64::from_le_bytes(*len)).ok()?;
+            let data = tail.get(..len)?;
+            ranges.push(Range {
+                offset: u64::from_le_bytes(*offset),
+                data: data.to_vec(),
+                context_md5: *context_md5,
+            });
+            rest = &tail[len..];
+        }
+
+        Some(Self {
+            file_size: u64::from_le_bytes(*file_size),
+            ranges,
+        })
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(RANGE_MAGIC);
+        data.extend_from_slice(&self.file_size.to_le_bytes());
+        data.extend_from_slice(&(self.ranges.len() as u32).to_le_bytes());
+        for range in &self.ranges {
+            data.extend_from_slice(&range.offset.to_le_bytes());
+            data.extend_from_slice(&(range.data.len() as u64).to_le_bytes());
+            data.extend_from_slice(&range.context_md5);
+            data.extend_from_slice(&range.data);
+        }
+        data
+    }
+
+    /// Puts the ranges back into `container`, newest first so every range sees the file
+    /// as it was when it was taken. Returns false if the bytes around a range differ.
+    fn revert(&self, container: &mut [u8]) -> bool {
+        if container.len() as u64 != self.file_size {
+            return false;
+        }
+        for range in self.ranges.iter().rev() {
+            let Some(target) = container.get(range.offset as usize..range.end() as usize) else {
+                return false;
+            };
+            if target.len() != range.data.len()
+                || context_md5(container, range.offset, range.data.len()) != range.context_md5
+            {
+                return false;
+            }
+            container[range.offset as usize..range.end() as usize].copy_from_slice(&range.data);
+        }
+        true
+    }
+}
+
+/// MD5 of up to [`RANGE_CONTEXT`] bytes before and after a range of `container`.
+fn context_md5(container: &[u8], offset: u64, len: usize) -> [u8; 16] {
+    let start = offset.saturating_sub(RANGE_CONTEXT) as usize;
+    let end = offset as usize + len;
+    let after_end = (end as u64 + RANGE_CONTEXT).min(container.len() as u64) as usize;
+
+    let mut md5 = Md5::new();
+    md5.update(&container[start.min(container.len())..(offset as usize).min(container.len())]);
+    md5.update(&container[end.min(container.len())..after_end]);
+    md5.finalize().into()
+}
+
+impl Retention {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_count.is_none() && self.max_age.is_none() && self.max_size.is_none()
+    }
+}
+
+impl Backup {
+    fn from_path(path: PathBuf, size: u64) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        let stem = name
+            .strip_suffix(COMPRESSED_EXTENSION)
+            .or_else(|| name.strip_suffix(RAW_EXTENSION))
+            .or_else(|| name.strip_suffix(RANGE_EXTENSION))?;
+        let (file_hash, created) = stem.rsplit_once('-')?;
+        Some(Self {
+            file_hash: file_hash.to_string(),
+            created: created.parse().ok()?,
+            size,
+            path,
+        })
+    }
+
+    /// Name used to pick the backup in `backups restore`, the file name without extension.
+    pub fn id(&self) -> String {
+        format!("{}-{}", self.file_hash, self.created)
+    }
+
+    /// Whether only the patched byte ranges were backed up. Their contents are read
+    /// through [`Game::read_backup`].
+    pub fn is_range(&self) -> bool {
+        self.path
+            .to_str()
+            .is_some_and(|path| path.ends_with(RANGE_EXTENSION))
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.path
+            .to_str()
+            .is_some_and(|path| path.ends_with(COMPRESSED_EXTENSION))
+    }
+
+    /// The container as it was backed up. Compressed backups are checked against their
+    /// stored MD5. Range backups need the container, see [`Game::read_backup`].
+    pub fn read(&self) -> Result<Vec<u8>> {
+        if self.is_range() {
+            return Err(PatcherError::BackupCorrupt {
+                path: self.path.clone(),
+            });
+        }
+        let data = read(&self.path)?;
+        if !self.is_compressed() {
+            return Ok(data);
+        }
+
+        let (size, md5) = self.parse_header(&data)?;
+        let mut content = Vec::with_capacity(size as usize);
+        let decoded = StreamingDecoder::new(&data[HEADER_SIZE..])
+            .ok()
+            .and_then(|mut decoder| decoder.read_to_end(&mut content).ok());
+
+        if decoded.is_none()
+            || content.len() as u64 != size
+            || hex::encode(Md5::digest(&content)) != md5
+        {
+            return Err(PatcherError::BackupCorrupt {
+                path: self.path.clone(),
+            });
+        }
+        Ok(content)
+    }
+
+    fn read_ranges(&self) -> Result<RangeBackup> {
+        RangeBackup::parse(&read(&self.path)?).ok_or_else(|| PatcherError::BackupCorrupt {
+            path: self.path.clone(),
+        })
+    }
+
+    /// Size and MD5 of a full backup, without decompressing it.
+    pub fn content_hash(&self) -> Result<(u64, String)> {
+        let read_error = |source| PatcherError::Read {
+            path: self.path.clone(),
+            source,
+        };
+        if !self.is_compressed() {
+            return hash_file(&self.path).map_err(read_error);
+        }
+
+        let mut header = [0; HEADER_SIZE];
+        File::open(long_path(&self.path))
+            .and_then(|mut file| file.read_exact(&mut header))
+            .map_err(read_error)?;
+        self.parse_header(&header)
+    }
+
+    fn parse_header(&self, data: &[u8]) -> Result<(u64, String)> {
+        let corrupt = || PatcherError::BackupCorrupt {
+            path: self.path.clone(),
+        };
+        let header = data.get(..HEADER_SIZE).ok_or_else(corrupt)?;
+        let (magic, rest) = header.split_at(4);
+        let (length, rest) = rest.split_at(4);
+        let (md5, size) = rest.split_at(16);
+        if magic != HEADER_MAGIC.to_le_bytes() || length != (HEADER_DATA_SIZE as u32).to_le_bytes()
+        {
+            return Err(corrupt());
+        }
+
+        let size = u64::from_le_bytes(size.try_into().map_err(|_| corrupt())?);
+        Ok((size, hex::encode(md5)))
+    }
+}
+
+/// The compressed backup file of `data`, see [`HEADER_MAGIC`].
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut file = Vec::with_capacity(HEADER_SIZE + data.len() / 2);
+    file.extend_from_slice(&HEADER_MAGIC.to_le_bytes());
+    file.extend_from_slice(&(HEADER_DATA_SIZE as u32).to_le_bytes());
+    file.extend_from_slice(&Md5::digest(data));
+    file.extend_from_slice(&(data.len() as u64).to_le_bytes());
+    file.extend(compress_to_vec(data, CompressionLevel::Fastest));
+    file
+}
+
+impl Game {
+    /// Backups go below [`Game::backup_root`] when set, else where the last patch put
+    /// them, else next to the game.
+    pub fn backup_dir(&self) -> PathBuf {
+        if let Some(root) = &self.backup_root {
+            return root.join(self.install_name());
+        }
+        self.patch_state()
+            .ok()
+            .flatten()
+            .and_then(|state| state.backup_dir)
+            .unwrap_or_else(|| self.local_backup_dir())
+    }
+
+    /// Next to the game executable, or next to the DesignData folder when only that was
+    /// given.
+    pub(crate) fn local_backup_dir(&self) -> PathBuf {
+        self.install_dir().join(BACKUP_DIR)
+    }
+
+    fn install_dir(&self) -> PathBuf {
+        let exe = get_game_executable(&self.design_data_path);
+        exe.as_deref()
+            .and_then(Path::parent)
+            .unwrap_or(&self.design_data_path)
+            .to_path_buf()
+    }
+
+    /// Folder name of the install with a hash of its path, so installs sharing a backup
+    /// root don't mix their backups.
+    fn install_name(&self) -> String {
+        let dir = canonical_path(&self.install_dir());
+        let hash = hex::encode(Md5::digest(dir.to_string_lossy().as_bytes()));
+        let name = dir.file_name().map(|name| name.to_string_lossy());
+        format!("{}-{}", name.as_deref().unwrap_or("game"), &hash[..8])
+    }
+
+    /// Backups of the current excel container, newest first.
+    pub fn backups(&self) -> Result<Vec<Backup>> {
+        self.container_backups(&self.file_hash)
+    }
+
+    /// Backups of one excel container, newest first.
+    pub fn container_backups(&self, file_hash: &str) -> Result<Vec<Backup>> {
+        let mut backups = self.all_backups()?;
+        backups.retain(|backup| backup.file_hash == file_hash);
+        Ok(backups)
+    }
+
+    /// Backups of every container, including ones of earlier game versions, newest first.
+    pub fn all_backups(&self) -> Result<Vec<Backup>> {
+        let dir = self.backup_dir();
+        let entries = match fs::read_dir(long_path(&dir)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(source) => return Err(PatcherError::Read { path: dir, source }),
+        };
+
+        let mut backups: Vec<Backup> = entries
+            .flatten()
+            .filter_map(|entry| {
+                let size = entry.metadata().ok()?.len();
+                Backup::from_path(dir.join(entry.file_name()), size)
+            })
+            .collect();
+        backups.sort_by_key(|backup| std::cmp::Reverse(backup.created));
+
+        Ok(backups)
+    }
+
+    /// Whether the backup holds the original data of a container the game still uses.
+    /// Those are never pruned, since restoring depends on them.
+    pub fn is_original(&self, backup: &Backup, backups: &[Backup]) -> bool {
+        let oldest = backups
+            .iter()
+            .filter(|other| other.file_hash == backup.file_hash)
+            .min_by_key(|other| other.created);
+        oldest.is_some_and(|oldest| oldest.path == backup.path)
+            && long_path(&self.container_path(&backup.file_hash)).is_file()
+    }
+
+    /// Deletes the backups over the limits, oldest first, and returns them. Originals of
+    /// containers still in use are kept even if that leaves the limits exceeded.
+    pub fn prune_backups(&self, retention: &Retention) -> Result<Vec<Backup>> {
+        let backups = self.all_backups()?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+
+        let mut kept_count = backups.len();
+        let mut kept_size: u64 = backups.iter().map(|backup| backup.size).sum();
+        let mut pruned = HashSet::new();
+        // Oldest first, so the newest backups survive the count and size limits
+        for backup in backups.iter().rev() {
+            if self.is_original(backup, &backups) {
+                continue;
+            }
+            let too_old = retention
+                .max_age
+                .is_some_and(|age| now.saturating_sub(backup.created) > age.as_secs());
+            let too_many = retention.max_count.is_some_and(|max| kept_count > max);
+            let too_large = retention.max_size.is_some_and(|max| kept_size > max);
+            if too_old || too_many || too_large {
+                pruned.insert(backup.path.clone());
+                kept_count -= 1;
+                kept_size -= backup.size;
+            }
+        }
+
+        let pruned: Vec<Backup> = backups
+            .into_iter()
+            .filter(|backup| pruned.contains(&backup.path))
+            .collect();
+        for backup in &pruned {
+            fs::remove_file(long_path(&backup.path)).map_err(|source| PatcherError::Write {
+                path: backup.path.clone(),
+                offset: 0,
+                source,
+            })?;
+        }
+        Ok(pruned)
+    }
+
+    /// The container as it was backed up. For range backups that is the current
+    /// container with the ranges put back, once the bytes around them are checked.
+    pub fn read_backup(&self, backup: &Backup) -> Result<Vec<u8>> {
+        if !backup.is_range() {
+            return backup.read();
+        }
+
+        let ranges = backup.read_ranges()?;
+        let mut container = read(&self.container_path(&backup.file_hash))?;
+        if !ranges.revert(&mut container) {
+            return Err(PatcherError::BackupStale {
+                path: backup.path.clone(),
+            });
+        }
+        Ok(container)
+    }
+
+    /// Size and MD5 of the container as it was backed up.
+    pub fn backup_hash(&self, backup: &Backup) -> Result<(u64, String)> {
+        if !backup.is_range() {
+            return backup.content_hash();
+        }
+        let data = self.read_backup(backup)?;
+        Ok((data.len() as u64, hex::encode(Md5::digest(&data))))
+    }
+
+    /// Copies a backup back over its container, undoing every change made since. The
+    /// container must still be part of the game.
+    pub fn restore_backup(&self, id: &str) -> Result<Backup> {
+        let backup = self
+            .all_backups()?
+            .into_iter()
+            .find(|backup| backup.id() == id)
+            .ok_or_else(|| PatcherError::BackupNotFound { id: id.to_string() })?;
+
+        let path = self.container_path(&backup.file_hash);
+        if !long_path(&path).is_file() {
+            return Err(PatcherError::ContainerNotFound {
+                file_hash: backup.file_hash,
+            });
+        }
+        self.check_restorable(&backup.file_hash, None)?;
+        let data = self.read_backup(&backup)?;
+        if backup.file_hash == self.file_hash {
+            self.clear_patch_state()?;
+        }
+        self.forget_writes(&backup.file_hash)?;
+        if !backup.is_range() {
+            fs::write(long_path(&path), data).map_err(|source| write_error(&path, 0, source))?;
+            return Ok(backup);
+        }
+
+        // Only the ranges differ, the rest of the container was just checked
+        let mut file = File::options()
+            .write(true)
+            .open(long_path(&path))
+            .map_err(|source| write_error(&path, 0, source))?;
+        for range in backup.read_ranges()?.ranges {
+            file.seek(SeekFrom::Start(range.offset))
+                .and_then(|_| file.write_all(&data[range.offset as usize..range.end() as usize]))
+                .map_err(|source| write_error(&path, range.offset, source))?;
+        }
+        Ok(backup)
+    }
+
+    /// Writes the AllowedLanguage region of the oldest backup back to the container,
+    /// undoing all patches. Returns whether anything was written.
+    pub fn restore_original(&self) -> Result<bool> {
+        self.check_restorable(&self.file_hash, Some(self.data_entry.offset as u64))?;
+        let backup = self
+            .backups()?
+            .pop()
+            .ok_or_else(|| PatcherError::NoBackup {
+                file_hash: self.file_hash.clone(),
+            })?;
+        let original = self.read_backup(&backup)?;
+
+        let offset = self.data_entry.offset as usize;
+        let Some(region) = original.get(offset..offset + self.data_entry.size as usize) else {
+            return Err(PatcherError::Read {
+                path: backup.path,
+                source: io::ErrorKind::UnexpectedEof.into(),
+            });
+        };
+        if region == self.allowed_language().read_raw()? {
+            return Ok(false);
+        }
+
+        self.write_data(region)?;
+        self.clear_patch_state()?;
+        Ok(true)
+    }
+
+    /// Saves the original bytes of `ranges` (offset and length) of a container before
+    /// they are overwritten. Ranges already in the backup keep their original data,
+    /// and a full backup covers everything. With [`Game::full_backups`] set the whole
+    /// container is copied instead.
+    pub(crate) fn ensure_range_backup(
+        &self,
+        file_hash: &str,
+        ranges: &[(u64, usize)],
+    ) -> Result<()> {
+        if self.full_backups {
+            return self.ensure_container_backup(file_hash);
+        }
+
+        let existing = self.container_backups(file_hash)?.pop();
+        let mut backup = match &existing {
+            Some(backup) if !backup.is_range() => return Ok(()),
+            Some(backup) => backup.read_ranges()?,
+            None => RangeBackup::default(),
+        };
+        let missing: Vec<_> = ranges
+            .iter()
+            .filter(|&&(offset, len)| !backup.ranges.iter().any(|range| range.covers(offset, len)))
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let container_path = self.container_path(file_hash);
+        let container = read(&container_path)?;
+        if existing.is_none() {
+            backup.file_size = container.len() as u64;
+        }
+        for &&(offset, len) in &missing {
+            let data = container
+                .get(offset as usize..offset as usize + len)
+                .ok_or_else(|| PatcherError::Read {
+                    path: container_path.clone(),
+                    source: io::ErrorKind::UnexpectedEof.into(),
+                })?;
+            backup.ranges.push(Range {
+                offset,
+                data: data.to_vec(),
+                context_md5: context_md5(&container, offset, len),
+            });
+        }
+
+        let path = match existing {
+            Some(existing) => existing.path,
+            None => self
+                .backup_dir()
+                .join(format!("{file_hash}-{}{RANGE_EXTENSION}", now())),
+        };
+        self.write_backup(path, &backup.serialize())
+    }
+
+    /// AllowedLanguage rows as the game shipped them, from the oldest backup. Without a
+    /// usable backup nothing was patched yet, so the current rows are the original ones.
+    pub fn original_rows(&self) -> Result<Vec<AllowedLanguageRow>> {
+        let allowed_language = self.allowed_language();
+        let original = self
+            .backups()?
+            .pop()
+            .and_then(|backup| self.read_backup(&backup).ok());
+        let offset = self.data_entry.offset as usize;
+        match original
+            .as_deref()
+            .and_then(|data| data.get(offset..offset + self.data_entry.size as usize))
+        {
+            Some(region) => allowed_language.parse_raw(region),
+            None => allowed_language.parse(),
+        }
+    }
+
+    /// Copies the whole container before it is modified for the first time. Later
+    /// writes keep the existing backup, since it already holds the original data. A
+    /// range backup is turned into a full one, keeping its timestamp.
+    pub(crate) fn ensure_container_backup(&self, file_hash: &str) -> Result<()> {
+        let (created, data, replaced) = match self.container_backups(file_hash)?.pop() {
+            Some(backup) if !backup.is_range() => return Ok(()),
+            Some(backup) => (
+                backup.created,
+                self.read_backup(&backup)?,
+                Some(backup.path),
+            ),
+            None => (now(), read(&self.container_path(file_hash))?, None),
+        };
+
+        let path = self
+            .backup_dir()
+            .join(format!("{file_hash}-{created}{COMPRESSED_EXTENSION}"));
+        self.write_backup(path, &compress(&data))?;
+        if let Some(replaced) = replaced {
+            fs::remove_file(long_path(&replaced)).map_err(|source| PatcherError::Backup {
+                path: replaced,
+                source,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Checks the space a container rewrite needs before anything is written: the new
+    /// container and index next to the old ones until they replace them, and at most
+    /// their old sizes for the backups of the container, the index and `M_DesignV`
+    /// that don't exist yet.
+    pub(crate) fn ensure_rewrite_space(
+        &self,
+        file_hash: &str,
+        old_size: u64,
+        new_size: u64,
+    ) -> Result<()> {
+        let index = format!("DesignV_{}", self.index_hash);
+        let size = |name: &str| {
+            fs::metadata(long_path(&self.container_path(name))).map_or(0, |meta| meta.len())
+        };
+
+        let mut backups = 0;
+        for (name, size) in [
+            (file_hash, old_size),
+            (&index, size(&index)),
+            ("M_DesignV", size("M_DesignV")),
+        ] {
+            let has_full_backup = self
+                .container_backups(name)?
+                .iter()
+                .any(|backup| !backup.is_range());
+            if !has_full_backup {
+                backups += size;
+            }
+        }
+        ensure_space(&self.backup_dir(), backups)?;
+        ensure_space(&self.container_path(file_hash), new_size + size(&index))
+    }
+
+    fn write_backup(&self, path: PathBuf, data: &[u8]) -> Result<()> {
+        // Written under another name first, so a half written file is never taken for
+        // the original
+        let partial = path.with_extension("partial");
+        ensure_space(&self.backup_dir(), data.len() as u64)?;
+        fs::create_dir_all(long_path(&self.backup_dir()))
+            .and_then(|_| fs::write(long_path(&partial), data))
+            .and_then(|_| fs::rename(long_path(&partial), long_path(&path)))
+            .map_err(|source| PatcherError::Backup { path, source })
+    }
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs())
+}
@@ -0,0 +1,125 @@
+//! `backups list|prune|restore`. Every game update gets new containers, and each one
+//! patched gets a full copy, so old backups pile up without these.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+
+use hsr_lang_patcher::{ErrorKind, backup::Retention, game::Game};
+
+use crate::{
+    Result,
+    args::{Args, BackupAction},
+    config::Config,
+    output,
+};
+
+pub fn run(game: &Game, action: BackupAction, args: &Args) -> Result<()> {
+    match action {
+        BackupAction::List => list(game),
+        BackupAction::Prune => {
+            let retention = Config::load()?.retention();
+            if retention.is_unlimited() {
+                println!(
+                    "No limits configured. Set backup_max_count, backup_max_age_days or \
+                    backup_max_size_mb in hsr-lang-patcher.toml"
+                );
+                return Ok(());
+            }
+            prune(game, &retention, args.quiet)
+        }
+        BackupAction::Restore => {
+            let id = args
+                .backup_id
+                .as_deref()
+                .context("Expected the ID of the backup, as shown by `backups list`")
+                .context(ErrorKind::InvalidArguments)?;
+            let backup = game.restore_backup(id)?;
+            if !args.quiet {
+                println!(
+                    "Restored {}.bytes from the backup taken {}",
+                    backup.file_hash,
+                    age(backup.created)
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+fn list(game: &Game) -> Result<()> {
+    let backups = game.all_backups()?;
+    if backups.is_empty() {
+        println!("No backups in '{}'", game.backup_dir().display());
+        return Ok(());
+    }
+
+    println!("{:<43}  {:>14}  {:>10}  NOTE", "ID", "TAKEN", "SIZE");
+    for backup in &backups {
+        let note = if game.is_original(backup, &backups) {
+            "original, kept"
+        } else if backup.file_hash == game.file_hash {
+            ""
+        } else {
+            "other container"
+        };
+        println!(
+            "{:<43}  {:>14}  {:>10}  {note}",
+            backup.id(),
+            age(backup.created),
+            size(backup.size)
+        );
+    }
+    println!(
+        "\n{} backups, {} in '{}'",
+        backups.len(),
+        size(backups.iter().map(|backup| backup.size).sum()),
+        game.backup_dir().display()
+    );
+    Ok(())
+}
+
+/// Deletes the backups over the configured limits, also run after every patch.
+pub fn prune(game: &Game, retention: &Retention, quiet: bool) -> Result<()> {
+    let pruned = game.prune_backups(retention)?;
+    if quiet {
+        return Ok(());
+    }
+
+    for backup in &pruned {
+        println!("Deleted backup {}", backup.id());
+    }
+    if !pruned.is_empty() {
+        let freed: u64 = pruned.iter().map(|backup| backup.size).sum();
+        println!(
+            "{}",
+            output::success(&format!(
+                "Deleted {} backups, freed {}",
+                pruned.len(),
+                size(freed)
+            ))
+        );
+    }
+    Ok(())
+}
+
+pub fn age(created: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let secs = now.saturating_sub(created);
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{} min ago", secs / 60),
+        3600..86400 => format!("{} h ago", secs / 3600),
+        _ => format!("{} days ago", secs / 86400),
+    }
+}
+
+fn size(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        1024..1048576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1048576.0),
+    }
+}
@@ -0,0 +1,335 @@
+//! `batch`: runs the jobs listed in a `jobs.toml` one after the other and reports on all
+//! of them at the end, e.g. to patch every install on a shared machine in one go.
+//!
+//! ```toml
+//! stop_on_error = false
+//!
+//! [[job]]
+//! action = "patch"
+//! path = "D:/Games/Star Rail"
+//! text = "en"
+//! voice = "jp"
+//!
+//! [[job]]
+//! action = "extract"
+//! path = "E:/Star Rail"
+//! excels = ["AllowedLanguage", "-1234567"]
+//! output_dir = "dumps"
+//!
+//! [[job]]
+//! action = "verify"
+//! path = "E:/Star Rail"
+//! ```
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, anyhow};
+use serde::Deserialize;
+
+use hsr_lang_patcher::{
+    ErrorKind,
+    allowed_language::ListMode,
+    detect::default_game_path,
+    game::{Game, Platform, get_platform_design_data_path},
+    integrity::Integrity,
+    language::Language,
+    recipe,
+    state::PatchStatus,
+};
+
+use crate::{
+    LOCK_TIMEOUT, Result,
+    args::{self, Args},
+    backups,
+    config::Config,
+    confirm_combination, container, error_kind, output,
+};
+
+const DEFAULT_FILE: &str = "jobs.toml";
+
+#[derive(Deserialize)]
+struct JobsFile {
+    /// Skip the remaining jobs after a failed one.
+    #[serde(default)]
+    stop_on_error: bool,
+    #[serde(default, rename = "job")]
+    jobs: Vec<Job>,
+}
+
+/// Which install a job works on. Relative paths are relative to the jobs file.
+#[derive(Deserialize)]
+struct Install {
+    /// Detected like without a game path when unset.
+    path: Option<PathBuf>,
+    platform: Option<Platform>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+enum Job {
+    /// Without `text` and `voice`, the saved profile is applied.
+    Patch {
+        #[serde(flatten)]
+        install: Install,
+        text: Option<String>,
+        voice: Option<String>,
+        #[serde(default)]
+        tweaks: Vec<String>,
+        #[serde(default)]
+        force: bool,
+        #[serde(default)]
+        keep_lists: bool,
+    },
+    Restore {
+        #[serde(flatten)]
+        install: Install,
+    },
+    /// Writes `ExcelHASH.bytes` for every excel to `output_dir`, decompressed unless
+    /// `raw` is set.
+    Extract {
+        #[serde(flatten)]
+        install: Install,
+        excels: Vec<String>,
+        output_dir: Option<PathBuf>,
+        #[serde(default)]
+        raw: bool,
+    },
+    /// Checks the container against `pkg_version` and whether the last patch is in place.
+    Verify {
+        #[serde(flatten)]
+        install: Install,
+    },
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let path = args
+        .file
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_FILE));
+    let text = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read '{}'", path.display()))?;
+    let file: JobsFile = toml::from_str(&text)
+        .with_context(|| format!("Invalid jobs file '{}'", path.display()))
+        .context(ErrorKind::InvalidArguments)?;
+    if file.jobs.is_empty() {
+        return Err(anyhow!("'{}' lists no [[job]]", path.display()))
+            .context(ErrorKind::InvalidArguments);
+    }
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    let mut results = Vec::new();
+    for (i, job) in file.jobs.iter().enumerate() {
+        let name = job.name(base);
+        if !args.quiet {
+            println!("[{}/{}] {name}", i + 1, file.jobs.len());
+        }
+        let result = job.run(base);
+        if !args.quiet {
+            match &result {
+                Ok(summary) => println!("  {summary}"),
+                Err(e) => eprintln!("  {}: {e:#}", output::error()),
+            }
+        }
+        let failed = result.is_err();
+        results.push((name, result));
+        if failed && file.stop_on_error {
+            break;
+        }
+    }
+
+    if !args.quiet {
+        print_summary(&results, file.jobs.len());
+    }
+
+    match results.iter().find_map(|(_, result)| result.as_ref().err()) {
+        None => Ok(()),
+        Some(first) => {
+            let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+            Err(anyhow!("{failed} of {} jobs failed", file.jobs.len())).context(error_kind(first))
+        }
+    }
+}
+
+fn print_summary(results: &[(String, Result<String>)], total: usize) {
+    println!("\nSummary:");
+    for (i, (name, result)) in results.iter().enumerate() {
+        let outcome = match result {
+            Ok(_) => output::success("ok"),
+            Err(_) => output::error(),
+        };
+        println!("  {:>3}  {outcome:<7}  {name}", i + 1);
+    }
+    for i in results.len()..total {
+        println!("  {:>3}  skipped", i + 1);
+    }
+}
+
+impl Install {
+    fn load(&self, base: &Path) -> Result<Game> {
+        let game_path = match &self.path {
+            Some(path) => base.join(path),
+            None => default_game_path()?,
+        };
+        let design_data_path =
+            get_platform_design_data_path(&game_path, self.platform.unwrap_or_default())?;
+        let mut game = Game::load(design_data_path)?;
+        let config = Config::load()?;
+        game.full_backups = config.full_backups;
+        game.all_regions = config.all_regions;
+        game.strict = config.strict;
+        game.backup_root = config.backup_dir;
+        Ok(game)
+    }
+
+    fn describe(&self, base: &Path) -> String {
+        match &self.path {
+            Some(path) => format!("'{}'", base.join(path).display()),
+            None => "the detected install".to_string(),
+        }
+    }
+}
+
+impl Job {
+    fn name(&self, base: &Path) -> String {
+        let (action, install) = match self {
+            Self::Patch { install, .. } => ("Patch", install),
+            Self::Restore { install } => ("Restore", install),
+            Self::Extract { install, .. } => ("Extract from", install),
+            Self::Verify { install } => ("Verify", install),
+        };
+        format!("{action} {}", install.describe(base))
+    }
+
+    /// Runs the job without prompting, returns a one line summary.
+    fn run(&self, base: &Path) -> Result<String> {
+        match self {
+            Self::Patch {
+                install,
+                text,
+                voice,
+                tweaks,
+                force,
+                keep_lists,
+            } => {
+                let (text, voice) = match (text, voice) {
+                    (Some(text), Some(voice)) => (
+                        Language::parse_text(text)?,
+                        Language::parse_voice(voice)?.into(),
+                    ),
+                    (None, None) => Config::load()?.profile()?,
+                    _ => {
+                        return Err(anyhow!("Expected both text and voice, or neither"))
+                            .context(ErrorKind::InvalidArguments);
+                    }
+                };
+                let tweaks = tweaks
+                    .iter()
+                    .map(|name| recipe::find_recipe(name))
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+
+                let game = install.load(base)?;
+                let _lock = game.lock(LOCK_TIMEOUT, |_| {})?;
+                confirm_combination(&game, text, voice, *force)?;
+                // Keep refuses instead of asking, there is nobody to answer
+                let mode = if *keep_lists {
+                    ListMode::Keep
+                } else {
+                    ListMode::Replace
+                };
+                let report = game.patch_with(text, voice, mode)?;
+                if !tweaks.is_empty() {
+                    game.apply_recipes(&tweaks)?;
+                }
+
+                let retention = Config::load()?.retention();
+                if !retention.is_unlimited() {
+                    backups::prune(&game, &retention, true)?;
+                }
+
+                let outcome = if report.changed() {
+                    format!("Patched to text {text}, voice {voice}")
+                } else {
+                    format!("Already text {text}, voice {voice}")
+                };
+                Ok(match report.warnings.len() {
+                    0 => outcome,
+                    count => format!("{outcome}, {count} warnings"),
+                })
+            }
+            Self::Restore { install } => {
+                let game = install.load(base)?;
+                let _lock = game.lock(LOCK_TIMEOUT, |_| {})?;
+                Ok(if game.restore_original()? {
+                    "Restored the original container".to_string()
+                } else {
+                    "Nothing to restore".to_string()
+                })
+            }
+            Self::Extract {
+                install,
+                excels,
+                output_dir,
+                raw,
+            } => {
+                let hashes = excels
+                    .iter()
+                    .map(|excel| args::parse_excel(excel))
+                    .collect::<Result<Vec<_>>>()
+                    .context(ErrorKind::InvalidArguments)?;
+                let dir = base.join(output_dir.as_deref().unwrap_or(Path::new("")));
+                if !dir.as_os_str().is_empty() {
+                    fs::create_dir_all(&dir)
+                        .with_context(|| format!("Failed to create '{}'", dir.display()))?;
+                }
+
+                let game = install.load(base)?;
+                for hash in &hashes {
+                    let path = dir.join(format!("Excel{hash}.bytes"));
+                    let (data, _) = container::read_decompressed(&game, *hash, *raw)?;
+                    fs::write(&path, data)
+                        .with_context(|| format!("Failed to write '{}'", path.display()))?;
+                }
+                Ok(format!(
+                    "Wrote {} excels to '{}'",
+                    hashes.len(),
+                    dir.display()
+                ))
+            }
+            Self::Verify { install } => verify(&install.load(base)?),
+        }
+    }
+}
+
+/// Fails on a broken download or a lost patch, a patched container is expected to
+/// differ from `pkg_version`.
+fn verify(game: &Game) -> Result<String> {
+    let integrity = match game.check_integrity()? {
+        None => "no pkg_version manifest",
+        Some(Integrity::Intact) => "container as downloaded",
+        Some(Integrity::Modified) => "container modified",
+        Some(Integrity::Unlisted) => "container not in pkg_version",
+        Some(Integrity::SizeMismatch { expected, actual }) => {
+            return Err(anyhow!(
+                "{}.bytes is {actual} bytes but pkg_version expects {expected}",
+                game.file_hash
+            ));
+        }
+    };
+    let status = match game.patch_status()? {
+        PatchStatus::NotPatched => "not patched",
+        PatchStatus::Intact => "patch intact",
+        PatchStatus::GameUpdated => {
+            return Err(anyhow!("Patch lost, the game was updated since"));
+        }
+        PatchStatus::Reverted => {
+            return Err(anyhow!("Patch lost, the game files were reverted"));
+        }
+        PatchStatus::Modified => {
+            return Err(anyhow!("Patch lost, something else changed the languages"));
+        }
+    };
+    Ok(format!("{integrity}, {status}"))
+}
@@ -0,0 +1,541 @@
+//! `browse`: walks the index down to a single excel and shows its rows, decoded when a
+//! schema for it is known and as a hexdump otherwise. Decoded fields can be edited and
+//! written back.
+
+use anyhow::Context;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::{
+    DefaultTerminal, Frame,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, Paragraph, Row, Table, TableState},
+};
+
+use hsr_lang_patcher::{
+    ErrorKind,
+    design_index::{DataEntry, DesignIndex},
+    game::Game,
+    schema::{self, Schema, Value},
+};
+
+use crate::{Result, args::Args, container, output};
+
+/// Widest a decoded column gets, longer values are cut off.
+const MAX_COLUMN_WIDTH: usize = 40;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Screen {
+    Files,
+    Entries,
+    Excel,
+}
+
+/// The excel being looked at.
+struct View {
+    name_hash: i32,
+    data: Vec<u8>,
+    schema: Option<Schema>,
+    rows: Vec<schema::Row>,
+    /// Why the schema didn't decode the data.
+    decode_error: Option<String>,
+    hex: bool,
+    hex_scroll: u16,
+    /// Edited but not written yet.
+    dirty: bool,
+}
+
+struct Browser<'a> {
+    game: &'a Game,
+    index: DesignIndex,
+    schemas: Vec<Schema>,
+    screen: Screen,
+    files: TableState,
+    entries: TableState,
+    rows: TableState,
+    view: Option<View>,
+    /// Text of the field being edited.
+    input: Option<String>,
+    /// Set by the first Esc on unsaved edits, the second one discards them.
+    discard: bool,
+    status: Option<Line<'static>>,
+}
+
+pub fn run(game: &Game, args: &Args) -> Result<()> {
+    let mut schemas = Schema::known();
+    if let Some(path) = &args.schema {
+        // Checked first, so it can override a built-in one
+        schemas.insert(0, container::read_schema(path)?);
+    }
+
+    let mut browser = Browser {
+        game,
+        index: game.design_index()?,
+        schemas,
+        screen: Screen::Files,
+        files: TableState::new().with_selected(0),
+        entries: TableState::new(),
+        rows: TableState::new(),
+        view: None,
+        input: None,
+        discard: false,
+        status: None,
+    };
+    if let Some(hash) = args.excel {
+        browser.open_hash(hash)?;
+    }
+
+    let mut terminal = ratatui::init();
+    let result = browser.event_loop(&mut terminal);
+    ratatui::restore();
+
+    result
+}
+
+/// Foreground color, or no style at all when colors are disabled.
+fn color(fg: Color) -> Style {
+    if output::color() {
+        Style::new().fg(fg)
+    } else {
+        Style::new()
+    }
+}
+
+fn selected_style() -> Style {
+    Style::new().reversed()
+}
+
+fn format_value(value: &Option<Value>) -> String {
+    match value {
+        None => "-".to_string(),
+        Some(Value::String(text)) => text.clone(),
+        Some(Value::StringArray(list)) => list.join(","),
+        Some(Value::Int(n)) => n.to_string(),
+    }
+}
+
+impl View {
+    fn new(name_hash: i32, data: Vec<u8>, schema: Option<Schema>) -> Self {
+        let (rows, decode_error) = match schema.as_ref().map(|schema| schema.decode_stored(&data)) {
+            Some(Ok(rows)) => (rows, None),
+            Some(Err(e)) => (Vec::new(), Some(e.to_string())),
+            None => (Vec::new(), None),
+        };
+        Self {
+            name_hash,
+            hex: schema.is_none() || decode_error.is_some(),
+            data,
+            schema,
+            rows,
+            decode_error,
+            hex_scroll: 0,
+            dirty: false,
+        }
+    }
+
+    fn decoded(&self) -> bool {
+        self.schema.is_some() && self.decode_error.is_none()
+    }
+}
+
+impl Browser<'_> {
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            if self.input.is_some() {
+                self.edit_key(key.code);
+                continue;
+            }
+            if key.code != KeyCode::Esc && key.code != KeyCode::Char('q') {
+                self.discard = false;
+            }
+
+            match (self.screen, key.code) {
+                (_, KeyCode::Char('q')) if self.may_leave() => return Ok(()),
+                (Screen::Files, KeyCode::Esc) => return Ok(()),
+                (Screen::Files, KeyCode::Up) => self.files.select_previous(),
+                (Screen::Files, KeyCode::Down) => self.files.select_next(),
+                (Screen::Files, KeyCode::Enter) => {
+                    self.entries.select(Some(0));
+                    self.screen = Screen::Entries;
+                }
+                (Screen::Entries, KeyCode::Esc) => self.screen = Screen::Files,
+                (Screen::Entries, KeyCode::Up) => self.entries.select_previous(),
+                (Screen::Entries, KeyCode::Down) => self.entries.select_next(),
+                (Screen::Entries, KeyCode::Enter) => {
+                    if let Err(e) = self.open_selected() {
+                        self.error(e);
+                    }
+                }
+                (Screen::Excel, KeyCode::Esc) if self.may_leave() => {
+                    self.view = None;
+                    self.screen = Screen::Entries;
+                }
+                (Screen::Excel, code) => self.excel_key(code),
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether the excel can be closed. With unsaved edits, only on the second try.
+    fn may_leave(&mut self) -> bool {
+        if !self.view.as_ref().is_some_and(|view| view.dirty) || self.discard {
+            return true;
+        }
+        self.discard = true;
+        self.status = Some(
+            Line::from("Unsaved edits, press w to write them or the key again to discard them")
+                .style(color(Color::Yellow)),
+        );
+        false
+    }
+
+    fn excel_key(&mut self, code: KeyCode) {
+        let Some(view) = &mut self.view else {
+            return;
+        };
+        match code {
+            KeyCode::Tab if view.decoded() => view.hex = !view.hex,
+            KeyCode::Up if view.hex => view.hex_scroll = view.hex_scroll.saturating_sub(1),
+            KeyCode::Down if view.hex => view.hex_scroll = view.hex_scroll.saturating_add(1),
+            KeyCode::PageUp if view.hex => view.hex_scroll = view.hex_scroll.saturating_sub(16),
+            KeyCode::PageDown if view.hex => view.hex_scroll = view.hex_scroll.saturating_add(16),
+            _ if view.hex => {}
+            KeyCode::Up => self.rows.select_previous(),
+            KeyCode::Down => self.rows.select_next(),
+            KeyCode::PageUp => self.rows.scroll_up_by(16),
+            KeyCode::PageDown => self.rows.scroll_down_by(16),
+            KeyCode::Left => self.rows.select_previous_column(),
+            KeyCode::Right => self.rows.select_next_column(),
+            KeyCode::Enter | KeyCode::Char('e') => {
+                let cell = self.selected_cell();
+                let view = self.view.as_ref();
+                if let Some(((row, field), view)) = cell.zip(view) {
+                    self.input = Some(match &view.rows[row][field] {
+                        None => String::new(),
+                        value => format_value(value),
+                    });
+                }
+            }
+            KeyCode::Char('w') => self.write(),
+            _ => {}
+        }
+    }
+
+    /// Row and field under the cursor, clamped to the table.
+    fn selected_cell(&self) -> Option<(usize, usize)> {
+        let view = self.view.as_ref()?;
+        let fields = view.schema.as_ref()?.fields.len();
+        if view.rows.is_empty() || fields == 0 {
+            return None;
+        }
+        let row = self.rows.selected()?.min(view.rows.len() - 1);
+        let field = self.rows.selected_column().unwrap_or(0).min(fields - 1);
+        Some((row, field))
+    }
+
+    fn edit_key(&mut self, code: KeyCode) {
+        let Some(input) = &mut self.input else {
+            return;
+        };
+        match code {
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Esc => self.input = None,
+            KeyCode::Enter => {
+                let text = self.input.take().unwrap_or_default();
+                self.commit_edit(&text);
+            }
+            _ => {}
+        }
+    }
+
+    /// Stores an edited value in the row, an empty one drops the field.
+    fn commit_edit(&mut self, text: &str) {
+        let Some((row, field)) = self.selected_cell() else {
+            return;
+        };
+        let Some(view) = &mut self.view else {
+            return;
+        };
+        let Some(kind) = view.schema.as_ref().map(|schema| schema.fields[field].kind) else {
+            return;
+        };
+
+        let value = if text.is_empty() {
+            None
+        } else {
+            match kind.parse_value(text) {
+                Some(value) => Some(value),
+                None => {
+                    self.status = Some(
+                        Line::from(format!("'{text}' is not a valid {}", kind.name()))
+                            .style(color(Color::Red)),
+                    );
+                    return;
+                }
+            }
+        };
+        if view.rows[row][field] != value {
+            view.rows[row][field] = value;
+            view.dirty = true;
+        }
+    }
+
+    /// Encodes the edited rows and writes them over the excel.
+    fn write(&mut self) {
+        let Some(view) = &mut self.view else {
+            return;
+        };
+        let Some(schema) = &view.schema else {
+            return;
+        };
+
+        let result = schema
+            .encode_stored(&view.rows)
+            .context("Failed to encode the rows")
+            .and_then(|data| Ok(self.game.write_entry(view.name_hash, &data)?))
+            .and_then(|written| Ok((written, self.game.read_entry(view.name_hash)?)));
+
+        match result {
+            Ok((written, data)) => {
+                view.data = data;
+                view.dirty = false;
+                self.discard = false;
+                self.status = Some(if written {
+                    Line::from(format!("Wrote excel {}", view.name_hash))
+                        .bold()
+                        .style(color(Color::Green))
+                } else {
+                    Line::from("Nothing changed, nothing written")
+                });
+            }
+            Err(e) => self.error(e),
+        }
+    }
+
+    fn error(&mut self, e: anyhow::Error) {
+        self.status = Some(Line::from(format!("Error: {e:#}")).style(color(Color::Red)));
+    }
+
+    fn selected_entry(&self) -> Option<&DataEntry> {
+        let file = self.index.files.get(self.files.selected()?)?;
+        file.entries.get(self.entries.selected()?)
+    }
+
+    fn schema_for(&self, name_hash: i32) -> Option<&Schema> {
+        self.schemas
+            .iter()
+            .find(|schema| schema.name_hash == Some(name_hash))
+    }
+
+    fn open_selected(&mut self) -> Result<()> {
+        let Some(entry) = self.selected_entry() else {
+            return Ok(());
+        };
+        let name_hash = entry.name_hash;
+        let data = self.game.read_entry(name_hash)?;
+        self.view = Some(View::new(
+            name_hash,
+            data,
+            self.schema_for(name_hash).cloned(),
+        ));
+        self.rows = TableState::new().with_selected(0).with_selected_column(0);
+        self.screen = Screen::Excel;
+        Ok(())
+    }
+
+    /// Starts on the excel given with `--excel`.
+    fn open_hash(&mut self, name_hash: i32) -> Result<()> {
+        let (file, entry) = self
+            .index
+            .files
+            .iter()
+            .enumerate()
+            .find_map(|(i, file)| {
+                let entry = file
+                    .entries
+                    .iter()
+                    .position(|entry| entry.name_hash == name_hash)?;
+                Some((i, entry))
+            })
+            .with_context(|| format!("Excel {name_hash} is not in the index"))
+            .context(ErrorKind::InvalidArguments)?;
+        self.files.select(Some(file));
+        self.entries.select(Some(entry));
+        self.open_selected()
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, footer] =
+            Layout::vertical([Constraint::Min(5), Constraint::Length(1)]).areas(frame.area());
+
+        match self.screen {
+            Screen::Files => self.draw_files(frame, main),
+            Screen::Entries => self.draw_entries(frame, main),
+            Screen::Excel => self.draw_excel(frame, main),
+        }
+
+        let updown = output::glyph("↑/↓", "Up/Down");
+        let footer_line = if let Some(input) = &self.input {
+            let field = self
+                .selected_cell()
+                .zip(self.view.as_ref().and_then(|view| view.schema.as_ref()))
+                .map(|((_, field), schema)| schema.fields[field].name.clone())
+                .unwrap_or_default();
+            Line::from(vec![
+                format!("{field}: ").bold(),
+                input.clone().into(),
+                "_".slow_blink(),
+                "   Enter keep, Esc cancel, empty drops the field".dim(),
+            ])
+        } else if let Some(status) = self.status.take() {
+            status
+        } else {
+            let help = match self.screen {
+                Screen::Files => format!("{updown} select, Enter open, q quit"),
+                Screen::Entries => format!("{updown} select, Enter open, Esc back, q quit"),
+                Screen::Excel if self.view.as_ref().is_some_and(|view| view.hex) => {
+                    format!("{updown} scroll, Tab rows, Esc back, q quit")
+                }
+                Screen::Excel => format!(
+                    "{updown}{} select, e edit, w write, Tab hexdump, Esc back, q quit",
+                    output::glyph("/←/→", "/Left/Right")
+                ),
+            };
+            Line::from(help).dim()
+        };
+        frame.render_widget(Paragraph::new(footer_line), footer);
+    }
+
+    fn draw_files(&mut self, frame: &mut Frame, area: Rect) {
+        let rows = self.index.files.iter().map(|file| {
+            Row::new([
+                file.file_hash.clone(),
+                file.entries.len().to_string(),
+                file.read_size.to_string(),
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(34),
+                Constraint::Length(8),
+                Constraint::Min(10),
+            ],
+        )
+        .header(Row::new(["Container", "Excels", "Size"]).bold())
+        .row_highlight_style(selected_style())
+        .block(Block::bordered().title(format!(
+            " {} containers in the index ",
+            self.index.files.len()
+        )));
+        frame.render_stateful_widget(table, area, &mut self.files);
+    }
+
+    fn draw_entries(&mut self, frame: &mut Frame, area: Rect) {
+        let Some(file) = self.files.selected().and_then(|i| self.index.files.get(i)) else {
+            return;
+        };
+        let rows = file.entries.iter().map(|entry| {
+            Row::new([
+                entry.name_hash.to_string(),
+                format!("0x{:X}", entry.offset),
+                entry.size.to_string(),
+                self.schema_for(entry.name_hash)
+                    .map(|schema| schema.name.clone())
+                    .unwrap_or_default(),
+            ])
+        });
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Length(12),
+                Constraint::Length(12),
+                Constraint::Length(10),
+                Constraint::Min(10),
+            ],
+        )
+        .header(Row::new(["Name hash", "Offset", "Size", "Schema"]).bold())
+        .row_highlight_style(selected_style())
+        .block(Block::bordered().title(format!(" {}.bytes ", file.file_hash)));
+        frame.render_stateful_widget(table, area, &mut self.entries);
+    }
+
+    fn draw_excel(&mut self, frame: &mut Frame, area: Rect) {
+        let Some(view) = &self.view else {
+            return;
+        };
+
+        let name = match (&view.schema, &view.decode_error) {
+            (Some(schema), None) => format!("{} ({})", schema.name, view.name_hash),
+            (Some(schema), Some(e)) => {
+                format!(
+                    "{} (schema {} doesn't fit: {e})",
+                    view.name_hash, schema.name
+                )
+            }
+            (None, _) => format!("{} (no schema)", view.name_hash),
+        };
+        let title = format!(
+            " {name}, {} bytes{} ",
+            view.data.len(),
+            if view.dirty { ", edited" } else { "" }
+        );
+        let block = Block::bordered().title(title);
+
+        if view.hex {
+            let lines: Vec<Line> = container::hexdump_lines(&view.data, 0)
+                .map(Line::from)
+                .collect();
+            frame.render_widget(
+                Paragraph::new(lines)
+                    .scroll((view.hex_scroll, 0))
+                    .block(block),
+                area,
+            );
+            return;
+        }
+
+        let Some(schema) = &view.schema else {
+            return;
+        };
+        let cells: Vec<Vec<String>> = view
+            .rows
+            .iter()
+            .map(|row| row.iter().map(format_value).collect())
+            .collect();
+        let widths = schema
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let width = cells
+                    .iter()
+                    .map(|row| row[i].chars().count())
+                    .chain([field.name.chars().count()])
+                    .max()
+                    .unwrap_or_default();
+                Constraint::Length(width.min(MAX_COLUMN_WIDTH) as u16)
+            })
+            .collect::<Vec<_>>();
+        let header = Row::new(schema.fields.iter().map(|field| field.name.clone())).bold();
+
+        let table = Table::new(cells.into_iter().map(Row::new), widths)
+            .header(header)
+            .row_highlight_style(Style::new().bold())
+            .cell_highlight_style(selected_style())
+            .block(block);
+        frame.render_stateful_widget(table, area, &mut self.rows);
+    }
+}
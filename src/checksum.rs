@@ -0,0 +1,146 @@
+//! Checksums that cover the patched files. None of the index fields are known to be
+//! checksums, so what remains are the `pkg_version` manifest, file names derived from
+//! the contents and the container size stored in the index.
+
+use std::{fs, path::Path};
+
+use crate::{
+    PatcherError, Result,
+    design_index::DesignIndex,
+    game::{Game, read},
+    integrity::hash_file,
+    paths::long_path,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Checksum {
+    /// MD5 and size listed in `pkg_version`. The launcher compares against its own copy
+    /// from the server, so this can't be fixed locally.
+    Manifest { file: String },
+    /// The file is named after the MD5 of its contents. Renaming it would break the
+    /// manifest, so this can't be fixed either.
+    FileName { file: String },
+    /// Container size stored in the index record of the container. That the field is the
+    /// container's length is only inferred from it matching the download, so it is
+    /// reported but never rewritten.
+    ReadSize { file_hash: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumState {
+    Valid,
+    Stale,
+}
+
+#[derive(Debug, Clone)]
+pub struct ChecksumStatus {
+    pub checksum: Checksum,
+    pub state: ChecksumState,
+}
+
+impl Game {
+    /// Checks every checksum covering the excel container, the index and `M_DesignV.bytes`.
+    /// None of them can be recomputed locally. Checksums a file doesn't use, e.g. a name
+    /// that never matched its contents, are left out.
+    pub fn check_checksums(&self) -> Result<Vec<ChecksumStatus>> {
+        let mut statuses = Vec::new();
+        let files = [
+            (format!("{}.bytes", self.file_hash), Some(&self.file_hash)),
+            (
+                format!("DesignV_{}.bytes", self.index_hash),
+                Some(&self.index_hash),
+            ),
+            ("M_DesignV.bytes".to_string(), None),
+        ];
+
+        for (file, name_hash) in files {
+            let path = self.design_data_path.join(&file);
+            let (size, md5) = hash_file(&path).map_err(|source| PatcherError::Read {
+                path: path.clone(),
+                source,
+            })?;
+
+            if let Some(Some(entry)) = self.find_manifest_entry(&file)? {
+                let valid = entry.file_size == size && entry.md5.eq_ignore_ascii_case(&md5);
+                statuses.push(ChecksumStatus {
+                    checksum: Checksum::Manifest { file: file.clone() },
+                    state: state(valid),
+                });
+            }
+
+            let Some(name_hash) = name_hash else {
+                continue;
+            };
+            let original_matched = self.original_md5(&file)?.as_ref() == Some(name_hash);
+            if md5 == *name_hash || original_matched {
+                statuses.push(ChecksumStatus {
+                    checksum: Checksum::FileName { file },
+                    state: state(md5 == *name_hash),
+                });
+            }
+        }
+
+        statuses.extend(self.check_read_size()?);
+        Ok(statuses)
+    }
+
+    /// MD5 of the oldest backup of a file, i.e. of its contents as downloaded.
+    fn original_md5(&self, file: &str) -> Result<Option<String>> {
+        let stem = file.strip_suffix(".bytes").unwrap_or(file);
+        let Some(backup) = self.container_backups(stem)?.pop() else {
+            return Ok(None);
+        };
+        Ok(Some(self.backup_hash(&backup)?.1))
+    }
+
+    fn check_read_size(&self) -> Result<Option<ChecksumStatus>> {
+        let index = DesignIndex::parse(&read(&self.index_path())?)?;
+        let Some(file) = index
+            .files
+            .iter()
+            .find(|file| file.file_hash == self.file_hash)
+        else {
+            return Ok(None);
+        };
+
+        let checksum = Checksum::ReadSize {
+            file_hash: self.file_hash.clone(),
+        };
+        let size = file_size(&self.bytes_path)?;
+        if file.read_size == size {
+            return Ok(Some(ChecksumStatus {
+                checksum,
+                state: ChecksumState::Valid,
+            }));
+        }
+
+        // Otherwise the field is only a size if it matched the container as downloaded
+        let Some(backup) = self.container_backups(&self.file_hash)?.pop() else {
+            return Ok(None);
+        };
+        if file.read_size != self.backup_hash(&backup)?.0 {
+            return Ok(None);
+        }
+        Ok(Some(ChecksumStatus {
+            checksum,
+            state: ChecksumState::Stale,
+        }))
+    }
+}
+
+fn state(valid: bool) -> ChecksumState {
+    if valid {
+        ChecksumState::Valid
+    } else {
+        ChecksumState::Stale
+    }
+}
+
+fn file_size(path: &Path) -> Result<u64> {
+    fs::metadata(long_path(path))
+        .map(|metadata| metadata.len())
+        .map_err(|source| PatcherError::Read {
+            path: path.to_path_buf(),
+            source,
+        })
+}
@@ -0,0 +1,212 @@
+//! Compressed entries. None has been seen in DesignData so far, but the index has
+//! unknown fields that could flag one, so extracted data is checked for the magic of the
+//! usual formats. Excels always start with a zero byte and never match.
+
+use std::io::{self, Read, Write};
+
+use flate2::{read::GzDecoder, write::GzEncoder};
+use ruzstd::{
+    decoding::StreamingDecoder,
+    encoding::{CompressionLevel, compress_to_vec},
+};
+use serde::Serialize;
+
+use crate::design_index::invalid_data;
+
+/// Decompressed data larger than this is refused, a damaged size field shouldn't fill
+/// the disk.
+const MAX_SIZE: u64 = 1 << 30;
+const LZ4_MAX_BLOCK: usize = 4 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Zstd,
+    Gzip,
+    /// LZ4 frame format, not raw blocks which have no magic.
+    Lz4,
+}
+
+impl Compression {
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        match data {
+            [0x28, 0xB5, 0x2F, 0xFD, ..] => Some(Self::Zstd),
+            [0x1F, 0x8B, ..] => Some(Self::Gzip),
+            [0x04, 0x22, 0x4D, 0x18, ..] => Some(Self::Lz4),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Zstd => "zstd",
+            Self::Gzip => "gzip",
+            Self::Lz4 => "lz4",
+        }
+    }
+
+    pub fn decompress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut content = Vec::new();
+        match self {
+            Self::Zstd => {
+                let decoder =
+                    StreamingDecoder::new(data).map_err(|e| invalid_data(e.to_string()))?;
+                read_limited(decoder, &mut content)?;
+            }
+            Self::Gzip => read_limited(GzDecoder::new(data), &mut content)?,
+            Self::Lz4 => lz4_frame(data, &mut content)?,
+        }
+        Ok(content)
+    }
+
+    pub fn compress(self, data: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Zstd => Ok(compress_to_vec(data, CompressionLevel::Fastest)),
+            Self::Gzip => {
+                let mut encoder = GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+            Self::Lz4 => Ok(lz4_frame_stored(data)),
+        }
+    }
+}
+
+fn read_limited(reader: impl Read, content: &mut Vec<u8>) -> io::Result<()> {
+    reader.take(MAX_SIZE + 1).read_to_end(content)?;
+    check_size(content.len())
+}
+
+fn check_size(size: usize) -> io::Result<()> {
+    if size as u64 > MAX_SIZE {
+        return Err(invalid_data(format!(
+            "decompresses to more than {MAX_SIZE} bytes"
+        )));
+    }
+    Ok(())
+}
+
+/// Decodes an LZ4 frame. Checksums are skipped, linked blocks work since every block is
+/// decoded into the same buffer.
+fn lz4_frame(data: &[u8], content: &mut Vec<u8>) -> io::Result<()> {
+    let truncated = || invalid_data("lz4 frame is truncated".to_string());
+    let mut reader = Lz4Reader { data, position: 4 };
+
+    let flags = reader.byte().ok_or_else(truncated)?;
+    reader.byte().ok_or_else(truncated)?;
+    if flags >> 6 != 1 {
+        return Err(invalid_data(format!("lz4 frame version {}", flags >> 6)));
+    }
+    let block_checksums = flags & 0x10 != 0;
+    let content_size = flags & 0x08 != 0;
+    let content_checksum = flags & 0x04 != 0;
+    let dictionary = flags & 0x01 != 0;
+    // Header checksum after the optional fields
+    reader
+        .take(8 * content_size as usize + 4 * dictionary as usize + 1)
+        .ok_or_else(truncated)?;
+
+    loop {
+        let size = u32::from_le_bytes(reader.array().ok_or_else(truncated)?);
+        if size == 0 {
+            break;
+        }
+        let block = reader
+            .take((size & 0x7FFF_FFFF) as usize)
+            .ok_or_else(truncated)?;
+        if size & 0x8000_0000 != 0 {
+            content.extend_from_slice(block);
+        } else {
+            lz4_block(block, content)?;
+        }
+        check_size(content.len())?;
+        if block_checksums {
+            reader.take(4).ok_or_else(truncated)?;
+        }
+    }
+    if content_checksum {
+        reader.take(4).ok_or_else(truncated)?;
+    }
+    Ok(())
+}
+
+/// An LZ4 frame of uncompressed blocks, which every decoder reads. Writing is rare and
+/// the data small, so it isn't worth an encoder.
+fn lz4_frame_stored(data: &[u8]) -> Vec<u8> {
+    // Version 1, independent blocks, 4 MiB maximum block size and its header checksum
+    let mut frame = vec![0x04, 0x22, 0x4D, 0x18, 0x60, 0x70, 0x73];
+    for block in data.chunks(LZ4_MAX_BLOCK) {
+        frame.extend_from_slice(&(block.len() as u32 | 0x8000_0000).to_le_bytes());
+        frame.extend_from_slice(block);
+    }
+    frame.extend_from_slice(&0u32.to_le_bytes());
+    frame
+}
+
+fn lz4_block(block: &[u8], content: &mut Vec<u8>) -> io::Result<()> {
+    let corrupt = || invalid_data("lz4 block is corrupt".to_string());
+    let mut reader = Lz4Reader {
+        data: block,
+        position: 0,
+    };
+
+    while let Some(token) = reader.byte() {
+        let literals = reader.length(token >> 4).ok_or_else(corrupt)?;
+        content.extend_from_slice(reader.take(literals).ok_or_else(corrupt)?);
+        // The last sequence has only literals
+        if reader.position == block.len() {
+            break;
+        }
+
+        let offset = u16::from_le_bytes(reader.array().ok_or_else(corrupt)?) as usize;
+        let length = reader.length(token & 0x0F).ok_or_else(corrupt)? + 4;
+        if offset == 0 || offset > content.len() {
+            return Err(corrupt());
+        }
+        check_size(content.len() + length)?;
+        // Matches may overlap the bytes they produce, so they're copied one by one
+        let start = content.len() - offset;
+        for i in 0..length {
+            content.push(content[start + i]);
+        }
+    }
+    Ok(())
+}
+
+struct Lz4Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Lz4Reader<'a> {
+    fn take(&mut self, count: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.position..self.position.checked_add(count)?)?;
+        self.position += count;
+        Some(bytes)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.take(N)?.try_into().ok()
+    }
+
+    /// A 4 bit length from the token, continued by bytes while they are 255.
+    fn length(&mut self, nibble: u8) -> Option<usize> {
+        let mut length = nibble as usize;
+        if nibble == 0x0F {
+            loop {
+                let byte = self.byte()?;
+                length = length.checked_add(byte as usize)?;
+                if byte != 0xFF {
+                    break;
+                }
+            }
+        }
+        Some(length)
+    }
+}
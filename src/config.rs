@@ -0,0 +1,130 @@
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, anyhow};
+use serde::{Deserialize, Serialize};
+
+use hsr_lang_patcher::{
+    allowed_language::Voices, backup::Retention, game::Platform, language::Language,
+};
+
+use crate::Result;
+
+pub const FILE_NAME: &str = "hsr-lang-patcher.toml";
+
+/// Settings persisted next to the executable. The last successful patch is
+/// stored here so background modes can re-apply it after a game update.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub game_path: Option<PathBuf>,
+    pub text_language: Option<String>,
+    pub voice_language: Option<String>,
+    /// Voice of the CN client rows when it differs from `voice_language`.
+    pub cn_voice_language: Option<String>,
+    /// DesignData platform folder the profile was saved for.
+    pub platform: Platform,
+    /// Start the game after a successful patch, same as `--launch`.
+    pub launch: bool,
+    /// Extra arguments passed to the game when launching it.
+    pub launch_args: Vec<String>,
+    /// Look for a newer release on GitHub at startup.
+    pub check_updates: bool,
+    /// Backup retention, applied after every patch and by `backups prune`.
+    pub backup_max_count: Option<usize>,
+    pub backup_max_age_days: Option<u64>,
+    pub backup_max_size_mb: Option<u64>,
+    /// Back up whole containers, same as `--full-backup`.
+    pub full_backups: bool,
+    /// Keep the rows' language lists, same as `--keep-lists`.
+    pub keep_lists: bool,
+    /// Patch the rows of both clients, same as `--all-regions`.
+    pub all_regions: bool,
+    /// Fail on an unexpected excel format, same as `--strict`.
+    pub strict: bool,
+    /// Keep backups below this directory, same as `--backup-dir`.
+    pub backup_dir: Option<PathBuf>,
+    /// Shell commands run before a patch writes anything, a failing one cancels it.
+    pub pre_patch: Vec<String>,
+    /// Shell commands run after a patch, before the game is launched.
+    pub post_patch: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            game_path: None,
+            text_language: None,
+            voice_language: None,
+            cn_voice_language: None,
+            platform: Platform::default(),
+            launch: false,
+            launch_args: Vec::new(),
+            check_updates: true,
+            backup_max_count: None,
+            backup_max_age_days: None,
+            backup_max_size_mb: None,
+            full_backups: false,
+            keep_lists: false,
+            all_regions: false,
+            strict: false,
+            backup_dir: None,
+            pre_patch: Vec::new(),
+            post_patch: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Result<PathBuf> {
+        Ok(env::current_exe()?.with_file_name(FILE_NAME))
+    }
+
+    pub fn load() -> Result<Self> {
+        let path = Self::path()?;
+        match fs::read_to_string(&path) {
+            Ok(data) => toml::from_str(&data)
+                .with_context(|| format!("Failed to parse '{}'", path.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.save_to(&Self::path()?)
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        fs::write(path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write '{}'", path.display()))
+    }
+
+    pub fn retention(&self) -> Retention {
+        Retention {
+            max_count: self.backup_max_count,
+            max_age: self
+                .backup_max_age_days
+                .map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+            max_size: self.backup_max_size_mb.map(|mb| mb * 1024 * 1024),
+        }
+    }
+
+    /// Returns the saved text and voice languages of the last successful patch.
+    pub fn profile(&self) -> Result<(Language, Voices)> {
+        let (Some(text), Some(voice)) = (&self.text_language, &self.voice_language) else {
+            return Err(anyhow!(
+                "No saved language profile. Run a normal patch first"
+            ));
+        };
+
+        let os = Language::parse_voice(voice)?;
+        let cn = match &self.cn_voice_language {
+            Some(cn) => Language::parse_voice(cn)?,
+            None => os,
+        };
+        Ok((Language::parse_text(text)?, Voices { os, cn }))
+    }
+}
@@ -0,0 +1,553 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, anyhow};
+
+use hsr_lang_patcher::{
+    ErrorKind, PatcherError,
+    checksum::{Checksum, ChecksumState},
+    compression::Compression,
+    design_index::{DesignIndex, EntryQuery},
+    game::{ALLOWED_LANGUAGE_HASH, Game},
+    infer::infer_schema,
+    master_index::MasterUpdate,
+    recipe::FieldEdit,
+    schema::Schema,
+};
+
+use crate::{
+    Result,
+    args::{Args, Command, Format},
+    output,
+};
+
+const BYTES_PER_LINE: usize = 16;
+
+/// Rewrites the AllowedLanguage container without gaps and padding.
+pub fn rebuild(game: &Game, quiet: bool) -> Result<()> {
+    let report = game.rebuild_container(&game.file_hash)?;
+
+    if !quiet {
+        println!(
+            "Rebuilt '{}': {} entries, {} -> {} bytes",
+            game.bytes_path.display(),
+            report.entries,
+            report.old_size,
+            report.new_size
+        );
+    }
+    print_master_update(&report.master, quiet);
+    Ok(())
+}
+
+/// Appends the payload from `--file` as a new entry named `--name-hash`.
+pub fn add_entry(game: &Game, args: &Args) -> Result<()> {
+    let name_hash = args
+        .name_hash
+        .context("Expected the new entry's hash with '--name-hash'")
+        .context(ErrorKind::InvalidArguments)?;
+    let path = args
+        .file
+        .as_deref()
+        .context("Expected the payload with '--file'")
+        .context(ErrorKind::InvalidArguments)?;
+    let payload = fs::read(path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+
+    let file_hash = args.container.as_deref().unwrap_or(&game.file_hash);
+    let (entry, master) = game.add_entry(file_hash, name_hash, &payload)?;
+
+    if !args.quiet {
+        println!(
+            "Added entry {name_hash} to {file_hash}.bytes at offset 0x{:X}, {} bytes",
+            entry.offset, entry.size
+        );
+    }
+    print_master_update(&master, args.quiet);
+    Ok(())
+}
+
+/// Lists the checksums covering the patched files and whether they still match.
+pub fn checksums(game: &Game) -> Result<()> {
+    let statuses = game.check_checksums()?;
+    if statuses.is_empty() {
+        println!("None of the files use a known checksum");
+        return Ok(());
+    }
+
+    for status in &statuses {
+        let name = match &status.checksum {
+            Checksum::Manifest { file } => format!("pkg_version entry of {file}"),
+            Checksum::FileName { file } => format!("MD5 file name of {file}"),
+            Checksum::ReadSize { file_hash } => format!("index size of {file_hash}.bytes"),
+        };
+        let state = match status.state {
+            ChecksumState::Valid => output::success("valid"),
+            ChecksumState::Stale => output::removed("stale"),
+        };
+        println!("{name}: {state}");
+    }
+
+    let stale = statuses
+        .iter()
+        .any(|status| status.state == ChecksumState::Stale);
+    if stale {
+        eprintln!(
+            "{}: The launcher's \"Repair game files\" will replace files with stale \
+            checksums",
+            output::warning()
+        );
+    }
+    Ok(())
+}
+
+/// Prints the index entries matching `query` with their container.
+pub fn find(index: &DesignIndex, query: &EntryQuery) -> Result<()> {
+    let mut found = 0;
+    for (file, entry) in index.find_entries(query) {
+        if found == 0 {
+            println!(
+                "{:<32}  {:>11}  {:>10}  {:>10}",
+                "CONTAINER", "HASH", "OFFSET", "SIZE"
+            );
+        }
+        println!(
+            "{:<32}  {:>11}  {:>#10X}  {:>10}",
+            file.file_hash, entry.name_hash, entry.offset, entry.size
+        );
+        found += 1;
+    }
+
+    if found == 0 {
+        println!("No entries found");
+    }
+    Ok(())
+}
+
+/// Writes every file record and entry of the index to `--file` as CSV or JSON.
+pub fn dump_index(index: &DesignIndex, args: &Args) -> Result<()> {
+    let format = match output_format(args) {
+        Some(Format::Csv) => Format::Csv,
+        None | Some(Format::Json) => Format::Json,
+        Some(_) => {
+            return Err(anyhow!(
+                "Invalid format for dump-index. Must be csv or json"
+            ))
+            .context(ErrorKind::InvalidArguments);
+        }
+    };
+    let path = match (&args.file, format) {
+        (Some(path), _) => path.clone(),
+        (None, Format::Csv) => PathBuf::from("design-index.csv"),
+        _ => PathBuf::from("design-index.json"),
+    };
+
+    let data = match format {
+        Format::Csv => index_csv(index),
+        _ => serde_json::to_string_pretty(index)?,
+    };
+    fs::write(&path, data).with_context(|| format!("Failed to write '{}'", path.display()))?;
+
+    if !args.quiet {
+        println!(
+            "Wrote the index with {} file records to '{}'",
+            index.files.len(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// One line per entry, repeating the fields of its file record. Files without
+/// entries get a line with empty entry fields.
+fn index_csv(index: &DesignIndex) -> String {
+    let mut csv = String::from(
+        "file_name_hash,file_hash,read_size,entry_count,file_unk_1,name_hash,size,offset\n",
+    );
+    for file in &index.files {
+        let prefix = format!(
+            "{},{},{},{},{}",
+            file.name_hash, file.file_hash, file.read_size, file.entry_count, file.unk_1
+        );
+        if file.entries.is_empty() {
+            csv.push_str(&format!("{prefix},,,\n"));
+        }
+        for entry in &file.entries {
+            csv.push_str(&format!(
+                "{prefix},{},{},{}\n",
+                entry.name_hash, entry.size, entry.offset
+            ));
+        }
+    }
+    csv
+}
+
+/// Prints the bytes of an entry as hex and ASCII, like `xxd`. Offsets start at `base`.
+pub fn hexdump(data: &[u8], base: u64, args: &Args) -> Result<()> {
+    let len = args
+        .len
+        .map_or(data.len(), |len| data.len().min(len as usize));
+
+    for line in hexdump_lines(&data[..len], base) {
+        println!("{line}");
+    }
+
+    if len < data.len() {
+        println!("... {} more bytes", data.len() - len);
+    }
+    Ok(())
+}
+
+/// Offset, hex bytes and ASCII of each 16 byte line.
+pub fn hexdump_lines(data: &[u8], base: u64) -> impl Iterator<Item = String> + '_ {
+    data.chunks(BYTES_PER_LINE)
+        .enumerate()
+        .map(move |(i, line)| {
+            let hex: Vec<String> = line.iter().map(|byte| format!("{byte:02x}")).collect();
+            let ascii: String = line
+                .iter()
+                .map(|&byte| {
+                    if byte.is_ascii_graphic() || byte == b' ' {
+                        byte as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!(
+                "{:08x}  {:<width$}  |{ascii}|",
+                base as usize + i * BYTES_PER_LINE,
+                hex.join(" "),
+                width = BYTES_PER_LINE * 3 - 1
+            )
+        })
+}
+
+/// Sets fields of the rows matching `--where`, using `--schema` or a built-in schema.
+pub fn set_field(game: &Game, args: &Args) -> Result<()> {
+    if args.assignments.is_empty() {
+        return Err(anyhow!("Expected at least one '--set name=value'"))
+            .context(ErrorKind::InvalidArguments);
+    }
+
+    let (hash, schema) = match &args.schema {
+        Some(path) => {
+            let schema = read_schema(path)?;
+            let hash = args
+                .excel
+                .or(schema.name_hash)
+                .context("The schema has no name_hash, pass the excel with '--excel'")
+                .context(ErrorKind::InvalidArguments)?;
+            (hash, schema)
+        }
+        None => {
+            let hash = args
+                .excel
+                .context("Expected the excel with '--excel NAME'")
+                .context(ErrorKind::InvalidArguments)?;
+            let schema = Schema::known()
+                .into_iter()
+                .find(|schema| schema.name_hash == Some(hash))
+                .with_context(|| {
+                    format!("No built-in schema for excel {hash}, pass one with '--schema'")
+                })
+                .context(ErrorKind::InvalidArguments)?;
+            (hash, schema)
+        }
+    };
+
+    let edit = FieldEdit::new(hash, schema.clone(), &args.filters, &args.assignments)?;
+    let rows = schema
+        .decode_stored(&game.read_entry(hash)?)
+        .with_context(|| format!("The schema doesn't fit excel {hash}"))?;
+    let matched = rows.iter().filter(|row| edit.matches(row)).count();
+    let changed = game.apply_recipes(&[&edit])? > 0;
+
+    if !args.quiet {
+        let rows = format!("{matched} of {} rows of {}", rows.len(), schema.name);
+        if changed {
+            println!("{}", output::success(&format!("Updated {rows}")));
+        } else if matched == 0 {
+            println!("No rows matched, {rows}");
+        } else {
+            println!("Already set in {rows}, nothing written");
+        }
+    }
+    Ok(())
+}
+
+/// Writes the raw bytes of an excel to `-o`, or to stdout with `-o -`.
+pub fn extract(game: &Game, args: &Args) -> Result<()> {
+    let hash = args.excel.unwrap_or(ALLOWED_LANGUAGE_HASH);
+    let (data, compression) = read_decompressed(game, hash, args.raw)?;
+    if let Some(compression) = compression
+        && !args.quiet
+    {
+        // Stdout may be the data itself
+        eprintln!(
+            "Excel {hash} is {} compressed, {}",
+            compression.name(),
+            if args.raw {
+                "extracting it as stored"
+            } else {
+                "extracting it decompressed. --raw keeps it as stored"
+            }
+        );
+    }
+
+    let path = args
+        .output
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("Excel{hash}.bytes")));
+    if path == Path::new("-") {
+        let mut stdout = io::stdout().lock();
+        stdout
+            .write_all(&data)
+            .and_then(|_| stdout.flush())
+            .context("Failed to write to stdout")?;
+        return Ok(());
+    }
+
+    fs::write(&path, &data).with_context(|| format!("Failed to write '{}'", path.display()))?;
+    if !args.quiet {
+        println!(
+            "Wrote excel {hash}, {} bytes, to '{}'",
+            data.len(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Reads an entry, decompressed unless `raw` is set. Also returns how it was stored if
+/// it was compressed.
+pub fn read_decompressed(
+    game: &Game,
+    hash: i32,
+    raw: bool,
+) -> Result<(Vec<u8>, Option<Compression>)> {
+    let data = game.read_entry(hash)?;
+    let Some(compression) = Compression::detect(&data) else {
+        return Ok((data, None));
+    };
+    if raw {
+        return Ok((data, Some(compression)));
+    }
+    let content = compression.decompress(&data).with_context(|| {
+        format!(
+            "Failed to decompress excel {hash}, which looks {} compressed. --raw extracts it as stored",
+            compression.name()
+        )
+    })?;
+    Ok((content, Some(compression)))
+}
+
+/// Replaces the bytes of an excel with the data from `-i`, or from stdin with `-i -`.
+pub fn inject(game: &Game, args: &Args) -> Result<()> {
+    let hash = args.excel.unwrap_or(ALLOWED_LANGUAGE_HASH);
+    let path = args
+        .input
+        .as_deref()
+        .context("Expected the data with '-i', or '-i -' to read it from stdin")
+        .context(ErrorKind::InvalidArguments)?;
+
+    let data = if path == Path::new("-") {
+        let mut data = Vec::new();
+        io::stdin()
+            .lock()
+            .read_to_end(&mut data)
+            .context("Failed to read from stdin")?;
+        data
+    } else {
+        fs::read(path).with_context(|| format!("Failed to read '{}'", path.display()))?
+    };
+
+    let changed = game.write_entry(hash, &data)?;
+    if !args.quiet {
+        if changed {
+            println!("Wrote {} bytes to excel {hash}", data.len());
+        } else {
+            println!("Excel {hash} already holds this data, nothing written");
+        }
+    }
+    Ok(())
+}
+
+/// Guesses the row layout of an excel and writes it as a draft schema. `hash` is `None`
+/// for a region of a loose container, which has no name.
+pub fn infer(hash: Option<i32>, data: &[u8], args: &Args) -> Result<()> {
+    let name = match hash {
+        Some(hash) => hash.to_string(),
+        None => format!("0x{:X}", args.query.offset.unwrap_or(0)),
+    };
+    let inferred = infer_schema(&format!("Excel{name}"), hash, data)
+        .with_context(|| format!("No known row layout decodes excel {name}"))?;
+
+    let path = args
+        .file
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("{name}.schema.toml")));
+    let draft = format!(
+        "# Draft inferred from {} rows, check the types and rename the fields before use\n{}",
+        inferred.rows,
+        inferred.schema.to_toml()
+    );
+    fs::write(&path, draft).with_context(|| format!("Failed to write '{}'", path.display()))?;
+
+    if !args.quiet {
+        for (i, field) in inferred.schema.fields.iter().enumerate() {
+            let note = if inferred.unused_fields.contains(&i) {
+                "  (never set, type unknown)"
+            } else {
+                ""
+            };
+            println!("{:<10} {}{note}", field.name, field.kind.name());
+        }
+        if inferred.alternatives > 0 {
+            println!(
+                "{} other layouts decode the data too, the one with the most readable strings was picked",
+                inferred.alternatives
+            );
+        }
+        println!("Wrote the draft schema to '{}'", path.display());
+    }
+    Ok(())
+}
+
+/// Runs `find`, `dump-index`, `hexdump` or `infer` on a `DesignV_*.bytes` index or an
+/// excel container passed directly, e.g. loose files pulled off a device.
+pub fn run_on_file(path: &Path, args: &Args) -> Result<()> {
+    let is_index = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.to_ascii_lowercase().starts_with("designv_"));
+
+    if !is_index {
+        // A container has no index, so the region is given by `--offset` and `--len`
+        let offset = args.query.offset.unwrap_or(0);
+        return match args.command {
+            Command::Hexdump => hexdump(&read_region(path, offset, args.len)?, offset, args),
+            Command::Infer => infer(None, &read_region(path, offset, args.len)?, args),
+            _ => Err(anyhow!(
+                "'{}' is not a DesignV index, which find and dump-index need",
+                path.display()
+            ))
+            .context(ErrorKind::InvalidArguments),
+        };
+    }
+
+    let data = fs::read(path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+    let index = DesignIndex::parse(&data)?;
+    match args.command {
+        Command::Find => find(&index, &args.query),
+        Command::DumpIndex => dump_index(&index, args),
+        _ => {
+            // The containers are usually pulled together with their index
+            let hash = args.excel.unwrap_or(ALLOWED_LANGUAGE_HASH);
+            let (entry, file) = index
+                .find_by_hash(hash)
+                .ok_or(PatcherError::ExcelNotFound { hash })?;
+            let container = path.with_file_name(format!("{}.bytes", file.file_hash));
+            let data = read_region(&container, entry.offset as u64, Some(entry.size as u64))?;
+            match args.command {
+                Command::Infer => infer(Some(hash), &data, args),
+                _ => hexdump(&data, 0, args),
+            }
+        }
+    }
+}
+
+/// `len` bytes from `offset`, or up to the end of the file.
+fn read_region(path: &Path, offset: u64, len: Option<u64>) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    File::open(path)
+        .and_then(|mut file| {
+            file.seek(SeekFrom::Start(offset))?;
+            file.take(len.unwrap_or(u64::MAX)).read_to_end(&mut data)
+        })
+        .with_context(|| format!("Failed to read '{}'", path.display()))?;
+    Ok(data)
+}
+
+/// Runs the Rhai script from `--file` against the game.
+#[cfg(feature = "scripting")]
+pub fn run_script(game: &Game, args: &Args) -> Result<()> {
+    let path = args
+        .file
+        .as_deref()
+        .context("Expected the script with '--file'")
+        .context(ErrorKind::InvalidArguments)?;
+    let changed = game.run_script(path)?;
+
+    if !args.quiet {
+        println!("The script changed {changed} excels");
+    }
+    Ok(())
+}
+
+pub fn read_schema(path: &Path) -> Result<Schema> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+    Schema::from_toml(&text)
+        .with_context(|| format!("Invalid schema '{}'", path.display()))
+        .context(ErrorKind::InvalidArguments)
+}
+
+/// Writes a Kaitai Struct or 010 Editor description of an excel layout, so the data
+/// can be inspected in those tools.
+pub fn template(args: &Args) -> Result<()> {
+    let schema = match &args.schema {
+        Some(path) => read_schema(path)?,
+        None => Schema::allowed_language(),
+    };
+
+    let format = match output_format(args) {
+        None | Some(Format::Ksy) => Format::Ksy,
+        Some(Format::Bt) => Format::Bt,
+        Some(_) => {
+            return Err(anyhow!("Invalid format for template. Must be ksy or 010"))
+                .context(ErrorKind::InvalidArguments);
+        }
+    };
+    let (data, extension) = match format {
+        Format::Bt => (schema.to_010_template(args.query.offset), "bt"),
+        _ => (schema.to_kaitai(args.query.offset), "ksy"),
+    };
+    let path = args
+        .file
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(format!("{}.{extension}", schema.name)));
+    fs::write(&path, data).with_context(|| format!("Failed to write '{}'", path.display()))?;
+
+    if !args.quiet {
+        println!("Wrote the {} template to '{}'", schema.name, path.display());
+    }
+    Ok(())
+}
+
+/// `--format`, or the extension of `--file` when it names a known format.
+fn output_format(args: &Args) -> Option<Format> {
+    args.format.or_else(|| {
+        args.file
+            .as_deref()
+            .and_then(Path::extension)
+            .and_then(|extension| Format::from_name(&extension.to_string_lossy()))
+    })
+}
+
+fn print_master_update(update: &MasterUpdate, quiet: bool) {
+    if let Some(hash) = &update.renamed_to
+        && !quiet
+    {
+        println!("Renamed the index to DesignV_{hash}.bytes and updated M_DesignV.bytes");
+    }
+    if update.size_unknown {
+        eprintln!(
+            "{}: The index size changed. Only the index hash in M_DesignV.bytes is understood, \
+            so anything else it stores was left unchanged",
+            output::warning()
+        );
+    }
+}
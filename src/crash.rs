@@ -0,0 +1,102 @@
+//! Panic hook that writes a diagnostic bundle next to the exe. When the .exe is started by
+//! double-clicking, the console closes right after a panic and the message is lost.
+
+use std::{
+    backtrace::Backtrace,
+    collections::VecDeque,
+    env,
+    fmt::Write as _,
+    fs,
+    panic::{self, PanicHookInfo},
+    path::PathBuf,
+    sync::{Mutex, PoisonError},
+};
+
+use hsr_lang_patcher::game::Game;
+
+const FILE_NAME: &str = "hsr-lang-patcher-crash.txt";
+const LOG_LINES: usize = 20;
+const ISSUES_URL: &str = "https://github.com/nie4/hsr-lang-patcher/issues";
+
+static GAME: Mutex<Option<String>> = Mutex::new(None);
+static LOG: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Installs the hook. The default hook still prints the message first.
+pub fn install(should_pause: bool) {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+
+        match write_bundle(info) {
+            Ok(path) => eprintln!(
+                "\nThe patcher crashed. Please attach {} to an issue at {ISSUES_URL}",
+                path.display()
+            ),
+            Err(e) => {
+                eprintln!("\nThe patcher crashed and the crash report couldn't be saved: {e}")
+            }
+        }
+        if should_pause {
+            crate::wait_for_exit();
+        }
+    }));
+}
+
+/// Remembers the loaded game for the report.
+pub fn set_game(game: &Game) {
+    let info = format!(
+        "DesignData: {}\nIndex hash: {}\nContainer: {}\nAllowedLanguage: offset {:#x}, size {}\nGame version: {}",
+        game.design_data_path.display(),
+        game.index_hash,
+        game.file_hash,
+        game.data_entry.offset,
+        game.data_entry.size,
+        game.game_version().as_deref().unwrap_or("unknown"),
+    );
+    *GAME.lock().unwrap_or_else(PoisonError::into_inner) = Some(info);
+}
+
+/// Keeps the last few steps of the run for the report.
+pub fn log(line: impl Into<String>) {
+    let mut log = LOG.lock().unwrap_or_else(PoisonError::into_inner);
+    if log.len() == LOG_LINES {
+        log.pop_front();
+    }
+    log.push_back(line.into());
+}
+
+fn write_bundle(info: &PanicHookInfo) -> std::io::Result<PathBuf> {
+    let mut report = String::new();
+    let _ = writeln!(
+        report,
+        "{} v{}\nOS: {} {}\nArguments: {:?}\n",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        env::consts::OS,
+        env::consts::ARCH,
+        env::args_os().skip(1).collect::<Vec<_>>(),
+    );
+    let _ = writeln!(report, "Panic: {info}\n");
+
+    match &*GAME.lock().unwrap_or_else(PoisonError::into_inner) {
+        Some(game) => {
+            let _ = writeln!(report, "{game}\n");
+        }
+        None => report.push_str("No game loaded\n\n"),
+    }
+
+    report.push_str("Log:\n");
+    for line in LOG.lock().unwrap_or_else(PoisonError::into_inner).iter() {
+        let _ = writeln!(report, "  {line}");
+    }
+
+    let _ = writeln!(report, "\nBacktrace:\n{}", Backtrace::force_capture());
+
+    let dir = env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(PathBuf::from))
+        .unwrap_or_default();
+    let path = dir.join(FILE_NAME);
+    fs::write(&path, report)?;
+    Ok(path)
+}
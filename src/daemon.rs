@@ -0,0 +1,224 @@
+//! `daemon`: stays in the background and repairs the patch on its own. It watches the
+//! index hash in `M_DesignV.bytes`, and once the launcher finished installing an update
+//! (no updater running, no fresh downloads left) and the game isn't running, re-applies
+//! the saved profile. What it does is logged to
+//! `hsr-lang-patcher-daemon.log` next to the exe.
+
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::PathBuf,
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+
+use hsr_lang_patcher::{
+    detect::{UpdateActivity, is_game_running, update_activity},
+    master_index::MasterIndex,
+};
+
+use crate::{
+    Result,
+    args::Args,
+    config::Config,
+    tray::{CHECK_INTERVAL, profile_design_data_path, profile_game_path, reapply_profile},
+};
+
+const LOG_FILE_NAME: &str = "hsr-lang-patcher-daemon.log";
+
+struct Daemon {
+    log: Option<File>,
+    quiet: bool,
+    notify: bool,
+    /// Last message logged for a failed check, so a lasting problem is logged once.
+    last_error: Option<String>,
+}
+
+pub fn run(args: &Args) -> Result<()> {
+    let log_path = log_path()?;
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .with_context(|| format!("Failed to open '{}'", log_path.display()))?;
+    let mut daemon = Daemon {
+        log: Some(log),
+        quiet: args.quiet,
+        notify: args.notify,
+        last_error: None,
+    };
+    daemon.log(&format!(
+        "Started v{}, checking the game files every {} s",
+        env!("CARGO_PKG_VERSION"),
+        CHECK_INTERVAL.as_secs()
+    ));
+
+    let mut seen_hash: Option<String> = None;
+    // An update was seen but not handled yet
+    let mut pending = false;
+    loop {
+        match index_hash() {
+            Ok(hash) if seen_hash.as_ref().is_some_and(|seen| *seen != hash) => {
+                // The launcher writes the index before the containers, so only a hash that
+                // stayed the same for a whole interval counts as a finished update
+                daemon.log(&format!(
+                    "Game files changed (index {hash}), waiting for the update to finish"
+                ));
+                seen_hash = Some(hash);
+                pending = true;
+            }
+            Ok(hash) => {
+                seen_hash = Some(hash);
+                // Some updates keep the index until the last file is in place
+                if let Some(activity) = launcher_update() {
+                    daemon.log_error(&format!(
+                        "The launcher is updating the game ({activity}), waiting for it to finish"
+                    ));
+                    pending = true;
+                } else if !is_game_running() {
+                    // The game reads the excels at startup, so it's only patched when it
+                    // isn't running. Also catches launcher repairs, which keep the index hash
+                    pending = !daemon.repair();
+                } else if pending {
+                    daemon.log_error("StarRail.exe is running, waiting for it to exit");
+                }
+            }
+            // Also expected halfway through an update
+            Err(e) => daemon.log_error(&format!("Failed to read the game files: {e:#}")),
+        }
+        thread::sleep(CHECK_INTERVAL);
+    }
+}
+
+impl Daemon {
+    /// Re-applies the saved profile if the game files no longer match it, returns
+    /// whether they match it now.
+    fn repair(&mut self) -> bool {
+        match reapply_profile() {
+            Ok(Some(reapplied)) => {
+                let message = reapplied.message();
+                self.log(&message);
+                if self.notify {
+                    notify(&message, false);
+                }
+                true
+            }
+            Ok(None) => {
+                self.last_error = None;
+                true
+            }
+            Err(e) => {
+                let message = format!("Failed to re-apply the profile: {e:#}");
+                if self.notify && self.last_error.as_ref() != Some(&message) {
+                    notify(&message, true);
+                }
+                self.log_error(&message);
+                false
+            }
+        }
+    }
+
+    fn log(&mut self, message: &str) {
+        self.last_error = None;
+        self.write(message);
+    }
+
+    fn log_error(&mut self, message: &str) {
+        if self.last_error.as_deref() != Some(message) {
+            self.write(message);
+            self.last_error = Some(message.to_string());
+        }
+    }
+
+    fn write(&mut self, message: &str) {
+        let line = format!("[{}] {message}", timestamp());
+        if !self.quiet {
+            println!("{line}");
+        }
+        // A log that can't be written isn't worth stopping for, the console still has it
+        if let Some(log) = &mut self.log
+            && writeln!(log, "{line}").is_err()
+        {
+            self.log = None;
+        }
+    }
+}
+
+fn log_path() -> Result<PathBuf> {
+    Ok(env::current_exe()?.with_file_name(LOG_FILE_NAME))
+}
+
+/// Reads just the master index instead of loading the game, which is cheap enough to do
+/// every interval.
+fn index_hash() -> Result<String> {
+    let path = profile_design_data_path(&Config::load()?)?.join("M_DesignV.bytes");
+    let data = fs::read(&path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+    let index = MasterIndex::parse(&data)
+        .with_context(|| format!("'{}' holds no index hash", path.display()))?;
+    Ok(index.index_hash_hex())
+}
+
+/// Checks the profile's game folder, a game path that can't be found has nothing to
+/// update.
+fn launcher_update() -> Option<UpdateActivity> {
+    let game_path = profile_game_path(&Config::load().ok()?).ok()?;
+    update_activity(&game_path)
+}
+
+/// Shows a toast on Windows, elsewhere a desktop notification where a notifier is at
+/// hand. Errors are ignored.
+#[cfg(windows)]
+fn notify(message: &str, is_error: bool) {
+    let _ = crate::notifications::show(message, is_error);
+}
+
+#[cfg(not(windows))]
+fn notify(message: &str, _is_error: bool) {
+    use std::process::{Command, Stdio};
+
+    let command = if cfg!(target_os = "macos") {
+        let script = format!(
+            "display notification {message:?} with title {:?}",
+            env!("CARGO_PKG_NAME")
+        );
+        Command::new("osascript").args(["-e", &script]).spawn()
+    } else {
+        Command::new("notify-send")
+            .args([env!("CARGO_PKG_NAME"), message])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+    };
+    if let Ok(mut child) = command {
+        thread::spawn(move || child.wait());
+    }
+}
+
+/// `YYYY-MM-DD HH:MM:SS` in UTC.
+fn timestamp() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (days, time) = (secs / 86400, secs % 86400);
+
+    // Civil date from days since 1970-01-01, after Howard Hinnant's algorithm
+    let z = days as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
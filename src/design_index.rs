@@ -1,10 +1,14 @@
-use std::io::{BufReader, Cursor, Read};
+use std::{
+    collections::BTreeMap,
+    io::{self, Cursor, Read, Seek, SeekFrom},
+};
 
-use crate::Result;
-use byteorder::{BE, LE, ReadBytesExt};
+use crate::{PatcherError, Result};
+use byteorder::{BE, LE, ReadBytesExt, WriteBytesExt};
+use serde::Serialize;
 
 #[allow(unused)]
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Clone, Serialize)]
 pub struct DataEntry {
     pub name_hash: i32,
     pub size: i32,
@@ -12,7 +16,7 @@ pub struct DataEntry {
 }
 
 #[allow(unused)]
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Serialize)]
 pub struct FileEntry {
     pub name_hash: i32,
     pub file_hash: String,
@@ -23,12 +27,17 @@ pub struct FileEntry {
 }
 
 #[allow(unused)]
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Serialize)]
 pub struct DesignIndex {
     pub unk_1: u64,
     pub file_count: u32,
     pub unk_2: u32,
     pub files: Vec<FileEntry>,
+    /// Older format with a single trailing byte per file.
+    pub legacy: bool,
+    /// Anything after the last file record, kept so the index can be written back as is.
+    #[serde(skip)]
+    pub trailing: Vec<u8>,
 }
 
 impl DesignIndex {
@@ -37,62 +46,390 @@ impl DesignIndex {
     }
 
     fn parse_data(data: &[u8], legacy: bool) -> Result<Self> {
-        let mut reader = BufReader::new(data);
+        let mut reader = IndexReader::new(Cursor::new(data), legacy)?;
+        let mut header = DesignIndex {
+            unk_1: reader.unk_1,
+            file_count: reader.file_count,
+            unk_2: reader.unk_2,
+            files: Vec::with_capacity(reader.file_count as usize),
+            legacy,
+            trailing: Vec::new(),
+        };
+
+        while let Some(file) = reader.next_file()? {
+            header.files.push(file);
+        }
+        let end = reader.into_inner().position() as usize;
+        header.trailing = data[end..].to_vec();
+
+        Ok(header)
+    }
+
+    /// Reads file records from `reader` only until one contains `hash`, without loading
+    /// the rest of the index.
+    pub fn find_in_reader<R: Read + Seek>(
+        mut reader: R,
+        hash: i32,
+    ) -> Result<Option<(DataEntry, FileEntry)>> {
+        Self::find_in(&mut reader, hash, false).or_else(|_| {
+            reader
+                .seek(SeekFrom::Start(0))
+                .map_err(|source| PatcherError::IndexParse { offset: 0, source })?;
+            Self::find_in(&mut reader, hash, true)
+        })
+    }
 
+    fn find_in<R: Read + Seek>(
+        reader: &mut R,
+        hash: i32,
+        legacy: bool,
+    ) -> Result<Option<(DataEntry, FileEntry)>> {
+        let mut reader = IndexReader::new(reader, legacy)?;
+        while let Some(file) = reader.next_file()? {
+            let entry = file.entries.iter().find(|entry| entry.name_hash == hash);
+            if let Some(entry) = entry.cloned() {
+                return Ok(Some((entry, file)));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Writes the index back in its original format. An unmodified index comes out byte
+    /// for byte identical to the parsed data, changed sizes, offsets and entries are
+    /// written as they are.
+    pub fn serialize(&self) -> io::Result<Vec<u8>> {
         let mut buffer = Vec::new();
-        reader.read_to_end(&mut buffer)?;
+        buffer.write_u64::<LE>(self.unk_1)?;
+        buffer.write_u32::<BE>(self.files.len() as u32)?;
+        buffer.write_u32::<LE>(self.unk_2)?;
 
-        let mut cursor = Cursor::new(buffer);
+        for file in &self.files {
+            buffer.write_i32::<BE>(file.name_hash)?;
+            let mut file_hash = [0u8; 0x10];
+            hex::decode_to_slice(&file.file_hash, &mut file_hash)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            buffer.extend_from_slice(&file_hash);
+            buffer.write_u64::<BE>(file.read_size)?;
+            buffer.write_u32::<BE>(file.entries.len() as u32)?;
+            for entry in &file.entries {
+                buffer.write_i32::<BE>(entry.name_hash)?;
+                buffer.write_i32::<BE>(entry.size)?;
+                buffer.write_i32::<BE>(entry.offset)?;
+            }
+            if self.legacy {
+                buffer.write_u8(file.unk_1 as u8)?;
+            } else {
+                buffer.write_u24::<BE>(file.unk_1)?;
+            }
+        }
+        buffer.extend_from_slice(&self.trailing);
 
-        let mut header = DesignIndex {
-            unk_1: cursor.read_u64::<LE>()?,
-            file_count: cursor.read_u32::<BE>()?,
-            unk_2: cursor.read_u32::<LE>()?,
-            files: Default::default(),
-        };
+        Ok(buffer)
+    }
 
-        for _ in 0..header.file_count {
-            let name_hash = cursor.read_i32::<BE>()?;
+    /// The file records in index order, one per container.
+    pub fn files(&self) -> std::slice::Iter<'_, FileEntry> {
+        self.files.iter()
+    }
 
-            let mut file_hash_bytes = [0u8; 0x10];
-            cursor.read_exact(&mut file_hash_bytes)?;
-            let file_hash = hex::encode(file_hash_bytes);
+    /// Every entry in index order, with the file record of its container. The contents
+    /// can be streamed with `Game::entry_reader`.
+    pub fn entries(&self) -> impl Iterator<Item = (&FileEntry, &DataEntry)> {
+        self.files
+            .iter()
+            .flat_map(|file| file.entries.iter().map(move |entry| (file, entry)))
+    }
 
-            let read_size = cursor.read_u64::<BE>()?;
-            let entry_count = cursor.read_u32::<BE>()?;
+    /// All entries matching `query`, with the file record of their container.
+    pub fn find_entries<'a>(
+        &'a self,
+        query: &'a EntryQuery,
+    ) -> impl Iterator<Item = (&'a FileEntry, &'a DataEntry)> + 'a {
+        self.entries().filter(|(_, entry)| query.matches(entry))
+    }
 
-            let mut entries = Vec::with_capacity(entry_count as usize);
-            for _ in 0..entry_count {
-                entries.push(DataEntry {
-                    name_hash: cursor.read_i32::<BE>()?,
-                    size: cursor.read_i32::<BE>()?,
-                    offset: cursor.read_i32::<BE>()?,
-                });
-            }
+    /// Compares the entries of two indexes by name hash, e.g. before and after a game
+    /// update. Removed entries come first, then changed and added ones.
+    pub fn diff(&self, new: &DesignIndex) -> Vec<EntryChange> {
+        let old_entries = self.entries_by_hash();
+        let new_entries = new.entries_by_hash();
 
-            header.files.push(FileEntry {
-                name_hash,
-                file_hash,
-                read_size,
-                entry_count,
-                entries,
-                unk_1: if legacy {
-                    cursor.read_u8()? as u32
-                } else {
-                    cursor.read_u24::<BE>()?
-                },
+        let removed = old_entries
+            .iter()
+            .filter(|(hash, _)| !new_entries.contains_key(hash))
+            .map(|(_, (file, entry))| EntryChange::Removed {
+                file_hash: file.file_hash.clone(),
+                entry: (*entry).clone(),
+            });
+        let changed = old_entries.iter().filter_map(|(hash, (old_file, old))| {
+            let (new_file, new) = new_entries.get(hash)?;
+            let same = old.size == new.size
+                && old.offset == new.offset
+                && old_file.file_hash == new_file.file_hash;
+            (!same).then(|| EntryChange::Changed {
+                old_file_hash: old_file.file_hash.clone(),
+                old: (*old).clone(),
+                new_file_hash: new_file.file_hash.clone(),
+                new: (*new).clone(),
+            })
+        });
+        let added = new_entries
+            .iter()
+            .filter(|(hash, _)| !old_entries.contains_key(hash))
+            .map(|(_, (file, entry))| EntryChange::Added {
+                file_hash: file.file_hash.clone(),
+                entry: (*entry).clone(),
             });
-        }
 
-        Ok(header)
+        removed.chain(changed).chain(added).collect()
+    }
+
+    fn entries_by_hash(&self) -> BTreeMap<i32, (&FileEntry, &DataEntry)> {
+        self.entries()
+            .map(|(file, entry)| (entry.name_hash, (file, entry)))
+            .collect()
     }
 
     pub fn find_by_hash(&self, hash: i32) -> Option<(&DataEntry, &FileEntry)> {
-        self.files.iter().find_map(|file| {
-            file.entries
-                .iter()
-                .find(|entry| entry.name_hash == hash)
-                .map(|entry| (entry, file))
+        self.entries()
+            .find(|(_, entry)| entry.name_hash == hash)
+            .map(|(file, entry)| (entry, file))
+    }
+}
+
+/// An entry that differs between two indexes, see [`DesignIndex::diff`].
+#[derive(Debug, Clone)]
+pub enum EntryChange {
+    Added {
+        file_hash: String,
+        entry: DataEntry,
+    },
+    Removed {
+        file_hash: String,
+        entry: DataEntry,
+    },
+    /// Same name hash, but a different size, offset or container.
+    Changed {
+        old_file_hash: String,
+        old: DataEntry,
+        new_file_hash: String,
+        new: DataEntry,
+    },
+}
+
+impl EntryChange {
+    pub fn name_hash(&self) -> i32 {
+        match self {
+            Self::Added { entry, .. } | Self::Removed { entry, .. } => entry.name_hash,
+            Self::Changed { new, .. } => new.name_hash,
+        }
+    }
+}
+
+/// Name hash of an excel as the game computes it from the path it was built to, e.g.
+/// `AllowedLanguage` gives [`crate::allowed_language::ALLOWED_LANGUAGE_HASH`].
+pub fn excel_name_hash(name: &str) -> i32 {
+    stable_hash(&format!("BakedConfig/ExcelOutput/{name}.bytes"))
+}
+
+/// The string hash the game names index entries with.
+pub fn stable_hash(text: &str) -> i32 {
+    let (mut even, mut odd) = (5381i32, 5381i32);
+    for pair in text.as_bytes().chunks(2) {
+        even = (even << 5).wrapping_add(even) ^ i32::from(pair[0]);
+        if let Some(&byte) = pair.get(1) {
+            odd = (odd << 5).wrapping_add(odd) ^ i32::from(byte);
+        }
+    }
+    even.wrapping_add(odd.wrapping_mul(1566083941))
+}
+
+/// Filters for [`DesignIndex::find_entries`]. Unset fields match everything.
+#[derive(Debug, Default, Clone)]
+pub struct EntryQuery {
+    pub name_hash: Option<i32>,
+    /// Matches the entry whose data contains this container offset.
+    pub offset: Option<u64>,
+    /// Inclusive bounds on the entry size.
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+}
+
+impl EntryQuery {
+    pub fn matches(&self, entry: &DataEntry) -> bool {
+        let start = entry.offset as u64;
+        let size = entry.size as u64;
+        self.name_hash.is_none_or(|hash| entry.name_hash == hash)
+            && self
+                .offset
+                .is_none_or(|offset| (start..start + size).contains(&offset))
+            && self.min_size.is_none_or(|min| size >= min)
+            && self.max_size.is_none_or(|max| size <= max)
+    }
+}
+
+/// Incremental index parser that reads one [`FileEntry`] at a time.
+pub struct IndexReader<R> {
+    reader: R,
+    legacy: bool,
+    len: u64,
+    files_left: u32,
+    pub unk_1: u64,
+    pub file_count: u32,
+    pub unk_2: u32,
+}
+
+impl<R: Read + Seek> IndexReader<R> {
+    /// Reads the index header. `legacy` selects the older format with a one byte
+    /// trailing field per file.
+    pub fn new(mut reader: R, legacy: bool) -> Result<Self> {
+        let len = reader
+            .seek(SeekFrom::End(0))
+            .and_then(|len| reader.seek(SeekFrom::Start(0)).map(|_| len))
+            .map_err(|source| PatcherError::IndexParse { offset: 0, source })?;
+
+        let mut index = Self {
+            reader,
+            legacy,
+            len,
+            files_left: 0,
+            unk_1: 0,
+            file_count: 0,
+            unk_2: 0,
+        };
+        index.with_offset(|index| {
+            index.unk_1 = index.reader.read_u64::<LE>()?;
+            index.file_count = index.reader.read_u32::<BE>()?;
+            index.unk_2 = index.reader.read_u32::<LE>()?;
+
+            // Counts come straight from the file, so check them against the remaining data
+            // before allocating or looping
+            let file_header_size = FILE_HEADER_SIZE + if index.legacy { 1 } else { 3 };
+            ensure_count(index.file_count, MAX_FILE_COUNT, "file_count")?;
+            index.ensure_remaining(index.file_count, file_header_size, "file_count")?;
+            index.files_left = index.file_count;
+            Ok(())
+        })?;
+
+        Ok(index)
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Returns the next file record, or `None` after the last one.
+    pub fn next_file(&mut self) -> Result<Option<FileEntry>> {
+        if self.files_left == 0 {
+            return Ok(None);
+        }
+        self.files_left -= 1;
+        self.with_offset(Self::read_file).map(Some)
+    }
+
+    /// Runs `read` and attaches the position where it failed to the error.
+    fn with_offset<T>(&mut self, read: impl FnOnce(&mut Self) -> io::Result<T>) -> Result<T> {
+        read(self).map_err(|source| PatcherError::IndexParse {
+            offset: self.reader.stream_position().unwrap_or_default(),
+            source,
+        })
+    }
+
+    fn read_file(&mut self) -> io::Result<FileEntry> {
+        let reader = &mut self.reader;
+        let name_hash = reader.read_i32::<BE>()?;
+
+        let mut file_hash_bytes = [0u8; 0x10];
+        reader.read_exact(&mut file_hash_bytes)?;
+        let file_hash = hex::encode(file_hash_bytes);
+
+        let read_size = reader.read_u64::<BE>()?;
+        let entry_count = reader.read_u32::<BE>()?;
+        ensure_count(entry_count, MAX_ENTRY_COUNT, "entry_count")?;
+        self.ensure_remaining(entry_count, ENTRY_SIZE, "entry_count")?;
+
+        let reader = &mut self.reader;
+        let mut entries = Vec::with_capacity(entry_count as usize);
+        for _ in 0..entry_count {
+            let entry = DataEntry {
+                name_hash: reader.read_i32::<BE>()?,
+                size: reader.read_i32::<BE>()?,
+                offset: reader.read_i32::<BE>()?,
+            };
+            if entry.size < 0 || entry.offset < 0 {
+                return Err(invalid_data(format!(
+                    "entry {} has a negative size or offset",
+                    entry.name_hash
+                )));
+            }
+            entries.push(entry);
+        }
+
+        Ok(FileEntry {
+            name_hash,
+            file_hash,
+            read_size,
+            entry_count,
+            entries,
+            unk_1: if self.legacy {
+                reader.read_u8()? as u32
+            } else {
+                reader.read_u24::<BE>()?
+            },
         })
     }
+
+    fn ensure_remaining(&mut self, count: u32, size: u64, field: &str) -> io::Result<()> {
+        let position = self.reader.stream_position()?;
+        check_remaining(self.len.saturating_sub(position), count.into(), size, field)
+    }
+}
+
+impl<R: Read + Seek> Iterator for IndexReader<R> {
+    type Item = Result<FileEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_file().transpose()
+    }
+}
+
+/// Smallest possible file record without entries or the trailing field.
+const FILE_HEADER_SIZE: u64 = 4 + 0x10 + 8 + 4;
+const ENTRY_SIZE: u64 = 4 * 3;
+
+// Far above anything the game ships, only there to reject garbage early
+const MAX_FILE_COUNT: u32 = 1 << 16;
+const MAX_ENTRY_COUNT: u32 = 1 << 20;
+
+fn ensure_count(count: u32, max: u32, field: &str) -> io::Result<()> {
+    if count > max {
+        return Err(invalid_data(format!(
+            "{field} {count} is above the limit of {max}"
+        )));
+    }
+    Ok(())
+}
+
+pub(crate) fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Fails if `count` records of at least `size` bytes can't fit in the rest of the data.
+pub(crate) fn ensure_remaining<T: AsRef<[u8]>>(
+    cursor: &Cursor<T>,
+    count: impl Into<u64>,
+    size: u64,
+    field: &str,
+) -> io::Result<()> {
+    let remaining = (cursor.get_ref().as_ref().len() as u64).saturating_sub(cursor.position());
+    check_remaining(remaining, count.into(), size, field)
+}
+
+fn check_remaining(remaining: u64, count: u64, size: u64, field: &str) -> io::Result<()> {
+    if count.saturating_mul(size) > remaining {
+        return Err(invalid_data(format!(
+            "{field} {count} exceeds the remaining {remaining} bytes"
+        )));
+    }
+    Ok(())
 }
@@ -0,0 +1,314 @@
+use std::{
+    env,
+    ffi::OsStr,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use sysinfo::{ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
+
+use crate::game::get_design_data_path;
+
+/// Folders below `drive_c` the launchers install the game to.
+#[cfg(unix)]
+const WINE_INSTALL_DIRS: [&str; 4] = [
+    "Program Files/HoYoPlay/games/Star Rail Games",
+    "Program Files/Star Rail/Games",
+    "Program Files/Star Rail Games",
+    "Program Files (x86)/Star Rail/Games",
+];
+
+/// Folders below a drive (or a Wine `drive_c`) games are commonly installed to, with how
+/// many levels below them to look.
+const INSTALL_ROOTS: [(&str, usize); 6] = [
+    ("", 2),
+    ("Games", 2),
+    ("Program Files", 2),
+    ("Program Files (x86)", 2),
+    ("Program Files/HoYoPlay/games", 1),
+    ("Program Files/Epic Games", 1),
+];
+
+/// Uses the current directory if it contains the game, otherwise the first detected install.
+pub fn default_game_path() -> io::Result<PathBuf> {
+    let current_dir = env::current_dir()?;
+    if get_design_data_path(&current_dir).is_ok() {
+        return Ok(current_dir);
+    }
+
+    Ok(find_installs()
+        .into_iter()
+        .next()
+        .or_else(running_game_path)
+        .unwrap_or(current_dir))
+}
+
+/// Finds the game folder of a running `StarRail.exe`, falling back to the path the
+/// game wrote to its `Player.log` the last time it ran.
+pub fn running_game_path() -> Option<PathBuf> {
+    running_process_path()
+        .or_else(last_run_path)
+        .filter(|path| get_design_data_path(path).is_ok())
+}
+
+/// Whether a `StarRail.exe` is running right now, unlike [`running_game_path`] without
+/// falling back to the last run.
+pub fn is_game_running() -> bool {
+    running_process_path().is_some()
+}
+
+/// Blocks until no `StarRail.exe` is running, checking every `interval`.
+pub fn wait_for_game_exit(interval: Duration) {
+    while is_game_running() {
+        thread::sleep(interval);
+    }
+}
+
+/// Processes the HoYoPlay launcher runs only while it updates itself or a game.
+const UPDATER_PROCESSES: [&str; 2] = ["HYPUpdater.exe", "HYPDownloader.exe"];
+/// Extensions of the files the launchers download to before moving them in place.
+const DOWNLOAD_EXTENSIONS: [&str; 4] = ["tmp", "temp", "downloading", "hdiff"];
+/// Folders below the game folder the launchers stage an update in.
+const STAGING_DIRS: [&str; 2] = ["ldiff", "chunk"];
+/// Downloads not written to for this long are left over from a cancelled update.
+const STALE_DOWNLOAD: Duration = Duration::from_secs(15 * 60);
+
+/// Why the game looks like it's being updated right now.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UpdateActivity {
+    /// An updater process is running, by its name.
+    Updater(String),
+    /// A download was written to recently.
+    Download(PathBuf),
+}
+
+impl fmt::Display for UpdateActivity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Updater(name) => write!(f, "{name} is running"),
+            Self::Download(path) => write!(f, "'{}' is being downloaded", path.display()),
+        }
+    }
+}
+
+/// Checks whether a launcher is updating the game in `game_path`. Patching in the middle
+/// of it would race the updater, which may replace the patched file moments later.
+pub fn update_activity(game_path: &Path) -> Option<UpdateActivity> {
+    running_updater()
+        .map(UpdateActivity::Updater)
+        .or_else(|| fresh_download(game_path).map(UpdateActivity::Download))
+}
+
+fn running_updater() -> Option<String> {
+    let mut system = System::new();
+    system.refresh_processes_specifics(ProcessesToUpdate::All, true, ProcessRefreshKind::nothing());
+
+    UPDATER_PROCESSES
+        .into_iter()
+        .find(|name| {
+            system
+                .processes_by_exact_name(OsStr::new(name))
+                .next()
+                .is_some()
+        })
+        .map(str::to_string)
+}
+
+/// A download file in the game, DesignData or a staging folder that was written to
+/// recently. Everything in a staging folder counts, other folders only by extension.
+fn fresh_download(game_path: &Path) -> Option<PathBuf> {
+    let is_download = |path: &Path| {
+        path.extension().and_then(OsStr::to_str).is_some_and(|ext| {
+            DOWNLOAD_EXTENSIONS
+                .iter()
+                .any(|d| ext.eq_ignore_ascii_case(d))
+        })
+    };
+    let is_fresh = |metadata: fs::Metadata| {
+        metadata
+            .modified()
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_none_or(|age| age < STALE_DOWNLOAD)
+    };
+
+    let staging = STAGING_DIRS.map(|dir| (game_path.join(dir), true));
+    let others = [
+        Some(game_path.to_path_buf()),
+        get_design_data_path(game_path).ok(),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|dir| (dir, false));
+    staging.into_iter().chain(others).find_map(|(dir, all)| {
+        fs::read_dir(dir).ok()?.flatten().find_map(|entry| {
+            let path = entry.path();
+            let fresh = (all || is_download(&path)) && entry.metadata().is_ok_and(is_fresh);
+            fresh.then_some(path)
+        })
+    })
+}
+
+fn running_process_path() -> Option<PathBuf> {
+    let mut system = System::new();
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::All,
+        true,
+        ProcessRefreshKind::nothing().with_exe(UpdateKind::OnlyIfNotSet),
+    );
+
+    system
+        .processes_by_exact_name(OsStr::new("StarRail.exe"))
+        .filter_map(|process| process.exe())
+        // Under Wine the process image can be the loader instead of the game
+        .filter(|exe| {
+            exe.file_name()
+                .is_some_and(|name| name.eq_ignore_ascii_case("StarRail.exe"))
+        })
+        .find_map(|exe| exe.parent().map(Path::to_path_buf))
+}
+
+/// Global and CN client log folders below `AppData/LocalLow`.
+const PLAYER_LOG_DIRS: [&str; 2] = ["Cognosphere/Star Rail", "miHoYo/崩坏：星穹铁道"];
+
+fn last_run_path() -> Option<PathBuf> {
+    let local_low = PathBuf::from(env::var_os("USERPROFILE")?).join("AppData/LocalLow");
+
+    PLAYER_LOG_DIRS
+        .iter()
+        .flat_map(|dir| ["Player.log", "Player-prev.log"].map(|log| local_low.join(dir).join(log)))
+        .filter_map(|log| fs::read(log).ok())
+        .find_map(|data| {
+            // e.g. "Loading player data from D:/Star Rail/Games/StarRail_Data/data.unity3d"
+            String::from_utf8_lossy(&data).lines().find_map(|line| {
+                let end = line.find("StarRail_Data")?;
+                let start = line[..end].find(":/").or_else(|| line[..end].find(":\\"))?;
+                // The drive letter, checked as a character since the lossy decoding can
+                // leave multi-byte replacement characters before the colon
+                let (drive, letter) = line[..start].char_indices().next_back()?;
+                letter
+                    .is_ascii_alphabetic()
+                    .then(|| PathBuf::from(&line[drive..end]))
+            })
+        })
+}
+
+/// Looks for game installs in the usual places when no path was given and the
+/// current directory isn't the game folder. Returns game folders in priority order.
+pub fn find_installs() -> Vec<PathBuf> {
+    let mut installs: Vec<PathBuf> = Vec::new();
+
+    #[cfg(unix)]
+    for drive_c in wine_drives() {
+        for dir in WINE_INSTALL_DIRS {
+            installs.push(drive_c.join(dir));
+        }
+    }
+
+    installs.retain(|path| get_design_data_path(path).is_ok());
+    installs
+}
+
+/// Slower search for when the game isn't where it was expected: looks a few levels below
+/// the common install folders of every drive. Returns game folders in drive order.
+pub fn scan_install_roots() -> Vec<PathBuf> {
+    let mut installs = Vec::new();
+    for drive in drives() {
+        for (dir, depth) in INSTALL_ROOTS {
+            scan_dir(&drive.join(dir), depth, &mut installs);
+        }
+    }
+    installs
+}
+
+fn scan_dir(dir: &Path, depth: usize, installs: &mut Vec<PathBuf>) {
+    if get_design_data_path(dir).is_ok() {
+        if !installs.iter().any(|install| install == dir) {
+            installs.push(dir.to_path_buf());
+        }
+        return;
+    }
+    if depth == 0 {
+        return;
+    }
+    for subdir in subdirs(dir.to_path_buf()) {
+        let skip = subdir
+            .file_name()
+            .and_then(OsStr::to_str)
+            .is_some_and(|name| {
+                name.starts_with(['.', '$']) || name.eq_ignore_ascii_case("Windows")
+            });
+        if !skip {
+            scan_dir(&subdir, depth - 1, installs);
+        }
+    }
+}
+
+/// Fixed drives, or the `drive_c` folders of the Wine prefixes.
+fn drives() -> Vec<PathBuf> {
+    #[cfg(windows)]
+    {
+        // A: and B: are floppy drives that can take long to answer
+        ('C'..='Z')
+            .map(|letter| PathBuf::from(format!("{letter}:\\")))
+            .filter(|drive| drive.is_dir())
+            .collect()
+    }
+    #[cfg(unix)]
+    {
+        wine_drives()
+    }
+    #[cfg(not(any(windows, unix)))]
+    {
+        Vec::new()
+    }
+}
+
+/// `drive_c` folders of Proton (Steam compatdata), Lutris, Bottles and plain Wine prefixes.
+#[cfg(unix)]
+fn wine_drives() -> Vec<PathBuf> {
+    let Some(home) = env::var_os("HOME").map(PathBuf::from) else {
+        return Vec::new();
+    };
+
+    let mut drives = Vec::new();
+
+    // `WINEPREFIX` first so an explicitly selected prefix wins
+    if let Some(prefix) = env::var_os("WINEPREFIX") {
+        drives.push(PathBuf::from(prefix).join("drive_c"));
+    }
+    drives.push(home.join(".wine/drive_c"));
+
+    for steam in [
+        ".steam/steam",
+        ".local/share/Steam",
+        ".var/app/com.valvesoftware.Steam/.local/share/Steam",
+    ] {
+        drives.extend(
+            subdirs(home.join(steam).join("steamapps/compatdata"))
+                .map(|dir| dir.join("pfx/drive_c")),
+        );
+    }
+
+    drives.extend(subdirs(home.join("Games")).map(|dir| dir.join("drive_c")));
+    for bottles in [
+        ".local/share/bottles/bottles",
+        ".var/app/com.usebottles.bottles/data/bottles/bottles",
+    ] {
+        drives.extend(subdirs(home.join(bottles)).map(|dir| dir.join("drive_c")));
+    }
+
+    drives.retain(|drive| drive.is_dir());
+    drives
+}
+
+fn subdirs(dir: PathBuf) -> impl Iterator<Item = PathBuf> {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+}
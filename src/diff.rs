@@ -0,0 +1,227 @@
+use std::fs;
+
+use anyhow::{Context, anyhow};
+
+use hsr_lang_patcher::{
+    ErrorKind,
+    allowed_language::AllowedLanguageRow,
+    design_index::EntryChange,
+    game::{ALLOWED_LANGUAGE_HASH, Game, get_platform_design_data_path},
+    paths::long_path,
+};
+
+use crate::{Result, args::Args, output};
+
+const BYTES_PER_LINE: usize = 16;
+
+/// Compares the AllowedLanguage region of the oldest available backup with the
+/// current file, both per row field and per byte.
+pub fn diff(game: &Game) -> Result<()> {
+    let backup = game.backups()?.pop().context(
+        "No backup of the game files exists yet. A backup is made before the first patch",
+    )?;
+    let original = game.read_backup(&backup)?;
+    let current = fs::read(long_path(&game.bytes_path))
+        .with_context(|| format!("Failed to read '{}'", game.bytes_path.display()))?;
+
+    let offset = game.data_entry.offset as usize;
+    let range = offset..offset + game.data_entry.size as usize;
+    let (Some(old), Some(new)) = (original.get(range.clone()), current.get(range.clone())) else {
+        return Err(anyhow!(
+            "The backup doesn't contain the AllowedLanguage region"
+        ));
+    };
+
+    println!("Backup:  {}", backup.path.display());
+    println!("Current: {}", game.bytes_path.display());
+    println!(
+        "Region:  offset 0x{offset:X}, size {} bytes\n",
+        game.data_entry.size
+    );
+
+    let allowed_language = game.allowed_language();
+    let old_rows = allowed_language.parse_raw(old)?;
+    let new_rows = allowed_language.parse_raw(new)?;
+    print_row_changes(&old_rows, &new_rows);
+
+    println!();
+    print_byte_changes(offset, old, new);
+
+    let outside = original
+        .iter()
+        .zip(&current)
+        .enumerate()
+        .filter(|(i, (a, b))| !range.contains(i) && a != b)
+        .count()
+        + original.len().abs_diff(current.len());
+    println!();
+    if outside == 0 {
+        println!(
+            "{}",
+            output::success("No bytes outside the AllowedLanguage region changed")
+        );
+    } else {
+        println!(
+            "{}: {outside} bytes outside the AllowedLanguage region differ from the backup",
+            output::warning()
+        );
+    }
+
+    Ok(())
+}
+
+/// Compares the DesignV indexes of two installs, e.g. before and after a game update.
+pub fn diff_index(args: &Args) -> Result<()> {
+    let (Some(old_path), Some(new_path)) = (&args.game_path, &args.other_game_path) else {
+        return Err(anyhow!("Expected the old and the new game folder"))
+            .context(ErrorKind::InvalidArguments);
+    };
+    let platform = args.platform.unwrap_or_default();
+    let load = |path| -> Result<_> {
+        let game = Game::load(get_platform_design_data_path(path, platform)?)?;
+        Ok((game.design_index()?, game.index_hash))
+    };
+    let (old_index, old_hash) = load(old_path)?;
+    let (new_index, new_hash) = load(new_path)?;
+
+    println!(
+        "Old: DesignV_{old_hash}.bytes, {} files",
+        old_index.files.len()
+    );
+    println!(
+        "New: DesignV_{new_hash}.bytes, {} files\n",
+        new_index.files.len()
+    );
+
+    let changes = old_index.diff(&new_index);
+    for change in &changes {
+        let line = match change {
+            EntryChange::Removed { file_hash, entry } => output::removed(&format!(
+                "- {:>11}  {file_hash}  0x{:X}  {} bytes",
+                entry.name_hash, entry.offset, entry.size
+            )),
+            EntryChange::Added { file_hash, entry } => output::added(&format!(
+                "+ {:>11}  {file_hash}  0x{:X}  {} bytes",
+                entry.name_hash, entry.offset, entry.size
+            )),
+            EntryChange::Changed {
+                old_file_hash,
+                old,
+                new_file_hash,
+                new,
+            } => {
+                let mut parts = Vec::new();
+                if old.size != new.size {
+                    parts.push(format!("resized {} -> {} bytes", old.size, new.size));
+                }
+                if old_file_hash != new_file_hash {
+                    parts.push(format!("moved to {new_file_hash}"));
+                }
+                if old.offset != new.offset {
+                    parts.push(format!("offset 0x{:X} -> 0x{:X}", old.offset, new.offset));
+                }
+                format!("~ {:>11}  {}", new.name_hash, parts.join(", "))
+            }
+        };
+        if change.name_hash() == ALLOWED_LANGUAGE_HASH {
+            println!("{line}  (AllowedLanguage)");
+        } else {
+            println!("{line}");
+        }
+    }
+
+    let count = |f: fn(&EntryChange) -> bool| changes.iter().filter(|c| f(c)).count();
+    println!(
+        "\n{} removed, {} changed, {} added",
+        count(|c| matches!(c, EntryChange::Removed { .. })),
+        count(|c| matches!(c, EntryChange::Changed { .. })),
+        count(|c| matches!(c, EntryChange::Added { .. })),
+    );
+    Ok(())
+}
+
+fn row_label(row: &AllowedLanguageRow) -> String {
+    format!(
+        "{} {}",
+        row.area().unwrap_or("-"),
+        if row.is_voice() { "voice" } else { "text" }
+    )
+}
+
+fn print_row_changes(old_rows: &[AllowedLanguageRow], new_rows: &[AllowedLanguageRow]) {
+    let list = |row: Option<&AllowedLanguageRow>| {
+        row.and_then(|row| row.language_list())
+            .map(|list| list.join(","))
+            .unwrap_or_else(|| "-".to_string())
+    };
+    let default = |row: Option<&AllowedLanguageRow>| {
+        row.and_then(|row| row.default_language())
+            .unwrap_or("-")
+            .to_string()
+    };
+
+    let mut changed = false;
+    for i in 0..old_rows.len().max(new_rows.len()) {
+        let (old, new) = (old_rows.get(i), new_rows.get(i));
+        if old == new {
+            continue;
+        }
+        changed = true;
+
+        let label = new.or(old).map(row_label).unwrap_or_default();
+        for (field, before, after) in [
+            ("default_language", default(old), default(new)),
+            ("language_list", list(old), list(new)),
+        ] {
+            if before != after {
+                println!(
+                    "Row {i} ({label}) {field}: {} {} {}",
+                    output::removed(&before),
+                    output::glyph("→", "->"),
+                    output::added(&after)
+                );
+            }
+        }
+    }
+
+    if !changed {
+        println!("No row fields changed");
+    }
+}
+
+fn print_byte_changes(offset: usize, old: &[u8], new: &[u8]) {
+    let changed = old.iter().zip(new).filter(|(a, b)| a != b).count();
+    println!("{changed} bytes changed in the region");
+
+    for (line, (old_line, new_line)) in old
+        .chunks(BYTES_PER_LINE)
+        .zip(new.chunks(BYTES_PER_LINE))
+        .enumerate()
+    {
+        if old_line == new_line {
+            continue;
+        }
+
+        let address = offset + line * BYTES_PER_LINE;
+        let hex = |bytes: &[u8], other: &[u8], paint: fn(&str) -> String| {
+            bytes
+                .iter()
+                .zip(other)
+                .map(|(byte, other)| {
+                    let text = format!("{byte:02x}");
+                    if byte != other { paint(&text) } else { text }
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+
+        println!(
+            "- {address:08X}  {}",
+            hex(old_line, new_line, output::removed)
+        );
+        println!(
+            "+ {address:08X}  {}",
+            hex(new_line, old_line, output::added)
+        );
+    }
+}
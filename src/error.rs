@@ -0,0 +1,347 @@
+use std::{fmt, io, path::PathBuf};
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::language::Language;
+
+/// Errors returned by the library. Each variant carries enough context to be
+/// reported without the surrounding call site.
+#[derive(Debug, Error)]
+pub enum PatcherError {
+    #[error(
+        "Could not find required files in '{}'!\n\
+        Make sure to either: \n\
+        - Run this .exe from the game's root folder\n\
+        - Pass the game's root path as an argument\n\
+        - Pass the StreamingAssets/DesignData folder path as an argument",
+        path.display()
+    )]
+    GameNotFound { path: PathBuf },
+
+    #[error("Failed to get index hash. Is '{}' the correct directory?", path.display())]
+    IndexHash { path: PathBuf },
+
+    #[error("Failed to parse DesignV at offset 0x{offset:X}")]
+    IndexParse {
+        offset: u64,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Excel {hash} not found in the DesignV index")]
+    ExcelNotFound { hash: i32 },
+
+    #[error("Failed to decode excel data at offset 0x{offset:X} in '{}'", path.display())]
+    Codec {
+        path: PathBuf,
+        offset: u64,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Container {file_hash} not found in the DesignV index")]
+    ContainerNotFound { file_hash: String },
+
+    #[error("An entry with the name hash {name_hash} already exists")]
+    EntryExists { name_hash: i32 },
+
+    #[error("Can't add {size} bytes at offset 0x{offset:X}, containers are limited to 2 GiB")]
+    EntryTooLarge { size: u64, offset: u64 },
+
+    #[error("The data for excel {hash} is {size} bytes, larger than its {region} byte region")]
+    PayloadTooLarge {
+        hash: i32,
+        size: usize,
+        region: usize,
+    },
+
+    #[error("{} {} AllowedLanguageRow not found", area.to_uppercase(), if *voice { "voice" } else { "text" })]
+    RowNotFound { area: String, voice: bool },
+
+    /// Something a lenient run only warns about.
+    #[error("{0}, stopping since strict mode is on")]
+    Strict(String),
+
+    #[error(
+        "The {} {} row doesn't list '{language}', setting it as the default would leave the row inconsistent",
+        area.to_uppercase(),
+        if *voice { "voice" } else { "text" }
+    )]
+    LanguageNotListed {
+        area: String,
+        voice: bool,
+        language: String,
+    },
+
+    #[error("Refusing to write, the serialized excel data {0}. This is a bug, please report it")]
+    SelfCheck(String),
+
+    #[error(
+        "Invalid language '{0}'. Text can be cn, en, kr, jp, cht, de, es, fr, id, pt, ru, th \
+        or vi, voice cn, en, kr or jp"
+    )]
+    InvalidLanguage(String),
+
+    #[error(
+        "The game has no {name} voice-over, '{0}' can only be the text language. Voice must \
+        be cn, en, kr or jp",
+        name = .0.display_name()
+    )]
+    NoVoice(Language),
+
+    #[error(
+        "Unknown tweak '{name}'. Available tweaks: {}",
+        crate::recipe::recipe_names()
+    )]
+    UnknownTweak { name: String },
+
+    #[error("Invalid field '{field}': {message}")]
+    InvalidField { field: String, message: String },
+
+    #[error(
+        "The tweak '{name}' makes the excel {size} bytes, larger than its {region} byte region"
+    )]
+    TweakTooLarge {
+        name: String,
+        size: usize,
+        region: usize,
+    },
+
+    #[error("Script '{}' failed: {message}", path.display())]
+    Script { path: PathBuf, message: String },
+
+    #[error("Failed to read '{}'", path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Failed to write '{}' at offset 0x{offset:X}", path.display())]
+    Write {
+        path: PathBuf,
+        offset: u64,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Two edits of '{}' overlap at offset 0x{offset:X}. This is a bug, please report it", path.display())]
+    OverlappingWrites { path: PathBuf, offset: u64 },
+
+    #[error(
+        "'{}' reads back different bytes at offset 0x{offset:X} than were written",
+        path.display()
+    )]
+    WriteMismatch { path: PathBuf, offset: u64 },
+
+    #[error("The patch is for '{expected}' but this game uses '{actual}'")]
+    PatchTarget { expected: String, actual: String },
+
+    #[error(
+        "The patch doesn't match the data in '{}'. It was made for a different game version",
+        path.display()
+    )]
+    PatchMismatch { path: PathBuf },
+
+    #[error("No backup of {file_hash}.bytes exists yet. A backup is made before the first patch")]
+    NoBackup { file_hash: String },
+
+    #[error("No backup named '{id}'. `backups list` shows the available ones")]
+    BackupNotFound { id: String },
+
+    #[error("The backup '{}' is damaged, its contents don't match the stored hash", path.display())]
+    BackupCorrupt { path: PathBuf },
+
+    #[error(
+        "The game file changed around the bytes saved in '{}', so they can't be put back safely",
+        path.display()
+    )]
+    BackupStale { path: PathBuf },
+
+    #[error(
+        "'{}' was changed at offset {offset:#x} since the patcher wrote it, likely by a game \
+        update. Restoring the backup would put outdated data over it, verify the game files \
+        in the launcher instead",
+        path.display()
+    )]
+    RegionChanged { path: PathBuf, offset: u64 },
+
+    #[error(
+        "Another hsr-lang-patcher run{} is changing this install, it holds '{}'. Wait for it \
+        to finish and try again",
+        pid.map(|pid| format!(" (PID {pid})")).unwrap_or_default(),
+        path.display()
+    )]
+    InstallLocked { path: PathBuf, pid: Option<u32> },
+
+    #[error("Failed to back up the game files to '{}'", path.display())]
+    Backup {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error(
+        "Not enough free space for '{}': {} needed, {} available. Free up space or pick \
+        another drive with --backup-dir",
+        path.display(),
+        size(*required),
+        size(*available)
+    )]
+    NoSpace {
+        path: PathBuf,
+        required: u64,
+        available: u64,
+    },
+
+    #[error(
+        "'{}' is on a read-only drive. Remount it read-write (e.g. `sudo mount -o \
+        remount,rw` on its mount point), turn off the drive's write protection, or move the \
+        game to a writable drive",
+        path.display()
+    )]
+    ReadOnlyVolume { path: PathBuf },
+
+    #[error(
+        "'{}' is marked read-only. Untick \"Read-only\" in its Properties (`attrib -r` on \
+        Windows, `chmod u+w` elsewhere), or run the patcher without --yes to have it cleared",
+        path.display()
+    )]
+    ReadOnlyFile { path: PathBuf },
+
+    #[error(
+        "Access to '{}' was denied. Game Pass installs inside WindowsApps are protected.\n\
+        Either:\n\
+        - Enable \"Advanced management features\" for the game in the Xbox app and move it to a regular folder\n\
+        - Or take ownership of the game folder (Properties > Security > Advanced) and run this .exe as administrator",
+        path.display()
+    )]
+    ProtectedInstall {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error(
+        "Access to '{}' was denied. The game files likely belong to another user or lost \
+        their permissions when the game was moved. Run `hsr-lang-patcher fix-permissions` to \
+        get write access to the DesignData folder, or run this .exe as administrator",
+        path.display()
+    )]
+    AccessDenied {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl PatcherError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::GameNotFound { .. } => ErrorKind::GameNotFound,
+            Self::IndexHash { .. }
+            | Self::IndexParse { .. }
+            | Self::ExcelNotFound { .. }
+            | Self::ContainerNotFound { .. }
+            | Self::Codec { .. }
+            | Self::RowNotFound { .. }
+            | Self::Strict(_)
+            | Self::PatchTarget { .. }
+            | Self::PatchMismatch { .. } => ErrorKind::UnsupportedVersion,
+            Self::InvalidLanguage(_)
+            | Self::NoVoice(_)
+            | Self::LanguageNotListed { .. }
+            | Self::UnknownTweak { .. }
+            | Self::InvalidField { .. }
+            | Self::EntryExists { .. }
+            | Self::EntryTooLarge { .. }
+            | Self::PayloadTooLarge { .. }
+            | Self::BackupNotFound { .. } => ErrorKind::InvalidArguments,
+            Self::SelfCheck(_)
+            | Self::TweakTooLarge { .. }
+            | Self::Script { .. }
+            | Self::OverlappingWrites { .. }
+            | Self::NoBackup { .. }
+            | Self::BackupCorrupt { .. }
+            | Self::BackupStale { .. }
+            | Self::RegionChanged { .. } => ErrorKind::Other,
+            Self::Read { source, .. }
+            | Self::Write { source, .. }
+            | Self::Backup { source, .. }
+                if is_locked(source) =>
+            {
+                ErrorKind::FileLocked
+            }
+            Self::InstallLocked { .. } => ErrorKind::FileLocked,
+            Self::Read { .. } => ErrorKind::Other,
+            Self::Write { .. }
+            | Self::WriteMismatch { .. }
+            | Self::Backup { .. }
+            | Self::NoSpace { .. }
+            | Self::ReadOnlyVolume { .. }
+            | Self::ReadOnlyFile { .. }
+            | Self::ProtectedInstall { .. }
+            | Self::AccessDenied { .. } => ErrorKind::WriteFailed,
+        }
+    }
+}
+
+fn size(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        1024..1048576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1048576.0),
+    }
+}
+
+// Windows ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+const SHARING_VIOLATION: i32 = 32;
+const LOCK_VIOLATION: i32 = 33;
+
+pub fn is_locked(error: &io::Error) -> bool {
+    error
+        .raw_os_error()
+        .is_some_and(|code| code == SHARING_VIOLATION || code == LOCK_VIOLATION)
+}
+
+/// Failure categories reported through the exit code and in JSON mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Other,
+    GameNotFound,
+    UnsupportedVersion,
+    FileLocked,
+    WriteFailed,
+    InvalidArguments,
+    Cancelled,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Other => 1,
+            Self::GameNotFound => 2,
+            Self::UnsupportedVersion => 3,
+            Self::FileLocked => 4,
+            Self::WriteFailed => 5,
+            Self::InvalidArguments => 6,
+            Self::Cancelled => 7,
+        }
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Other => "Failed to patch",
+            Self::GameNotFound => "Game not found",
+            Self::UnsupportedVersion => "Unsupported game version",
+            Self::FileLocked => "Game files are locked, close the game and try again",
+            Self::WriteFailed => "Failed to write game files",
+            Self::InvalidArguments => "Invalid arguments",
+            Self::Cancelled => "Cancelled",
+        })
+    }
+}
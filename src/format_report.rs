@@ -0,0 +1,121 @@
+//! `report-format`: a summary of the index and `AllowedLanguage` layout to paste into an
+//! issue when a game update changed the format. Only sizes, counts and the unknown
+//! fields are included, no paths or anything else about the machine, and nothing is sent
+//! anywhere.
+
+use std::{collections::BTreeMap, fs};
+
+use anyhow::Context;
+use serde::Serialize;
+
+use hsr_lang_patcher::{
+    allowed_language::{ClientRegion, FormatDrift},
+    game::Game,
+};
+
+use crate::{Result, args::Args};
+
+#[derive(Serialize)]
+struct FormatReport {
+    patcher_version: &'static str,
+    game_version: Option<String>,
+    client_region: Option<ClientRegion>,
+    index: IndexSummary,
+    allowed_language: ExcelSummary,
+}
+
+#[derive(Serialize)]
+struct IndexSummary {
+    legacy: bool,
+    unk_1: u64,
+    unk_2: u32,
+    file_count: u32,
+    entry_count: usize,
+    trailing_bytes: usize,
+}
+
+#[derive(Serialize)]
+struct ExcelSummary {
+    region_size: i32,
+    /// The byte before the row count, always 0 so far.
+    header: Option<u8>,
+    row_count: usize,
+    /// Rows per bitmask, as `0x0F`.
+    bitmasks: BTreeMap<String, usize>,
+    /// Area, type and encoded size of every row.
+    rows: Vec<RowSummary>,
+    drift: Vec<FormatDrift>,
+}
+
+#[derive(Serialize)]
+struct RowSummary {
+    area: Option<String>,
+    #[serde(rename = "type")]
+    row_type: Option<u8>,
+    languages: usize,
+    size: usize,
+}
+
+pub fn report_format(game: &Game, args: &Args) -> Result<()> {
+    let index = game.design_index()?;
+    let allowed_language = game.allowed_language();
+    let raw = allowed_language.read_raw()?;
+    let rows = allowed_language.parse_raw(&raw)?;
+
+    let mut bitmasks = BTreeMap::new();
+    for row in &rows {
+        *bitmasks
+            .entry(format!("0x{:02X}", row.bitmask()))
+            .or_default() += 1;
+    }
+    let report = FormatReport {
+        patcher_version: env!("CARGO_PKG_VERSION"),
+        game_version: game.game_version(),
+        client_region: game.client_region(),
+        index: IndexSummary {
+            legacy: index.legacy,
+            unk_1: index.unk_1,
+            unk_2: index.unk_2,
+            file_count: index.file_count,
+            entry_count: index.entries().count(),
+            trailing_bytes: index.trailing.len(),
+        },
+        allowed_language: ExcelSummary {
+            region_size: game.data_entry.size,
+            header: raw.first().copied(),
+            row_count: rows.len(),
+            bitmasks,
+            rows: rows
+                .iter()
+                .map(|row| RowSummary {
+                    area: row.area().map(str::to_string),
+                    row_type: row.row_type(),
+                    languages: row.language_list().map_or(0, <[String]>::len),
+                    size: row.serialize().map_or(0, |data| data.len()),
+                })
+                .collect(),
+            drift: allowed_language.format_drift(&raw)?,
+        },
+    };
+    let json = serde_json::to_string_pretty(&report)?;
+
+    match &args.file {
+        Some(path) => {
+            fs::write(path, json)
+                .with_context(|| format!("Failed to write '{}'", path.display()))?;
+            if !args.quiet {
+                println!(
+                    "Wrote the format report to '{}', attach it to an issue",
+                    path.display()
+                );
+            }
+        }
+        None => {
+            if !args.quiet {
+                println!("Paste this into an issue, it contains no personal data:\n");
+            }
+            println!("{json}");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,345 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    PatcherError, Result,
+    allowed_language::{AllowedLanguage, Voices},
+    design_index::{DataEntry, DesignIndex, FileEntry},
+    index_cache,
+    language::Language,
+    master_index::MasterIndex,
+    paths::{
+        available_space, canonical_path, child_ignore_case, data_dirs, is_protected,
+        is_read_only_file, is_read_only_volume, long_path, resolve_dropped_path,
+    },
+    pipeline::PatchPipeline,
+};
+
+pub use crate::allowed_language::{ALLOWED_LANGUAGE_HASH, ClientRegion};
+
+/// Platform subfolder of `StreamingAssets/DesignData`. Mobile distributions ship
+/// `Android`/`iOS` folders, e.g. when patching data copied for an emulator.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Platform {
+    #[default]
+    Windows,
+    Android,
+    Ios,
+}
+
+impl Platform {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "windows" => Some(Self::Windows),
+            "android" => Some(Self::Android),
+            "ios" => Some(Self::Ios),
+            _ => None,
+        }
+    }
+
+    pub fn dir_name(self) -> &'static str {
+        match self {
+            Self::Windows => "Windows",
+            Self::Android => "Android",
+            Self::Ios => "iOS",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Game {
+    pub design_data_path: PathBuf,
+    pub index_hash: String,
+    pub file_hash: String,
+    pub bytes_path: PathBuf,
+    pub data_entry: DataEntry,
+    /// Back up whole containers instead of only the byte ranges that get overwritten.
+    pub full_backups: bool,
+    /// Patch the rows of both clients, even when the install is known to be one of them.
+    pub all_regions: bool,
+    /// Keep backups in a folder per install below this directory, e.g. on another drive.
+    pub backup_root: Option<PathBuf>,
+    /// Fail on a missing or unknown row, unknown bitmask bits or data after the rows,
+    /// instead of warning and patching what is known.
+    pub strict: bool,
+}
+
+impl Game {
+    pub fn load(design_data_path: PathBuf) -> Result<Self> {
+        PatchPipeline::default().index(design_data_path)
+    }
+
+    pub(crate) fn load_index(design_data_path: PathBuf) -> Result<Self> {
+        let m_design_v_path = design_data_path.join("M_DesignV.bytes");
+        let index_hash = MasterIndex::parse(&read(&m_design_v_path)?)
+            .ok_or(PatcherError::IndexHash {
+                path: m_design_v_path,
+            })?
+            .index_hash_hex();
+
+        let design_v_path = design_data_path.join(format!("DesignV_{index_hash}.bytes"));
+        let found = match index_cache::load(&design_v_path, &index_hash) {
+            Some(index) => index
+                .find_by_hash(ALLOWED_LANGUAGE_HASH)
+                .map(|(entry, file)| (entry.clone(), file.file_hash.clone())),
+            None => {
+                // Only read the index up to the AllowedLanguage entry, which matters on slow
+                // drives
+                let design_v_file =
+                    File::open(long_path(&design_v_path)).map_err(|source| PatcherError::Read {
+                        path: design_v_path,
+                        source,
+                    })?;
+                DesignIndex::find_in_reader(BufReader::new(design_v_file), ALLOWED_LANGUAGE_HASH)?
+                    .map(|(entry, file)| (entry, file.file_hash))
+            }
+        };
+        let (data_entry, file_hash) = found.ok_or(PatcherError::ExcelNotFound {
+            hash: ALLOWED_LANGUAGE_HASH,
+        })?;
+
+        let bytes_path = design_data_path.join(format!("{file_hash}.bytes"));
+
+        Ok(Self {
+            index_hash,
+            file_hash,
+            bytes_path,
+            data_entry,
+            design_data_path,
+            full_backups: false,
+            all_regions: false,
+            strict: false,
+            backup_root: None,
+        })
+    }
+
+    pub fn allowed_language(&self) -> AllowedLanguage<'_> {
+        AllowedLanguage::new(&self.data_entry, &self.bytes_path)
+    }
+
+    /// Patches the AllowedLanguage rows, skipping the write if they already
+    /// match. Returns whether anything was written.
+    pub fn apply(&self, text_lang: Language, voices: impl Into<Voices>) -> Result<bool> {
+        Ok(self.patch(text_lang, voices)?.changed())
+    }
+
+    pub fn write_data(&self, data: &[u8]) -> Result<()> {
+        PatchPipeline::default().write(self, data)
+    }
+
+    /// Parses the whole `DesignV` index, unlike [`Game::load`] which stops at the
+    /// AllowedLanguage entry. The result is cached for later runs, see [`index_cache`].
+    pub fn design_index(&self) -> Result<DesignIndex> {
+        let index_path = self.index_path();
+        if let Some(index) = index_cache::load(&index_path, &self.index_hash) {
+            return Ok(index);
+        }
+        let index = DesignIndex::parse(&read(&index_path)?)?;
+        index_cache::store(&index_path, &self.index_hash, &index);
+        Ok(index)
+    }
+
+    /// Reads the bytes of any entry listed in the index from its container.
+    pub fn read_entry(&self, name_hash: i32) -> Result<Vec<u8>> {
+        let index = self.design_index()?;
+        let (entry, file) = index
+            .find_by_hash(name_hash)
+            .ok_or(PatcherError::ExcelNotFound { hash: name_hash })?;
+        self.read_region(&file.file_hash, entry)
+    }
+
+    pub(crate) fn read_region(&self, file_hash: &str, entry: &DataEntry) -> Result<Vec<u8>> {
+        let bytes_path = self.container_path(file_hash);
+        let mut buffer = vec![0u8; entry.size as usize];
+        open_region(&bytes_path, entry)
+            .and_then(|mut reader| reader.read_exact(&mut buffer))
+            .map_err(|source| PatcherError::Read {
+                path: bytes_path,
+                source,
+            })?;
+        Ok(buffer)
+    }
+
+    /// Streams the bytes of an entry from its container, e.g. one from
+    /// [`DesignIndex::entries`], without reading the rest of the file. The reader ends after
+    /// the entry's last byte.
+    pub fn entry_reader(&self, file: &FileEntry, entry: &DataEntry) -> Result<impl Read + use<>> {
+        let bytes_path = self.container_path(&file.file_hash);
+        open_region(&bytes_path, entry).map_err(|source| PatcherError::Read {
+            path: bytes_path,
+            source,
+        })
+    }
+
+    /// Whether the DesignData folder is part of a game install, rather than a copy of
+    /// it made for inspection. Copies can be read and patched, but the game won't see it.
+    pub fn is_installed(&self) -> bool {
+        get_game_executable(&self.design_data_path).is_some()
+    }
+
+    /// Files a patch writes to that are marked read-only.
+    pub fn read_only_files(&self) -> Vec<PathBuf> {
+        [
+            self.bytes_path.clone(),
+            self.state_path(),
+            self.manifest_path(),
+        ]
+        .into_iter()
+        .filter(|path| is_read_only_file(path))
+        .collect()
+    }
+
+    /// Fails with what to change when a patch couldn't write: the DesignData folder is on
+    /// a read-only drive or a file it writes is marked read-only. Checked up front so the
+    /// problem isn't found halfway through a write.
+    pub fn check_writable(&self) -> Result<()> {
+        if is_read_only_volume(&self.design_data_path) {
+            return Err(PatcherError::ReadOnlyVolume {
+                path: self.design_data_path.clone(),
+            });
+        }
+        match self.read_only_files().into_iter().next() {
+            Some(path) => Err(PatcherError::ReadOnlyFile { path }),
+            None => Ok(()),
+        }
+    }
+
+    pub fn index_path(&self) -> PathBuf {
+        self.design_data_path
+            .join(format!("DesignV_{}.bytes", self.index_hash))
+    }
+
+    /// Path of another excel container listed in the same index.
+    pub fn container_path(&self, file_hash: &str) -> PathBuf {
+        self.design_data_path.join(format!("{file_hash}.bytes"))
+    }
+}
+
+fn open_region(path: &Path, entry: &DataEntry) -> io::Result<io::Take<BufReader<File>>> {
+    let mut file = File::open(long_path(path))?;
+    file.seek(SeekFrom::Start(entry.offset as u64))?;
+    Ok(BufReader::new(file).take(entry.size as u64))
+}
+
+pub(crate) fn write_error(path: &Path, offset: u64, source: io::Error) -> PatcherError {
+    let denied = source.kind() == io::ErrorKind::PermissionDenied;
+    if source.kind() == io::ErrorKind::ReadOnlyFilesystem || (denied && is_read_only_volume(path)) {
+        PatcherError::ReadOnlyVolume {
+            path: path.to_path_buf(),
+        }
+    } else if denied && is_read_only_file(path) {
+        PatcherError::ReadOnlyFile {
+            path: path.to_path_buf(),
+        }
+    } else if denied && is_protected(path) {
+        PatcherError::ProtectedInstall {
+            path: path.to_path_buf(),
+            source,
+        }
+    } else if denied {
+        PatcherError::AccessDenied {
+            path: path.to_path_buf(),
+            source,
+        }
+    } else {
+        PatcherError::Write {
+            path: path.to_path_buf(),
+            offset,
+            source,
+        }
+    }
+}
+
+pub fn get_design_data_path(dropped_path: &Path) -> Result<PathBuf> {
+    get_platform_design_data_path(dropped_path, Platform::Windows)
+}
+
+/// Like [`get_design_data_path`], but looks for the DesignData folder of another platform.
+/// Copied mobile data can also be passed as its `StreamingAssets` or `DesignData` folder.
+pub fn get_platform_design_data_path(dropped_path: &Path, platform: Platform) -> Result<PathBuf> {
+    // Only the given folder is resolved, links below it stay so the exe is still found
+    // next to its data
+    let path = &canonical_path(&resolve_dropped_path(dropped_path));
+    let design_data_dir = |data_dir: PathBuf| {
+        ["StreamingAssets", "DesignData", platform.dir_name()]
+            .iter()
+            .fold(data_dir, |dir, name| child_ignore_case(&dir, name))
+    };
+
+    // Game Pass installs keep the game in a `Content` folder below the package root
+    for root in [path.to_path_buf(), child_ignore_case(path, "Content")] {
+        // Bilibili and beta clients ship their data in a differently named `*_Data` folder
+        for data_dir in data_dirs(&root) {
+            let design_data_path = design_data_dir(data_dir);
+            if long_path(&design_data_path.join("M_DesignV.bytes")).is_file() {
+                return Ok(design_data_path);
+            }
+        }
+
+        if long_path(&child_ignore_case(&root, "StarRail.exe")).is_file() {
+            return Ok(design_data_dir(child_ignore_case(&root, "StarRail_Data")));
+        }
+    }
+
+    for design_data_path in [
+        path.to_path_buf(),
+        child_ignore_case(path, platform.dir_name()),
+        ["DesignData", platform.dir_name()]
+            .iter()
+            .fold(path.to_path_buf(), |dir, name| {
+                child_ignore_case(&dir, name)
+            }),
+    ] {
+        if long_path(&design_data_path.join("M_DesignV.bytes")).is_file() {
+            return Ok(design_data_path);
+        }
+    }
+
+    Err(PatcherError::GameNotFound {
+        path: dropped_path.to_path_buf(),
+    })
+}
+
+/// Resolves the game executable from the `*_Data/StreamingAssets/DesignData/Windows` folder.
+/// `<name>_Data` belongs to `<name>.exe`, falling back to `StarRail.exe`.
+pub fn get_game_executable(design_data_path: &Path) -> Option<PathBuf> {
+    let data_dir = design_data_path.ancestors().nth(3)?;
+    let root = data_dir.parent()?;
+
+    let data_name = data_dir.file_name()?.to_str()?;
+    let exe_name = data_name
+        .get(..data_name.len().checked_sub("_Data".len())?)
+        .map(|name| format!("{name}.exe"));
+
+    exe_name
+        .into_iter()
+        .chain(Some("StarRail.exe".to_string()))
+        .map(|name| child_ignore_case(root, &name))
+        .find(|exe| long_path(exe).is_file())
+}
+
+/// Fails before anything is written when a volume can't take `required` more bytes at
+/// `path`, instead of leaving a half written file. Passes when the space can't be told.
+pub(crate) fn ensure_space(path: &Path, required: u64) -> Result<()> {
+    match available_space(path) {
+        Some(available) if available < required => Err(PatcherError::NoSpace {
+            path: path.to_path_buf(),
+            required,
+            available,
+        }),
+        _ => Ok(()),
+    }
+}
+
+pub(crate) fn read(path: &Path) -> Result<Vec<u8>> {
+    fs::read(long_path(path)).map_err(|source| PatcherError::Read {
+        path: path.to_path_buf(),
+        source,
+    })
+}
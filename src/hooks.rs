@@ -0,0 +1,119 @@
+//! Commands from the config run before and after a patch, e.g. to close the game, clear
+//! the shader cache or start something else afterwards. They run through the shell with
+//! the `HSR_*` variables below describing the run.
+
+use std::{
+    io::stderr,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use anyhow::{Context, bail};
+
+use hsr_lang_patcher::{
+    allowed_language::Voices, game::Game, language::Language, report::PatchReport,
+};
+
+use crate::{Result, crash};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Hook {
+    PrePatch,
+    PostPatch,
+}
+
+impl Hook {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::PrePatch => "pre_patch",
+            Self::PostPatch => "post_patch",
+        }
+    }
+}
+
+/// What the hooks learn about the run.
+pub struct HookContext<'a> {
+    pub game: &'a Game,
+    pub game_path: &'a Path,
+    pub text_lang: Language,
+    pub voices: Voices,
+    /// Only known after the patch.
+    pub report: Option<&'a PatchReport>,
+}
+
+impl HookContext<'_> {
+    fn env(&self, hook: Hook) -> Vec<(&'static str, String)> {
+        let mut env = vec![
+            ("HSR_HOOK", hook.name().to_string()),
+            ("HSR_GAME_PATH", self.game_path.display().to_string()),
+            (
+                "HSR_DESIGN_DATA_PATH",
+                self.game.design_data_path.display().to_string(),
+            ),
+            ("HSR_TEXT_LANG", self.text_lang.code().to_string()),
+            ("HSR_VOICE_LANG", self.voices.os.code().to_string()),
+            ("HSR_CN_VOICE_LANG", self.voices.cn.code().to_string()),
+        ];
+        if let Some(version) = self.game.game_version() {
+            env.push(("HSR_GAME_VERSION", version));
+        }
+        if let Some(report) = self.report {
+            env.push(("HSR_CHANGED", (report.changed() as u8).to_string()));
+            if let Some(id) = &report.backup_id {
+                env.push(("HSR_BACKUP_ID", id.clone()));
+            }
+        }
+        env
+    }
+}
+
+/// Runs `commands` one after another, stopping at the first that fails. With `json` their
+/// output goes to stderr, so stdout stays a single JSON object.
+pub fn run(
+    hook: Hook,
+    commands: &[String],
+    context: &HookContext,
+    quiet: bool,
+    json: bool,
+) -> Result<()> {
+    let env = context.env(hook);
+    for command in commands {
+        if !quiet && !json {
+            println!("Running {} hook: {command}", hook.name());
+        }
+        crash::log(format!("Running {} hook: {command}", hook.name()));
+
+        let mut process = shell(command);
+        process
+            .envs(env.iter().map(|(key, value)| (key, value)))
+            .current_dir(context.game_path)
+            .stdin(Stdio::null());
+        if json {
+            process.stdout(stderr());
+        }
+        let status = process
+            .status()
+            .with_context(|| format!("Failed to run the {} hook '{command}'", hook.name()))?;
+        if !status.success() {
+            bail!("The {} hook '{command}' failed with {status}", hook.name());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn shell(command: &str) -> Command {
+    use std::os::windows::process::CommandExt;
+
+    // `cmd` parses its command line itself, so it is passed as is instead of quoted
+    let mut process = Command::new("cmd");
+    process.arg("/C").raw_arg(command);
+    process
+}
+
+#[cfg(not(windows))]
+fn shell(command: &str) -> Command {
+    let mut process = Command::new("sh");
+    process.arg("-c").arg(command);
+    process
+}
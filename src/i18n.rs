@@ -0,0 +1,460 @@
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use anyhow::anyhow;
+use hsr_lang_patcher::ErrorKind;
+
+use crate::Result;
+
+/// Interface language of the tool itself, unrelated to the game languages being patched.
+#[derive(Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum UiLang {
+    En,
+    Zh,
+    Ja,
+    Ko,
+    Ru,
+}
+
+static UI_LANG: AtomicU8 = AtomicU8::new(UiLang::En as u8);
+
+impl UiLang {
+    const ALL: [Self; 5] = [Self::En, Self::Zh, Self::Ja, Self::Ko, Self::Ru];
+
+    fn from_code(code: &str) -> Option<Self> {
+        let code = code.to_lowercase();
+        let prefix = code.split(['-', '_', '.']).next().unwrap_or_default();
+        match prefix {
+            "en" => Some(Self::En),
+            "zh" => Some(Self::Zh),
+            "ja" | "jp" => Some(Self::Ja),
+            "ko" | "kr" => Some(Self::Ko),
+            "ru" => Some(Self::Ru),
+            _ => None,
+        }
+    }
+}
+
+/// Selects the interface language from `--ui-lang`, falling back to the system locale.
+pub fn init(ui_lang: Option<&str>) -> Result<()> {
+    let lang = match ui_lang {
+        Some(code) => UiLang::from_code(code).ok_or_else(|| {
+            anyhow!("Invalid UI language '{code}'. Must be en, zh, ja, ko, or ru")
+        })?,
+        None => sys_locale::get_locale()
+            .as_deref()
+            .and_then(UiLang::from_code)
+            .unwrap_or(UiLang::En),
+    };
+
+    UI_LANG.store(lang as u8, Ordering::Relaxed);
+    Ok(())
+}
+
+fn current() -> UiLang {
+    UiLang::ALL[UI_LANG.load(Ordering::Relaxed) as usize]
+}
+
+#[derive(Clone, Copy)]
+pub enum Msg {
+    PromptVoice,
+    PromptText,
+    Done,
+    LaunchedGame,
+    WaitingForGame,
+    WaitingForLock,
+    PressEnter,
+    Error,
+    Warning,
+    SaveProfileFailed,
+    UpdateAvailable,
+    NoLanguages,
+    GameNotFound,
+    GameNotFoundHelp,
+    UnsupportedVersion,
+    FileLocked,
+    WriteFailed,
+    InvalidArguments,
+    Cancelled,
+    Reapplied,
+    ReappliedAfterUpdate,
+    #[cfg(windows)]
+    TrayRunning,
+    #[cfg(windows)]
+    TrayReapply,
+    #[cfg(windows)]
+    TrayExit,
+    WizardWelcome,
+    WizardGameFolder,
+    WizardOtherFolder,
+    WizardEnterFolder,
+    WizardNotGameFolder,
+    SearchingInstalls,
+    WizardFullBackups,
+    WizardFullBackupsHelp,
+    #[cfg(windows)]
+    WizardTask,
+    WizardSaved,
+    RetryPrompt,
+    RetryAgain,
+    RetryQuit,
+    TuiHelp,
+    TuiLanguages,
+    TuiText,
+    TuiVoice,
+    TuiUnshipped,
+    InstallGame,
+    InstallVersion,
+    InstallClient,
+    ClientGlobal,
+    ClientCn,
+    Unknown,
+    ConfirmInstall,
+    ReadOnlyFiles,
+    ClearReadOnly,
+    RowMissingText,
+    RowMissingVoice,
+    AppendLanguages,
+}
+
+impl Msg {
+    /// Translations in [`UiLang`] order: en, zh, ja, ko, ru.
+    fn catalog(self) -> [&'static str; 5] {
+        match self {
+            Self::PromptVoice => [
+                "What language should be used for voice?",
+                "语音使用哪种语言？",
+                "ボイスに使用する言語は？",
+                "음성에 사용할 언어는?",
+                "Какой язык использовать для озвучки?",
+            ],
+            Self::PromptText => [
+                "What language should be used for text?",
+                "文本使用哪种语言？",
+                "テキストに使用する言語は？",
+                "텍스트에 사용할 언어는?",
+                "Какой язык использовать для текста?",
+            ],
+            Self::Done => ["Done", "完成", "完了", "완료", "Готово"],
+            Self::LaunchedGame => [
+                "Launched the game",
+                "已启动游戏",
+                "ゲームを起動しました",
+                "게임을 실행했습니다",
+                "Игра запущена",
+            ],
+            Self::WaitingForGame => [
+                "StarRail.exe is running, the patch is applied once it exits (Ctrl+C to cancel)",
+                "StarRail.exe 正在运行，将在其退出后应用补丁（Ctrl+C 取消）",
+                "StarRail.exe が実行中です。終了後にパッチを適用します（Ctrl+C でキャンセル）",
+                "StarRail.exe가 실행 중입니다. 종료되면 패치를 적용합니다 (Ctrl+C로 취소)",
+                "StarRail.exe запущен, патч будет применён после его закрытия (Ctrl+C для отмены)",
+            ],
+            Self::WaitingForLock => [
+                "Another hsr-lang-patcher run is changing this install, waiting for it to finish (Ctrl+C to cancel)",
+                "另一个 hsr-lang-patcher 正在修改此游戏，等待其完成（Ctrl+C 取消）",
+                "別の hsr-lang-patcher がこのインストールを変更中です。完了を待っています（Ctrl+C でキャンセル）",
+                "다른 hsr-lang-patcher가 이 설치를 변경하는 중입니다. 완료될 때까지 기다립니다 (Ctrl+C로 취소)",
+                "Другой запуск hsr-lang-patcher изменяет эту установку, ожидание его завершения (Ctrl+C для отмены)",
+            ],
+            Self::PressEnter => [
+                "Press enter to exit",
+                "按回车键退出",
+                "Enterキーで終了",
+                "종료하려면 Enter를 누르세요",
+                "Нажмите Enter для выхода",
+            ],
+            Self::Error => ["error", "错误", "エラー", "오류", "ошибка"],
+            Self::Warning => ["warning", "警告", "警告", "경고", "предупреждение"],
+            Self::SaveProfileFailed => [
+                "failed to save profile",
+                "保存配置失败",
+                "プロファイルの保存に失敗しました",
+                "프로필 저장 실패",
+                "не удалось сохранить профиль",
+            ],
+            Self::UpdateAvailable => [
+                "{version} is available",
+                "新版本 {version} 可用",
+                "{version} が利用可能です",
+                "{version} 버전을 사용할 수 있습니다",
+                "Доступна версия {version}",
+            ],
+            Self::NoLanguages => [
+                "No languages given. Pass -lang:0XX,1YY when running non-interactively",
+                "未指定语言。非交互模式下请传入 -lang:0XX,1YY",
+                "言語が指定されていません。非対話モードでは -lang:0XX,1YY を指定してください",
+                "언어가 지정되지 않았습니다. 비대화형 모드에서는 -lang:0XX,1YY 를 전달하세요",
+                "Языки не указаны. В неинтерактивном режиме передайте -lang:0XX,1YY",
+            ],
+            Self::GameNotFound => [
+                "Game not found",
+                "未找到游戏",
+                "ゲームが見つかりません",
+                "게임을 찾을 수 없습니다",
+                "Игра не найдена",
+            ],
+            Self::GameNotFoundHelp => [
+                "Make sure to either:\n\
+                - Run this .exe from the game's root folder\n\
+                - Pass the game's root path as an argument\n\
+                - Pass the StreamingAssets/DesignData folder path as an argument",
+                "请确认以下任一项：\n\
+                - 在游戏根目录中运行此 .exe\n\
+                - 将游戏根目录路径作为参数传入\n\
+                - 将 StreamingAssets/DesignData 文件夹路径作为参数传入",
+                "次のいずれかを確認してください：\n\
+                - ゲームのルートフォルダでこの .exe を実行する\n\
+                - ゲームのルートパスを引数として渡す\n\
+                - StreamingAssets/DesignData フォルダのパスを引数として渡す",
+                "다음 중 하나를 확인하세요:\n\
+                - 게임 루트 폴더에서 이 .exe를 실행\n\
+                - 게임 루트 경로를 인수로 전달\n\
+                - StreamingAssets/DesignData 폴더 경로를 인수로 전달",
+                "Убедитесь, что вы:\n\
+                - Запускаете этот .exe из корневой папки игры\n\
+                - Или передаёте путь к корневой папке игры аргументом\n\
+                - Или передаёте путь к папке StreamingAssets/DesignData аргументом",
+            ],
+            Self::UnsupportedVersion => [
+                "Unsupported game version",
+                "不支持的游戏版本",
+                "サポートされていないゲームバージョンです",
+                "지원되지 않는 게임 버전입니다",
+                "Неподдерживаемая версия игры",
+            ],
+            Self::FileLocked => [
+                "Game files are locked, close the game and try again",
+                "游戏文件被占用，请关闭游戏后重试",
+                "ゲームファイルが使用中です。ゲームを終了してから再試行してください",
+                "게임 파일이 사용 중입니다. 게임을 종료한 후 다시 시도하세요",
+                "Файлы игры заняты, закройте игру и попробуйте снова",
+            ],
+            Self::WriteFailed => [
+                "Failed to write game files",
+                "写入游戏文件失败",
+                "ゲームファイルの書き込みに失敗しました",
+                "게임 파일 쓰기에 실패했습니다",
+                "Не удалось записать файлы игры",
+            ],
+            Self::InvalidArguments => [
+                "Invalid arguments",
+                "参数无效",
+                "引数が無効です",
+                "잘못된 인수입니다",
+                "Неверные аргументы",
+            ],
+            Self::Cancelled => [
+                "Cancelled",
+                "已取消",
+                "キャンセルしました",
+                "취소됨",
+                "Отменено",
+            ],
+            Self::Reapplied => [
+                "Language patch re-applied",
+                "已重新应用语言补丁",
+                "言語パッチを再適用しました",
+                "언어 패치를 다시 적용했습니다",
+                "Языковой патч применён повторно",
+            ],
+            Self::ReappliedAfterUpdate => [
+                "Game update detected, language patch re-applied",
+                "检测到游戏更新，已重新应用语言补丁",
+                "ゲームの更新を検出し、言語パッチを再適用しました",
+                "게임 업데이트가 감지되어 언어 패치를 다시 적용했습니다",
+                "Обнаружено обновление игры, языковой патч применён повторно",
+            ],
+            #[cfg(windows)]
+            Self::TrayRunning => [
+                "Running in the notification area. Right-click the icon to exit.",
+                "正在通知区域运行。右键单击图标以退出。",
+                "通知領域で実行中です。終了するにはアイコンを右クリックしてください。",
+                "알림 영역에서 실행 중입니다. 종료하려면 아이콘을 마우스 오른쪽 버튼으로 클릭하세요.",
+                "Работает в области уведомлений. Щёлкните значок правой кнопкой мыши для выхода.",
+            ],
+            #[cfg(windows)]
+            Self::TrayReapply => [
+                "Re-apply now",
+                "立即重新应用",
+                "今すぐ再適用",
+                "지금 다시 적용",
+                "Применить сейчас",
+            ],
+            #[cfg(windows)]
+            Self::TrayExit => ["Exit", "退出", "終了", "종료", "Выход"],
+            Self::WizardWelcome => [
+                "First start, let's set up the patcher. The answers are saved for later runs.",
+                "首次启动，先来设置补丁工具。所选内容会保存供以后使用。",
+                "初回起動です。パッチャーを設定します。回答は次回以降のために保存されます。",
+                "처음 실행입니다. 패처를 설정합니다. 선택한 내용은 다음 실행을 위해 저장됩니다.",
+                "Первый запуск, настроим патчер. Ответы сохранятся для следующих запусков.",
+            ],
+            Self::WizardGameFolder => [
+                "Where is the game installed?",
+                "游戏安装在哪里？",
+                "ゲームはどこにインストールされていますか？",
+                "게임이 어디에 설치되어 있나요?",
+                "Где установлена игра?",
+            ],
+            Self::WizardOtherFolder => [
+                "Another folder...",
+                "其他文件夹...",
+                "別のフォルダー...",
+                "다른 폴더...",
+                "Другая папка...",
+            ],
+            Self::WizardEnterFolder => [
+                "Game folder:",
+                "游戏文件夹：",
+                "ゲームフォルダー：",
+                "게임 폴더:",
+                "Папка игры:",
+            ],
+            Self::WizardNotGameFolder => [
+                "No game files found in this folder",
+                "此文件夹中未找到游戏文件",
+                "このフォルダーにゲームファイルが見つかりません",
+                "이 폴더에서 게임 파일을 찾을 수 없습니다",
+                "В этой папке не найдены файлы игры",
+            ],
+            Self::SearchingInstalls => [
+                "Looking for the game in the usual install folders...",
+                "正在常用安装文件夹中查找游戏...",
+                "一般的なインストール先でゲームを探しています...",
+                "일반적인 설치 폴더에서 게임을 찾는 중...",
+                "Поиск игры в обычных папках установки...",
+            ],
+            Self::WizardFullBackups => [
+                "Back up whole containers?",
+                "备份整个容器文件？",
+                "コンテナ全体をバックアップしますか？",
+                "컨테이너 전체를 백업할까요?",
+                "Создавать резервную копию всего контейнера?",
+            ],
+            Self::WizardFullBackupsHelp => [
+                "Otherwise only the overwritten bytes are backed up",
+                "否则只备份被覆盖的字节",
+                "いいえの場合、上書きされるバイトのみをバックアップします",
+                "아니요를 선택하면 덮어쓰는 바이트만 백업합니다",
+                "Иначе сохраняются только перезаписанные байты",
+            ],
+            #[cfg(windows)]
+            Self::WizardTask => [
+                "Re-apply the patch automatically after game updates?",
+                "游戏更新后自动重新应用补丁？",
+                "ゲームの更新後にパッチを自動で再適用しますか？",
+                "게임 업데이트 후 패치를 자동으로 다시 적용할까요?",
+                "Автоматически применять патч после обновлений игры?",
+            ],
+            Self::WizardSaved => [
+                "Saved the settings to {path}",
+                "设置已保存到 {path}",
+                "設定を {path} に保存しました",
+                "설정을 {path}에 저장했습니다",
+                "Настройки сохранены в {path}",
+            ],
+            Self::RetryPrompt => [
+                "What now?",
+                "接下来做什么？",
+                "どうしますか？",
+                "어떻게 할까요?",
+                "Что дальше?",
+            ],
+            Self::RetryAgain => [
+                "Try again",
+                "重试",
+                "やり直す",
+                "다시 시도",
+                "Попробовать снова",
+            ],
+            Self::RetryQuit => ["Quit", "退出", "終了", "종료", "Выйти"],
+            Self::TuiHelp => [
+                "{updown} select field  {leftright} change language  Enter apply  q quit",
+                "{updown} 选择项目  {leftright} 切换语言  Enter 应用  q 退出",
+                "{updown} 項目選択  {leftright} 言語変更  Enter 適用  q 終了",
+                "{updown} 항목 선택  {leftright} 언어 변경  Enter 적용  q 종료",
+                "{updown} выбор поля  {leftright} смена языка  Enter применить  q выход",
+            ],
+            Self::TuiLanguages => ["Languages", "语言", "言語", "언어", "Языки"],
+            Self::TuiText => ["Text", "文本", "テキスト", "텍스트", "Текст"],
+            Self::TuiVoice => ["Voice", "语音", "ボイス", "음성", "Озвучка"],
+            Self::InstallGame => ["Game", "游戏", "ゲーム", "게임", "Игра"],
+            Self::InstallVersion => ["Version", "版本", "バージョン", "버전", "Версия"],
+            Self::InstallClient => ["Client", "客户端", "クライアント", "클라이언트", "Клиент"],
+            Self::ClientGlobal => ["global", "国际服", "グローバル版", "글로벌", "глобальный"],
+            Self::ClientCn => ["CN", "国服", "中国版", "중국판", "китайский"],
+            Self::Unknown => ["unknown", "未知", "不明", "알 수 없음", "неизвестно"],
+            Self::ConfirmInstall => [
+                "Continue with this install?",
+                "是否继续使用此游戏？",
+                "このインストールで続行しますか？",
+                "이 설치로 계속하시겠습니까?",
+                "Продолжить с этой установкой?",
+            ],
+            Self::ReadOnlyFiles => [
+                "These game files are marked read-only:",
+                "以下游戏文件被标记为只读：",
+                "次のゲームファイルは読み取り専用です:",
+                "다음 게임 파일이 읽기 전용으로 설정되어 있습니다:",
+                "Эти файлы игры помечены как только для чтения:",
+            ],
+            Self::ClearReadOnly => [
+                "Clear the read-only attribute so they can be patched?",
+                "是否清除只读属性以便修改？",
+                "パッチできるように読み取り専用属性を解除しますか？",
+                "패치할 수 있도록 읽기 전용 속성을 해제하시겠습니까?",
+                "Снять атрибут «только чтение», чтобы их можно было изменить?",
+            ],
+            Self::RowMissingText => [
+                "The {area} text row doesn't list '{language}'",
+                "{area} 文本行未列出 '{language}'",
+                "{area} のテキスト行に '{language}' がありません",
+                "{area} 텍스트 행에 '{language}'이(가) 없습니다",
+                "Текстовая строка {area} не содержит '{language}'",
+            ],
+            Self::RowMissingVoice => [
+                "The {area} voice row doesn't list '{language}'",
+                "{area} 语音行未列出 '{language}'",
+                "{area} のボイス行に '{language}' がありません",
+                "{area} 음성 행에 '{language}'이(가) 없습니다",
+                "Строка озвучки {area} не содержит '{language}'",
+            ],
+            Self::AppendLanguages => [
+                "Add the missing languages to the lists?",
+                "是否将缺少的语言添加到列表中？",
+                "不足している言語をリストに追加しますか？",
+                "누락된 언어를 목록에 추가하시겠습니까?",
+                "Добавить недостающие языки в списки?",
+            ],
+            Self::TuiUnshipped => [
+                "Press Enter again to apply a combination the game doesn't ship: {warnings}",
+                "再次按 Enter 应用游戏未提供的组合：{warnings}",
+                "もう一度 Enter でゲームが提供していない組み合わせを適用します: {warnings}",
+                "게임이 제공하지 않는 조합을 적용하려면 Enter를 다시 누르세요: {warnings}",
+                "Нажмите Enter ещё раз, чтобы применить сочетание, которого нет в игре: {warnings}",
+            ],
+        }
+    }
+}
+
+pub fn tr(msg: Msg) -> &'static str {
+    msg.catalog()[current() as usize]
+}
+
+pub fn error_kind(kind: ErrorKind) -> Option<&'static str> {
+    let msg = match kind {
+        ErrorKind::Other => return None,
+        ErrorKind::GameNotFound => Msg::GameNotFound,
+        ErrorKind::UnsupportedVersion => Msg::UnsupportedVersion,
+        ErrorKind::FileLocked => Msg::FileLocked,
+        ErrorKind::WriteFailed => Msg::WriteFailed,
+        ErrorKind::InvalidArguments => Msg::InvalidArguments,
+        ErrorKind::Cancelled => Msg::Cancelled,
+    };
+    Some(tr(msg))
+}
+
+pub fn is_english() -> bool {
+    current() == UiLang::En
+}
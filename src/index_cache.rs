@@ -0,0 +1,241 @@
+//! Parsed `DesignV` indexes kept in the user cache folder, so commands that read the whole
+//! index don't parse and check its hundreds of thousands of entries on every run. An entry
+//! is keyed by the index hash and only used while the index file has the size and
+//! modification time it had when the entry was written, so an update or a rebuilt index
+//! invalidates it. Nothing is cached after [`disable`] is called.
+
+use std::{
+    cmp::Reverse,
+    env,
+    fs::{self, File},
+    io::{self, Cursor, Read, Write},
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, Ordering},
+    time::UNIX_EPOCH,
+};
+
+use byteorder::{LE, ReadBytesExt, WriteBytesExt};
+
+use crate::{
+    design_index::{DataEntry, DesignIndex, FileEntry, ensure_remaining, invalid_data},
+    paths::long_path,
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+const MAGIC: &[u8; 8] = b"HLPINDEX";
+/// Bumped whenever the layout below changes, older entries are then ignored.
+const VERSION: u32 = 1;
+/// Entries kept for other indexes, e.g. of a second install or the version before an
+/// update. Older ones are removed when a new one is written.
+const MAX_ENTRIES: usize = 4;
+
+const FILE_RECORD_SIZE: u64 = 4 + 0x10 + 8 + 4 + 4 + 4;
+const ENTRY_SIZE: u64 = 4 * 3;
+
+pub fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// `hsr-lang-patcher` in the platform's cache folder.
+pub fn dir() -> Option<PathBuf> {
+    let home = || env::var_os("HOME").map(PathBuf::from);
+    let base = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home().map(|home| home.join("Library/Caches"))
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| home().map(|home| home.join(".cache")))
+    };
+    Some(base?.join(env!("CARGO_PKG_NAME")))
+}
+
+/// The cached index for `index_path`, if there is one and the file hasn't changed since.
+pub fn load(index_path: &Path, index_hash: &str) -> Option<DesignIndex> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    let key = Key::of(index_path)?;
+    let data = fs::read(entry_path(index_hash)?).ok()?;
+    decode(&data, &key).ok()
+}
+
+/// Caches `index` as parsed from `index_path`. A cache that can't be written only costs
+/// the next run a parse, so errors are ignored.
+pub fn store(index_path: &Path, index_hash: &str, index: &DesignIndex) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let (Some(key), Some(path)) = (Key::of(index_path), entry_path(index_hash)) else {
+        return;
+    };
+    let _ = write_entry(&path, &key, index);
+    prune(&path);
+}
+
+fn entry_path(index_hash: &str) -> Option<PathBuf> {
+    Some(dir()?.join(format!("index-{index_hash}.bin")))
+}
+
+/// What the index file looked like when it was parsed.
+#[derive(PartialEq, Eq)]
+struct Key {
+    size: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+}
+
+impl Key {
+    fn of(index_path: &Path) -> Option<Self> {
+        let metadata = fs::metadata(long_path(index_path)).ok()?;
+        let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            size: metadata.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+        })
+    }
+}
+
+fn write_entry(path: &Path, key: &Key, index: &DesignIndex) -> io::Result<()> {
+    fs::create_dir_all(path.parent().unwrap_or(path))?;
+    let data = encode(key, index)?;
+    // Written next to it and renamed, so another run never reads half an entry
+    let temp_path = path.with_extension("tmp");
+    File::create(&temp_path)?.write_all(&data)?;
+    fs::rename(&temp_path, path)
+}
+
+/// Removes all but the [`MAX_ENTRIES`] most recently written entries.
+fn prune(kept: &Path) {
+    let Some(Ok(dir)) = kept.parent().map(fs::read_dir) else {
+        return;
+    };
+    let mut entries: Vec<_> = dir
+        .flatten()
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with("index-") && name.ends_with(".bin")
+        })
+        .filter_map(|entry| Some((entry.metadata().ok()?.modified().ok()?, entry.path())))
+        .collect();
+    entries.sort_by_key(|(modified, _)| Reverse(*modified));
+    for (_, path) in entries.into_iter().skip(MAX_ENTRIES) {
+        if path != kept {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+fn encode(key: &Key, index: &DesignIndex) -> io::Result<Vec<u8>> {
+    let entry_count: usize = index.files.iter().map(|file| file.entries.len()).sum();
+    let mut buffer = Vec::with_capacity(
+        64 + index.files.len() * FILE_RECORD_SIZE as usize + entry_count * ENTRY_SIZE as usize,
+    );
+    buffer.extend_from_slice(MAGIC);
+    buffer.write_u32::<LE>(VERSION)?;
+    buffer.write_u64::<LE>(key.size)?;
+    buffer.write_u64::<LE>(key.modified_secs)?;
+    buffer.write_u32::<LE>(key.modified_nanos)?;
+
+    buffer.write_u8(index.legacy as u8)?;
+    buffer.write_u64::<LE>(index.unk_1)?;
+    buffer.write_u32::<LE>(index.file_count)?;
+    buffer.write_u32::<LE>(index.unk_2)?;
+    buffer.write_u32::<LE>(index.trailing.len() as u32)?;
+    buffer.extend_from_slice(&index.trailing);
+
+    buffer.write_u32::<LE>(index.files.len() as u32)?;
+    for file in &index.files {
+        buffer.write_i32::<LE>(file.name_hash)?;
+        let mut file_hash = [0u8; 0x10];
+        hex::decode_to_slice(&file.file_hash, &mut file_hash)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        buffer.extend_from_slice(&file_hash);
+        buffer.write_u64::<LE>(file.read_size)?;
+        buffer.write_u32::<LE>(file.entry_count)?;
+        buffer.write_u32::<LE>(file.unk_1)?;
+        buffer.write_u32::<LE>(file.entries.len() as u32)?;
+        for entry in &file.entries {
+            buffer.write_i32::<LE>(entry.name_hash)?;
+            buffer.write_i32::<LE>(entry.size)?;
+            buffer.write_i32::<LE>(entry.offset)?;
+        }
+    }
+    Ok(buffer)
+}
+
+/// Reads an entry written by [`encode`]. Its counts were checked when the index was
+/// parsed, here they are only kept from allocating more than the entry holds.
+fn decode(data: &[u8], key: &Key) -> io::Result<DesignIndex> {
+    let mut reader = Cursor::new(data);
+    let mut magic = [0u8; 8];
+    reader.read_exact(&mut magic)?;
+    let stale = || invalid_data("cache entry is stale".to_string());
+    if &magic != MAGIC || reader.read_u32::<LE>()? != VERSION {
+        return Err(stale());
+    }
+    let cached = Key {
+        size: reader.read_u64::<LE>()?,
+        modified_secs: reader.read_u64::<LE>()?,
+        modified_nanos: reader.read_u32::<LE>()?,
+    };
+    if cached != *key {
+        return Err(stale());
+    }
+
+    let legacy = reader.read_u8()? != 0;
+    let unk_1 = reader.read_u64::<LE>()?;
+    let file_count = reader.read_u32::<LE>()?;
+    let unk_2 = reader.read_u32::<LE>()?;
+    let trailing_len = reader.read_u32::<LE>()?;
+    ensure_remaining(&reader, trailing_len, 1, "trailing")?;
+    let mut trailing = vec![0u8; trailing_len as usize];
+    reader.read_exact(&mut trailing)?;
+
+    let files_len = reader.read_u32::<LE>()?;
+    ensure_remaining(&reader, files_len, FILE_RECORD_SIZE, "files")?;
+    let mut files = Vec::with_capacity(files_len as usize);
+    for _ in 0..files_len {
+        let name_hash = reader.read_i32::<LE>()?;
+        let mut file_hash = [0u8; 0x10];
+        reader.read_exact(&mut file_hash)?;
+        let read_size = reader.read_u64::<LE>()?;
+        let entry_count = reader.read_u32::<LE>()?;
+        let file_unk_1 = reader.read_u32::<LE>()?;
+        let entries_len = reader.read_u32::<LE>()?;
+        ensure_remaining(&reader, entries_len, ENTRY_SIZE, "entries")?;
+        let mut entries = Vec::with_capacity(entries_len as usize);
+        for _ in 0..entries_len {
+            entries.push(DataEntry {
+                name_hash: reader.read_i32::<LE>()?,
+                size: reader.read_i32::<LE>()?,
+                offset: reader.read_i32::<LE>()?,
+            });
+        }
+        files.push(FileEntry {
+            name_hash,
+            file_hash: hex::encode(file_hash),
+            read_size,
+            entry_count,
+            entries,
+            unk_1: file_unk_1,
+        });
+    }
+    if reader.position() != data.len() as u64 {
+        return Err(invalid_data("cache entry has trailing data".to_string()));
+    }
+
+    Ok(DesignIndex {
+        unk_1,
+        file_count,
+        unk_2,
+        files,
+        legacy,
+        trailing,
+    })
+}
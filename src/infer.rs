@@ -0,0 +1,188 @@
+//! Guesses the [`Schema`] of an unknown excel. Every field type is tried for each field
+//! the first time a row uses it, and of all layouts that decode every row and consume
+//! the data exactly, the one whose strings look most like text wins.
+
+use std::io::Cursor;
+
+use byteorder::ReadBytesExt;
+use varint_rs::VarintReader;
+
+use crate::schema::{Field, FieldType, Schema, Value, read_value};
+
+/// Upper bound on decoding steps, so a pathological excel can't stall the search.
+const MAX_STEPS: usize = 1 << 20;
+/// Bitmasks of more than two bytes haven't been seen in any excel.
+const MAX_BITMASK_SIZE: usize = 2;
+
+#[derive(Debug, Clone)]
+pub struct InferredSchema {
+    pub schema: Schema,
+    pub rows: usize,
+    /// Fields that no row sets. Their type can't be known and defaults to `u8`.
+    pub unused_fields: Vec<usize>,
+    /// Other layouts that also decode the data, a rough measure of doubt.
+    pub alternatives: usize,
+}
+
+struct Search<'a> {
+    data: &'a [u8],
+    bitmask_size: usize,
+    rows: usize,
+    types: Vec<Option<FieldType>>,
+    steps: usize,
+    best: Option<(f64, Vec<Option<FieldType>>)>,
+    found: usize,
+}
+
+/// Returns `None` if no layout decodes the data.
+pub fn infer_schema(name: &str, name_hash: Option<i32>, data: &[u8]) -> Option<InferredSchema> {
+    let mut header = Cursor::new(data);
+    header.read_u8().ok()?;
+    let rows = usize::try_from(header.read_i32_varint().ok()?).ok()?;
+    let start = header.position() as usize;
+
+    (1..=MAX_BITMASK_SIZE).find_map(|bitmask_size| {
+        let mut search = Search {
+            data,
+            bitmask_size,
+            rows,
+            types: vec![None; bitmask_size * 8],
+            steps: 0,
+            best: None,
+            found: 0,
+        };
+        search.row(start, 0);
+        let (_, types) = search.best?;
+
+        // Drop trailing fields no row uses, keeping the bitmask size
+        let used = types.iter().rposition(Option::is_some).map_or(0, |i| i + 1);
+        let min_fields = (bitmask_size - 1) * 8 + 1;
+        let types = &types[..used.max(min_fields)];
+
+        Some(InferredSchema {
+            schema: Schema {
+                name: name.to_string(),
+                name_hash,
+                fields: types
+                    .iter()
+                    .enumerate()
+                    .map(|(i, kind)| Field {
+                        name: format!("field_{i}"),
+                        kind: kind.unwrap_or(FieldType::U8),
+                    })
+                    .collect(),
+                transforms: Vec::new(),
+            },
+            rows,
+            unused_fields: (0..types.len()).filter(|&i| types[i].is_none()).collect(),
+            alternatives: search.found - 1,
+        })
+    })
+}
+
+impl Search<'_> {
+    fn row(&mut self, pos: usize, row: usize) {
+        if row == self.rows {
+            self.finish(pos);
+            return;
+        }
+        let Some(bitmask) = self.data.get(pos..pos + self.bitmask_size) else {
+            return;
+        };
+        self.field(pos + self.bitmask_size, row, 0, bitmask);
+    }
+
+    fn field(&mut self, pos: usize, row: usize, field: usize, bitmask: &[u8]) {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return;
+        }
+        if field == self.types.len() {
+            self.row(pos, row + 1);
+            return;
+        }
+        if bitmask[field / 8] & (1 << (field % 8)) == 0 {
+            self.field(pos, row, field + 1, bitmask);
+            return;
+        }
+
+        let candidates = match self.types[field] {
+            Some(kind) => vec![kind],
+            None => FieldType::ALL.to_vec(),
+        };
+        let known = self.types[field].is_some();
+        for kind in candidates {
+            let mut cursor = Cursor::new(self.data);
+            cursor.set_position(pos as u64);
+            if read_value(&mut cursor, kind).is_err() {
+                continue;
+            }
+            self.types[field] = Some(kind);
+            self.field(cursor.position() as usize, row, field + 1, bitmask);
+            if !known {
+                self.types[field] = None;
+            }
+        }
+    }
+
+    fn finish(&mut self, pos: usize) {
+        if self.data[pos..].iter().any(|&byte| byte != 0) {
+            return;
+        }
+        self.found += 1;
+
+        let score = self.score();
+        if self.best.as_ref().is_none_or(|(best, _)| score > *best) {
+            self.best = Some((score, self.types.clone()));
+        }
+    }
+
+    /// Strings that read as text count for a layout, strings of control characters
+    /// against it. Integers are neutral, with `u8` slightly ahead of varints.
+    fn score(&self) -> f64 {
+        let schema = Schema {
+            name: String::new(),
+            name_hash: None,
+            fields: self
+                .types
+                .iter()
+                .map(|kind| Field {
+                    name: String::new(),
+                    kind: kind.unwrap_or(FieldType::U8),
+                })
+                .collect(),
+            transforms: Vec::new(),
+        };
+        let Ok(rows) = schema.decode(self.data) else {
+            return f64::MIN;
+        };
+
+        let text = |s: &String| {
+            if !s.is_empty() && s.chars().all(|c| !c.is_control()) {
+                1.0
+            } else {
+                -1.0
+            }
+        };
+        rows.iter()
+            .flatten()
+            .flatten()
+            .map(|value| match value {
+                Value::String(s) => 2.0 * text(s),
+                Value::StringArray(strings) if strings.is_empty() => 0.5,
+                Value::StringArray(strings) => {
+                    2.0 * strings.iter().map(text).sum::<f64>() / strings.len() as f64
+                }
+                Value::Int(_) => 0.0,
+            })
+            .sum::<f64>()
+            + self
+                .types
+                .iter()
+                .map(|kind| match kind {
+                    Some(FieldType::U8) => 0.1,
+                    _ => 0.0,
+                })
+                .sum::<f64>()
+    }
+}
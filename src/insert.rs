@@ -0,0 +1,72 @@
+use crate::{
+    PatcherError, Result,
+    design_index::{DataEntry, DesignIndex},
+    game::{Game, read},
+    master_index::MasterUpdate,
+    rebuild::replace,
+};
+
+impl Game {
+    /// Appends `payload` to a container as a new entry and registers it in the index.
+    /// The name hash must not be used by any other entry. Both files are backed up first.
+    pub fn add_entry(
+        &self,
+        file_hash: &str,
+        name_hash: i32,
+        payload: &[u8],
+    ) -> Result<(DataEntry, MasterUpdate)> {
+        let index_path = self.index_path();
+        let index_data = read(&index_path)?;
+        let mut index = DesignIndex::parse(&index_data)?;
+        if index.find_by_hash(name_hash).is_some() {
+            return Err(PatcherError::EntryExists { name_hash });
+        }
+
+        let file = index
+            .files
+            .iter_mut()
+            .find(|file| file.file_hash == file_hash)
+            .ok_or_else(|| PatcherError::ContainerNotFound {
+                file_hash: file_hash.to_string(),
+            })?;
+
+        let bytes_path = self.container_path(file_hash);
+        let mut container = read(&bytes_path)?;
+        let old_size = container.len() as u64;
+
+        // Offsets and sizes are stored as i32, so the container can't grow past 2 GiB
+        let end = old_size + payload.len() as u64;
+        if end > i32::MAX as u64 {
+            return Err(PatcherError::EntryTooLarge {
+                size: payload.len() as u64,
+                offset: old_size,
+            });
+        }
+        let entry = DataEntry {
+            name_hash,
+            size: payload.len() as i32,
+            offset: old_size as i32,
+        };
+        container.extend_from_slice(payload);
+
+        file.entries.push(entry.clone());
+        file.entry_count = file.entries.len() as u32;
+        // Only follow the container size when the index actually stored it
+        if file.read_size == old_size {
+            file.read_size = container.len() as u64;
+        }
+
+        let new_index_data = index.serialize().map_err(|source| PatcherError::Codec {
+            path: index_path.clone(),
+            offset: 0,
+            source,
+        })?;
+
+        self.ensure_rewrite_space(file_hash, old_size, container.len() as u64)?;
+        self.ensure_container_backup(file_hash)?;
+        replace(&bytes_path, &container)?;
+        let master = self.write_index(&index_data, &new_index_data)?;
+
+        Ok((entry, master))
+    }
+}
@@ -0,0 +1,86 @@
+use std::{env, fs, path::Path, process::Command};
+
+use anyhow::{Context, anyhow};
+
+use hsr_lang_patcher::{
+    detect::default_game_path,
+    game::{get_design_data_path, get_game_executable},
+    paths::{canonical_path, resolve_dropped_path},
+};
+
+use crate::{
+    Result,
+    args::Args,
+    config::{self, Config},
+};
+
+const SHORTCUT_NAME: &str = "Patch & Play";
+
+/// Copies the patcher next to `StarRail.exe`, writes a config for it and
+/// creates "Patch & Play" shortcuts on Windows.
+pub fn install(args: &Args) -> Result<()> {
+    let game_path = match args.game_path {
+        // Resolved like everywhere else, so a dropped shortcut works and the config gets a
+        // plain path instead of a verbatim one
+        Some(ref path) => canonical_path(&resolve_dropped_path(path)),
+        None => default_game_path()?,
+    };
+    let game_exe = get_game_executable(&get_design_data_path(&game_path)?)
+        .context("Installing requires the game's root folder containing StarRail.exe")?;
+    let game_dir = game_exe.parent().unwrap_or(&game_path);
+
+    let current_exe = env::current_exe()?;
+    let target_exe = game_dir.join(current_exe.file_name().unwrap_or_default());
+    if canonical_path(&current_exe) != canonical_path(&target_exe) {
+        fs::copy(&current_exe, &target_exe)
+            .with_context(|| format!("Failed to copy to '{}'", target_exe.display()))?;
+    }
+    println!("Installed to '{}'", target_exe.display());
+
+    let (text_lang, voice_lang) = args.get_or_prompt_languages()?;
+    let voices = args.voices(voice_lang.into());
+    let config = Config {
+        game_path: Some(game_dir.to_path_buf()),
+        text_language: Some(text_lang.to_string()),
+        voice_language: Some(voices.os.to_string()),
+        cn_voice_language: voices.cn_override().map(|lang| lang.to_string()),
+        ..Default::default()
+    };
+    config.save_to(&target_exe.with_file_name(config::FILE_NAME))?;
+
+    if cfg!(windows) {
+        create_shortcuts(&target_exe, game_dir)?;
+        println!("Created '{SHORTCUT_NAME}' shortcuts on the desktop and in the start menu");
+    }
+
+    Ok(())
+}
+
+fn create_shortcuts(target_exe: &Path, working_dir: &Path) -> Result<()> {
+    let quote = |path: &Path| path.display().to_string().replace('\'', "''");
+
+    let script = format!(
+        "$shell = New-Object -ComObject WScript.Shell; \
+        foreach ($folder in 'Desktop', 'Programs') {{ \
+            $path = Join-Path ([Environment]::GetFolderPath($folder)) '{SHORTCUT_NAME}.lnk'; \
+            $shortcut = $shell.CreateShortcut($path); \
+            $shortcut.TargetPath = '{}'; \
+            $shortcut.Arguments = '--last --launch'; \
+            $shortcut.WorkingDirectory = '{}'; \
+            $shortcut.Save() \
+        }}",
+        quote(target_exe),
+        quote(working_dir),
+    );
+
+    let status = Command::new("powershell")
+        .args(["-NoProfile", "-NonInteractive", "-Command", &script])
+        .status()
+        .context("Failed to run powershell")?;
+
+    if !status.success() {
+        return Err(anyhow!("Failed to create shortcuts"));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+};
+
+use md5::{Digest, Md5};
+use serde::Deserialize;
+
+use crate::{PatcherError, Result, game::Game, paths::long_path};
+
+/// Result of comparing the excel container against the game's `pkg_version` manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Integrity {
+    /// Matches the manifest, the file is as downloaded.
+    Intact,
+    /// Same size but different contents, either patched before or corrupted.
+    Modified,
+    /// The size differs, which a language patch never causes.
+    SizeMismatch { expected: u64, actual: u64 },
+    /// The container isn't listed in the manifest.
+    Unlisted,
+}
+
+/// A line of `pkg_version`, describing one file of the game as downloaded.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManifestEntry {
+    /// Path relative to the game folder, also used to download the file.
+    pub remote_name: String,
+    pub md5: String,
+    pub file_size: u64,
+}
+
+impl Game {
+    /// Directory containing `pkg_version`, i.e. the folder of the game executable.
+    fn root_path(&self) -> Option<&Path> {
+        self.design_data_path.ancestors().nth(4)
+    }
+
+    /// Looks up a file of the DesignData folder in `pkg_version`. The outer `None` means
+    /// the game folder has no manifest, e.g. for a bare DesignData folder.
+    pub(crate) fn find_manifest_entry(
+        &self,
+        file_name: &str,
+    ) -> Result<Option<Option<ManifestEntry>>> {
+        let Some(root) = self.root_path() else {
+            return Ok(None);
+        };
+        let manifest_path = root.join("pkg_version");
+        let manifest = match File::open(long_path(&manifest_path)) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => {
+                return Err(PatcherError::Read {
+                    path: manifest_path,
+                    source,
+                });
+            }
+        };
+
+        Ok(Some(
+            BufReader::new(manifest)
+                .lines()
+                .map_while(io::Result::ok)
+                .filter_map(|line| serde_json::from_str::<ManifestEntry>(&line).ok())
+                .find(|entry| entry.remote_name.rsplit('/').next() == Some(file_name)),
+        ))
+    }
+
+    fn container_name(&self) -> String {
+        format!("{}.bytes", self.file_hash)
+    }
+
+    /// The `pkg_version` entry of the excel container, if the game has one.
+    pub fn manifest_entry(&self) -> Result<Option<ManifestEntry>> {
+        Ok(self.find_manifest_entry(&self.container_name())?.flatten())
+    }
+
+    /// Verifies the excel container against `pkg_version`. Returns `None` if the
+    /// game folder has no manifest, e.g. for a bare DesignData folder.
+    pub fn check_integrity(&self) -> Result<Option<Integrity>> {
+        let Some(entry) = self.find_manifest_entry(&self.container_name())? else {
+            return Ok(None);
+        };
+        let Some(entry) = entry else {
+            return Ok(Some(Integrity::Unlisted));
+        };
+
+        let read_error = |source| PatcherError::Read {
+            path: self.bytes_path.clone(),
+            source,
+        };
+        let (size, md5) = hash_file(&self.bytes_path).map_err(read_error)?;
+
+        Ok(Some(if size != entry.file_size {
+            Integrity::SizeMismatch {
+                expected: entry.file_size,
+                actual: size,
+            }
+        } else if !md5.eq_ignore_ascii_case(&entry.md5) {
+            Integrity::Modified
+        } else {
+            Integrity::Intact
+        }))
+    }
+}
+
+pub(crate) fn hash_file(path: &Path) -> io::Result<(u64, String)> {
+    let mut file = File::open(long_path(path))?;
+    let mut hasher = Md5::new();
+    let mut buffer = vec![0; 64 * 1024];
+    let mut size = 0;
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        size += read as u64;
+    }
+
+    Ok((size, hex::encode(hasher.finalize())))
+}
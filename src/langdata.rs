@@ -0,0 +1,126 @@
+//! `snapshot-langdata`: dumps every excel in [`LANGUAGE_EXCELS`] to a folder, raw and
+//! decoded, so two game versions can be compared with any diff tool.
+
+use std::{fs, path::PathBuf};
+
+use anyhow::Context;
+use serde::Serialize;
+use serde_json::json;
+
+use hsr_lang_patcher::{
+    design_index::excel_name_hash,
+    game::Game,
+    infer::infer_schema,
+    schema::{LANGUAGE_EXCELS, Row, Schema, Value},
+};
+
+use crate::{Result, args::Args};
+
+#[derive(Serialize)]
+struct SnapshotEntry {
+    name: String,
+    name_hash: i32,
+    /// `None` if this game version doesn't have the excel.
+    file_hash: Option<String>,
+    size: usize,
+    /// `built-in`, `inferred` or `none` when no layout decodes the data.
+    schema: &'static str,
+    rows: usize,
+}
+
+pub fn snapshot(game: &Game, args: &Args) -> Result<()> {
+    let dir = args.output.clone().unwrap_or_else(|| {
+        PathBuf::from(format!(
+            "langdata-{}",
+            game.game_version()
+                .unwrap_or_else(|| game.index_hash.clone())
+        ))
+    });
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create '{}'", dir.display()))?;
+    let index = game.design_index()?;
+
+    let mut entries = Vec::new();
+    for &name in LANGUAGE_EXCELS {
+        let name_hash = excel_name_hash(name);
+        let Some((_, file)) = index.find_by_hash(name_hash) else {
+            if !args.quiet {
+                println!("{name}: not in this version");
+            }
+            entries.push(SnapshotEntry {
+                name: name.to_string(),
+                name_hash,
+                file_hash: None,
+                size: 0,
+                schema: "none",
+                rows: 0,
+            });
+            continue;
+        };
+
+        let data = game.read_entry(name_hash)?;
+        let write = |extension: &str, contents: &[u8]| {
+            let path = dir.join(format!("{name}.{extension}"));
+            fs::write(&path, contents)
+                .with_context(|| format!("Failed to write '{}'", path.display()))
+        };
+        write("bytes", &data)?;
+
+        let (schema, kind) = match Schema::find_known(name) {
+            Some(schema) => (Some(schema), "built-in"),
+            None => match infer_schema(name, Some(name_hash), &data) {
+                Some(inferred) => (Some(inferred.schema), "inferred"),
+                None => (None, "none"),
+            },
+        };
+        let rows = match schema
+            .as_ref()
+            .map(|schema| (schema, schema.decode_stored(&data)))
+        {
+            Some((schema, Ok(rows))) => {
+                write("schema.toml", schema.to_toml().as_bytes())?;
+                let decoded: Vec<_> = rows.iter().map(|row| row_json(schema, row)).collect();
+                write("json", serde_json::to_string_pretty(&decoded)?.as_bytes())?;
+                rows.len()
+            }
+            _ => 0,
+        };
+
+        if !args.quiet {
+            println!("{name}: {} bytes, {rows} rows, {kind} schema", data.len());
+        }
+        entries.push(SnapshotEntry {
+            name: name.to_string(),
+            name_hash,
+            file_hash: Some(file.file_hash.clone()),
+            size: data.len(),
+            schema: kind,
+            rows,
+        });
+    }
+
+    let manifest = json!({
+        "game_version": game.game_version(),
+        "index_hash": game.index_hash,
+        "excels": entries,
+    });
+    let path = dir.join("snapshot.json");
+    fs::write(&path, serde_json::to_string_pretty(&manifest)?)
+        .with_context(|| format!("Failed to write '{}'", path.display()))?;
+    if !args.quiet {
+        println!("Wrote the snapshot to '{}'", dir.display());
+    }
+    Ok(())
+}
+
+/// A row as an object keyed by field name, unset fields left out.
+fn row_json(schema: &Schema, row: &Row) -> serde_json::Value {
+    let fields = schema.fields.iter().zip(row).filter_map(|(field, value)| {
+        let value = match value.as_ref()? {
+            Value::String(s) => json!(s),
+            Value::StringArray(strings) => json!(strings),
+            Value::Int(n) => json!(n),
+        };
+        Some((field.name.clone(), value))
+    });
+    serde_json::Value::Object(fields.collect())
+}
@@ -0,0 +1,135 @@
+//! The languages the patcher sets, named by the codes the AllowedLanguage rows use.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{PatcherError, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    /// Simplified Chinese text, Mandarin voice.
+    Cn,
+    En,
+    Kr,
+    Jp,
+    /// Traditional Chinese.
+    Cht,
+    De,
+    Es,
+    Fr,
+    Id,
+    Pt,
+    Ru,
+    Th,
+    Vi,
+}
+
+impl Language {
+    /// In the order the prompts list them, the dubbed languages first. Every one of them
+    /// can be the text language.
+    pub const ALL: [Self; 13] = [
+        Self::Cn,
+        Self::En,
+        Self::Kr,
+        Self::Jp,
+        Self::Cht,
+        Self::De,
+        Self::Es,
+        Self::Fr,
+        Self::Id,
+        Self::Pt,
+        Self::Ru,
+        Self::Th,
+        Self::Vi,
+    ];
+
+    /// Code in the `language_list` and `default_language` fields of the rows.
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::Cn => "cn",
+            Self::En => "en",
+            Self::Kr => "kr",
+            Self::Jp => "jp",
+            Self::Cht => "cht",
+            Self::De => "de",
+            Self::Es => "es",
+            Self::Fr => "fr",
+            Self::Id => "id",
+            Self::Pt => "pt",
+            Self::Ru => "ru",
+            Self::Th => "th",
+            Self::Vi => "vi",
+        }
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|lang| lang.code() == code)
+    }
+
+    pub fn display_name(self) -> &'static str {
+        match self {
+            Self::Cn => "Chinese",
+            Self::En => "English",
+            Self::Kr => "Korean",
+            Self::Jp => "Japanese",
+            Self::Cht => "Traditional Chinese",
+            Self::De => "German",
+            Self::Es => "Spanish",
+            Self::Fr => "French",
+            Self::Id => "Indonesian",
+            Self::Pt => "Portuguese",
+            Self::Ru => "Russian",
+            Self::Th => "Thai",
+            Self::Vi => "Vietnamese",
+        }
+    }
+
+    /// Whether the game is dubbed in this language, so the launcher offers a voice pack
+    /// for it.
+    pub fn has_voice(self) -> bool {
+        matches!(self, Self::Cn | Self::En | Self::Kr | Self::Jp)
+    }
+
+    pub fn voice_languages() -> impl Iterator<Item = Self> {
+        Self::ALL.into_iter().filter(|lang| lang.has_voice())
+    }
+
+    /// Parses a text language, which can be any of them.
+    pub fn parse_text(code: &str) -> Result<Self> {
+        code.parse()
+    }
+
+    /// Parses a voice language, failing with [`PatcherError::NoVoice`] for a language
+    /// that is only shown as text.
+    pub fn parse_voice(code: &str) -> Result<Self> {
+        let lang: Self = code.parse()?;
+        if !lang.has_voice() {
+            return Err(PatcherError::NoVoice(lang));
+        }
+        Ok(lang)
+    }
+
+    /// Parses `TEXT/VOICE`, e.g. `en/jp`, or a single code for both, e.g. `en`. Returns
+    /// the text and the voice language.
+    pub fn parse_pair(spec: &str) -> Result<(Self, Self)> {
+        let (text, voice) = spec.split_once('/').unwrap_or((spec, spec));
+        Ok((Self::parse_text(text)?, Self::parse_voice(voice)?))
+    }
+}
+
+impl FromStr for Language {
+    type Err = PatcherError;
+
+    fn from_str(code: &str) -> Result<Self> {
+        Self::from_code(code).ok_or_else(|| PatcherError::InvalidLanguage(code.to_string()))
+    }
+}
+
+/// The code, as the rows and the command line spell it.
+impl fmt::Display for Language {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
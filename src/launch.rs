@@ -0,0 +1,81 @@
+use std::{ffi::OsString, path::Path, process::Command};
+
+use anyhow::Context;
+
+use hsr_lang_patcher::game::get_game_executable;
+
+use crate::Result;
+
+#[cfg(windows)]
+const ERROR_ELEVATION_REQUIRED: i32 = 740;
+
+pub fn launch_game(design_data_path: &Path, args: &[OsString]) -> Result<()> {
+    let exe = get_game_executable(design_data_path).context(
+        "Could not find StarRail.exe, the game can only be launched from a full install",
+    )?;
+    let game_dir = exe.parent().unwrap_or(design_data_path);
+
+    match Command::new(&exe).args(args).current_dir(game_dir).spawn() {
+        Ok(_) => Ok(()),
+        #[cfg(windows)]
+        Err(e) if e.raw_os_error() == Some(ERROR_ELEVATION_REQUIRED) => {
+            windows::launch_elevated(&exe, args, game_dir)
+        }
+        Err(e) => Err(e).with_context(|| format!("Failed to launch '{}'", exe.display())),
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::{
+        ffi::{OsStr, OsString},
+        io,
+        os::windows::ffi::OsStrExt,
+        path::Path,
+        ptr,
+    };
+
+    use windows_sys::Win32::UI::{Shell::ShellExecuteW, WindowsAndMessaging::SW_SHOWNORMAL};
+
+    use crate::Result;
+
+    fn wide(s: impl AsRef<OsStr>) -> Vec<u16> {
+        s.as_ref().encode_wide().chain(Some(0)).collect()
+    }
+
+    /// `CreateProcess` refuses executables that request elevation, so go through the shell
+    /// which shows the UAC prompt instead.
+    pub fn launch_elevated(exe: &Path, args: &[OsString], game_dir: &Path) -> Result<()> {
+        let mut params = OsString::new();
+        for (i, arg) in args.iter().enumerate() {
+            if i > 0 {
+                params.push(" ");
+            }
+            if arg.encode_wide().any(|c| c == u16::from(b' ')) {
+                params.push("\"");
+                params.push(arg);
+                params.push("\"");
+            } else {
+                params.push(arg);
+            }
+        }
+
+        let result = unsafe {
+            ShellExecuteW(
+                ptr::null_mut(),
+                wide("runas").as_ptr(),
+                wide(exe).as_ptr(),
+                wide(params).as_ptr(),
+                wide(game_dir).as_ptr(),
+                SW_SHOWNORMAL,
+            )
+        };
+
+        // Values above 32 indicate success
+        if result as usize > 32 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error().into())
+        }
+    }
+}
@@ -0,0 +1,57 @@
+pub mod allowed_language;
+#[cfg(feature = "fs")]
+pub mod backup;
+#[cfg(feature = "fs")]
+pub mod checksum;
+#[cfg(feature = "fs")]
+pub mod compression;
+pub mod design_index;
+#[cfg(feature = "fs")]
+pub mod detect;
+pub mod error;
+#[cfg(feature = "fs")]
+pub mod game;
+#[cfg(feature = "fs")]
+pub mod index_cache;
+pub mod infer;
+#[cfg(feature = "fs")]
+pub mod insert;
+#[cfg(feature = "fs")]
+pub mod integrity;
+pub mod language;
+#[cfg(feature = "fs")]
+pub mod lock;
+#[cfg(feature = "fs")]
+pub mod manifest;
+pub mod master_index;
+#[cfg(feature = "fs")]
+pub mod patch_file;
+#[cfg(feature = "fs")]
+pub mod paths;
+#[cfg(feature = "fs")]
+pub mod pipeline;
+#[cfg(feature = "pyo3")]
+mod python;
+#[cfg(feature = "fs")]
+pub mod rebuild;
+pub mod recipe;
+#[cfg(feature = "fs")]
+pub mod report;
+pub mod schema;
+#[cfg(feature = "scripting")]
+pub mod script;
+#[cfg(feature = "fs")]
+pub mod state;
+pub mod template;
+#[cfg(feature = "testkit")]
+pub mod testkit;
+#[cfg(feature = "fs")]
+pub mod text_map;
+pub mod timings;
+#[cfg(feature = "fs")]
+pub mod transform;
+pub mod warning;
+
+pub use error::{ErrorKind, PatcherError};
+
+pub type Result<T, E = PatcherError> = std::result::Result<T, E>;
@@ -0,0 +1,96 @@
+//! A lock file per install, so two runs can't interleave their writes to the same
+//! containers, e.g. a scheduled task firing while the patcher is open. The operating
+//! system releases the lock when the process exits, even after a crash, so a leftover
+//! file never blocks a later run.
+
+use std::{
+    fs::{self, File, TryLockError},
+    io::Write,
+    path::{Path, PathBuf},
+    process, thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    PatcherError, Result,
+    game::{Game, write_error},
+    paths::long_path,
+};
+
+const LOCK_FILE: &str = "hsr-lang-patcher.lock";
+/// How often a waiting run checks whether the lock was released.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Held while a run may write to the install, released when dropped.
+#[derive(Debug)]
+pub struct InstallLock {
+    _file: File,
+}
+
+impl Game {
+    /// Next to the state file, see [`Game::state_path`].
+    pub fn lock_path(&self) -> PathBuf {
+        self.local_backup_dir().with_file_name(LOCK_FILE)
+    }
+
+    /// Takes the install's lock, failing with [`PatcherError::InstallLocked`] if another
+    /// run holds it.
+    pub fn try_lock(&self) -> Result<InstallLock> {
+        let path = self.lock_path();
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(long_path(&path))
+            .map_err(|source| write_error(&path, 0, source))?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(PatcherError::InstallLocked {
+                    pid: holder_pid(&path),
+                    path,
+                });
+            }
+            Err(TryLockError::Error(source)) => return Err(write_error(&path, 0, source)),
+        }
+
+        // Only for the message of a run that has to wait, the lock itself is what counts
+        let _ = file
+            .set_len(0)
+            .and_then(|_| (&file).write_all(process::id().to_string().as_bytes()));
+        Ok(InstallLock { _file: file })
+    }
+
+    /// Like [`Game::try_lock`], but waits up to `timeout` for another run to finish.
+    /// `waiting` is called once if it has to wait, with the PID of the other run if known.
+    pub fn lock(
+        &self,
+        timeout: Duration,
+        waiting: impl FnOnce(Option<u32>),
+    ) -> Result<InstallLock> {
+        let start = Instant::now();
+        let mut waiting = Some(waiting);
+        loop {
+            match self.try_lock() {
+                Err(PatcherError::InstallLocked { pid, .. }) if start.elapsed() < timeout => {
+                    if let Some(waiting) = waiting.take() {
+                        waiting(pid);
+                    }
+                    thread::sleep(POLL_INTERVAL);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// The PID the holder wrote into the lock file. Windows doesn't let other processes read
+/// a locked file, so there it is usually unknown.
+fn holder_pid(path: &Path) -> Option<u32> {
+    fs::read_to_string(long_path(path))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
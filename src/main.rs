@@ -1,23 +1,71 @@
 use std::{
     env,
-    fs::{self, File},
-    io::{Seek, SeekFrom, Write, stdin, stdout},
+    ffi::OsString,
+    io::{IsTerminal, Write, stdin, stdout},
     path::{Path, PathBuf},
     process,
+    time::Duration,
 };
 
 use anyhow::{Context, anyhow};
-use crossterm::{ExecutableCommand, style::Stylize, terminal::SetTitle};
+use crossterm::{ExecutableCommand, terminal::SetTitle};
+use inquire::{Confirm, InquireError};
+use serde_json::json;
+
+use hsr_lang_patcher::{
+    ErrorKind, PatcherError,
+    allowed_language::{ListMode, Voices, check_combination, unlisted_languages},
+    detect::{default_game_path, is_game_running, running_game_path, wait_for_game_exit},
+    game::{ALLOWED_LANGUAGE_HASH, ClientRegion, Game, Platform, get_game_executable},
+    index_cache,
+    integrity::Integrity,
+    language::Language,
+    lock::InstallLock,
+    paths::{canonical_path, clear_read_only},
+    pipeline::{PatchPipeline, ProgressObserver},
+    report::PatchReport,
+    timings,
+    warning::WarningKind,
+};
 
 use crate::{
-    allowed_language::{AllowedLanguage, AllowedLanguageRow},
-    args::Args,
-    design_index::DesignIndex,
+    args::{Args, BackupAction, Command},
+    config::Config,
+    hooks::{Hook, HookContext},
+    i18n::Msg,
+    output::{CurrentStage, StatusLine},
+    update::Release,
 };
 
-mod allowed_language;
 mod args;
-mod design_index;
+mod backups;
+mod batch;
+mod browser;
+mod config;
+mod container;
+mod crash;
+mod daemon;
+mod diff;
+mod format_report;
+mod hooks;
+mod i18n;
+mod install;
+mod langdata;
+mod launch;
+#[cfg(windows)]
+mod notifications;
+mod offline;
+mod output;
+mod permissions;
+mod repair;
+mod roundtrip;
+mod serve;
+mod status;
+mod task;
+mod tray;
+mod tui;
+mod update;
+mod wizard;
 
 pub type Result<T> = anyhow::Result<T>;
 
@@ -30,146 +78,753 @@ fn print_banner() {
 }
 
 fn main() {
-    print_banner();
-    let _ = stdout().execute(SetTitle(format!(
-        "{} v{} | Made by nie",
-        env!("CARGO_PKG_NAME"),
-        env!("CARGO_PKG_VERSION")
-    )));
+    let args = Args::parse().context(ErrorKind::InvalidArguments);
+    let (json, quiet, no_color, ui_lang, pause) = match &args {
+        Ok(args) => (
+            args.json,
+            args.quiet,
+            args.no_color,
+            args.ui_lang.clone(),
+            args.pause,
+        ),
+        Err(_) => {
+            let has_flag = |flags: &[&str]| {
+                env::args_os()
+                    .take_while(|arg| arg != "--")
+                    .any(|arg| flags.iter().any(|flag| arg == *flag))
+            };
+            let pause = if has_flag(&["--no-pause"]) {
+                Some(false)
+            } else {
+                has_flag(&["--pause"]).then_some(true)
+            };
+            (
+                has_flag(&["--json"]),
+                has_flag(&["--quiet", "-q"]),
+                has_flag(&["--no-color"]),
+                None,
+                pause,
+            )
+        }
+    };
+    // A window that closes on exit would take errors with it, e.g. after a double click or
+    // a folder dropped on the exe, while a terminal keeps them
+    let should_pause =
+        pause.unwrap_or_else(|| !json && !quiet && !args::noninteractive() && owns_console());
+    crash::install(should_pause);
+
+    // An invalid `--ui-lang` is reported like any other argument error, in English
+    let args = i18n::init(ui_lang.as_deref())
+        .context(ErrorKind::InvalidArguments)
+        .and(args);
+    output::init(no_color || json);
+
+    if !json && !quiet {
+        print_banner();
+        if output::is_terminal() {
+            let _ = stdout().execute(SetTitle(format!(
+                "{} v{} | Made by nie",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            )));
+        }
+    }
+
+    let show_timings = args.as_ref().is_ok_and(|args| args.timings);
+    if show_timings {
+        timings::enable();
+    }
+    if args.as_ref().is_ok_and(|args| args.no_cache) {
+        index_cache::disable();
+    }
 
-    let should_pause = env::args().len() == 1;
+    let update_check = args
+        .as_ref()
+        .is_ok_and(|_| !quiet && Config::load().is_ok_and(|c| c.check_updates))
+        .then(update::spawn_check);
+
+    let result = args.and_then(run);
+    let stages = timings::take();
+    if show_timings && !json {
+        print_timings(&stages);
+    }
 
-    match run(should_pause) {
-        Ok(_) => process::exit(0),
+    if let Some(release) = update_check.and_then(|handle| handle.join().ok().flatten()) {
+        print_update_notice(&release);
+    }
+
+    match result {
+        Ok(report) => {
+            if json {
+                let mut message = json!({ "status": "ok" });
+                if let Some(report) = report {
+                    message["report"] = json!(report);
+                }
+                if show_timings {
+                    message["timings"] = timings_json(&stages);
+                }
+                println!("{message}");
+            }
+
+            if should_pause {
+                wait_for_exit();
+            }
+
+            process::exit(0)
+        }
         Err(e) => {
-            eprintln!("{}: {:?}", "error".red(), e);
+            let kind = error_kind(&e);
+
+            if json {
+                let mut message = json!({
+                    "status": "error",
+                    "kind": kind,
+                    "exit_code": kind.exit_code(),
+                    "message": format!("{e:#}"),
+                });
+                if let Some(stage) = JSON_STAGE.get() {
+                    message["stage"] = json!(stage);
+                }
+                if show_timings {
+                    message["timings"] = timings_json(&stages);
+                }
+                println!("{message}");
+            } else if quiet {
+                let message = format!("{e:#}");
+                let message: Vec<&str> = message.lines().map(str::trim).collect();
+                eprintln!("error: {}", message.join(" "));
+            } else {
+                // The detailed chain stays untranslated, so lead with a localized summary
+                match i18n::error_kind(kind) {
+                    Some(summary) if !i18n::is_english() => {
+                        eprintln!("{}: {summary}", output::error());
+                        if kind == ErrorKind::GameNotFound {
+                            eprintln!("{}", i18n::tr(Msg::GameNotFoundHelp));
+                        }
+                        eprintln!("\n{e:?}");
+                    }
+                    _ => eprintln!("{}: {:?}", output::error(), e),
+                }
+            }
 
             if should_pause {
                 wait_for_exit();
             }
 
-            process::exit(1)
+            process::exit(kind.exit_code())
         }
     }
 }
 
-pub fn run(should_pause: bool) -> Result<()> {
-    let args = Args::parse()?;
-    let design_data_path = get_design_data_path(args.game_path.as_deref())?;
+/// Stage of the patch run `--json` is in, so an error can name it.
+static JSON_STAGE: CurrentStage = CurrentStage::new();
 
-    let m_design_v_path = design_data_path.join("M_DesignV.bytes");
-    let index_hash = get_index_hash(&fs::read(&m_design_v_path)?).with_context(|| {
-        format!(
-            "Failed to get index hash. Is '{}' the correct directory?",
-            m_design_v_path.display()
-        )
-    })?;
-
-    let design_v_data = fs::read(design_data_path.join(format!("DesignV_{index_hash}.bytes")))?;
-    let design_index = DesignIndex::parse(&design_v_data).context("Failed to parse DesignV")?;
+fn error_kind(error: &anyhow::Error) -> ErrorKind {
+    if let Some(kind) = error.downcast_ref::<ErrorKind>() {
+        return *kind;
+    }
 
-    let (data_entry, file_entry) = design_index
-        .find_by_hash(-515329346)
-        .context("Failed to find the correct excel lol")?;
+    for cause in error.chain() {
+        if let Some(error) = cause.downcast_ref::<PatcherError>() {
+            return error.kind();
+        }
 
-    let bytes_path = design_data_path.join(format!("{}.bytes", file_entry.file_hash));
+        if let Some(InquireError::OperationCanceled | InquireError::OperationInterrupted) =
+            cause.downcast_ref::<InquireError>()
+        {
+            return ErrorKind::Cancelled;
+        }
+    }
 
-    let allowed_language = AllowedLanguage::new(data_entry, &bytes_path);
-    let mut allowed_language_rows = allowed_language.parse()?;
+    ErrorKind::Other
+}
 
-    let (text_lang, voice_lang) = args.get_or_prompt_languages()?;
-    patch_languages(&mut allowed_language_rows, text_lang, voice_lang)?;
+/// On stderr, so it doesn't get mixed into output that is piped somewhere.
+fn print_timings(stages: &[(&str, Duration)]) {
+    eprintln!("\nTimings:");
+    for (stage, duration) in stages {
+        eprintln!("  {stage:<16} {:>9.1} ms", duration.as_secs_f64() * 1000.0);
+    }
+    let total: Duration = stages.iter().map(|(_, duration)| *duration).sum();
+    eprintln!("  {:<16} {:>9.1} ms", "total", total.as_secs_f64() * 1000.0);
+}
 
-    let data = allowed_language.serialize_rows(allowed_language_rows)?;
+fn timings_json(stages: &[(&str, Duration)]) -> serde_json::Value {
+    stages
+        .iter()
+        .map(|(stage, duration)| json!({ "stage": stage, "ms": duration.as_secs_f64() * 1000.0 }))
+        .collect()
+}
 
-    write_data(
-        &bytes_path,
-        data_entry.offset as u64,
-        &data,
-        data_entry.size as usize,
-    )?;
+fn print_update_notice(release: &Release) {
+    println!(
+        "\n{} {}: {}",
+        output::highlight("Update:"),
+        i18n::tr(Msg::UpdateAvailable).replace("{version}", &release.tag_name),
+        release.html_url
+    );
+    if let Some(line) = release.supported_versions() {
+        println!("{line}");
+    }
+}
 
-    println!("{}", "Done".bold().green());
+pub fn run(mut args: Args) -> Result<Option<PatchReport>> {
+    crash::log(format!("Command: {:?}", args.command));
+    if wizard::should_run(&args) {
+        wizard::retry(|| wizard::run(&mut args))?;
+    }
 
-    if should_pause {
-        wait_for_exit();
+    // Loose files are read directly, without looking for a game around them
+    if let Some(path) = args.game_path.as_deref().filter(|path| path.is_file())
+        && matches!(
+            args.command,
+            Command::Find | Command::DumpIndex | Command::Hexdump | Command::Infer
+        )
+    {
+        container::run_on_file(path, &args)?;
+        return Ok(None);
     }
 
-    Ok(())
+    match args.command {
+        Command::Patch if args.serve => serve::run()?,
+        Command::Patch
+        | Command::Repair
+        | Command::FixPermissions
+        | Command::ExportPatch
+        | Command::ApplyPatch
+        | Command::Diff
+        | Command::Rebuild
+        | Command::AddEntry
+        | Command::Checksums
+        | Command::Find
+        | Command::DumpIndex
+        | Command::Hexdump
+        | Command::Infer
+        | Command::Extract
+        | Command::Inject
+        | Command::Backups(_)
+        | Command::Status
+        | Command::SetField
+        | Command::Browse
+        | Command::ReportFormat
+        | Command::SnapshotLangdata
+        | Command::VerifyRoundtrip => return run_on_game(args),
+        #[cfg(feature = "scripting")]
+        Command::RunScript => return run_on_game(args),
+        Command::Tray => tray::run()?,
+        Command::Daemon => daemon::run(&args)?,
+        Command::InstallTask => task::install(args.schedule.as_deref(), args.daemon, args.notify)?,
+        Command::UninstallTask => task::uninstall()?,
+        Command::Install => install::install(&args)?,
+        Command::DiffIndex => diff::diff_index(&args)?,
+        Command::Template => container::template(&args)?,
+        Command::Batch => batch::run(&args)?,
+    }
+    Ok(None)
 }
 
-fn patch_languages(
-    rows: &mut [AllowedLanguageRow],
-    text_lang: &str,
-    voice_lang: &str,
-) -> Result<()> {
-    for (area, lang, voice) in [
-        ("os", text_lang, false),
-        ("cn", voice_lang, true),
-        ("os", voice_lang, true),
-        ("cn", text_lang, false),
-    ] {
-        rows.iter_mut()
-            .find(|row| {
-                row.area() == Some(area) && if voice { row.is_voice() } else { row.is_text() }
-            })
-            .with_context(|| format!("{} AllowedLanguageRow not found", area.to_uppercase()))?
-            .update_language(lang);
+/// Commands that need a game install. Only patching returns a report.
+fn run_on_game(args: Args) -> Result<Option<PatchReport>> {
+    let config = Config::load()?;
+
+    let platform = match args.platform {
+        Some(platform) => platform,
+        None if args.last => config.platform,
+        None => Platform::default(),
+    };
+    // `--json` names the stage a run failed in, a terminal shows the one that is running
+    let status = StatusLine::new(!args.quiet && !args.json);
+    let observer: &dyn ProgressObserver = if args.json { &JSON_STAGE } else { &status };
+    let pipeline = PatchPipeline::new(observer);
+    let game_path = match args.game_path {
+        Some(ref path) => path.clone(),
+        None if args.from_process => running_game_path()
+            .context("StarRail.exe is not running and no previous run was found")
+            .context(ErrorKind::GameNotFound)?,
+        None if args.last => config
+            .game_path
+            .clone()
+            .context("No saved game path. Run a normal patch first")?,
+        None => default_game_path()?,
+    };
+    let (game_path, design_data_path) = match pipeline.locate(&game_path, platform) {
+        Ok(design_data_path) => (game_path, design_data_path),
+        Err(e @ PatcherError::GameNotFound { .. }) => {
+            // Most failed runs are a wrong folder, offer the installs that can be found
+            if !wizard::is_interactive(&args) {
+                return Err(e.into());
+            }
+            status.clear();
+            println!(
+                "{}: '{}'\n",
+                i18n::tr(Msg::WizardNotGameFolder),
+                game_path.display()
+            );
+            let found = wizard::detected_installs(platform);
+            if found.is_empty() {
+                return Err(e.into());
+            }
+            let game_path = wizard::retry(|| wizard::prompt_game_path(&found, platform))?;
+            let design_data_path = pipeline.locate(&game_path, platform)?;
+            (game_path, design_data_path)
+        }
+        Err(e) => return Err(e.into()),
+    };
+    crash::log(format!("Game path: {}", game_path.display()));
+    let mut game = pipeline.index(design_data_path)?;
+    game.full_backups = args.full_backup || config.full_backups;
+    game.all_regions = args.all_regions || config.all_regions;
+    game.strict = (args.strict || config.strict) && !args.lenient;
+    game.backup_root = args.backup_dir.clone().or(config.backup_dir.clone());
+    crash::set_game(&game);
+    if !game.is_installed() && !args.quiet {
+        print_copy_notice(&args);
     }
+    // The TUI and the browser show the game themselves and only write on request,
+    // fix-permissions asks itself
+    if !args.yes
+        && !args.tui
+        && args.command != Command::Browse
+        && args.command != Command::FixPermissions
+        && !is_read_only(&args)
+        && stdin().is_terminal()
+        && output::is_terminal()
+    {
+        confirm_install(&game, &game_path)?;
+    }
+    // The lock file sits in the game folder, which may be what can't be written yet
+    let _lock = if is_read_only(&args) || args.command == Command::FixPermissions {
+        None
+    } else {
+        Some(lock_install(&game, &args)?)
+    };
+
+    match args.command {
+        Command::Repair => repair::repair(&game, args.quiet)?,
+        Command::FixPermissions => permissions::fix_permissions(&game, &args)?,
+        Command::ExportPatch => offline::export(&game, &args)?,
+        Command::ApplyPatch => offline::apply(&game, &args)?,
+        Command::Diff => diff::diff(&game)?,
+        Command::Rebuild => container::rebuild(&game, args.quiet)?,
+        Command::AddEntry => container::add_entry(&game, &args)?,
+        Command::Checksums => container::checksums(&game)?,
+        Command::Find => container::find(&game.design_index()?, &args.query)?,
+        Command::DumpIndex => container::dump_index(&game.design_index()?, &args)?,
+        Command::Hexdump => {
+            let data = game.read_entry(args.excel.unwrap_or(ALLOWED_LANGUAGE_HASH))?;
+            container::hexdump(&data, 0, &args)?
+        }
+        Command::Infer => {
+            let hash = args.excel.unwrap_or(ALLOWED_LANGUAGE_HASH);
+            container::infer(Some(hash), &game.read_entry(hash)?, &args)?
+        }
+        Command::Extract => container::extract(&game, &args)?,
+        Command::Inject => container::inject(&game, &args)?,
+        Command::Backups(action) => backups::run(&game, action, &args)?,
+        Command::Status => status::status(&game, args.quiet)?,
+        Command::SetField => container::set_field(&game, &args)?,
+        Command::Browse => browser::run(&game, &args)?,
+        Command::ReportFormat => format_report::report_format(&game, &args)?,
+        Command::SnapshotLangdata => langdata::snapshot(&game, &args)?,
+        Command::VerifyRoundtrip => roundtrip::verify_roundtrip(&game, &args)?,
+        #[cfg(feature = "scripting")]
+        Command::RunScript => container::run_script(&game, &args)?,
+        _ if args.tui => tui::run(&game, args.force)?,
+        _ => return patch(&game, &pipeline, args, &config, game_path, platform).map(Some),
+    }
+    Ok(None)
+}
 
-    Ok(())
+/// How long a run waits for another one that changes the same install.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// Waits for another run that changes the same install, e.g. a scheduled task, before
+/// anything is written. Held until the command is done.
+fn lock_install(game: &Game, args: &Args) -> Result<InstallLock> {
+    Ok(game.lock(LOCK_TIMEOUT, |pid| {
+        if !args.quiet && !args.json {
+            eprintln!("{}", i18n::tr(Msg::WaitingForLock));
+        }
+        crash::log(format!("Waiting for the install lock, held by {pid:?}"));
+    })?)
 }
 
-fn get_index_hash(data: &[u8]) -> Result<String> {
-    let mut hash = [0u8; 16];
-    let mut index = 0;
-    for i in 0..4 {
-        let offset = 0x1C + (i * 4);
-        let chunk = data
-            .get(offset..offset + 4)
-            .context("M_DesignV.bytes is too short")?;
-        for &byte in chunk.iter().rev() {
-            hash[index] = byte;
-            index += 1;
+/// How often `--wait-for-game-exit` checks whether the game is still running.
+const GAME_EXIT_INTERVAL: Duration = Duration::from_secs(2);
+
+fn patch(
+    game: &Game,
+    pipeline: &PatchPipeline,
+    args: Args,
+    config: &Config,
+    game_path: PathBuf,
+    platform: Platform,
+) -> Result<PatchReport> {
+    if args.verify {
+        timings::stage("integrity check", || check_integrity(game, args.quiet))?;
+    }
+    ensure_writable(game, &args)?;
+
+    // The command line and the environment go before the saved profile
+    let prompted = args.languages.is_none() && !args.last;
+    let choose = || -> Result<_> {
+        let (text_lang, voices) = match &args.languages {
+            Some(languages) => (languages.text, languages.voice.into()),
+            None if args.last => config.profile()?,
+            None => {
+                let (text_lang, voice_lang) = args.get_or_prompt_languages()?;
+                (text_lang, voice_lang.into())
+            }
+        };
+        let voices = args.voices(voices);
+        confirm_combination(game, text_lang, voices, args.force)?;
+        let mode = list_mode(game, &args, config, text_lang, voices)?;
+        Ok((text_lang, voices, mode))
+    };
+    // Answers from a prompt can be changed, the command line has to be fixed by hand
+    let (text_lang, voices, mode) = if prompted && wizard::is_interactive(&args) {
+        wizard::retry(choose)?
+    } else {
+        choose()?
+    };
+
+    let hooks = !args.no_hooks;
+    let hook_context = |report| HookContext {
+        game,
+        game_path: &game_path,
+        text_lang,
+        voices,
+        report,
+    };
+    if hooks {
+        hooks::run(
+            Hook::PrePatch,
+            &config.pre_patch,
+            &hook_context(None),
+            args.quiet,
+            args.json,
+        )
+        .context(ErrorKind::Cancelled)?;
+    }
+
+    // The game only reads the excels at startup, so the patch takes effect on the next launch
+    if args.wait_for_game_exit && is_game_running() {
+        if !args.quiet {
+            eprintln!("{}", i18n::tr(Msg::WaitingForGame));
         }
+        crash::log("Waiting for StarRail.exe to exit");
+        wait_for_game_exit(GAME_EXIT_INTERVAL);
+    }
+
+    crash::log(format!("Patching text {text_lang}, voice {voices}"));
+    let mut report = pipeline.patch(game, text_lang, voices, mode)?;
+    crash::log(format!("Wrote {} bytes", report.bytes_written));
+    if !args.tweaks.is_empty() {
+        game.apply_recipes(&args.tweaks)?;
+        if !args.quiet {
+            for recipe in &args.tweaks {
+                println!("Applied {}: {}", recipe.name(), recipe.description());
+            }
+        }
+    }
+
+    // A post hook can't undo the patch, so its failure is only a warning
+    if hooks
+        && let Err(e) = hooks::run(
+            Hook::PostPatch,
+            &config.post_patch,
+            &hook_context(Some(&report)),
+            args.quiet,
+            args.json,
+        )
+    {
+        report
+            .warnings
+            .push(WarningKind::Hook, format_args!("{e:#}"));
     }
-    Ok(hex::encode(hash))
+
+    save_profile(game_path, platform, text_lang, voices, args.quiet);
+
+    let retention = config.retention();
+    if !retention.is_unlimited()
+        && let Err(e) = backups::prune(game, &retention, args.quiet)
+    {
+        report.warnings.push(
+            WarningKind::Backups,
+            format_args!("Failed to prune backups: {e:#}"),
+        );
+    }
+
+    if !args.json {
+        if !args.quiet {
+            output::print_report(&report);
+        }
+        println!("{}", output::success(i18n::tr(Msg::Done)));
+    }
+
+    if args.launch || config.launch {
+        let game_args = if args.game_args.is_empty() {
+            config.launch_args.iter().map(OsString::from).collect()
+        } else {
+            args.game_args
+        };
+        launch::launch_game(&game.design_data_path, &game_args)?;
+        if !args.quiet {
+            println!("{}", i18n::tr(Msg::LaunchedGame));
+        }
+    }
+
+    Ok(report)
 }
 
-fn get_design_data_path(arg: Option<&str>) -> Result<PathBuf> {
-    let path = arg.map_or(env::current_dir()?, |p| PathBuf::from(p));
+/// Offers to clear the read-only attribute of the game files before anything is asked or
+/// written. A read-only drive, a declined prompt or `--yes` fails with what to change.
+fn ensure_writable(game: &Game, args: &Args) -> Result<()> {
+    let files = match game.check_writable() {
+        Err(PatcherError::ReadOnlyFile { .. }) if wizard::is_interactive(args) => {
+            game.read_only_files()
+        }
+        result => return Ok(result?),
+    };
 
-    if path.join("StarRail.exe").is_file() {
-        return Ok(path.join("StarRail_Data/StreamingAssets/DesignData/Windows"));
+    println!("{}", i18n::tr(Msg::ReadOnlyFiles));
+    for path in &files {
+        println!("  {}", path.display());
+    }
+    let clear = Confirm::new(i18n::tr(Msg::ClearReadOnly))
+        .with_default(true)
+        .prompt()?;
+    if !clear {
+        return Ok(game.check_writable()?);
     }
+    for path in &files {
+        clear_read_only(path).with_context(|| {
+            format!(
+                "Failed to clear the read-only attribute of '{}'",
+                path.display()
+            )
+        })?;
+        crash::log(format!("Cleared read-only: {}", path.display()));
+    }
+    Ok(())
+}
 
-    if path.join("M_DesignV.bytes").is_file() {
-        return Ok(path);
+/// Refuses languages the game doesn't offer for a row unless forced, since the result
+/// looks like a game bug. A forced combination ends up in the report's warnings.
+pub(crate) fn confirm_combination(
+    game: &Game,
+    text: Language,
+    voices: impl Into<Voices>,
+    force: bool,
+) -> Result<()> {
+    let warnings = check_combination(&game.original_rows()?, game.client_region(), text, voices);
+    if warnings.is_empty() || force {
+        return Ok(());
     }
 
+    let message = warnings
+        .iter()
+        .map(|warning| format!("- {warning}"))
+        .collect::<Vec<_>>()
+        .join("\n");
     Err(anyhow!(
-        "Could not find required files!\n\
-        Make sure to either: \n\
-        - Run this .exe from the game's root folder\n\
-        - Pass the game's root path as an argument\n\
-        - Pass the StreamingAssets/DesignData folder path as an argument"
+        "This combination isn't shipped by the game:\n{message}\n\
+        Pass --force to apply it anyway"
     ))
+    .context(ErrorKind::InvalidArguments)
+}
+
+/// With `--keep-lists`, offers to add a chosen language to the lists that lack it
+/// instead of writing a default the row doesn't allow.
+fn list_mode(
+    game: &Game,
+    args: &Args,
+    config: &Config,
+    text: Language,
+    voices: Voices,
+) -> Result<ListMode> {
+    if !args.keep_lists && !config.keep_lists {
+        return Ok(ListMode::Replace);
+    }
+
+    let unlisted = unlisted_languages(
+        &game.allowed_language().parse()?,
+        text,
+        voices,
+        game.patch_areas(),
+    );
+    // Without a prompt, Keep refuses the write with an explanation
+    if unlisted.is_empty() || args.yes {
+        return Ok(ListMode::Keep);
+    }
+
+    for (area, voice, language) in &unlisted {
+        let message = if *voice {
+            Msg::RowMissingVoice
+        } else {
+            Msg::RowMissingText
+        };
+        println!(
+            "{}",
+            i18n::tr(message)
+                .replace("{area}", &area.to_uppercase())
+                .replace("{language}", language.code())
+        );
+    }
+    let append = Confirm::new(i18n::tr(Msg::AppendLanguages))
+        .with_default(true)
+        .prompt()?;
+    if !append {
+        return Err(anyhow!(
+            "Not patched, the rows would not allow the chosen languages"
+        ))
+        .context(ErrorKind::Cancelled);
+    }
+    Ok(ListMode::Append)
 }
 
-fn write_data(file_path: &Path, offset: u64, data: &[u8], data_size: usize) -> Result<()> {
-    let mut file = File::options().read(true).write(true).open(file_path)?;
-    file.seek(SeekFrom::Start(offset))?;
-    file.write_all(data)?;
+fn is_read_only(args: &Args) -> bool {
+    matches!(
+        args.command,
+        Command::Find
+            | Command::DumpIndex
+            | Command::Hexdump
+            | Command::Infer
+            | Command::Diff
+            | Command::Extract
+            | Command::Backups(BackupAction::List)
+            | Command::Status
+            | Command::ReportFormat
+            | Command::SnapshotLangdata
+            | Command::VerifyRoundtrip
+            | Command::Checksums
+            | Command::ExportPatch
+    )
+}
 
-    if data.len() < data_size {
-        file.write_all(&vec![0; data_size - data.len()])?;
+/// Shows which install is about to be changed, since with several installs it's easy to
+/// patch the wrong copy.
+fn confirm_install(game: &Game, game_path: &Path) -> Result<()> {
+    let root = get_game_executable(&game.design_data_path)
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+        .unwrap_or_else(|| game_path.to_path_buf());
+    let unknown = i18n::tr(Msg::Unknown);
+    let region = match game.client_region() {
+        Some(region) => {
+            let name = match region {
+                ClientRegion::Os => Msg::ClientGlobal,
+                ClientRegion::Cn => Msg::ClientCn,
+            };
+            format!("{} ({})", i18n::tr(name), region.area())
+        }
+        None => unknown.to_string(),
+    };
+    let label = |msg| format!("{}:", i18n::tr(msg));
+    println!("{:<11} {}", label(Msg::InstallGame), root.display());
+    println!("{:<11} {}", "DesignData:", game.design_data_path.display());
+    println!(
+        "{:<11} {}",
+        label(Msg::InstallVersion),
+        game.game_version().as_deref().unwrap_or(unknown)
+    );
+    println!("{:<11} {region}\n", label(Msg::InstallClient));
+
+    let proceed = Confirm::new(i18n::tr(Msg::ConfirmInstall))
+        .with_default(true)
+        .prompt()?;
+    if !proceed {
+        return Err(anyhow!("Nothing was changed")).context(ErrorKind::Cancelled);
     }
+    Ok(())
+}
 
+/// Explains what works on a DesignData folder copied out of the game.
+fn print_copy_notice(args: &Args) {
+    if is_read_only(args) {
+        println!(
+            "No game executable found, inspecting the DesignData folder as a copy. \
+            Read-only commands work without the game\n"
+        );
+    } else {
+        eprintln!(
+            "{}: No game executable found, this DesignData folder is a copy and not the \
+            installed game. Changes only affect the copy and backups are kept inside it. \
+            Run the patcher on the game folder to patch in place\n",
+            output::warning()
+        );
+    }
+}
+
+fn check_integrity(game: &Game, quiet: bool) -> Result<()> {
+    let message = match game.check_integrity()? {
+        None => "No pkg_version manifest found, skipping verification".to_string(),
+        Some(Integrity::Intact) => return Ok(()),
+        Some(Integrity::Unlisted) => {
+            format!("{}.bytes is not listed in pkg_version", game.file_hash)
+        }
+        Some(Integrity::Modified) => format!(
+            "{}.bytes differs from pkg_version. It was either patched before or is corrupted",
+            game.file_hash
+        ),
+        Some(Integrity::SizeMismatch { expected, actual }) => format!(
+            "{}.bytes is {actual} bytes but pkg_version expects {expected}. \
+            The download looks broken, use \"Repair game files\" in the launcher",
+            game.file_hash
+        ),
+    };
+
+    if !quiet {
+        eprintln!("{}: {message}", output::warning());
+    }
     Ok(())
 }
 
+fn save_profile(
+    game_path: PathBuf,
+    platform: Platform,
+    text_lang: Language,
+    voices: Voices,
+    quiet: bool,
+) {
+    let result = Config::load().and_then(|mut config| {
+        config.game_path = Some(canonical_path(&game_path));
+        config.text_language = Some(text_lang.to_string());
+        config.voice_language = Some(voices.os.to_string());
+        config.cn_voice_language = voices.cn_override().map(|lang| lang.to_string());
+        config.platform = platform;
+        config.save()
+    });
+
+    if let Err(e) = result
+        && !quiet
+    {
+        eprintln!(
+            "{}: {}: {e:#}",
+            output::warning(),
+            i18n::tr(Msg::SaveProfileFailed)
+        );
+    }
+}
+
+/// Whether the console was opened for this process rather than shared with the terminal
+/// it was started from, which then is the only process attached to it.
+#[cfg(windows)]
+fn owns_console() -> bool {
+    use windows_sys::Win32::System::Console::GetConsoleProcessList;
+
+    let mut processes = [0u32; 2];
+    let count = unsafe { GetConsoleProcessList(processes.as_mut_ptr(), processes.len() as u32) };
+    count == 1
+}
+
+/// Consoles on other systems belong to a terminal that stays open.
+#[cfg(not(windows))]
+fn owns_console() -> bool {
+    false
+}
+
 fn wait_for_exit() {
-    print!("Press enter to exit");
+    print!("{}", i18n::tr(Msg::PressEnter));
     let _ = stdout().flush();
     let _ = stdin().read_line(&mut String::new());
 }
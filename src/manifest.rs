@@ -0,0 +1,203 @@
+//! SHA-256 of every region the patcher wrote, kept next to the state file of an install.
+//! With the hash of the bytes each region held before, a changed region can be told
+//! apart: the patcher's data is still there, the game put its own back, or something
+//! else wrote over it. Restores check it so they don't put old bytes over an update.
+
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    PatcherError, Result,
+    game::{Game, write_error},
+    paths::long_path,
+};
+
+const MANIFEST_FILE: &str = "hsr-lang-patcher-manifest.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Ordered by container and offset.
+    pub regions: Vec<WrittenRegion>,
+}
+
+/// A region of a container the patcher wrote.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WrittenRegion {
+    pub file_hash: String,
+    pub offset: u64,
+    pub size: usize,
+    /// Of the bytes the region held before the patcher first wrote it.
+    pub original_sha256: String,
+    /// Of the bytes the patcher wrote last.
+    pub written_sha256: String,
+    /// Unix timestamp of the last write.
+    pub written_at: u64,
+}
+
+impl WrittenRegion {
+    fn overlaps(&self, other: &Self) -> bool {
+        self.file_hash == other.file_hash
+            && self.offset < other.offset + other.size as u64
+            && other.offset < self.offset + self.size as u64
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegionStatus {
+    /// The region holds what the patcher wrote.
+    Intact,
+    /// The region holds its original bytes again or the container is gone, what a game
+    /// update or a launcher repair leaves.
+    Updated,
+    /// The region holds bytes neither the patcher nor the game wrote.
+    Modified,
+}
+
+pub(crate) fn sha256(data: &[u8]) -> String {
+    hex::encode(Sha256::digest(data))
+}
+
+impl Game {
+    /// Next to the state file, see [`Game::state_path`].
+    pub fn manifest_path(&self) -> PathBuf {
+        self.local_backup_dir().with_file_name(MANIFEST_FILE)
+    }
+
+    /// The recorded writes, empty if there are none or the file can't be parsed.
+    pub fn manifest(&self) -> Result<Manifest> {
+        let path = self.manifest_path();
+        match fs::read(long_path(&path)) {
+            Ok(data) => Ok(serde_json::from_slice(&data).unwrap_or_default()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Manifest::default()),
+            Err(source) => Err(PatcherError::Read { path, source }),
+        }
+    }
+
+    /// What the region holds now compared to what the patcher wrote.
+    pub fn region_status(&self, region: &WrittenRegion) -> Result<RegionStatus> {
+        let path = self.container_path(&region.file_hash);
+        let mut current = vec![0u8; region.size];
+        let read = File::open(long_path(&path)).and_then(|mut file| {
+            file.seek(SeekFrom::Start(region.offset))?;
+            file.read_exact(&mut current)
+        });
+        match read {
+            Ok(()) => {}
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::UnexpectedEof
+                ) =>
+            {
+                return Ok(RegionStatus::Updated);
+            }
+            Err(source) => return Err(PatcherError::Read { path, source }),
+        }
+
+        let current = sha256(&current);
+        Ok(if current == region.written_sha256 {
+            RegionStatus::Intact
+        } else if current == region.original_sha256 {
+            RegionStatus::Updated
+        } else {
+            RegionStatus::Modified
+        })
+    }
+
+    /// The recorded region at `offset` of the container, if the patcher wrote it.
+    pub(crate) fn written_region(
+        &self,
+        file_hash: &str,
+        offset: u64,
+    ) -> Result<Option<WrittenRegion>> {
+        Ok(self
+            .manifest()?
+            .regions
+            .into_iter()
+            .find(|region| region.file_hash == file_hash && region.offset == offset))
+    }
+
+    /// Fails if a region of the container the patcher wrote, or only the one at `offset`,
+    /// was since changed by something else, e.g. an update that patched the container in
+    /// place. Putting a backup back would undo that change with outdated bytes.
+    pub(crate) fn check_restorable(&self, file_hash: &str, offset: Option<u64>) -> Result<()> {
+        for region in self.manifest()?.regions {
+            if region.file_hash == file_hash
+                && offset.is_none_or(|offset| offset == region.offset)
+                && self.region_status(&region)? == RegionStatus::Modified
+            {
+                return Err(PatcherError::RegionChanged {
+                    path: self.container_path(file_hash),
+                    offset: region.offset,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds writes to the manifest. A region the patcher wrote before keeps the hash of
+    /// its original bytes as long as the bytes it replaced were the patcher's, and is
+    /// dropped once it holds them again.
+    pub(crate) fn record_writes(&self, written: Vec<WrittenRegion>) -> Result<()> {
+        if written.is_empty() {
+            return Ok(());
+        }
+        let mut manifest = self.manifest()?;
+        for mut region in written {
+            let previous = manifest
+                .regions
+                .iter()
+                .find(|recorded| recorded.overlaps(&region));
+            if let Some(previous) = previous
+                && previous.offset == region.offset
+                && previous.size == region.size
+                && previous.written_sha256 == region.original_sha256
+            {
+                region.original_sha256 = previous.original_sha256.clone();
+            }
+            manifest
+                .regions
+                .retain(|recorded| !recorded.overlaps(&region));
+            if region.written_sha256 != region.original_sha256 {
+                manifest.regions.push(region);
+            }
+        }
+        self.save_manifest(manifest)
+    }
+
+    /// Drops the regions of a container, after a backup of it was restored.
+    pub(crate) fn forget_writes(&self, file_hash: &str) -> Result<()> {
+        let mut manifest = self.manifest()?;
+        let count = manifest.regions.len();
+        manifest
+            .regions
+            .retain(|region| region.file_hash != file_hash);
+        if manifest.regions.len() == count {
+            return Ok(());
+        }
+        self.save_manifest(manifest)
+    }
+
+    fn save_manifest(&self, mut manifest: Manifest) -> Result<()> {
+        manifest
+            .regions
+            .sort_by(|a, b| (&a.file_hash, a.offset).cmp(&(&b.file_hash, b.offset)));
+        let path = self.manifest_path();
+        if manifest.regions.is_empty() {
+            return match fs::remove_file(long_path(&path)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(write_error(&path, 0, e)),
+                _ => Ok(()),
+            };
+        }
+        let data = serde_json::to_vec_pretty(&manifest).map_err(io::Error::from);
+        data.and_then(|data| fs::write(long_path(&path), data))
+            .map_err(|source| write_error(&path, 0, source))
+    }
+}
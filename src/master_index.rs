@@ -0,0 +1,113 @@
+//! `M_DesignV.bytes`, the small master file that names the current `DesignV` index.
+//! Only the index hash at 0x1C is understood, everything around it is kept as is.
+
+#[cfg(feature = "fs")]
+use std::fs;
+
+#[cfg(feature = "fs")]
+use md5::{Digest, Md5};
+
+#[cfg(feature = "fs")]
+use crate::{
+    PatcherError, Result,
+    game::{Game, read, write_error},
+    paths::long_path,
+    rebuild::replace,
+};
+
+const HASH_OFFSET: usize = 0x1C;
+const HASH_SIZE: usize = 0x10;
+
+#[derive(Debug, Clone)]
+pub struct MasterIndex {
+    /// The whole file, with the hash region kept in sync with `index_hash`.
+    data: Vec<u8>,
+    pub index_hash: [u8; HASH_SIZE],
+}
+
+impl MasterIndex {
+    /// Returns `None` if the file is too short to hold the index hash.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let stored = data.get(HASH_OFFSET..HASH_OFFSET + HASH_SIZE)?;
+
+        // The hash is stored as four 4 byte chunks, each in reverse order
+        let mut index_hash = [0u8; HASH_SIZE];
+        for (chunk, stored) in index_hash.chunks_mut(4).zip(stored.chunks(4)) {
+            chunk.copy_from_slice(stored);
+            chunk.reverse();
+        }
+
+        Some(Self {
+            data: data.to_vec(),
+            index_hash,
+        })
+    }
+
+    pub fn index_hash_hex(&self) -> String {
+        hex::encode(self.index_hash)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = self.data.clone();
+        for (stored, chunk) in data[HASH_OFFSET..HASH_OFFSET + HASH_SIZE]
+            .chunks_mut(4)
+            .zip(self.index_hash.chunks(4))
+        {
+            stored.copy_from_slice(chunk);
+            stored.reverse();
+        }
+        data
+    }
+}
+
+/// What [`Game::write_index`] had to change in `M_DesignV.bytes`.
+#[derive(Debug, Clone, Default)]
+pub struct MasterUpdate {
+    /// The index is named after the MD5 of its contents, so it was renamed.
+    pub renamed_to: Option<String>,
+    /// The index size changed. Whether the file stores the size somewhere isn't known, so
+    /// nothing but the hash was updated.
+    pub size_unknown: bool,
+}
+
+#[cfg(feature = "fs")]
+impl Game {
+    /// Replaces the `DesignV` index and keeps `M_DesignV.bytes` consistent with it: if the
+    /// index hash is the MD5 of its contents the index is renamed and the hash updated.
+    /// Nothing else in the master file is touched, see [`MasterUpdate::size_unknown`].
+    pub fn write_index(&self, old_index: &[u8], new_index: &[u8]) -> Result<MasterUpdate> {
+        let master_path = self.design_data_path.join("M_DesignV.bytes");
+        let mut master =
+            MasterIndex::parse(&read(&master_path)?).ok_or_else(|| PatcherError::IndexHash {
+                path: master_path.clone(),
+            })?;
+        let mut update = MasterUpdate {
+            size_unknown: old_index.len() != new_index.len(),
+            ..Default::default()
+        };
+
+        let content_addressed = Md5::digest(old_index).as_slice() == master.index_hash;
+        let mut index_path = self.index_path();
+        if content_addressed && old_index != new_index {
+            master.index_hash = Md5::digest(new_index).into();
+            let new_hash = master.index_hash_hex();
+            index_path = self
+                .design_data_path
+                .join(format!("DesignV_{new_hash}.bytes"));
+            update.renamed_to = Some(new_hash);
+        }
+
+        self.ensure_container_backup(&format!("DesignV_{}", self.index_hash))?;
+        self.ensure_container_backup("M_DesignV")?;
+
+        // The master file is only pointed at the new index once it exists
+        replace(&index_path, new_index)?;
+        if update.renamed_to.is_some() {
+            replace(&master_path, &master.serialize())?;
+            let old_path = self.index_path();
+            fs::remove_file(long_path(&old_path))
+                .map_err(|source| write_error(&old_path, 0, source))?;
+        }
+        Ok(update)
+    }
+}
@@ -0,0 +1,44 @@
+//! Native toast notifications, so the tray and the daemon can report what they did
+//! while no console is visible.
+
+use windows::{
+    Data::Xml::Dom::XmlDocument,
+    UI::Notifications::{ToastNotification, ToastNotificationManager},
+    core::HSTRING,
+};
+
+use crate::{
+    Result,
+    i18n::{self, Msg},
+};
+
+/// Toasts need the ID of an app registered with the shell. The patcher is a loose exe,
+/// so its toasts are shown for PowerShell, which every Windows install has.
+const APP_ID: &str =
+    "{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\\WindowsPowerShell\\v1.0\\powershell.exe";
+
+pub fn show(message: &str, is_error: bool) -> Result<()> {
+    let title = if is_error {
+        format!("{}: {}", env!("CARGO_PKG_NAME"), i18n::tr(Msg::Error))
+    } else {
+        env!("CARGO_PKG_NAME").to_string()
+    };
+    let xml = format!(
+        "<toast><visual><binding template=\"ToastGeneric\"><text>{}</text><text>{}</text></binding></visual></toast>",
+        escape(&title),
+        escape(message)
+    );
+
+    let document = XmlDocument::new()?;
+    document.LoadXml(&HSTRING::from(xml))?;
+    let toast = ToastNotification::CreateToastNotification(&document)?;
+    ToastNotificationManager::CreateToastNotifierWithId(&HSTRING::from(APP_ID))?.Show(&toast)?;
+    Ok(())
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
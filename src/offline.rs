@@ -0,0 +1,48 @@
+use std::{fs, path::Path};
+
+use anyhow::Context;
+
+use hsr_lang_patcher::{game::Game, patch_file::PatchFile};
+
+use crate::{
+    Result,
+    args::Args,
+    i18n::{self, Msg},
+    output,
+};
+
+const DEFAULT_FILE: &str = "hsr-lang-patch.json";
+
+/// Writes a portable patch for the chosen languages without touching the game files.
+pub fn export(game: &Game, args: &Args) -> Result<()> {
+    let (text_lang, voice_lang) = args.get_or_prompt_languages()?;
+    let patch = game.export_patch(text_lang, voice_lang)?;
+
+    let path = args.file.as_deref().unwrap_or(Path::new(DEFAULT_FILE));
+    fs::write(path, serde_json::to_string_pretty(&patch)?)
+        .with_context(|| format!("Failed to write '{}'", path.display()))?;
+
+    if !args.quiet {
+        println!("Exported the patch to '{}'", path.display());
+    }
+    Ok(())
+}
+
+/// Applies a patch written by `export-patch`, e.g. on an offline machine.
+pub fn apply(game: &Game, args: &Args) -> Result<()> {
+    let path = args.file.as_deref().unwrap_or(Path::new(DEFAULT_FILE));
+    let data = fs::read(path).with_context(|| format!("Failed to read '{}'", path.display()))?;
+    let patch: PatchFile = serde_json::from_slice(&data)
+        .with_context(|| format!("'{}' is not a valid patch file", path.display()))?;
+
+    let written = game.apply_patch(&patch)?;
+
+    if !args.json {
+        if written {
+            println!("{}", output::success(i18n::tr(Msg::Done)));
+        } else if !args.quiet {
+            println!("The patch is already applied");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,284 @@
+use std::{
+    env,
+    fmt::Display,
+    io::{IsTerminal, Write, stderr, stdout},
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use crossterm::style::{Color, StyledContent, Stylize};
+use hsr_lang_patcher::{
+    allowed_language::{AllowedLanguageRow, ClientRegion, area_language},
+    pipeline::{ProgressObserver, Stage},
+    report::PatchReport,
+    warning::Warnings,
+};
+use inquire::ui::RenderConfig;
+
+use crate::{
+    crash,
+    i18n::{self, Msg},
+};
+
+static COLOR: AtomicBool = AtomicBool::new(true);
+static TERMINAL: AtomicBool = AtomicBool::new(true);
+
+/// Decides once at startup whether escape codes and non-ASCII glyphs may be used.
+/// Colors are disabled by `--no-color`, a non-empty `NO_COLOR` or a redirected stdout.
+pub fn init(no_color: bool) {
+    let terminal = stdout().is_terminal();
+    let no_color_env = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+    let color = terminal && !no_color && !no_color_env;
+
+    TERMINAL.store(terminal, Ordering::Relaxed);
+    COLOR.store(color, Ordering::Relaxed);
+
+    if !color {
+        inquire::set_global_render_config(RenderConfig::empty());
+    }
+}
+
+pub fn color() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+pub fn is_terminal() -> bool {
+    TERMINAL.load(Ordering::Relaxed)
+}
+
+/// Picks the ASCII replacement for a glyph when styled output is disabled.
+pub fn glyph(unicode: &'static str, ascii: &'static str) -> &'static str {
+    if color() { unicode } else { ascii }
+}
+
+fn paint<T: Display>(content: StyledContent<T>) -> String {
+    if color() {
+        content.to_string()
+    } else {
+        content.content().to_string()
+    }
+}
+
+pub fn error() -> String {
+    paint(i18n::tr(Msg::Error).red())
+}
+
+pub fn warning() -> String {
+    paint(i18n::tr(Msg::Warning).yellow())
+}
+
+pub fn success(text: &str) -> String {
+    paint(text.bold().green())
+}
+
+pub fn highlight(text: &str) -> String {
+    paint(text.bold().with(Color::Yellow))
+}
+
+pub fn removed(text: &str) -> String {
+    paint(text.red())
+}
+
+pub fn added(text: &str) -> String {
+    paint(text.green())
+}
+
+/// The stage a run is in, as `[3/8] parse` on stderr while it runs. Stages are always
+/// added to the crash log, the line is only drawn on a terminal and removed again when
+/// the stage finishes or the line is dropped.
+pub struct StatusLine {
+    draw: bool,
+    shown: AtomicBool,
+}
+
+impl StatusLine {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            draw: enabled && stderr().is_terminal(),
+            shown: AtomicBool::new(false),
+        }
+    }
+
+    /// Removes the line, e.g. before a prompt.
+    pub fn clear(&self) {
+        if self.shown.swap(false, Ordering::Relaxed) {
+            eprint!("\r\x1b[2K");
+            let _ = stderr().flush();
+        }
+    }
+}
+
+impl ProgressObserver for StatusLine {
+    fn stage_started(&self, stage: Stage) {
+        crash::log(format!("Stage: {}", stage.name()));
+        if self.draw {
+            let number = Stage::ALL.iter().position(|&s| s == stage).unwrap_or(0) + 1;
+            eprint!("\r\x1b[2K[{number}/{}] {}", Stage::ALL.len(), stage.name());
+            let _ = stderr().flush();
+            self.shown.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn stage_finished(&self, _stage: Stage, _elapsed: Duration) {
+        self.clear();
+    }
+}
+
+impl Drop for StatusLine {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Remembers the stage that is running, so a failure can say where it happened.
+#[derive(Default)]
+pub struct CurrentStage(Mutex<Option<Stage>>);
+
+impl CurrentStage {
+    pub const fn new() -> Self {
+        Self(Mutex::new(None))
+    }
+
+    /// The stage that started and didn't finish, the one that failed after an error.
+    pub fn get(&self) -> Option<Stage> {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn set(&self, stage: Option<Stage>) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = stage;
+    }
+}
+
+impl ProgressObserver for CurrentStage {
+    fn stage_started(&self, stage: Stage) {
+        self.set(Some(stage));
+    }
+
+    fn stage_finished(&self, _stage: Stage, _elapsed: Duration) {
+        self.set(None);
+    }
+}
+
+/// Summary of a patch run, above the "Done" line. After a write the final rows are shown,
+/// with the ones that changed highlighted, followed by where to find the undo and the
+/// warnings.
+pub fn print_report(report: &PatchReport) {
+    println!("Index: {}", report.index_hash);
+    let area = report.region.map_or("os", ClientRegion::area);
+    let mut lines = vec![("Text", area, false), ("Voice", area, true)];
+    // Both clients were patched, with their own voice
+    if report.region.is_none()
+        && area_language(&report.rows_after, "cn", true)
+            != area_language(&report.rows_after, area, true)
+    {
+        lines.push(("CN voice", "cn", true));
+    }
+    for (label, area, voice) in lines {
+        let before = area_language(&report.rows_before, area, voice).unwrap_or("-");
+        let after = area_language(&report.rows_after, area, voice).unwrap_or("-");
+        if before == after {
+            println!("{label}: {after}");
+        } else {
+            println!(
+                "{label}: {} {} {}",
+                removed(before),
+                glyph("→", "->"),
+                added(after)
+            );
+        }
+    }
+    if let Some(region) = report.region {
+        println!(
+            "Only the {} client's rows were patched, --all-regions patches both",
+            match region {
+                ClientRegion::Os => "global",
+                ClientRegion::Cn => "CN",
+            }
+        );
+    }
+    if report.changed() {
+        println!(
+            "Wrote {} bytes to {}.bytes\n",
+            report.bytes_written, report.file_hash
+        );
+        print_rows(&report.rows_before, &report.rows_after);
+        println!();
+    } else {
+        println!("Languages already set, nothing written");
+    }
+
+    let version = report.game_version.as_deref().unwrap_or("unknown");
+    println!("Game version: {version}");
+    if let Some(backup) = &report.backup {
+        println!("Backup:       {}", backup.display());
+    }
+    if let Some(id) = &report.backup_id {
+        println!(
+            "Undo:         {} backups restore {id}",
+            env!("CARGO_PKG_NAME")
+        );
+    }
+    print_warnings(&report.warnings);
+}
+
+/// On stderr after everything else, so they aren't lost between other output.
+pub fn print_warnings(warnings: &Warnings) {
+    if warnings.is_empty() {
+        return;
+    }
+    eprintln!();
+    for warning in warnings {
+        eprintln!("{}: {warning}", self::warning());
+    }
+}
+
+/// The rows as a table, changed ones highlighted.
+fn print_rows(before: &[AllowedLanguageRow], after: &[AllowedLanguageRow]) {
+    let cells: Vec<[String; 4]> = after
+        .iter()
+        .map(|row| {
+            let kind = match row.row_type() {
+                None => "text".to_string(),
+                Some(1) => "voice".to_string(),
+                Some(value) => value.to_string(),
+            };
+            let list = row
+                .language_list()
+                .map_or_else(|| "-".to_string(), |list| list.join(", "));
+            [
+                row.area().unwrap_or("-").to_string(),
+                kind,
+                row.default_language().unwrap_or("-").to_string(),
+                list,
+            ]
+        })
+        .collect();
+
+    let header = ["AREA", "TYPE", "DEFAULT", "LIST"].map(str::to_string);
+    let mut widths = header.clone().map(|cell| cell.len());
+    for row in &cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let line = |row: &[String; 4]| {
+        let padded: Vec<_> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        padded.join("  ").trim_end().to_string()
+    };
+
+    println!("{}", line(&header));
+    for (i, row) in cells.iter().enumerate() {
+        if before.get(i) == after.get(i) {
+            println!("{}", line(row));
+        } else {
+            println!("{}", added(&line(row)));
+        }
+    }
+}
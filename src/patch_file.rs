@@ -0,0 +1,96 @@
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    PatcherError, Result,
+    allowed_language::{current_language, patch_languages},
+    game::Game,
+    language::Language,
+};
+
+const FORMAT_VERSION: u32 = 1;
+
+/// A portable patch that can be applied without access to the machine it was made on.
+/// It stores the whole excel region before and after patching, hex encoded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PatchFile {
+    pub format: u32,
+    /// Name of the container file, `{file_hash}.bytes`.
+    pub file: String,
+    pub offset: u64,
+    pub original: String,
+    pub patched: String,
+    pub original_md5: String,
+    pub patched_md5: String,
+}
+
+impl PatchFile {
+    fn decode(&self, data: &str) -> Result<Vec<u8>> {
+        hex::decode(data).map_err(|_| PatcherError::PatchMismatch {
+            path: self.file.clone().into(),
+        })
+    }
+}
+
+fn md5_hex(data: &[u8]) -> String {
+    hex::encode(Md5::digest(data))
+}
+
+impl Game {
+    /// Builds a patch setting the given languages without writing anything.
+    pub fn export_patch(&self, text_lang: Language, voice_lang: Language) -> Result<PatchFile> {
+        let allowed_language = self.allowed_language();
+        let original = allowed_language.read_raw()?;
+
+        let mut rows = allowed_language.parse_raw(&original)?;
+        patch_languages(&mut rows, text_lang, voice_lang)?;
+        let mut patched = allowed_language.serialize_rows(rows)?;
+        patched.resize(original.len().max(patched.len()), 0);
+
+        Ok(PatchFile {
+            format: FORMAT_VERSION,
+            file: format!("{}.bytes", self.file_hash),
+            offset: self.data_entry.offset as u64,
+            original_md5: md5_hex(&original),
+            patched_md5: md5_hex(&patched),
+            original: hex::encode(original),
+            patched: hex::encode(patched),
+        })
+    }
+
+    /// Applies a patch made by [`Game::export_patch`], refusing data that doesn't match
+    /// what the patch was made from. Returns whether anything was written.
+    pub fn apply_patch(&self, patch: &PatchFile) -> Result<bool> {
+        let file = format!("{}.bytes", self.file_hash);
+        if patch.file != file || patch.offset != self.data_entry.offset as u64 {
+            return Err(PatcherError::PatchTarget {
+                expected: patch.file.clone(),
+                actual: file,
+            });
+        }
+
+        let current = self.allowed_language().read_raw()?;
+        let current_md5 = md5_hex(&current);
+        if current_md5 == patch.patched_md5 {
+            return Ok(false);
+        }
+
+        let patched = patch.decode(&patch.patched)?;
+        let is_valid = current_md5 == patch.original_md5
+            && patch.decode(&patch.original)? == current
+            && md5_hex(&patched) == patch.patched_md5;
+        if !is_valid {
+            return Err(PatcherError::PatchMismatch {
+                path: self.bytes_path.clone(),
+            });
+        }
+
+        self.write_data(&patched)?;
+
+        // The patch only holds bytes, the languages are read back for the state file
+        let rows = self.allowed_language().parse_raw(&patched)?;
+        let language = |voice| current_language(&rows, voice).unwrap_or_default();
+        self.save_patch_state(language(false), language(true), None)?;
+        Ok(true)
+    }
+}
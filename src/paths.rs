@@ -0,0 +1,382 @@
+use std::{
+    borrow::Cow,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Converts an absolute Windows path to its `\\?\` form so file operations aren't limited to
+/// `MAX_PATH`. Paths shown to the user should keep their original form.
+#[cfg(windows)]
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    use std::{
+        ffi::OsString,
+        path::{Component, Prefix},
+    };
+
+    let Ok(absolute) = std::path::absolute(path) else {
+        return Cow::Borrowed(path);
+    };
+    let Some(Component::Prefix(prefix)) = absolute.components().next() else {
+        return Cow::Borrowed(path);
+    };
+
+    let mut long = match prefix.kind() {
+        Prefix::Disk(_) => {
+            let mut long = OsString::from(r"\\?\");
+            long.push(prefix.as_os_str());
+            long
+        }
+        Prefix::UNC(server, share) => {
+            let mut long = OsString::from(r"\\?\UNC\");
+            long.push(server);
+            long.push(r"\");
+            long.push(share);
+            long
+        }
+        // Already verbatim or a device path
+        _ => return Cow::Borrowed(path),
+    };
+
+    // Verbatim paths are passed to the filesystem as-is, so only plain components may remain
+    let mut has_parts = false;
+    for component in absolute.components() {
+        if let Component::Normal(part) = component {
+            long.push(r"\");
+            long.push(part);
+            has_parts = true;
+        }
+    }
+    if !has_parts {
+        long.push(r"\");
+    }
+
+    Cow::Owned(long.into())
+}
+
+#[cfg(not(windows))]
+pub fn long_path(path: &Path) -> Cow<'_, Path> {
+    Cow::Borrowed(path)
+}
+
+/// Free space for the current user on the volume holding `path`, which doesn't have to
+/// exist yet. `None` if it can't be told.
+pub fn available_space(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|dir| long_path(dir).exists())?;
+    free_space(existing)
+}
+
+#[cfg(windows)]
+fn free_space(path: &Path) -> Option<u64> {
+    use std::{os::windows::ffi::OsStrExt, ptr};
+    use windows_sys::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+    let wide: Vec<u16> = long_path(path)
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect();
+    let mut available = 0;
+    let ok = unsafe {
+        GetDiskFreeSpaceExW(
+            wide.as_ptr(),
+            &mut available,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    };
+    (ok != 0).then_some(available)
+}
+
+#[cfg(unix)]
+fn free_space(path: &Path) -> Option<u64> {
+    use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(path.as_os_str().as_bytes()).ok()?;
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    let stat = unsafe { stat.assume_init() };
+    // The field types differ between platforms
+    #[allow(clippy::useless_conversion)]
+    Some(u64::from(stat.f_bavail) * u64::from(stat.f_frsize))
+}
+
+/// Whether the volume holding `path` is mounted read-only or write protected. `false` if
+/// it can't be told.
+#[cfg(windows)]
+pub fn is_read_only_volume(path: &Path) -> bool {
+    use std::{os::windows::ffi::OsStrExt, ptr};
+    use windows_sys::Win32::Storage::FileSystem::{GetVolumeInformationW, GetVolumePathNameW};
+
+    // FILE_READ_ONLY_VOLUME, in a windows-sys module not worth a feature for one flag
+    const READ_ONLY_VOLUME: u32 = 0x0008_0000;
+
+    let wide: Vec<u16> = long_path(path)
+        .as_os_str()
+        .encode_wide()
+        .chain(Some(0))
+        .collect();
+    let mut root = [0u16; 1024];
+    let mut flags = 0;
+    unsafe {
+        GetVolumePathNameW(wide.as_ptr(), root.as_mut_ptr(), root.len() as u32) != 0
+            && GetVolumeInformationW(
+                root.as_ptr(),
+                ptr::null_mut(),
+                0,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut flags,
+                ptr::null_mut(),
+                0,
+            ) != 0
+            && flags & READ_ONLY_VOLUME != 0
+    }
+}
+
+#[cfg(unix)]
+pub fn is_read_only_volume(path: &Path) -> bool {
+    use std::{ffi::CString, mem::MaybeUninit, os::unix::ffi::OsStrExt};
+
+    let Ok(path) = CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let mut stat = MaybeUninit::<libc::statvfs>::uninit();
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return false;
+    }
+    let stat = unsafe { stat.assume_init() };
+    stat.f_flag & libc::ST_RDONLY != 0
+}
+
+/// Whether the file has the read-only attribute, or on Unix no write permission for
+/// anyone. `false` for files that don't exist.
+pub fn is_read_only_file(path: &Path) -> bool {
+    fs::metadata(long_path(path)).is_ok_and(|metadata| metadata.permissions().readonly())
+}
+
+/// Clears the read-only attribute, on Unix gives the owner write permission.
+pub fn clear_read_only(path: &Path) -> io::Result<()> {
+    let mut permissions = fs::metadata(long_path(path))?.permissions();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        permissions.set_mode(permissions.mode() | 0o200);
+    }
+    // Only clears the attribute on Windows, it doesn't touch the ACLs
+    #[cfg(not(unix))]
+    #[allow(clippy::permissions_set_readonly_false)]
+    permissions.set_readonly(false);
+    fs::set_permissions(long_path(path), permissions)
+}
+
+#[cfg(not(any(windows, unix)))]
+fn free_space(_path: &Path) -> Option<u64> {
+    None
+}
+
+/// Resolves symlinks and junctions, so every alias of a folder maps to the same path and
+/// files kept next to the install aren't split between them. Paths that can't be resolved
+/// are returned as they are.
+pub fn canonical_path(path: &Path) -> PathBuf {
+    match fs::canonicalize(long_path(path)) {
+        Ok(canonical) => strip_verbatim(canonical),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// Turns the `\\?\` paths `canonicalize` returns on Windows back into their plain form,
+/// since they end up in messages and the config. [`long_path`] adds the prefix again.
+#[cfg(windows)]
+fn strip_verbatim(path: PathBuf) -> PathBuf {
+    use std::{
+        ffi::OsString,
+        path::{Component, Prefix},
+    };
+
+    let mut components = path.components();
+    let Some(Component::Prefix(prefix)) = components.next() else {
+        return path;
+    };
+    let plain = match prefix.kind() {
+        Prefix::VerbatimDisk(drive) => OsString::from(format!("{}:", drive as char)),
+        Prefix::VerbatimUNC(server, share) => {
+            let mut plain = OsString::from(r"\\");
+            plain.push(server);
+            plain.push(r"\");
+            plain.push(share);
+            plain
+        }
+        _ => return path,
+    };
+    PathBuf::from(plain).join(components.as_path())
+}
+
+#[cfg(not(windows))]
+fn strip_verbatim(path: PathBuf) -> PathBuf {
+    path
+}
+
+/// Normalizes paths users commonly drag onto the executable: a shortcut is resolved to its
+/// target, `StarRail.exe` and the `StarRail_Data` folder are mapped to the game's root folder.
+pub fn resolve_dropped_path(path: &Path) -> Cow<'_, Path> {
+    let mut path = Cow::Borrowed(path);
+
+    if has_extension(&path, "lnk")
+        && let Some(target) = fs::read(long_path(&path))
+            .ok()
+            .and_then(|data| shortcut_target(&data))
+    {
+        path = Cow::Owned(target);
+    }
+
+    // Unity keeps the data of `<name>.exe` in `<name>_Data`, client variants only differ in the name
+    let is_game_exe = has_extension(&path, "exe")
+        && long_path(&path).is_file()
+        && path
+            .file_stem()
+            .zip(path.parent())
+            .is_some_and(|(stem, parent)| {
+                let mut data_dir = stem.to_os_string();
+                data_dir.push("_Data");
+                long_path(&parent.join(data_dir)).is_dir()
+            });
+    let is_data_dir = is_data_dir_name(&path) && long_path(&path).is_dir();
+
+    if (is_game_exe || is_data_dir)
+        && let Some(parent) = path.parent()
+    {
+        return Cow::Owned(parent.to_path_buf());
+    }
+
+    path
+}
+
+fn is_data_dir_name(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.to_ascii_lowercase().ends_with("_data"))
+}
+
+/// Lists the Unity `*_Data` folders of a game folder, `StarRail_Data` first.
+pub fn data_dirs(root: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(long_path(root))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| root.join(entry.file_name()))
+        .filter(|path| is_data_dir_name(path) && long_path(path).is_dir())
+        .collect();
+
+    dirs.sort_by_key(|dir| {
+        !dir.file_name()
+            .is_some_and(|name| name.eq_ignore_ascii_case("StarRail_Data"))
+    });
+    dirs
+}
+
+fn has_extension(path: &Path, extension: &str) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+}
+
+const LINK_HEADER_SIZE: usize = 0x4C;
+const HAS_LINK_TARGET_ID_LIST: u32 = 0x1;
+const HAS_LINK_INFO: u32 = 0x2;
+const VOLUME_ID_AND_LOCAL_BASE_PATH: u32 = 0x1;
+
+/// Reads the local target path of a Windows shell link (`.lnk`), see [MS-SHLLINK].
+///
+/// [MS-SHLLINK]: https://learn.microsoft.com/en-us/openspecs/windows_protocols/ms-shllink
+fn shortcut_target(data: &[u8]) -> Option<PathBuf> {
+    let u16_at = |offset: usize| {
+        data.get(offset..offset + 2)
+            .map(|b| u16::from_le_bytes([b[0], b[1]]) as usize)
+    };
+    let u32_at = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    };
+
+    if u32_at(0)? as usize != LINK_HEADER_SIZE {
+        return None;
+    }
+
+    let flags = u32_at(0x14)?;
+    if flags & HAS_LINK_INFO == 0 {
+        return None;
+    }
+
+    let mut offset = LINK_HEADER_SIZE;
+    if flags & HAS_LINK_TARGET_ID_LIST != 0 {
+        offset += 2 + u16_at(offset)?;
+    }
+
+    let link_info = data.get(offset..offset + u32_at(offset)? as usize)?;
+    let info_u32 = |offset: usize| {
+        link_info
+            .get(offset..offset + 4)
+            .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize)
+    };
+
+    if info_u32(0x8)? as u32 & VOLUME_ID_AND_LOCAL_BASE_PATH == 0 {
+        return None;
+    }
+
+    let header_size = info_u32(0x4)?;
+    let (base_path, suffix) = if header_size >= 0x24 {
+        (
+            utf16_string(link_info.get(info_u32(0x1C)?..)?),
+            utf16_string(link_info.get(info_u32(0x20)?..)?),
+        )
+    } else {
+        (
+            ansi_string(link_info.get(info_u32(0x10)?..)?),
+            ansi_string(link_info.get(info_u32(0x18)?..)?),
+        )
+    };
+
+    let mut target = PathBuf::from(base_path);
+    if !suffix.is_empty() {
+        target.push(suffix);
+    }
+    Some(target)
+}
+
+fn ansi_string(data: &[u8]) -> String {
+    let end = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+    String::from_utf8_lossy(&data[..end]).into_owned()
+}
+
+fn utf16_string(data: &[u8]) -> String {
+    let units: Vec<u16> = data
+        .chunks_exact(2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .take_while(|&unit| unit != 0)
+        .collect();
+    String::from_utf16_lossy(&units)
+}
+
+/// Joins `name` onto `dir`, preferring an existing entry that only differs in casing.
+/// Store builds of the game don't always match the casing of the launcher install.
+pub fn child_ignore_case(dir: &Path, name: &str) -> PathBuf {
+    let path = dir.join(name);
+    if long_path(&path).exists() {
+        return path;
+    }
+
+    fs::read_dir(long_path(dir))
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.file_name())
+        .find(|entry| entry.eq_ignore_ascii_case(name))
+        .map_or(path, |entry| dir.join(entry))
+}
+
+/// Whether the path lies inside the ACL protected `WindowsApps` folder used by Game Pass.
+pub fn is_protected(path: &Path) -> bool {
+    path.components()
+        .any(|component| component.as_os_str().eq_ignore_ascii_case("WindowsApps"))
+}
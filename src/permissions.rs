@@ -0,0 +1,223 @@
+//! `fix-permissions`: gives the current user write access to the DesignData folder when
+//! its files belong to another account or lost their permissions, e.g. after the game
+//! was moved between drives or Windows users. Nothing outside the folder is changed, and
+//! nothing is changed without a confirmation or `--yes`.
+
+use std::fs::File;
+
+use anyhow::{Context, anyhow};
+use inquire::Confirm;
+
+use hsr_lang_patcher::{ErrorKind, game::Game, paths::long_path};
+
+use crate::{Result, args::Args, crash, wizard};
+
+pub fn fix_permissions(game: &Game, args: &Args) -> Result<()> {
+    let dir = &game.design_data_path;
+    if !args.quiet {
+        println!("{}\n", platform::explanation(dir));
+        println!("Steps:");
+        for step in platform::steps(dir) {
+            println!("  {step}");
+        }
+        println!();
+    }
+
+    if !args.yes {
+        if !wizard::is_interactive(args) {
+            return Err(anyhow!(
+                "Pass --yes to change the permissions without asking"
+            ))
+            .context(ErrorKind::InvalidArguments);
+        }
+        let change = Confirm::new("Change the permissions of this folder?")
+            .with_default(false)
+            .prompt()?;
+        if !change {
+            return Err(anyhow!("Left the permissions as they are")).context(ErrorKind::Cancelled);
+        }
+    }
+
+    crash::log(format!("Fixing permissions of {}", dir.display()));
+    platform::fix(dir)?;
+
+    File::options()
+        .write(true)
+        .open(long_path(&game.bytes_path))
+        .with_context(|| {
+            format!(
+                "'{}' still can't be written, run this .exe as administrator and try again",
+                game.bytes_path.display()
+            )
+        })
+        .context(ErrorKind::WriteFailed)?;
+    if !args.quiet {
+        println!("The current user can now write to '{}'", dir.display());
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::{
+        env,
+        path::Path,
+        process::{Command, Stdio},
+    };
+
+    use anyhow::{Context, bail};
+
+    use hsr_lang_patcher::ErrorKind;
+
+    use crate::Result;
+
+    pub fn explanation(dir: &Path) -> String {
+        format!(
+            "This makes the current user the owner of '{}' and everything in it and gives it \
+            full access, so the patcher can write the game files without running as \
+            administrator. The launcher keeps working, it runs with more rights than the \
+            files need. Nothing outside the folder is changed.",
+            dir.display()
+        )
+    }
+
+    /// Runs a system tool, failing with its output.
+    fn run(command: &mut Command) -> Result<()> {
+        let output = command
+            .stdin(Stdio::null())
+            .output()
+            .with_context(|| format!("Failed to run {command:?}"))?;
+        if !output.status.success() {
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            bail!(
+                "{command:?} failed with {}: {}",
+                output.status,
+                format!("{stdout}{stderr}").trim()
+            );
+        }
+        Ok(())
+    }
+
+    fn user() -> String {
+        let name = env::var("USERNAME").unwrap_or_default();
+        match env::var("USERDOMAIN") {
+            Ok(domain) if !domain.is_empty() => format!("{domain}\\{name}"),
+            _ => name,
+        }
+    }
+
+    fn take_ownership(dir: &Path) -> Command {
+        let mut command = Command::new("takeown");
+        command.arg("/F").arg(dir).arg("/R");
+        command
+    }
+
+    fn grant_access(dir: &Path) -> Command {
+        let mut command = Command::new("icacls");
+        command
+            .arg(dir)
+            .arg("/grant")
+            .arg(format!("{}:(OI)(CI)F", user()))
+            .args(["/T", "/C", "/Q"]);
+        command
+    }
+
+    pub fn steps(dir: &Path) -> Vec<String> {
+        vec![
+            format!("takeown /F \"{}\" /R", dir.display()),
+            format!(
+                "icacls \"{}\" /grant \"{}:(OI)(CI)F\" /T /C /Q",
+                dir.display(),
+                user()
+            ),
+        ]
+    }
+
+    pub fn fix(dir: &Path) -> Result<()> {
+        // Taking ownership of another account's files needs an elevated process
+        run(&mut take_ownership(dir))
+            .context("Taking ownership failed, run this .exe as administrator and try again")
+            .context(ErrorKind::WriteFailed)?;
+        run(&mut grant_access(dir))
+            .context("Granting access failed")
+            .context(ErrorKind::WriteFailed)
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use std::{
+        fs,
+        os::unix::fs::{MetadataExt, PermissionsExt},
+        path::Path,
+    };
+
+    use anyhow::{Context, anyhow};
+
+    use hsr_lang_patcher::{ErrorKind, paths::long_path};
+
+    use crate::Result;
+
+    pub fn explanation(dir: &Path) -> String {
+        format!(
+            "This gives the current user read and write access to '{}' and everything in it \
+            that it owns. Files of other users can only be handed over by root, for them the \
+            command to run is shown. Nothing outside the folder is changed.",
+            dir.display()
+        )
+    }
+
+    pub fn steps(dir: &Path) -> Vec<String> {
+        vec![
+            format!(
+                "chmod -R u+rwX '{}' for what the current user owns",
+                dir.display()
+            ),
+            "Files of other users are listed with the command that changes their owner".to_string(),
+        ]
+    }
+
+    pub fn fix(dir: &Path) -> Result<()> {
+        // SAFETY: geteuid has no preconditions
+        let uid = unsafe { libc::geteuid() };
+        let mut foreign = Vec::new();
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(path) = pending.pop() {
+            let metadata = fs::symlink_metadata(long_path(&path))
+                .with_context(|| format!("Failed to read '{}'", path.display()))?;
+            if metadata.file_type().is_symlink() {
+                continue;
+            }
+            if metadata.uid() != uid {
+                foreign.push(path);
+                continue;
+            }
+            let mode = metadata.mode() & 0o7777;
+            let wanted = mode | 0o600 | if metadata.is_dir() { 0o100 } else { 0 };
+            if wanted != mode {
+                fs::set_permissions(long_path(&path), fs::Permissions::from_mode(wanted))
+                    .with_context(|| format!("Failed to change '{}'", path.display()))?;
+            }
+            // Listed after the change, which may have been what kept it from being read
+            if metadata.is_dir() {
+                let entries = fs::read_dir(long_path(&path))
+                    .with_context(|| format!("Failed to list '{}'", path.display()))?;
+                pending.extend(entries.flatten().map(|entry| entry.path()));
+            }
+        }
+
+        if foreign.is_empty() {
+            return Ok(());
+        }
+        Err(anyhow!(
+            "{} files in '{}' belong to another user, e.g. '{}'. Change their owner with\n  \
+            sudo chown -R \"$(id -u):$(id -g)\" '{}'",
+            foreign.len(),
+            dir.display(),
+            foreign[0].display(),
+            dir.display()
+        ))
+        .context(ErrorKind::WriteFailed)
+    }
+}
@@ -0,0 +1,506 @@
+//! The stages of a patch run and the batched writes they end in. [`PatchPipeline`] runs
+//! the stages in order and tells a [`ProgressObserver`] about each, so the command line,
+//! `--json`, the TUI and `serve` all follow the same run.
+
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use rayon::prelude::*;
+use serde::Serialize;
+
+use crate::{
+    PatcherError, Result,
+    allowed_language::{
+        Areas, ListMode, Voices, check_combination, patch_languages_with, validate_rows,
+    },
+    backup::now,
+    design_index::DataEntry,
+    game::{Game, Platform, get_platform_design_data_path, write_error},
+    language::Language,
+    manifest::{WrittenRegion, sha256},
+    paths::long_path,
+    report::PatchReport,
+    timings,
+    warning::{WarningKind, Warnings},
+};
+
+/// Free bytes left in the AllowedLanguage region below which a patch warns. Listing
+/// another language takes a few bytes per row.
+const LOW_SLACK: usize = 16;
+
+/// A step of a patch run, in the order they happen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stage {
+    /// Finding the DesignData folder of the game path.
+    Locate,
+    /// Reading the `DesignV` index up to the AllowedLanguage entry.
+    Index,
+    /// Decoding the AllowedLanguage rows.
+    Parse,
+    /// Changing the language lists of the rows.
+    Edit,
+    /// Encoding the rows and checking they decode back.
+    Serialize,
+    Backup,
+    Write,
+    /// Reading the written bytes back and comparing them.
+    Verify,
+}
+
+impl Stage {
+    pub const ALL: [Self; 8] = [
+        Self::Locate,
+        Self::Index,
+        Self::Parse,
+        Self::Edit,
+        Self::Serialize,
+        Self::Backup,
+        Self::Write,
+        Self::Verify,
+    ];
+
+    /// Name in `--timings`, the JSON output and `serve`'s `progress` notifications.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Locate => "locate",
+            Self::Index => "index",
+            Self::Parse => "parse",
+            Self::Edit => "edit",
+            Self::Serialize => "serialize",
+            Self::Backup => "backup",
+            Self::Write => "write",
+            Self::Verify => "verify",
+        }
+    }
+}
+
+/// Told when each stage of a [`PatchPipeline`] starts and finishes. A stage that fails
+/// is started but never finished. Both do nothing by default.
+pub trait ProgressObserver {
+    fn stage_started(&self, _stage: Stage) {}
+
+    fn stage_finished(&self, _stage: Stage, _elapsed: Duration) {}
+}
+
+/// Ignores the progress, what [`Game::load`] and [`Game::patch_with`] use.
+pub struct NoProgress;
+
+impl ProgressObserver for NoProgress {}
+
+/// Runs a patch stage by stage. Each stage is reported to the observer and recorded for
+/// `--timings`.
+pub struct PatchPipeline<'a> {
+    observer: &'a dyn ProgressObserver,
+}
+
+impl Default for PatchPipeline<'static> {
+    fn default() -> Self {
+        Self::new(&NoProgress)
+    }
+}
+
+impl<'a> PatchPipeline<'a> {
+    pub fn new(observer: &'a dyn ProgressObserver) -> Self {
+        Self { observer }
+    }
+
+    /// Runs `f` as `stage`. For callers whose stage does more than the methods below,
+    /// e.g. asks for another folder when the game isn't found.
+    pub fn stage<T>(&self, stage: Stage, f: impl FnOnce() -> T) -> T {
+        self.observer.stage_started(stage);
+        let start = Instant::now();
+        let result = timings::stage(stage.name(), f);
+        self.observer.stage_finished(stage, start.elapsed());
+        result
+    }
+
+    /// Like [`PatchPipeline::stage`], but a failure leaves the stage unfinished.
+    fn try_stage<T>(&self, stage: Stage, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.observer.stage_started(stage);
+        let start = Instant::now();
+        let result = timings::stage(stage.name(), f)?;
+        self.observer.stage_finished(stage, start.elapsed());
+        Ok(result)
+    }
+
+    /// The DesignData folder of a dropped game path, see [`get_platform_design_data_path`].
+    pub fn locate(&self, game_path: &Path, platform: Platform) -> Result<PathBuf> {
+        self.try_stage(Stage::Locate, || {
+            get_platform_design_data_path(game_path, platform)
+        })
+    }
+
+    /// Loads the game in `design_data_path`, see [`Game::load`].
+    pub fn index(&self, design_data_path: PathBuf) -> Result<Game> {
+        self.try_stage(Stage::Index, || Game::load_index(design_data_path))
+    }
+
+    /// Everything from parsing the rows to verifying the write, see [`Game::patch_with`].
+    pub fn patch(
+        &self,
+        game: &Game,
+        text_lang: Language,
+        voices: impl Into<Voices>,
+        mode: ListMode,
+    ) -> Result<PatchReport> {
+        let voices = voices.into();
+        let allowed_language = game.allowed_language();
+        let (rows_before, drift) = self.try_stage(Stage::Parse, || {
+            let raw = allowed_language.read_raw()?;
+            Ok((
+                allowed_language.parse_raw(&raw)?,
+                allowed_language.format_drift(&raw)?,
+            ))
+        })?;
+        if game.strict
+            && let Some(first) = drift.first()
+        {
+            return Err(PatcherError::Strict(first.to_string()));
+        }
+
+        let areas = game.patch_areas();
+        let mut rows_after = rows_before.clone();
+        let (skipped, mut warnings) = self.try_stage(Stage::Edit, || {
+            let skipped =
+                patch_languages_with(&mut rows_after, text_lang, voices, mode, areas, game.strict)?;
+
+            let mut warnings = Warnings::default();
+            warnings.extend(WarningKind::RowAnomaly, validate_rows(&rows_before));
+            warnings.extend(WarningKind::SkippedRow, &skipped);
+            warnings.extend(WarningKind::FormatDrift, &drift);
+            if let Some(problem) = game.text_map_problem(text_lang) {
+                warnings.push(
+                    WarningKind::TextMap,
+                    format_args!(
+                        "The '{text_lang}' TextMap {problem}, the game will show blank text. \
+                        Verify the game files in the launcher"
+                    ),
+                );
+            }
+            warnings.extend(
+                WarningKind::Unshipped,
+                check_combination(
+                    &game.original_rows()?,
+                    game.client_region(),
+                    text_lang,
+                    voices,
+                )
+                .iter()
+                .map(|warning| format!("Applied a combination the game doesn't ship: {warning}")),
+            );
+            Ok((skipped, warnings))
+        })?;
+
+        let mut bytes_written = 0;
+        if rows_after != rows_before {
+            let data = self.try_stage(Stage::Serialize, || {
+                allowed_language.serialize_rows(rows_after.clone())
+            })?;
+            let region = game.data_entry.size as usize;
+            let slack = region.saturating_sub(data.len());
+            if slack < LOW_SLACK {
+                warnings.push(
+                    WarningKind::SizeSlack,
+                    format_args!(
+                        "Only {slack} of the {region} bytes of the AllowedLanguage region are \
+                        left free, tweaks that add languages may not fit"
+                    ),
+                );
+            }
+            self.write(game, &data)?;
+            bytes_written = region;
+        }
+        game.save_patch_state(text_lang.code(), voices.os.code(), voices.cn_override())?;
+        let original = game.backups()?.pop();
+
+        Ok(PatchReport {
+            game_version: game.game_version(),
+            index_hash: game.index_hash.clone(),
+            file_hash: game.file_hash.clone(),
+            region: match areas {
+                Areas::All => None,
+                Areas::Only(region) => Some(region),
+            },
+            rows_before,
+            rows_after,
+            skipped,
+            drift,
+            bytes_written,
+            backup: original.as_ref().map(|backup| backup.path.clone()),
+            backup_id: original.map(|backup| backup.id()),
+            warnings,
+        })
+    }
+
+    /// Backs up the AllowedLanguage region, writes `data` over it and reads it back, see
+    /// [`Game::write_data`].
+    pub fn write(&self, game: &Game, data: &[u8]) -> Result<()> {
+        let mut batch = WriteBatch::new(&game.file_hash, game.bytes_path.clone());
+        batch.push(
+            game.data_entry.offset as u64,
+            game.data_entry.size as usize,
+            data.to_vec(),
+        )?;
+        self.try_stage(Stage::Backup, || {
+            game.ensure_range_backup(&game.file_hash, &batch.ranges())
+        })?;
+        let written = self.try_stage(Stage::Write, || batch.write())?;
+        self.try_stage(Stage::Verify, || batch.verify())?;
+        game.record_writes(written)
+    }
+}
+
+/// One excel region to patch. `transform` gets the current region and returns the new
+/// contents, or `None` to leave it unchanged.
+pub struct ExcelTarget<F> {
+    pub file_hash: String,
+    pub entry: DataEntry,
+    pub transform: F,
+}
+
+struct Region {
+    offset: u64,
+    size: usize,
+    data: Vec<u8>,
+}
+
+/// Edits of one container, written in a single open/seek/write sequence. Regions are
+/// kept ordered by offset and may not overlap, touching ones are written as one.
+/// [`WriteBatch::verify`] reads everything back once and compares it.
+pub(crate) struct WriteBatch {
+    file_hash: String,
+    path: PathBuf,
+    regions: Vec<Region>,
+}
+
+impl WriteBatch {
+    pub(crate) fn new(file_hash: &str, path: PathBuf) -> Self {
+        Self {
+            file_hash: file_hash.to_string(),
+            path,
+            regions: Vec::new(),
+        }
+    }
+
+    /// Adds `data` for the `size` byte region at `offset`, padded with zeros.
+    pub(crate) fn push(&mut self, offset: u64, size: usize, mut data: Vec<u8>) -> Result<()> {
+        if data.len() > size {
+            return Err(PatcherError::SelfCheck(format!(
+                "is {} bytes, larger than the {size} byte region",
+                data.len()
+            )));
+        }
+
+        let i = self
+            .regions
+            .partition_point(|region| region.offset < offset);
+        let overlaps_previous = i > 0 && {
+            let previous = &self.regions[i - 1];
+            previous.offset + previous.size as u64 > offset
+        };
+        let overlaps_next = self
+            .regions
+            .get(i)
+            .is_some_and(|next| next.offset < offset + size as u64);
+        if overlaps_previous || overlaps_next {
+            return Err(PatcherError::OverlappingWrites {
+                path: self.path.clone(),
+                offset,
+            });
+        }
+
+        data.resize(size, 0);
+        self.regions.insert(i, Region { offset, size, data });
+        Ok(())
+    }
+
+    pub(crate) fn ranges(&self) -> Vec<(u64, usize)> {
+        self.regions
+            .iter()
+            .map(|region| (region.offset, region.size))
+            .collect()
+    }
+
+    /// The regions, touching ones joined, so each run is one seek and one write.
+    fn runs(&self) -> Vec<(u64, Vec<u8>)> {
+        let mut runs: Vec<(u64, Vec<u8>)> = Vec::new();
+        for region in &self.regions {
+            match runs.last_mut() {
+                Some((offset, data)) if *offset + data.len() as u64 == region.offset => {
+                    data.extend_from_slice(&region.data)
+                }
+                _ => runs.push((region.offset, region.data.clone())),
+            }
+        }
+        runs
+    }
+
+    /// Writes the regions, returning what the manifest records about them.
+    pub(crate) fn write(&self) -> Result<Vec<WrittenRegion>> {
+        let path = &self.path;
+        let mut file = File::options()
+            .read(true)
+            .write(true)
+            .open(long_path(path))
+            .map_err(|source| write_error(path, 0, source))?;
+
+        let written_at = now();
+        let mut written = Vec::with_capacity(self.regions.len());
+        let mut previous = Vec::new();
+        for region in &self.regions {
+            previous.resize(region.size, 0);
+            file.seek(SeekFrom::Start(region.offset))
+                .and_then(|_| file.read_exact(&mut previous))
+                .map_err(|source| PatcherError::Read {
+                    path: path.clone(),
+                    source,
+                })?;
+            written.push(WrittenRegion {
+                file_hash: self.file_hash.clone(),
+                offset: region.offset,
+                size: region.size,
+                original_sha256: sha256(&previous),
+                written_sha256: sha256(&region.data),
+                written_at,
+            });
+        }
+
+        for (offset, data) in &self.runs() {
+            file.seek(SeekFrom::Start(*offset))
+                .and_then(|_| file.write_all(data))
+                .map_err(|source| write_error(path, *offset, source))?;
+        }
+        Ok(written)
+    }
+
+    /// Reads the regions back and compares them with what was written.
+    pub(crate) fn verify(&self) -> Result<()> {
+        let path = &self.path;
+        let read_error = |source| PatcherError::Read {
+            path: path.clone(),
+            source,
+        };
+        let mut file = File::open(long_path(path)).map_err(read_error)?;
+        let mut written = Vec::new();
+        for region in &self.regions {
+            written.resize(region.data.len(), 0);
+            file.seek(SeekFrom::Start(region.offset))
+                .and_then(|_| file.read_exact(&mut written))
+                .map_err(read_error)?;
+            if let Some(i) = written.iter().zip(&region.data).position(|(a, b)| a != b) {
+                return Err(PatcherError::WriteMismatch {
+                    path: path.clone(),
+                    offset: region.offset + i as u64,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Whether writing `data` over `region` would leave it as it is. Shorter data is padded
+/// with zeros when written.
+pub(crate) fn is_unchanged(region: &[u8], data: &[u8]) -> bool {
+    data.len() <= region.len()
+        && data == &region[..data.len()]
+        && region[data.len()..].iter().all(|&byte| byte == 0)
+}
+
+impl Game {
+    /// Patches several excels at once. Regions are read and transformed in parallel, then
+    /// the changed ones are written grouped per container, each container on its own
+    /// thread. Nothing is written if any target fails. A container that fails to write
+    /// doesn't undo the others. Returns the number of changed regions.
+    pub fn patch_excels<F>(&self, targets: &[ExcelTarget<F>]) -> Result<usize>
+    where
+        F: Fn(&[u8]) -> Result<Option<Vec<u8>>> + Sync,
+    {
+        let patched = targets
+            .par_iter()
+            .map(|target| self.patch_region(target))
+            .collect::<Result<Vec<_>>>()?;
+
+        // Batches are built before anything is written, so an overlap writes nothing
+        let mut containers: BTreeMap<&str, WriteBatch> = BTreeMap::new();
+        let mut changed = 0;
+        for (target, region) in targets.iter().zip(patched) {
+            if let Some(region) = region {
+                containers
+                    .entry(&target.file_hash)
+                    .or_insert_with(|| {
+                        WriteBatch::new(&target.file_hash, self.container_path(&target.file_hash))
+                    })
+                    .push(region.offset, region.size, region.data)?;
+                changed += 1;
+            }
+        }
+
+        // Each container is recorded once verified, so the manifest covers what is on disk
+        // even when a later one fails. One at a time, or the threads would overwrite each
+        // other's manifest
+        let manifest = Mutex::new(());
+        containers
+            .into_par_iter()
+            .try_for_each(|(file_hash, batch)| {
+                self.ensure_range_backup(file_hash, &batch.ranges())?;
+                let written = batch.write()?;
+                batch.verify()?;
+                let _guard = manifest.lock().unwrap_or_else(PoisonError::into_inner);
+                self.record_writes(written)
+            })?;
+
+        Ok(changed)
+    }
+
+    /// Replaces the data of an excel listed in the index, padding it with zeros to the
+    /// size of its region. Returns whether anything was written.
+    pub fn write_entry(&self, name_hash: i32, data: &[u8]) -> Result<bool> {
+        let index = self.design_index()?;
+        let (entry, file) = index
+            .find_by_hash(name_hash)
+            .ok_or(PatcherError::ExcelNotFound { hash: name_hash })?;
+        if data.len() > entry.size as usize {
+            return Err(PatcherError::PayloadTooLarge {
+                hash: name_hash,
+                size: data.len(),
+                region: entry.size as usize,
+            });
+        }
+
+        let target = ExcelTarget {
+            file_hash: file.file_hash.clone(),
+            entry: entry.clone(),
+            transform: |region: &[u8]| Ok((!is_unchanged(region, data)).then(|| data.to_vec())),
+        };
+        Ok(self.patch_excels(&[target])? > 0)
+    }
+
+    fn patch_region<F>(&self, target: &ExcelTarget<F>) -> Result<Option<Region>>
+    where
+        F: Fn(&[u8]) -> Result<Option<Vec<u8>>>,
+    {
+        let path = self.container_path(&target.file_hash);
+        let offset = target.entry.offset as u64;
+        let size = target.entry.size as usize;
+
+        let mut current = vec![0u8; size];
+        File::open(long_path(&path))
+            .and_then(|mut file| {
+                file.seek(SeekFrom::Start(offset))?;
+                file.read_exact(&mut current)
+            })
+            .map_err(|source| PatcherError::Read {
+                path: path.clone(),
+                source,
+            })?;
+
+        Ok((target.transform)(&current)?.map(|data| Region { offset, size, data }))
+    }
+}
@@ -0,0 +1,134 @@
+//! Python bindings, so datamining scripts can use the parsers instead of reimplementing
+//! the format. Built into the `hsr_lang_patcher` module with maturin, see `pyproject.toml`.
+
+use std::path::PathBuf;
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{
+    ErrorKind, PatcherError,
+    design_index::DesignIndex,
+    game::{Game, get_design_data_path},
+    language::Language,
+    recipe,
+};
+
+mod exceptions {
+    pyo3::create_exception!(
+        hsr_lang_patcher,
+        PatcherError,
+        pyo3::exceptions::PyException
+    );
+}
+
+impl From<PatcherError> for PyErr {
+    fn from(error: PatcherError) -> Self {
+        // Include the causes, e.g. the OS error behind a failed write
+        let mut message = error.to_string();
+        let mut source = std::error::Error::source(&error);
+        while let Some(cause) = source {
+            message.push_str(&format!(": {cause}"));
+            source = cause.source();
+        }
+
+        match error.kind() {
+            ErrorKind::InvalidArguments => PyValueError::new_err(message),
+            _ => exceptions::PatcherError::new_err(message),
+        }
+    }
+}
+
+#[derive(IntoPyObject)]
+struct IndexEntry {
+    name_hash: i32,
+    offset: i32,
+    size: i32,
+    file_hash: String,
+}
+
+#[derive(IntoPyObject)]
+struct LanguageRow {
+    area: Option<String>,
+    r#type: Option<u8>,
+    language_list: Option<Vec<String>>,
+    default_language: Option<String>,
+}
+
+fn load(path: PathBuf) -> PyResult<Game> {
+    Ok(Game::load(get_design_data_path(&path)?)?)
+}
+
+/// Every excel in a `DesignV` index, as dicts of `name_hash`, `offset`, `size` and the
+/// `file_hash` of the container holding it.
+#[pyfunction]
+fn parse_index(data: &[u8]) -> PyResult<Vec<IndexEntry>> {
+    let index = DesignIndex::parse(data)?;
+    Ok(index
+        .files
+        .iter()
+        .flat_map(|file| {
+            file.entries.iter().map(|entry| IndexEntry {
+                name_hash: entry.name_hash,
+                offset: entry.offset,
+                size: entry.size,
+                file_hash: file.file_hash.clone(),
+            })
+        })
+        .collect())
+}
+
+/// The AllowedLanguage rows of the game at `path`, the game folder or its `DesignData`
+/// folder.
+#[pyfunction]
+fn read_allowed_language(py: Python<'_>, path: PathBuf) -> PyResult<Vec<LanguageRow>> {
+    let rows = py.detach(|| -> PyResult<_> { Ok(load(path)?.allowed_language().parse()?) })?;
+    Ok(rows
+        .iter()
+        .map(|row| LanguageRow {
+            area: row.area().map(str::to_string),
+            r#type: row.row_type(),
+            language_list: row.language_list().map(<[String]>::to_vec),
+            default_language: row.default_language().map(str::to_string),
+        })
+        .collect())
+}
+
+/// Sets the text and voice language like the .exe does, then applies the named tweaks.
+/// Returns whether the languages changed.
+#[pyfunction]
+#[pyo3(signature = (path, text, voice, tweaks = Vec::new()))]
+fn apply_patch(
+    py: Python<'_>,
+    path: PathBuf,
+    text: &str,
+    voice: &str,
+    tweaks: Vec<String>,
+) -> PyResult<bool> {
+    let text = Language::parse_text(text)?;
+    let voice = Language::parse_voice(voice)?;
+    let tweaks = tweaks
+        .iter()
+        .map(|name| recipe::find_recipe(name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    py.detach(|| {
+        let game = load(path)?;
+        let changed = game.apply(text, voice)?;
+        if !tweaks.is_empty() {
+            game.apply_recipes(&tweaks)?;
+        }
+        Ok(changed)
+    })
+}
+
+#[pymodule(name = "hsr_lang_patcher")]
+fn python_module(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add(
+        "PatcherError",
+        m.py().get_type::<exceptions::PatcherError>(),
+    )?;
+    m.add_function(wrap_pyfunction!(parse_index, m)?)?;
+    m.add_function(wrap_pyfunction!(read_allowed_language, m)?)?;
+    m.add_function(wrap_pyfunction!(apply_patch, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use std::{fs, io, path::Path};
+
+use crate::{
+    PatcherError, Result,
+    allowed_language::AllowedLanguage,
+    design_index::{DesignIndex, invalid_data},
+    game::{ALLOWED_LANGUAGE_HASH, Game, read, write_error},
+    master_index::MasterUpdate,
+    paths::long_path,
+};
+
+/// Sizes of a container before and after [`Game::rebuild_container`].
+#[derive(Debug, Clone)]
+pub struct RebuildReport {
+    pub file_hash: String,
+    pub entries: usize,
+    pub old_size: u64,
+    pub new_size: u64,
+    pub master: MasterUpdate,
+}
+
+impl Game {
+    /// Lays out all entries of a container back to back in their original order, dropping
+    /// gaps between them and the zero padding a shorter patch leaves behind in the
+    /// AllowedLanguage excel. Writes the new container and the updated index, after
+    /// backing up both.
+    pub fn rebuild_container(&self, file_hash: &str) -> Result<RebuildReport> {
+        let index_path = self.index_path();
+        let index_data = read(&index_path)?;
+        let mut index = DesignIndex::parse(&index_data)?;
+        let file = index
+            .files
+            .iter_mut()
+            .find(|file| file.file_hash == file_hash)
+            .ok_or_else(|| PatcherError::ContainerNotFound {
+                file_hash: file_hash.to_string(),
+            })?;
+
+        let bytes_path = self.container_path(file_hash);
+        let container = read(&bytes_path)?;
+
+        let mut order: Vec<usize> = (0..file.entries.len()).collect();
+        order.sort_by_key(|&i| file.entries[i].offset);
+
+        let mut rebuilt = Vec::with_capacity(container.len());
+        for i in order {
+            let entry = &mut file.entries[i];
+            let start = entry.offset as usize;
+            let data = container
+                .get(start..start + entry.size as usize)
+                .ok_or_else(|| PatcherError::Codec {
+                    path: bytes_path.clone(),
+                    offset: start as u64,
+                    source: invalid_data(format!(
+                        "entry {} ends past the end of the container",
+                        entry.name_hash
+                    )),
+                })?;
+
+            let data = if entry.name_hash == ALLOWED_LANGUAGE_HASH {
+                let allowed_language = AllowedLanguage::new(entry, &bytes_path);
+                let rows = allowed_language.parse_raw(data)?;
+                AllowedLanguage::write_rows(&rows).map_err(|source| PatcherError::Codec {
+                    path: bytes_path.clone(),
+                    offset: start as u64,
+                    source,
+                })?
+            } else {
+                data.to_vec()
+            };
+
+            entry.offset = rebuilt.len() as i32;
+            entry.size = data.len() as i32;
+            rebuilt.extend_from_slice(&data);
+        }
+
+        // Only follow the container size when the index actually stored it
+        if file.read_size == container.len() as u64 {
+            file.read_size = rebuilt.len() as u64;
+        }
+        let entries = file.entries.len();
+
+        let new_index_data = index.serialize().map_err(|source| PatcherError::Codec {
+            path: index_path.clone(),
+            offset: 0,
+            source,
+        })?;
+
+        self.ensure_rewrite_space(file_hash, container.len() as u64, rebuilt.len() as u64)?;
+        self.ensure_container_backup(file_hash)?;
+        replace(&bytes_path, &rebuilt)?;
+        let master = self.write_index(&index_data, &new_index_data)?;
+
+        Ok(RebuildReport {
+            file_hash: file_hash.to_string(),
+            entries,
+            old_size: container.len() as u64,
+            new_size: rebuilt.len() as u64,
+            master,
+        })
+    }
+}
+
+/// Writes next to the file first, so an interrupted write never leaves a truncated file.
+pub(crate) fn replace(path: &Path, data: &[u8]) -> Result<()> {
+    let temp_path = path.with_extension("bytes.rebuild");
+    fs::write(long_path(&temp_path), data)
+        .and_then(|_| fs::rename(long_path(&temp_path), long_path(path)))
+        .map_err(|source: io::Error| write_error(path, 0, source))
+}
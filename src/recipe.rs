@@ -0,0 +1,275 @@
+//! Optional tweaks shipped with the patcher. Each one edits the rows of an excel decoded
+//! with a [`Schema`], so adding a tweak only takes a [`PatchRecipe`] impl and an entry in
+//! [`RECIPES`].
+
+#[cfg(feature = "fs")]
+use std::collections::BTreeMap;
+
+use crate::{
+    PatcherError, Result,
+    allowed_language::ALLOWED_LANGUAGE_HASH,
+    language::Language,
+    schema::{Row, Schema, Value},
+};
+#[cfg(feature = "fs")]
+use crate::{
+    game::Game,
+    pipeline::{ExcelTarget, is_unchanged},
+};
+
+pub trait PatchRecipe: Sync {
+    /// Name passed to `--tweak`.
+    fn name(&self) -> &'static str;
+    fn description(&self) -> &'static str;
+    /// Name hash of the excel the tweak edits.
+    fn excel(&self) -> i32;
+    fn schema(&self) -> Schema;
+    /// Edits the decoded rows in place. Leaving them unchanged writes nothing.
+    fn apply(&self, rows: &mut Vec<Row>) -> Result<()>;
+}
+
+/// All built-in tweaks, in the order they're listed.
+pub static RECIPES: &[&dyn PatchRecipe] = &[
+    &AllLanguages { voice: false },
+    &AllLanguages { voice: true },
+];
+
+pub fn find_recipe(name: &str) -> Result<&'static dyn PatchRecipe> {
+    RECIPES
+        .iter()
+        .find(|recipe| recipe.name() == name)
+        .copied()
+        .ok_or_else(|| PatcherError::UnknownTweak {
+            name: name.to_string(),
+        })
+}
+
+pub(crate) fn recipe_names() -> String {
+    RECIPES
+        .iter()
+        .map(|recipe| recipe.name())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[cfg(feature = "fs")]
+impl Game {
+    /// Applies the tweaks through [`Game::patch_excels`]. Tweaks of the same excel run
+    /// one after another on its rows. Returns the number of excels changed.
+    pub fn apply_recipes(&self, recipes: &[&dyn PatchRecipe]) -> Result<usize> {
+        let mut by_excel: BTreeMap<i32, Vec<&dyn PatchRecipe>> = BTreeMap::new();
+        for &recipe in recipes {
+            by_excel.entry(recipe.excel()).or_default().push(recipe);
+        }
+
+        let index = self.design_index()?;
+        let targets = by_excel
+            .into_iter()
+            .map(|(hash, recipes)| {
+                let (entry, file) = index
+                    .find_by_hash(hash)
+                    .ok_or(PatcherError::ExcelNotFound { hash })?;
+                let path = self.container_path(&file.file_hash);
+                let offset = entry.offset as u64;
+                let size = entry.size as usize;
+
+                let transform = move |region: &[u8]| {
+                    let mut data = region.to_vec();
+                    for recipe in &recipes {
+                        let schema = recipe.schema();
+                        let codec = |source| PatcherError::Codec {
+                            path: path.clone(),
+                            offset,
+                            source,
+                        };
+
+                        let mut rows = schema.decode_stored(&data).map_err(codec)?;
+                        recipe.apply(&mut rows)?;
+                        data = schema.encode_stored(&rows).map_err(codec)?;
+
+                        if data.len() > size {
+                            return Err(PatcherError::TweakTooLarge {
+                                name: recipe.name().to_string(),
+                                size: data.len(),
+                                region: size,
+                            });
+                        }
+                        if schema.decode_stored(&data).ok().as_ref() != Some(&rows) {
+                            return Err(PatcherError::SelfCheck(
+                                "decodes to different rows".to_string(),
+                            ));
+                        }
+                    }
+
+                    Ok((!is_unchanged(region, &data)).then_some(data))
+                };
+
+                Ok(ExcelTarget {
+                    file_hash: file.file_hash.clone(),
+                    entry: entry.clone(),
+                    transform,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        self.patch_excels(&targets)
+    }
+}
+
+/// `set-field`: sets fields of the rows whose fields match, e.g. `area=os`. An empty
+/// value stands for a field missing from the row, both when matching and setting.
+#[derive(Debug, Clone)]
+pub struct FieldEdit {
+    excel: i32,
+    schema: Schema,
+    filters: Vec<(usize, Option<Value>)>,
+    assignments: Vec<(usize, Option<Value>)>,
+}
+
+impl FieldEdit {
+    /// Parses the `name=value` pairs of `--where` and `--set` against the schema.
+    pub fn new(
+        excel: i32,
+        schema: Schema,
+        filters: &[String],
+        assignments: &[String],
+    ) -> Result<Self> {
+        let parse = |text: &String| -> Result<(usize, Option<Value>)> {
+            let invalid = |message: String| PatcherError::InvalidField {
+                field: text.clone(),
+                message,
+            };
+            let (name, value) = text
+                .split_once('=')
+                .ok_or_else(|| invalid("expected name=value".to_string()))?;
+            let index = schema.field_index(name).ok_or_else(|| {
+                let names: Vec<_> = schema
+                    .fields
+                    .iter()
+                    .map(|field| field.name.as_str())
+                    .collect();
+                invalid(format!(
+                    "{} has the fields {}",
+                    schema.name,
+                    names.join(", ")
+                ))
+            })?;
+            if value.is_empty() {
+                return Ok((index, None));
+            }
+            let kind = schema.fields[index].kind;
+            let value = kind
+                .parse_value(value)
+                .ok_or_else(|| invalid(format!("'{name}' is a {}", kind.name())))?;
+            Ok((index, Some(value)))
+        };
+
+        Ok(Self {
+            excel,
+            filters: filters.iter().map(parse).collect::<Result<_>>()?,
+            assignments: assignments.iter().map(parse).collect::<Result<_>>()?,
+            schema,
+        })
+    }
+
+    /// Whether `--where` selects the row. Without filters every row is selected.
+    pub fn matches(&self, row: &Row) -> bool {
+        self.filters
+            .iter()
+            .all(|(index, value)| row.get(*index) == Some(value))
+    }
+}
+
+impl PatchRecipe for FieldEdit {
+    fn name(&self) -> &'static str {
+        "set-field"
+    }
+
+    fn description(&self) -> &'static str {
+        "Set fields from the command line"
+    }
+
+    fn excel(&self) -> i32 {
+        self.excel
+    }
+
+    fn schema(&self) -> Schema {
+        self.schema.clone()
+    }
+
+    fn apply(&self, rows: &mut Vec<Row>) -> Result<()> {
+        for row in rows.iter_mut().filter(|row| self.matches(row)) {
+            for (index, value) in &self.assignments {
+                row[*index] = value.clone();
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Lists every supported language in the text or voice rows, so the language can
+/// also be switched in the game's settings. The default language stays first.
+struct AllLanguages {
+    voice: bool,
+}
+
+impl PatchRecipe for AllLanguages {
+    fn name(&self) -> &'static str {
+        if self.voice {
+            "all-voice-languages"
+        } else {
+            "all-text-languages"
+        }
+    }
+
+    fn description(&self) -> &'static str {
+        if self.voice {
+            "List every voice language in the game's settings"
+        } else {
+            "List every text language in the game's settings"
+        }
+    }
+
+    fn excel(&self) -> i32 {
+        ALLOWED_LANGUAGE_HASH
+    }
+
+    fn schema(&self) -> Schema {
+        Schema::allowed_language()
+    }
+
+    fn apply(&self, rows: &mut Vec<Row>) -> Result<()> {
+        // Fields of Schema::allowed_language
+        const TYPE: usize = 1;
+        const LANGUAGE_LIST: usize = 2;
+        const DEFAULT_LANGUAGE: usize = 3;
+
+        for row in rows {
+            let voice = match row[TYPE] {
+                None => false,
+                Some(Value::Int(1)) => true,
+                _ => continue,
+            };
+            if voice != self.voice {
+                continue;
+            }
+
+            let mut languages = Vec::new();
+            if let Some(Value::String(default)) = &row[DEFAULT_LANGUAGE] {
+                languages.push(default.clone());
+            }
+            let supported: Vec<_> = if self.voice {
+                Language::voice_languages().collect()
+            } else {
+                Language::ALL.to_vec()
+            };
+            for lang in supported {
+                if !languages.iter().any(|l| l == lang.code()) {
+                    languages.push(lang.to_string());
+                }
+            }
+            row[LANGUAGE_LIST] = Some(Value::StringArray(languages));
+        }
+        Ok(())
+    }
+}
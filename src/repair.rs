@@ -0,0 +1,89 @@
+use std::{fs, time::Duration};
+
+use anyhow::{Context, anyhow};
+use md5::{Digest, Md5};
+use serde_json::Value;
+
+use hsr_lang_patcher::{game::Game, paths::long_path};
+
+use crate::{Result, update};
+
+/// HoYoPlay endpoints listing the current game packages, global first.
+const PACKAGE_URLS: [&str; 2] = [
+    "https://sg-hyp-api.hoyoverse.com/hyp/hyp-connect/api/getGamePackages?launcher_id=VYTpXlbWo8&game_ids[]=4ziysqXOQ8",
+    "https://hyp-api.mihoyo.com/hyp/hyp-connect/api/getGamePackages?launcher_id=jGHBHlcOq1&game_ids[]=64kMb5iAWu",
+];
+
+/// Downloads the original excel container from the official CDN and replaces the local copy.
+/// The download is checked against the local `pkg_version`, so only the installed game
+/// version can be restored.
+pub fn repair(game: &Game, quiet: bool) -> Result<()> {
+    let entry = game.manifest_entry()?.context(
+        "The container is not listed in pkg_version, repairing requires a full game install",
+    )?;
+
+    let agent = update::agent(Duration::from_secs(60));
+    let mut last_error = anyhow!("No download location found");
+
+    for base_url in PACKAGE_URLS
+        .iter()
+        .filter_map(|url| resource_urls(&agent, url).ok())
+        .flatten()
+    {
+        let url = format!("{}/{}", base_url.trim_end_matches('/'), entry.remote_name);
+        let data = match download(&agent, &url) {
+            Ok(data) => data,
+            Err(e) => {
+                last_error = e.context(format!("Failed to download '{url}'"));
+                continue;
+            }
+        };
+
+        let md5 = hex::encode(Md5::digest(&data));
+        if data.len() as u64 != entry.file_size || !md5.eq_ignore_ascii_case(&entry.md5) {
+            last_error =
+                anyhow!("'{url}' doesn't match pkg_version, it belongs to another game version");
+            continue;
+        }
+
+        // Write next to the original first so a failed write can't leave a truncated file
+        let temp_path = game.bytes_path.with_extension("bytes.download");
+        fs::write(long_path(&temp_path), &data)
+            .with_context(|| format!("Failed to write '{}'", temp_path.display()))?;
+        fs::rename(long_path(&temp_path), long_path(&game.bytes_path))
+            .with_context(|| format!("Failed to replace '{}'", game.bytes_path.display()))?;
+
+        if !quiet {
+            println!("Restored '{}' from {url}", game.bytes_path.display());
+        }
+        return Ok(());
+    }
+
+    Err(last_error)
+}
+
+/// Base URLs of the unpacked game files, for the current and the pre-download version.
+fn resource_urls(agent: &ureq::Agent, url: &str) -> Result<Vec<String>> {
+    let body = agent.get(url).call()?.body_mut().read_to_string()?;
+    let response: Value = serde_json::from_str(&body)?;
+
+    Ok(response["data"]["game_packages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|package| [&package["main"]["major"], &package["pre_download"]["major"]])
+        .filter_map(|major| major["res_list_url"].as_str())
+        .filter(|url| !url.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+fn download(agent: &ureq::Agent, url: &str) -> Result<Vec<u8>> {
+    Ok(agent
+        .get(url)
+        .call()?
+        .body_mut()
+        .with_config()
+        .limit(256 * 1024 * 1024)
+        .read_to_vec()?)
+}
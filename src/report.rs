@@ -0,0 +1,110 @@
+//! What a patch run did, for callers that show or log more than "done".
+
+use std::{fs, path::PathBuf};
+
+use serde::Serialize;
+
+use crate::{
+    Result,
+    allowed_language::{AllowedLanguageRow, Areas, FormatDrift, ListMode, SkippedRow, Voices},
+    game::{ClientRegion, Game, get_game_executable},
+    language::Language,
+    paths::long_path,
+    pipeline::PatchPipeline,
+    text_map::TextMapStatus,
+    warning::Warnings,
+};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PatchReport {
+    /// `game_version` from the launcher's `config.ini`, if there is one.
+    pub game_version: Option<String>,
+    pub index_hash: String,
+    pub file_hash: String,
+    /// The client whose rows alone were patched, `None` if both were.
+    pub region: Option<ClientRegion>,
+    pub rows_before: Vec<AllowedLanguageRow>,
+    pub rows_after: Vec<AllowedLanguageRow>,
+    /// Rows of an unknown area or type, left as they were.
+    pub skipped: Vec<SkippedRow>,
+    /// What in the excel this version doesn't know, kept or ignored.
+    pub drift: Vec<FormatDrift>,
+    /// Size of the rewritten region, 0 if the rows already matched.
+    pub bytes_written: usize,
+    /// Backup holding the original container.
+    pub backup: Option<PathBuf>,
+    /// ID of [`PatchReport::backup`], which restores it.
+    pub backup_id: Option<String>,
+    /// Everything worth a look that didn't stop the patch, `skipped` and `drift` included.
+    pub warnings: Warnings,
+}
+
+impl PatchReport {
+    pub fn changed(&self) -> bool {
+        self.bytes_written > 0
+    }
+}
+
+impl Game {
+    /// Like [`Game::apply`], but reports what was found and written.
+    pub fn patch(&self, text_lang: Language, voices: impl Into<Voices>) -> Result<PatchReport> {
+        self.patch_with(text_lang, voices, ListMode::Replace)
+    }
+
+    /// [`Game::patch`] with a choice of what happens to the language lists. Runs a
+    /// [`PatchPipeline`] that doesn't report its progress.
+    pub fn patch_with(
+        &self,
+        text_lang: Language,
+        voices: impl Into<Voices>,
+        mode: ListMode,
+    ) -> Result<PatchReport> {
+        PatchPipeline::default().patch(self, text_lang, voices, mode)
+    }
+
+    /// Why the TextMap of `lang` would leave the game without text, `None` if it's there
+    /// or can't be told.
+    pub(crate) fn text_map_problem(&self, lang: Language) -> Option<&'static str> {
+        match self.text_map_status(lang) {
+            Ok(TextMapStatus::Missing) => Some("is missing"),
+            Ok(TextMapStatus::Empty) => Some("is empty"),
+            Ok(TextMapStatus::Present { .. } | TextMapStatus::Unknown) | Err(_) => None,
+        }
+    }
+
+    /// The rows of the detected client, or of both when it isn't known or
+    /// [`Game::all_regions`] is set.
+    pub fn patch_areas(&self) -> Areas {
+        match self.client_region() {
+            Some(region) if !self.all_regions => Areas::Only(region),
+            _ => Areas::All,
+        }
+    }
+
+    /// The launcher writes the installed version into `config.ini` next to the executable.
+    pub fn game_version(&self) -> Option<String> {
+        self.launcher_config("game_version")
+    }
+
+    /// Told apart by the launcher's distribution channel (`cps`) in `config.ini`, e.g.
+    /// `hyp_hoyoverse` for the global and `hyp_mihoyo` or `bilibili` for the CN client.
+    pub fn client_region(&self) -> Option<ClientRegion> {
+        let cps = self.launcher_config("cps")?.to_ascii_lowercase();
+        if cps.contains("hoyoverse") {
+            Some(ClientRegion::Os)
+        } else if cps.contains("mihoyo") || cps.contains("bilibili") {
+            Some(ClientRegion::Cn)
+        } else {
+            None
+        }
+    }
+
+    fn launcher_config(&self, name: &str) -> Option<String> {
+        let exe = get_game_executable(&self.design_data_path)?;
+        let config = fs::read_to_string(long_path(&exe.parent()?.join("config.ini"))).ok()?;
+        config.lines().find_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            (key.trim() == name).then(|| value.trim().to_string())
+        })
+    }
+}
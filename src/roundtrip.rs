@@ -0,0 +1,141 @@
+//! `verify-roundtrip`: decodes the AllowedLanguage excel and encodes the rows again
+//! unchanged. Anything but the original bytes means a patch would damage fields this
+//! version doesn't understand, so it's worth running on a new game version before
+//! trusting a real patch.
+
+use anyhow::{Context, anyhow};
+use serde::Serialize;
+
+use hsr_lang_patcher::{ErrorKind, allowed_language::AllowedLanguage, game::Game};
+
+use crate::{Result, args::Args, output};
+
+/// Mismatches listed before the rest is only counted.
+const MAX_SHOWN: usize = 20;
+/// Bytes of each mismatch shown.
+const MAX_BYTES: usize = 16;
+
+#[derive(Serialize)]
+struct Roundtrip {
+    container: String,
+    offset: u64,
+    region_size: usize,
+    encoded_size: usize,
+    rows: usize,
+    mismatches: Vec<Mismatch>,
+}
+
+/// A run of differing bytes. The encoded rows are padded with zeros to the region size,
+/// like when they are written.
+#[derive(Serialize)]
+struct Mismatch {
+    /// In the container file.
+    offset: u64,
+    /// From the start of the excel.
+    relative: usize,
+    len: usize,
+    original: String,
+    encoded: String,
+}
+
+pub fn verify_roundtrip(game: &Game, args: &Args) -> Result<()> {
+    let allowed_language = game.allowed_language();
+    let original = allowed_language.read_raw()?;
+    let rows = allowed_language.parse_raw(&original)?;
+    let encoded = AllowedLanguage::write_rows(&rows)?;
+
+    let offset = game.data_entry.offset as u64;
+    let mut padded = encoded.clone();
+    padded.resize(original.len().max(encoded.len()), 0);
+    let report = Roundtrip {
+        container: game.file_hash.clone(),
+        offset,
+        region_size: original.len(),
+        encoded_size: encoded.len(),
+        rows: rows.len(),
+        mismatches: mismatches(offset, &original, &padded),
+    };
+
+    if args.json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_report(&report, args.quiet);
+    }
+
+    if report.mismatches.is_empty() {
+        return Ok(());
+    }
+    let differing: usize = report.mismatches.iter().map(|m| m.len).sum();
+    Err(anyhow!(
+        "Re-encoding the unchanged rows changed {differing} bytes, patching this game version \
+        could damage the excel"
+    ))
+    .context(ErrorKind::UnsupportedVersion)
+}
+
+fn mismatches(offset: u64, original: &[u8], encoded: &[u8]) -> Vec<Mismatch> {
+    // Encoded data longer than the region differs from the bytes after it
+    let byte = |data: &[u8], i: usize| data.get(i).copied();
+    let mut mismatches: Vec<Mismatch> = Vec::new();
+    let mut start = None;
+    for i in 0..=encoded.len() {
+        let differs = i < encoded.len() && byte(original, i) != byte(encoded, i);
+        match (differs, start) {
+            (true, None) => start = Some(i),
+            (false, Some(first)) => {
+                let hex = |data: &[u8]| {
+                    let end = i.min(first + MAX_BYTES).min(data.len());
+                    hex::encode(data.get(first..end).unwrap_or_default())
+                };
+                mismatches.push(Mismatch {
+                    offset: offset + first as u64,
+                    relative: first,
+                    len: i - first,
+                    original: hex(original),
+                    encoded: hex(encoded),
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    mismatches
+}
+
+fn print_report(report: &Roundtrip, quiet: bool) {
+    if !quiet {
+        println!(
+            "AllowedLanguage: {} rows in {}.bytes at offset 0x{:X}",
+            report.rows, report.container, report.offset
+        );
+        println!(
+            "Region: {} bytes, re-encoded: {} bytes\n",
+            report.region_size, report.encoded_size
+        );
+    }
+
+    if report.mismatches.is_empty() {
+        println!(
+            "{}",
+            output::success("Round trip is lossless, the excel is encoded back byte for byte")
+        );
+        return;
+    }
+
+    println!(
+        "{}: {} mismatched ranges",
+        output::warning(),
+        report.mismatches.len()
+    );
+    for mismatch in report.mismatches.iter().take(MAX_SHOWN) {
+        println!(
+            "  0x{:08X} (+0x{:X}), {} bytes",
+            mismatch.offset, mismatch.relative, mismatch.len
+        );
+        println!("    - {}", output::removed(&mismatch.original));
+        println!("    + {}", output::added(&mismatch.encoded));
+    }
+    if report.mismatches.len() > MAX_SHOWN {
+        println!("  ... and {} more", report.mismatches.len() - MAX_SHOWN);
+    }
+}
@@ -0,0 +1,297 @@
+//! Row layouts of excels, so excels other than AllowedLanguage can be decoded without
+//! writing a codec for each. Every excel is a zero byte, a varint row count and the rows,
+//! each starting with a bitmask of the fields present, one bit per field.
+
+use std::io::{self, Cursor, Read, Write};
+
+use byteorder::{ReadBytesExt, WriteBytesExt};
+use serde::{Deserialize, Serialize};
+use varint_rs::{VarintReader, VarintWriter};
+
+use crate::design_index::{ensure_remaining, invalid_data};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Schema {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name_hash: Option<i32>,
+    pub fields: Vec<Field>,
+    /// How the excel is stored in its container. Undone in order before decoding and
+    /// redone in reverse after encoding, plain excels have none.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub transforms: Vec<TransformConfig>,
+}
+
+/// A step between the container bytes and the codec, written as e.g.
+/// `transforms = [{ type = "zstd" }]` in a schema file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TransformConfig {
+    Zstd,
+    Gzip,
+    Lz4,
+    /// XOR with a repeating key, given as hex.
+    Xor {
+        key: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Field {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: FieldType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FieldType {
+    /// A varint length followed by UTF-8.
+    String,
+    /// A varint count followed by strings.
+    StringArray,
+    U8,
+    /// A zigzag encoded signed varint.
+    Varint,
+}
+
+impl FieldType {
+    pub const ALL: [Self; 4] = [Self::String, Self::StringArray, Self::U8, Self::Varint];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::String => "string",
+            Self::StringArray => "string_array",
+            Self::U8 => "u8",
+            Self::Varint => "varint",
+        }
+    }
+
+    /// Parses a value written on the command line. String arrays are comma separated.
+    pub fn parse_value(self, text: &str) -> Option<Value> {
+        Some(match self {
+            Self::String => Value::String(text.to_string()),
+            Self::StringArray => Value::StringArray(text.split(',').map(str::to_string).collect()),
+            Self::U8 => Value::Int(text.parse::<u8>().ok()?.into()),
+            Self::Varint => Value::Int(text.parse().ok()?),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    StringArray(Vec<String>),
+    Int(i64),
+}
+
+/// Field values in schema order, `None` for fields missing from the bitmask.
+pub type Row = Vec<Option<Value>>;
+
+/// Excels that decide which languages the client offers and loads. Only the ones with a
+/// built-in schema can be edited, the others are decoded with an inferred layout.
+pub const LANGUAGE_EXCELS: &[&str] = &["AllowedLanguage", "VoiceConfig"];
+
+impl Schema {
+    /// The layout [`crate::allowed_language::AllowedLanguage`] decodes by hand.
+    pub fn allowed_language() -> Self {
+        let field = |name: &str, kind| Field {
+            name: name.to_string(),
+            kind,
+        };
+        Self {
+            name: "AllowedLanguage".to_string(),
+            name_hash: Some(crate::allowed_language::ALLOWED_LANGUAGE_HASH),
+            fields: vec![
+                field("area", FieldType::String),
+                field("type", FieldType::U8),
+                field("language_list", FieldType::StringArray),
+                field("default_language", FieldType::String),
+            ],
+            transforms: Vec::new(),
+        }
+    }
+
+    /// Schemas built into the patcher, for excels that can be edited by name.
+    pub fn known() -> Vec<Self> {
+        vec![Self::allowed_language()]
+    }
+
+    /// A built-in schema by excel name, ignoring case.
+    pub fn find_known(name: &str) -> Option<Self> {
+        Self::known()
+            .into_iter()
+            .find(|schema| schema.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Index of a field by name.
+    pub fn field_index(&self, name: &str) -> Option<usize> {
+        self.fields.iter().position(|field| field.name == name)
+    }
+
+    pub fn from_toml(text: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    pub fn to_toml(&self) -> String {
+        // A plain struct of strings and lists always serializes
+        toml::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// Bytes used by the bitmask at the start of each row.
+    pub fn bitmask_size(&self) -> usize {
+        self.fields.len().div_ceil(8).max(1)
+    }
+
+    /// Decodes all rows. Trailing zero padding after the rows is ignored, anything else
+    /// left over is an error since it means the layout doesn't fit.
+    pub fn decode(&self, data: &[u8]) -> io::Result<Vec<Row>> {
+        let mut cursor = Cursor::new(data);
+        let rows = self.decode_rows(&mut cursor)?;
+
+        let rest = &data[cursor.position() as usize..];
+        if rest.iter().any(|&byte| byte != 0) {
+            return Err(invalid_data(format!(
+                "{} bytes left after the last row",
+                rest.len()
+            )));
+        }
+        Ok(rows)
+    }
+
+    fn decode_rows(&self, cursor: &mut Cursor<&[u8]>) -> io::Result<Vec<Row>> {
+        cursor.read_u8()?;
+        let count = read_count(cursor, self.bitmask_size() as u64, "row count")?;
+
+        let mut rows = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut bitmask = vec![0u8; self.bitmask_size()];
+            cursor.read_exact(&mut bitmask)?;
+            if let Some(bit) = (self.fields.len()..bitmask.len() * 8).find(|&i| is_set(&bitmask, i))
+            {
+                return Err(invalid_data(format!(
+                    "row has field {bit} set, but the schema only has {} fields",
+                    self.fields.len()
+                )));
+            }
+
+            let row = self
+                .fields
+                .iter()
+                .enumerate()
+                .map(|(i, field)| {
+                    is_set(&bitmask, i)
+                        .then(|| read_value(cursor, field.kind))
+                        .transpose()
+                })
+                .collect::<io::Result<Row>>()?;
+            rows.push(row);
+        }
+        Ok(rows)
+    }
+
+    pub fn encode(&self, rows: &[Row]) -> io::Result<Vec<u8>> {
+        let mut buffer = Vec::new();
+        buffer.write_u8(0)?;
+        write_count(&mut buffer, rows.len(), "row count")?;
+
+        for row in rows {
+            let mut bitmask = vec![0u8; self.bitmask_size()];
+            for (i, value) in row.iter().enumerate() {
+                if value.is_some() {
+                    bitmask[i / 8] |= 1 << (i % 8);
+                }
+            }
+            buffer.write_all(&bitmask)?;
+
+            for (field, value) in self.fields.iter().zip(row) {
+                if let Some(value) = value {
+                    write_value(&mut buffer, field, value)?;
+                }
+            }
+        }
+        Ok(buffer)
+    }
+}
+
+fn is_set(bitmask: &[u8], bit: usize) -> bool {
+    bitmask[bit / 8] & (1 << (bit % 8)) != 0
+}
+
+/// Row and array counts are zigzag varints. Only small ones have been seen so far, which
+/// also fit the `i8` they used to be read as.
+pub(crate) fn read_count(
+    cursor: &mut Cursor<&[u8]>,
+    min_size: u64,
+    field: &str,
+) -> io::Result<usize> {
+    let count = cursor.read_i32_varint()?;
+    let count =
+        u32::try_from(count).map_err(|_| invalid_data(format!("{field} {count} is negative")))?;
+    ensure_remaining(cursor, count, min_size, field)?;
+    Ok(count as usize)
+}
+
+pub(crate) fn write_count(buffer: &mut impl Write, count: usize, field: &str) -> io::Result<()> {
+    let count = i32::try_from(count)
+        .map_err(|_| invalid_data(format!("{field} {count} doesn't fit a varint")))?;
+    buffer.write_i32_varint(count)
+}
+
+pub(crate) fn read_value(cursor: &mut Cursor<&[u8]>, kind: FieldType) -> io::Result<Value> {
+    Ok(match kind {
+        FieldType::String => Value::String(read_string(cursor)?),
+        FieldType::StringArray => Value::StringArray(read_string_array(cursor)?),
+        FieldType::U8 => Value::Int(cursor.read_u8()?.into()),
+        FieldType::Varint => Value::Int(cursor.read_i64_varint()?),
+    })
+}
+
+/// Strings are prefixed with their length in bytes as an unsigned varint, a single byte
+/// only below 128.
+pub(crate) fn read_string(cursor: &mut Cursor<&[u8]>) -> io::Result<String> {
+    let length = cursor.read_u32_varint()?;
+    ensure_remaining(cursor, length, 1, "string length")?;
+    let mut buffer = vec![0u8; length as usize];
+    cursor.read_exact(&mut buffer)?;
+    String::from_utf8(buffer).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+fn write_value(buffer: &mut Vec<u8>, field: &Field, value: &Value) -> io::Result<()> {
+    let mismatch = || {
+        invalid_data(format!(
+            "field '{}' expects a {} value",
+            field.name,
+            field.kind.name()
+        ))
+    };
+    match (field.kind, value) {
+        (FieldType::String, Value::String(s)) => write_string(buffer, s),
+        (FieldType::StringArray, Value::StringArray(strings)) => {
+            write_string_array(buffer, strings)
+        }
+        (FieldType::U8, Value::Int(n)) => {
+            buffer.write_u8(u8::try_from(*n).map_err(|_| mismatch())?)
+        }
+        (FieldType::Varint, Value::Int(n)) => buffer.write_i64_varint(*n),
+        _ => Err(mismatch()),
+    }
+}
+
+pub(crate) fn read_string_array(cursor: &mut Cursor<&[u8]>) -> io::Result<Vec<String>> {
+    // Each string takes at least its length byte
+    let count = read_count(cursor, 1, "string count")?;
+    (0..count).map(|_| read_string(cursor)).collect()
+}
+
+pub(crate) fn write_string(buffer: &mut impl Write, s: &str) -> io::Result<()> {
+    let length = u32::try_from(s.len())
+        .map_err(|_| invalid_data(format!("string of {} bytes is too long", s.len())))?;
+    buffer.write_u32_varint(length)?;
+    buffer.write_all(s.as_bytes())
+}
+
+pub(crate) fn write_string_array(buffer: &mut impl Write, strings: &[String]) -> io::Result<()> {
+    write_count(buffer, strings.len(), "string count")?;
+    strings.iter().try_for_each(|s| write_string(buffer, s))
+}
@@ -0,0 +1,269 @@
+//! Rhai scripts for batch edits the built-in commands don't cover. Scripts see the parsed
+//! index and the schema codec. Their writes are collected and applied together through
+//! [`Game::patch_excels`] once the script finishes, so a failing script changes nothing.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+};
+
+use rhai::{Array, Blob, Dynamic, Engine, EvalAltResult, Map};
+
+use crate::{
+    PatcherError, Result,
+    design_index::{DataEntry, DesignIndex, FileEntry},
+    game::{ALLOWED_LANGUAGE_HASH, Game},
+    paths::long_path,
+    pipeline::{ExcelTarget, is_unchanged},
+    schema::{Field, FieldType, Row, Schema, Value},
+};
+
+type ScriptResult<T> = std::result::Result<T, Box<EvalAltResult>>;
+
+struct Context {
+    game: Game,
+    index: DesignIndex,
+    /// New excel data by name hash, written after the script succeeds.
+    writes: Mutex<BTreeMap<i32, Vec<u8>>>,
+}
+
+impl Game {
+    /// Runs the Rhai script at `path` and writes the excels it changed. Returns their
+    /// number.
+    pub fn run_script(&self, path: &Path) -> Result<usize> {
+        let source = fs::read_to_string(long_path(path)).map_err(|source| PatcherError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        let context = Arc::new(Context {
+            game: self.clone(),
+            index: self.design_index()?,
+            writes: Mutex::default(),
+        });
+        engine(&context)
+            .run(&source)
+            .map_err(|e| PatcherError::Script {
+                path: path.to_path_buf(),
+                message: e.to_string(),
+            })?;
+
+        let writes = std::mem::take(
+            &mut *context
+                .writes
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+        );
+        let targets: Vec<_> = writes
+            .into_iter()
+            .filter_map(|(hash, data)| {
+                let (entry, file) = context.index.find_by_hash(hash)?;
+                Some(ExcelTarget {
+                    file_hash: file.file_hash.clone(),
+                    entry: entry.clone(),
+                    transform: move |region: &[u8]| {
+                        Ok((!is_unchanged(region, &data)).then(|| data.clone()))
+                    },
+                })
+            })
+            .collect();
+        self.patch_excels(&targets)
+    }
+}
+
+impl Context {
+    fn find(&self, hash: i64) -> ScriptResult<(&DataEntry, &FileEntry)> {
+        let hash = i32::try_from(hash).map_err(|_| format!("{hash} is not a name hash"))?;
+        self.index
+            .find_by_hash(hash)
+            .ok_or_else(|| format!("Excel {hash} not found in the DesignV index").into())
+    }
+
+    /// Current data of an excel, including earlier writes of the same script.
+    fn read(&self, hash: i64) -> ScriptResult<Vec<u8>> {
+        let (entry, file) = self.find(hash)?;
+        let writes = self.writes.lock().unwrap_or_else(PoisonError::into_inner);
+        match writes.get(&entry.name_hash) {
+            Some(data) => Ok(data.clone()),
+            None => Ok(self
+                .game
+                .read_region(&file.file_hash, entry)
+                .map_err(|e| e.to_string())?),
+        }
+    }
+
+    fn read_rows(&self, hash: i64, schema: &Schema) -> ScriptResult<Array> {
+        let rows = schema
+            .decode_stored(&self.read(hash)?)
+            .map_err(|e| format!("Failed to decode excel {hash}: {e}"))?;
+        Ok(rows
+            .iter()
+            .map(|row| row_to_map(schema, row).into())
+            .collect())
+    }
+
+    fn write_rows(&self, hash: i64, rows: Array, schema: &Schema) -> ScriptResult<()> {
+        let (entry, _) = self.find(hash)?;
+        let rows = rows
+            .into_iter()
+            .map(|row| {
+                let map = row
+                    .try_cast::<Map>()
+                    .ok_or("Rows must be object maps of field names to values")?;
+                map_to_row(schema, &map)
+            })
+            .collect::<ScriptResult<Vec<_>>>()?;
+
+        let data = schema.encode_stored(&rows).map_err(|e| e.to_string())?;
+        if data.len() > entry.size as usize {
+            return Err(format!(
+                "The rows of excel {hash} need {} bytes, but it only has {}",
+                data.len(),
+                entry.size
+            )
+            .into());
+        }
+        self.writes
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(entry.name_hash, data);
+        Ok(())
+    }
+}
+
+/// Schema used when a script doesn't pass one, only known for AllowedLanguage.
+fn default_schema(hash: i64) -> ScriptResult<Schema> {
+    if hash == i64::from(ALLOWED_LANGUAGE_HASH) {
+        Ok(Schema::allowed_language())
+    } else {
+        Err(format!("Excel {hash} has no built-in schema, pass one from load_schema").into())
+    }
+}
+
+fn engine(context: &Arc<Context>) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .register_type_with_name::<Schema>("Schema")
+        .register_fn("allowed_language_schema", Schema::allowed_language)
+        .register_fn("load_schema", |path: &str| -> ScriptResult<Schema> {
+            let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+            Ok(Schema::from_toml(&text).map_err(|e| format!("{path}: {e}"))?)
+        });
+
+    let ctx = context.clone();
+    engine.register_fn("entries", move || -> Array {
+        ctx.index
+            .files
+            .iter()
+            .flat_map(|file| {
+                file.entries
+                    .iter()
+                    .map(|entry| entry_map(entry, file).into())
+            })
+            .collect()
+    });
+    let ctx = context.clone();
+    engine.register_fn("find_entry", move |hash: i64| -> Dynamic {
+        ctx.find(hash)
+            .map_or(Dynamic::UNIT, |(entry, file)| entry_map(entry, file).into())
+    });
+    let ctx = context.clone();
+    engine.register_fn("read_bytes", move |hash: i64| -> ScriptResult<Blob> {
+        ctx.read(hash)
+    });
+    let ctx = context.clone();
+    engine.register_fn("read_rows", move |hash: i64| -> ScriptResult<Array> {
+        ctx.read_rows(hash, &default_schema(hash)?)
+    });
+    let ctx = context.clone();
+    engine.register_fn(
+        "read_rows",
+        move |hash: i64, schema: Schema| -> ScriptResult<Array> { ctx.read_rows(hash, &schema) },
+    );
+    let ctx = context.clone();
+    engine.register_fn(
+        "write_rows",
+        move |hash: i64, rows: Array| -> ScriptResult<()> {
+            ctx.write_rows(hash, rows, &default_schema(hash)?)
+        },
+    );
+    let ctx = context.clone();
+    engine.register_fn(
+        "write_rows",
+        move |hash: i64, rows: Array, schema: Schema| -> ScriptResult<()> {
+            ctx.write_rows(hash, rows, &schema)
+        },
+    );
+    engine
+}
+
+fn entry_map(entry: &DataEntry, file: &FileEntry) -> Map {
+    let mut map = Map::new();
+    map.insert("name_hash".into(), i64::from(entry.name_hash).into());
+    map.insert("offset".into(), i64::from(entry.offset).into());
+    map.insert("size".into(), i64::from(entry.size).into());
+    map.insert("file_hash".into(), file.file_hash.clone().into());
+    map
+}
+
+/// Fields missing from the row are left out of the map.
+fn row_to_map(schema: &Schema, row: &Row) -> Map {
+    schema
+        .fields
+        .iter()
+        .zip(row)
+        .filter_map(|(field, value)| {
+            let value = match value.as_ref()? {
+                Value::String(s) => s.clone().into(),
+                Value::StringArray(strings) => strings
+                    .iter()
+                    .map(|s| Dynamic::from(s.clone()))
+                    .collect::<Array>()
+                    .into(),
+                Value::Int(n) => (*n).into(),
+            };
+            Some((field.name.as_str().into(), value))
+        })
+        .collect()
+}
+
+/// Missing and `()` values leave the field out of the row.
+fn map_to_row(schema: &Schema, map: &Map) -> ScriptResult<Row> {
+    if let Some(name) = map.keys().find(|name| {
+        !schema
+            .fields
+            .iter()
+            .any(|field| field.name == name.as_str())
+    }) {
+        return Err(format!("{} has no field '{name}'", schema.name).into());
+    }
+
+    schema
+        .fields
+        .iter()
+        .map(|field| match map.get(field.name.as_str()) {
+            Some(value) if !value.is_unit() => field_value(field, value).map(Some),
+            _ => Ok(None),
+        })
+        .collect()
+}
+
+fn field_value(field: &Field, value: &Dynamic) -> ScriptResult<Value> {
+    let mismatch = || format!("Field '{}' expects a {}", field.name, field.kind.name());
+    let string = |value: Dynamic| value.into_string().map_err(|_| mismatch());
+    Ok(match field.kind {
+        FieldType::String => Value::String(string(value.clone())?),
+        FieldType::StringArray => Value::StringArray(
+            value
+                .clone()
+                .into_array()
+                .map_err(|_| mismatch())?
+                .into_iter()
+                .map(string)
+                .collect::<std::result::Result<_, _>>()?,
+        ),
+        FieldType::U8 | FieldType::Varint => Value::Int(value.as_int().map_err(|_| mismatch())?),
+    })
+}
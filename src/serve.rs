@@ -0,0 +1,445 @@
+//! `--serve`: a long-lived JSON-RPC 2.0 server on stdin/stdout for GUI wrappers. Every
+//! line on stdin is a request, every line on stdout a response or a notification.
+//! On Windows the tray answers the same protocol on a named pipe.
+
+use std::{
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    sync::{
+        Arc, Mutex, PoisonError,
+        atomic::{AtomicU64, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use anyhow::Context;
+use serde::Deserialize;
+use serde_json::{Value, json};
+
+use hsr_lang_patcher::{
+    ErrorKind,
+    allowed_language::{ListMode, current_language, validate_rows},
+    detect::default_game_path,
+    game::{Game, Platform},
+    language::Language,
+    pipeline::{PatchPipeline, ProgressObserver, Stage},
+    recipe,
+    state::PatchStatus,
+};
+
+use crate::{Result, confirm_combination, error_kind, tray::reapply_profile};
+
+// JSON-RPC error codes for malformed requests. Failures of a method use the exit codes.
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+
+const DEFAULT_WATCH_INTERVAL: u64 = 60;
+
+#[derive(Deserialize)]
+struct Request {
+    #[serde(default)]
+    id: Value,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct GameParams {
+    path: Option<PathBuf>,
+    platform: Option<Platform>,
+}
+
+#[derive(Deserialize)]
+struct PatchParams {
+    #[serde(flatten)]
+    game: GameParams,
+    text: String,
+    voice: String,
+    #[serde(default)]
+    tweaks: Vec<String>,
+    /// Apply languages the game doesn't list, like `--force`.
+    #[serde(default)]
+    force: bool,
+    /// Only change the defaults, like `--keep-lists`.
+    #[serde(default)]
+    keep_lists: bool,
+    /// Patch the rows of both clients, like `--all-regions`.
+    #[serde(default)]
+    all_regions: bool,
+    /// Fail on an unexpected excel format, like `--strict`.
+    #[serde(default)]
+    strict: bool,
+}
+
+#[derive(Deserialize)]
+struct WatchParams {
+    #[serde(flatten)]
+    game: GameParams,
+    /// Seconds between checks.
+    interval: Option<u64>,
+}
+
+/// One client. Clones share the output, so notifications from a watcher thread and
+/// responses never interleave within a line.
+#[derive(Clone)]
+struct Connection {
+    output: Arc<Mutex<Box<dyn Write + Send>>>,
+    /// Bumped by every `watch` and on disconnect, so a running watcher knows to stop.
+    watch_generation: Arc<AtomicU64>,
+}
+
+pub fn run() -> Result<()> {
+    serve(io::stdin().lock(), io::stdout())
+}
+
+fn serve(input: impl BufRead, output: impl Write + Send + 'static) -> Result<()> {
+    let connection = Connection {
+        output: Arc::new(Mutex::new(Box::new(output))),
+        watch_generation: Arc::new(AtomicU64::new(0)),
+    };
+    let result = connection.handle_requests(input);
+    connection.watch_generation.fetch_add(1, Ordering::SeqCst);
+    result
+}
+
+impl Connection {
+    fn handle_requests(&self, input: impl BufRead) -> Result<()> {
+        for line in input.lines() {
+            let line = line.context("Failed to read a request")?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let request = match serde_json::from_str::<Value>(&line) {
+                Ok(value) => value,
+                Err(e) => {
+                    self.send_error(&Value::Null, PARSE_ERROR, &e.to_string(), None);
+                    continue;
+                }
+            };
+            let id = request.get("id").cloned().unwrap_or_default();
+            // A request without an id is a notification, which never gets a reply
+            let notification = request.get("id").is_none();
+            let request = match serde_json::from_value::<Request>(request) {
+                Ok(request) => request,
+                Err(e) => {
+                    self.send_error(&id, INVALID_REQUEST, &e.to_string(), None);
+                    continue;
+                }
+            };
+
+            let result = match request.method.as_str() {
+                "inspect" => params(request.params).and_then(|p: GameParams| inspect(&p)),
+                "patch" => params(request.params)
+                    .and_then(|p| self.patch((!notification).then_some(&request.id), p)),
+                "restore" => params(request.params).and_then(|p: GameParams| restore(&p)),
+                "status" => params(request.params).and_then(|p: GameParams| status(&p)),
+                "reapply" => reapply_profile().map(|lost| {
+                    let status = lost.map(|reapplied| reapplied.status);
+                    json!({ "changed": status.is_some(), "status": status })
+                }),
+                "watch" => params(request.params).and_then(|p| self.watch(p)),
+                _ if notification => continue,
+                method => {
+                    self.send_error(
+                        &request.id,
+                        METHOD_NOT_FOUND,
+                        &format!("Unknown method '{method}'"),
+                        None,
+                    );
+                    continue;
+                }
+            };
+
+            match result {
+                _ if notification => {}
+                Ok(result) => {
+                    self.send(json!({ "jsonrpc": "2.0", "id": request.id, "result": result }));
+                }
+                Err(e) => {
+                    let kind = error_kind(&e);
+                    self.send_error(
+                        &request.id,
+                        kind.exit_code(),
+                        &format!("{e:#}"),
+                        Some(json!({ "kind": kind })),
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes one line. Returns false once the client is gone.
+    fn send(&self, message: Value) -> bool {
+        let mut output = self.output.lock().unwrap_or_else(PoisonError::into_inner);
+        writeln!(output, "{message}")
+            .and_then(|_| output.flush())
+            .is_ok()
+    }
+
+    fn send_error(&self, id: &Value, code: i32, message: &str, data: Option<Value>) {
+        let mut error = json!({ "code": code, "message": message });
+        if let Some(data) = data {
+            error["data"] = data;
+        }
+        self.send(json!({ "jsonrpc": "2.0", "id": id, "error": error }));
+    }
+
+    fn notify(&self, method: &str, params: Value) -> bool {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    /// `id` is `None` for a notification, which gets no progress either.
+    fn patch(&self, id: Option<&Value>, params: PatchParams) -> Result<Value> {
+        let text = Language::parse_text(&params.text)?;
+        let voice = Language::parse_voice(&params.voice)?;
+        let tweaks = params
+            .tweaks
+            .iter()
+            .map(|name| recipe::find_recipe(name))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let progress = RequestProgress {
+            connection: self,
+            id,
+        };
+        let pipeline = PatchPipeline::new(&progress);
+        let mut game = load_with(&params.game, &pipeline)?;
+        let _lock = game.try_lock()?;
+        game.all_regions = params.all_regions;
+        game.strict = params.strict;
+        confirm_combination(&game, text, voice, params.force)?;
+
+        let mode = if params.keep_lists {
+            ListMode::Keep
+        } else {
+            ListMode::Replace
+        };
+        let report = pipeline.patch(&game, text, voice, mode)?;
+        if !tweaks.is_empty() {
+            progress.send("tweaks");
+            game.apply_recipes(&tweaks)?;
+        }
+
+        Ok(json!({
+            "changed": report.changed(),
+            "skipped": report.skipped,
+            "warnings": report.warnings,
+        }))
+    }
+
+    /// Checks the languages in the background and sends a `changed` notification
+    /// whenever they or the patch status differ from the last check, e.g. after a game
+    /// update reset them.
+    fn watch(&self, params: WatchParams) -> Result<Value> {
+        let interval =
+            Duration::from_secs(params.interval.unwrap_or(DEFAULT_WATCH_INTERVAL).max(1));
+        let languages = move || -> Result<(Option<String>, Option<String>, PatchStatus)> {
+            let game = load(&params.game)?;
+            let rows = game.allowed_language().parse()?;
+            let language = |voice| current_language(&rows, voice).map(str::to_string);
+            Ok((language(false), language(true), game.patch_status()?))
+        };
+
+        let mut last = languages()?;
+        let current = last.clone();
+        let watch_id = self.watch_generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let connection = self.clone();
+
+        thread::spawn(move || {
+            loop {
+                thread::sleep(interval);
+                if connection.watch_generation.load(Ordering::SeqCst) != watch_id {
+                    return;
+                }
+
+                let sent = match languages() {
+                    Ok(now) if now != last => {
+                        let sent = connection.notify(
+                            "changed",
+                            json!({
+                                "text_language": now.0,
+                                "voice_language": now.1,
+                                "status": now.2,
+                            }),
+                        );
+                        last = now;
+                        sent
+                    }
+                    Ok(_) => true,
+                    // The files are briefly missing or locked while the launcher updates
+                    Err(e) => {
+                        connection.notify("watch_error", json!({ "message": format!("{e:#}") }))
+                    }
+                };
+                if !sent {
+                    return;
+                }
+            }
+        });
+
+        Ok(json!({
+            "text_language": current.0,
+            "voice_language": current.1,
+            "status": current.2,
+        }))
+    }
+}
+
+fn params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T> {
+    // Omitted params are the same as an empty object
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params)
+        .context("Invalid params")
+        .context(ErrorKind::InvalidArguments)
+}
+
+/// Sends a `progress` notification for each stage of the request's patch.
+struct RequestProgress<'a> {
+    connection: &'a Connection,
+    id: Option<&'a Value>,
+}
+
+impl RequestProgress<'_> {
+    fn send(&self, stage: &str) {
+        if let Some(id) = self.id {
+            self.connection
+                .notify("progress", json!({ "id": id, "stage": stage }));
+        }
+    }
+}
+
+impl ProgressObserver for RequestProgress<'_> {
+    fn stage_started(&self, stage: Stage) {
+        self.send(stage.name());
+    }
+}
+
+fn load(params: &GameParams) -> Result<Game> {
+    load_with(params, &PatchPipeline::default())
+}
+
+fn load_with(params: &GameParams, pipeline: &PatchPipeline) -> Result<Game> {
+    let game_path = match &params.path {
+        Some(path) => path.clone(),
+        None => default_game_path()?,
+    };
+    let design_data_path = pipeline.locate(&game_path, params.platform.unwrap_or_default())?;
+    Ok(pipeline.index(design_data_path)?)
+}
+
+fn inspect(params: &GameParams) -> Result<Value> {
+    let game = load(params)?;
+    let rows = game.allowed_language().parse()?;
+
+    Ok(json!({
+        "design_data_path": game.design_data_path,
+        "installed": game.is_installed(),
+        "index_hash": game.index_hash,
+        "file_hash": game.file_hash,
+        "offset": game.data_entry.offset,
+        "size": game.data_entry.size,
+        "region": game.client_region(),
+        "text_language": current_language(&rows, false),
+        "voice_language": current_language(&rows, true),
+        "rows": rows.iter().map(|row| json!({
+            "area": row.area(),
+            "type": row.row_type(),
+            "language_list": row.language_list(),
+            "default_language": row.default_language(),
+        })).collect::<Vec<_>>(),
+        "backups": game.backups()?.len(),
+        "anomalies": validate_rows(&rows).iter().map(ToString::to_string).collect::<Vec<_>>(),
+    }))
+}
+
+fn status(params: &GameParams) -> Result<Value> {
+    let game = load(params)?;
+    Ok(json!({ "status": game.patch_status()?, "state": game.patch_state()? }))
+}
+
+fn restore(params: &GameParams) -> Result<Value> {
+    let game = load(params)?;
+    let _lock = game.try_lock()?;
+    Ok(json!({ "restored": game.restore_original()? }))
+}
+
+/// Answers the protocol on [`pipe::NAME`] in the background, one thread per client.
+#[cfg(windows)]
+pub fn spawn_pipe_server() {
+    thread::spawn(|| {
+        loop {
+            let pipe = match pipe::accept() {
+                Ok(pipe) => pipe,
+                Err(e) => {
+                    eprintln!("Failed to open the named pipe {}: {e}", pipe::NAME);
+                    return;
+                }
+            };
+            thread::spawn(move || {
+                if let Ok(input) = pipe.try_clone() {
+                    let _ = serve(io::BufReader::new(input), pipe);
+                }
+            });
+        }
+    });
+}
+
+#[cfg(windows)]
+mod pipe {
+    use std::{
+        fs::File,
+        io,
+        os::windows::io::{FromRawHandle, RawHandle},
+        ptr,
+    };
+
+    use windows_sys::Win32::{
+        Foundation::{ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE},
+        Storage::FileSystem::PIPE_ACCESS_DUPLEX,
+        System::Pipes::{
+            ConnectNamedPipe, CreateNamedPipeW, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE,
+            PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+        },
+    };
+
+    pub const NAME: &str = r"\\.\pipe\hsr-lang-patcher";
+    const BUFFER_SIZE: u32 = 4096;
+
+    /// Creates a pipe instance and waits for a client to connect to it.
+    pub fn accept() -> io::Result<File> {
+        let name: Vec<u16> = NAME.encode_utf16().chain(Some(0)).collect();
+        let handle = unsafe {
+            CreateNamedPipeW(
+                name.as_ptr(),
+                PIPE_ACCESS_DUPLEX,
+                PIPE_TYPE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+                PIPE_UNLIMITED_INSTANCES,
+                BUFFER_SIZE,
+                BUFFER_SIZE,
+                0,
+                ptr::null(),
+            )
+        };
+        if handle == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        // Owns the handle from here on, so it's closed on every path
+        let pipe = unsafe { File::from_raw_handle(handle as RawHandle) };
+
+        if unsafe { ConnectNamedPipe(handle, ptr::null_mut()) } == 0 {
+            let error = io::Error::last_os_error();
+            // A client connecting between the two calls is fine
+            if error.raw_os_error() != Some(ERROR_PIPE_CONNECTED as i32) {
+                return Err(error);
+            }
+        }
+        Ok(pipe)
+    }
+}
@@ -0,0 +1,139 @@
+//! Sidecar file recording the last patch, to tell whether it is still in place or was
+//! lost to a game update.
+
+use std::{fs, io, path::PathBuf};
+
+use md5::{Digest, Md5};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    PatcherError, Result,
+    game::{Game, write_error},
+    language::Language,
+    manifest::RegionStatus,
+    paths::long_path,
+};
+
+const STATE_FILE: &str = "hsr-lang-patcher-state.json";
+
+/// What the last successful patch wrote.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PatchState {
+    pub game_version: Option<String>,
+    pub index_hash: String,
+    pub file_hash: String,
+    pub text_language: String,
+    pub voice_language: String,
+    /// Voice of the CN rows when it differs from `voice_language`, which the global rows
+    /// got.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cn_voice_language: Option<String>,
+    /// MD5 of the AllowedLanguage region after the patch.
+    pub region_md5: String,
+    /// Unix timestamp of the patch.
+    pub patched_at: u64,
+    /// Where the backups were written when it's not next to the game.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backup_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PatchStatus {
+    /// No patch was recorded for this install.
+    NotPatched,
+    /// The patched region is unchanged.
+    Intact,
+    /// The index or container changed since the patch, the patch has to be re-applied.
+    GameUpdated,
+    /// Same game files, but the region no longer holds the patched data, e.g. after the
+    /// launcher repaired the files.
+    Reverted,
+    /// Same game files, but the region holds data neither the patcher nor the game wrote,
+    /// e.g. from another tool. Told apart from [`PatchStatus::Reverted`] by the
+    /// [manifest](crate::manifest).
+    Modified,
+}
+
+impl PatchStatus {
+    pub fn is_lost(self) -> bool {
+        matches!(self, Self::GameUpdated | Self::Reverted | Self::Modified)
+    }
+}
+
+impl Game {
+    /// Kept next to the game even with [`Game::backup_root`], it records where the
+    /// backups are.
+    pub fn state_path(&self) -> PathBuf {
+        self.local_backup_dir().with_file_name(STATE_FILE)
+    }
+
+    /// The last recorded patch, `None` if there is none or it can't be parsed.
+    pub fn patch_state(&self) -> Result<Option<PatchState>> {
+        let path = self.state_path();
+        match fs::read(long_path(&path)) {
+            Ok(data) => Ok(serde_json::from_slice(&data).ok()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(source) => Err(PatcherError::Read { path, source }),
+        }
+    }
+
+    /// Compares the recorded patch with the game files.
+    pub fn patch_status(&self) -> Result<PatchStatus> {
+        let Some(state) = self.patch_state()? else {
+            return Ok(PatchStatus::NotPatched);
+        };
+        if state.index_hash != self.index_hash || state.file_hash != self.file_hash {
+            return Ok(PatchStatus::GameUpdated);
+        }
+        if self.region_md5()? != state.region_md5 {
+            let written = self.written_region(&self.file_hash, self.data_entry.offset as u64)?;
+            return Ok(match written {
+                Some(region) if self.region_status(&region)? == RegionStatus::Modified => {
+                    PatchStatus::Modified
+                }
+                _ => PatchStatus::Reverted,
+            });
+        }
+        Ok(PatchStatus::Intact)
+    }
+
+    /// Forgets the recorded patch, after the original data was restored.
+    pub(crate) fn clear_patch_state(&self) -> Result<()> {
+        let path = self.state_path();
+        match fs::remove_file(long_path(&path)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(write_error(&path, 0, e)),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn save_patch_state(
+        &self,
+        text_lang: &str,
+        voice_lang: &str,
+        cn_voice_lang: Option<Language>,
+    ) -> Result<()> {
+        let state = PatchState {
+            game_version: self.game_version(),
+            index_hash: self.index_hash.clone(),
+            file_hash: self.file_hash.clone(),
+            text_language: text_lang.to_string(),
+            voice_language: voice_lang.to_string(),
+            cn_voice_language: cn_voice_lang.map(|lang| lang.to_string()),
+            region_md5: self.region_md5()?,
+            patched_at: crate::backup::now(),
+            backup_dir: Some(self.backup_dir()).filter(|dir| *dir != self.local_backup_dir()),
+        };
+
+        let path = self.state_path();
+        let data = serde_json::to_vec_pretty(&state).map_err(io::Error::from);
+        data.and_then(|data| fs::write(long_path(&path), data))
+            .map_err(|source| write_error(&path, 0, source))
+    }
+
+    fn region_md5(&self) -> Result<String> {
+        Ok(hex::encode(Md5::digest(
+            self.allowed_language().read_raw()?,
+        )))
+    }
+}
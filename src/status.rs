@@ -0,0 +1,65 @@
+//! `status`: whether the last patch is still in place, from the state file it left, and
+//! what became of every region the patcher wrote.
+
+use hsr_lang_patcher::{game::Game, manifest::RegionStatus, state::PatchStatus};
+
+use crate::{Result, backups, output};
+
+pub fn status(game: &Game, quiet: bool) -> Result<()> {
+    let status = game.patch_status()?;
+    let summary = match status {
+        PatchStatus::NotPatched => "Not patched by this patcher yet".to_string(),
+        PatchStatus::Intact => output::success("Patch intact"),
+        PatchStatus::GameUpdated => format!(
+            "{}: Patch lost, the game was updated since. Patch again to restore the languages",
+            output::warning()
+        ),
+        PatchStatus::Reverted => format!(
+            "{}: Patch lost, the game files were reverted, e.g. by a launcher repair",
+            output::warning()
+        ),
+        PatchStatus::Modified => format!(
+            "{}: Patch lost, something other than the game or the patcher changed the \
+            languages",
+            output::warning()
+        ),
+    };
+    println!("{summary}");
+    if quiet {
+        return Ok(());
+    }
+
+    if let Some(state) = game.patch_state()? {
+        let voice = match &state.cn_voice_language {
+            Some(cn) => format!("os {}, cn {cn}", state.voice_language),
+            None => state.voice_language.clone(),
+        };
+        println!(
+            "\nLast patch: text {}, voice {voice}, {}",
+            state.text_language,
+            backups::age(state.patched_at)
+        );
+        println!(
+            "Game version: {}",
+            state.game_version.as_deref().unwrap_or("unknown")
+        );
+        println!("Index:        {}", state.index_hash);
+    }
+
+    let manifest = game.manifest()?;
+    if !manifest.regions.is_empty() {
+        println!("\nWritten regions:");
+        for region in &manifest.regions {
+            let status = match game.region_status(region)? {
+                RegionStatus::Intact => output::success("intact"),
+                RegionStatus::Updated => output::highlight("overwritten by a game update"),
+                RegionStatus::Modified => output::removed("modified by something else"),
+            };
+            println!(
+                "  {}.bytes {:#010x} {:>6} bytes  {status}",
+                region.file_hash, region.offset, region.size
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use std::{env, process::Command};
+
+use anyhow::{Context, anyhow};
+
+use crate::Result;
+
+const TASK_NAME: &str = "hsr-lang-patcher";
+
+/// Registers a scheduled task re-applying the saved profile, so the patch
+/// survives game updates installed in the background. With `daemon`, the task starts
+/// the `daemon` at logon instead, which also survives reboots.
+pub fn install(schedule: Option<&str>, daemon: bool, notify: bool) -> Result<()> {
+    let exe = env::current_exe()?;
+    let (task_run, schedule) = if daemon {
+        if schedule.is_some_and(|schedule| schedule != "logon") {
+            return Err(anyhow!("The daemon can only be started at logon"));
+        }
+        let notify = if notify { " --notify" } else { "" };
+        (
+            format!("\"{}\" daemon --quiet{notify}", exe.display()),
+            "ONLOGON",
+        )
+    } else {
+        let schedule = periodic_schedule(schedule)?;
+        (format!("\"{}\" --last --quiet", exe.display()), schedule)
+    };
+    // Runs with the user's normal rights. The hooks in the config are run by the task,
+    // so an elevated task would let anything that can edit the config run as admin
+    schtasks(&[
+        "/Create", "/F", "/TN", TASK_NAME, "/TR", &task_run, "/SC", schedule,
+    ])?;
+
+    println!("Registered scheduled task '{TASK_NAME}'");
+    Ok(())
+}
+
+fn periodic_schedule(schedule: Option<&str>) -> Result<&'static str> {
+    Ok(match schedule.unwrap_or("logon") {
+        "logon" => "ONLOGON",
+        "hourly" => "HOURLY",
+        "daily" => "DAILY",
+        other => {
+            return Err(anyhow!(
+                "Invalid schedule '{other}'. Must be logon, hourly, or daily"
+            ));
+        }
+    })
+}
+
+pub fn uninstall() -> Result<()> {
+    schtasks(&["/Delete", "/F", "/TN", TASK_NAME])?;
+
+    println!("Removed scheduled task '{TASK_NAME}'");
+    Ok(())
+}
+
+fn schtasks(args: &[&str]) -> Result<()> {
+    if !cfg!(windows) {
+        return Err(anyhow!("Scheduled tasks are only available on Windows"));
+    }
+
+    let output = Command::new("schtasks")
+        .args(args)
+        .output()
+        .context("Failed to run schtasks")?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "schtasks failed: {}\nTry running as administrator",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(())
+}
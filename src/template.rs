@@ -0,0 +1,171 @@
+//! Describes a [`Schema`] in the languages of external binary viewers, so an excel can
+//! be opened in them with the same layout the patcher uses.
+
+use std::fmt::Write;
+
+use crate::schema::{FieldType, Schema};
+
+impl Schema {
+    /// Kaitai Struct definition (`.ksy`). With `offset`, the excel is read from that
+    /// offset of a whole container instead of from a file holding only the excel.
+    pub fn to_kaitai(&self, offset: Option<u64>) -> String {
+        let id = identifier(&self.name);
+        let mut ksy = String::new();
+        let _ = writeln!(
+            ksy,
+            "# Generated by hsr-lang-patcher from the {} schema",
+            self.name
+        );
+        let _ = writeln!(ksy, "meta:\n  id: {id}\n  endian: le");
+
+        let excel_seq = "  - id: unk\n    type: u1\n\
+            \x20 - id: row_count\n    type: varint\n\
+            \x20 - id: rows\n    type: row\n    repeat: expr\n    repeat-expr: row_count.value\n";
+        match offset {
+            Some(offset) => {
+                let _ = write!(
+                    ksy,
+                    "instances:\n  excel:\n    pos: {offset}\n    type: excel\n\
+                    types:\n  excel:\n    seq:\n{}",
+                    indent(excel_seq, 4)
+                );
+            }
+            None => {
+                let _ = write!(ksy, "seq:\n{excel_seq}types:\n");
+            }
+        }
+
+        ksy.push_str("  row:\n    seq:\n");
+        for i in 0..self.bitmask_size() {
+            let _ = writeln!(ksy, "      - id: bitmask_{i}\n        type: u1");
+        }
+        for (i, field) in self.fields.iter().enumerate() {
+            let kind = match field.kind {
+                FieldType::String => "string",
+                FieldType::StringArray => "string_array",
+                FieldType::U8 => "u1",
+                FieldType::Varint => "varint",
+            };
+            let _ = writeln!(
+                ksy,
+                "      - id: {}\n        type: {kind}\n        if: (bitmask_{} & {}) != 0",
+                identifier(&field.name),
+                i / 8,
+                1 << (i % 8)
+            );
+        }
+
+        ksy.push_str(
+            "  string:\n    seq:\n\
+            \x20     - id: length\n        type: u1\n\
+            \x20     - id: value\n        type: str\n        size: length\n        encoding: UTF-8\n\
+            \x20 string_array:\n    seq:\n\
+            \x20     - id: count\n        type: varint\n\
+            \x20     - id: items\n        type: string\n        repeat: expr\n        repeat-expr: count.value\n\
+            \x20 varint:\n    doc: Zigzag encoded little endian base 128\n    seq:\n\
+            \x20     - id: groups\n        type: u1\n        repeat: until\n        repeat-until: (_ & 0x80) == 0\n\
+            \x20   instances:\n      raw:\n        value: >-\n",
+        );
+        // Kaitai has no loops in expressions, five groups cover 32 bit values
+        let groups: Vec<String> = (0..5)
+            .map(|i| match i {
+                0 => "(groups[0] & 0x7f)".to_string(),
+                _ => format!(
+                    "(groups.size > {i} ? (groups[{i}] & 0x7f) << {} : 0)",
+                    7 * i
+                ),
+            })
+            .collect();
+        let _ = writeln!(ksy, "          {}", groups.join("\n          + "));
+        ksy.push_str("      value:\n        value: (raw >> 1) ^ -(raw & 1)\n");
+        ksy
+    }
+
+    /// 010 Editor binary template (`.bt`). With `offset`, parsing starts at that offset
+    /// of a whole container.
+    pub fn to_010_template(&self, offset: Option<u64>) -> String {
+        let mut bt = String::new();
+        let _ = writeln!(
+            bt,
+            "// Generated by hsr-lang-patcher from the {} schema",
+            self.name
+        );
+        bt.push_str(
+            "LittleEndian();\n\n\
+            typedef struct {\n\
+            \x20   local int count = 0;\n\
+            \x20   while (ReadUByte(FTell() + count) & 0x80) count++;\n\
+            \x20   ubyte groups[count + 1];\n\
+            \x20   local uint64 raw = 0;\n\
+            \x20   local int i;\n\
+            \x20   for (i = count; i >= 0; i--) raw = (raw << 7) | (groups[i] & 0x7F);\n\
+            \x20   local int64 value = (raw >> 1) ^ -(int64)(raw & 1);\n\
+            } VARINT <read=ReadVARINT>;\n\n\
+            string ReadVARINT(VARINT &v) { return Str(\"%Ld\", v.value); }\n\n\
+            typedef struct {\n\
+            \x20   ubyte length;\n\
+            \x20   if (length > 0) char text[length];\n\
+            } STRING <read=ReadSTRING>;\n\n\
+            string ReadSTRING(STRING &s) { return s.length > 0 ? s.text : \"\"; }\n\n\
+            typedef struct {\n\
+            \x20   VARINT count;\n\
+            \x20   STRING items[count.value] <optimize=false>;\n\
+            } STRING_ARRAY;\n\n\
+            typedef struct {\n",
+        );
+        let _ = writeln!(bt, "    ubyte bitmask[{}];", self.bitmask_size());
+        for (i, field) in self.fields.iter().enumerate() {
+            let kind = match field.kind {
+                FieldType::String => "STRING",
+                FieldType::StringArray => "STRING_ARRAY",
+                FieldType::U8 => "ubyte",
+                FieldType::Varint => "VARINT",
+            };
+            let _ = writeln!(
+                bt,
+                "    if (bitmask[{}] & {}) {kind} {};",
+                i / 8,
+                1 << (i % 8),
+                identifier(&field.name)
+            );
+        }
+        bt.push_str("} ROW;\n\n");
+
+        if let Some(offset) = offset {
+            let _ = writeln!(bt, "FSeek({offset});");
+        }
+        bt.push_str("ubyte unk;\nVARINT row_count;\nROW rows[row_count.value] <optimize=false>;\n");
+        bt
+    }
+}
+
+/// Lower snake case with only ASCII letters, digits and underscores, which both
+/// formats accept as a field name.
+fn identifier(name: &str) -> String {
+    let mut id = String::new();
+    let mut previous = '_';
+    for c in name.chars() {
+        if c.is_ascii_uppercase()
+            && previous.is_ascii_alphanumeric()
+            && !previous.is_ascii_uppercase()
+        {
+            id.push('_');
+        }
+        id.push(if c.is_ascii_alphanumeric() {
+            c.to_ascii_lowercase()
+        } else {
+            '_'
+        });
+        previous = c;
+    }
+    if !id.starts_with(|c: char| c.is_ascii_lowercase()) {
+        id.insert_str(0, "f_");
+    }
+    id
+}
+
+fn indent(text: &str, spaces: usize) -> String {
+    text.lines()
+        .map(|line| format!("{}{line}\n", " ".repeat(spaces)))
+        .collect()
+}
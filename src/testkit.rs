@@ -0,0 +1,199 @@
+//! Builds synthetic game installs for integration tests, so the patch pipeline can be
+//! exercised without a copy of the game. Enabled with the `testkit` feature.
+
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU32, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    allowed_language::{AllowedLanguage, AllowedLanguageRow},
+    design_index::{DataEntry, DesignIndex, FileEntry},
+    game::ALLOWED_LANGUAGE_HASH,
+};
+
+/// Describes the files of a synthetic install. Defaults to the four os/cn text and
+/// voice rows of a global client, with some unrelated data around the excel.
+pub struct FixtureBuilder {
+    rows: Vec<AllowedLanguageRow>,
+    index_hash: [u8; 16],
+    file_hash: [u8; 16],
+    legacy: bool,
+    padding: usize,
+    with_executable: bool,
+}
+
+impl Default for FixtureBuilder {
+    fn default() -> Self {
+        let os_languages: &[&str] = &["en", "jp", "kr", "cn"];
+        Self {
+            rows: vec![
+                AllowedLanguageRow::new(Some("os"), None, Some(os_languages), Some("en")),
+                AllowedLanguageRow::new(Some("os"), Some(1), Some(os_languages), Some("en")),
+                AllowedLanguageRow::new(Some("cn"), None, Some(&["cn"]), Some("cn")),
+                AllowedLanguageRow::new(Some("cn"), Some(1), Some(&["cn"]), Some("cn")),
+            ],
+            index_hash: *b"\x00\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c\x0d\x0e\x0f",
+            file_hash: *b"\x00\x11\x22\x33\x44\x55\x66\x77\x88\x99\xaa\xbb\xcc\xdd\xee\xff",
+            legacy: false,
+            padding: 16,
+            with_executable: true,
+        }
+    }
+}
+
+impl FixtureBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rows of the AllowedLanguage excel.
+    pub fn rows(mut self, rows: Vec<AllowedLanguageRow>) -> Self {
+        self.rows = rows;
+        self
+    }
+
+    pub fn index_hash(mut self, hash: [u8; 16]) -> Self {
+        self.index_hash = hash;
+        self
+    }
+
+    pub fn file_hash(mut self, hash: [u8; 16]) -> Self {
+        self.file_hash = hash;
+        self
+    }
+
+    /// Writes the index in the older format with a single trailing byte per file.
+    pub fn legacy(mut self, legacy: bool) -> Self {
+        self.legacy = legacy;
+        self
+    }
+
+    /// Zero bytes reserved after the rows, room the patch can grow into.
+    pub fn padding(mut self, padding: usize) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    /// Without an executable only the bare DesignData folder is created.
+    pub fn with_executable(mut self, with_executable: bool) -> Self {
+        self.with_executable = with_executable;
+        self
+    }
+
+    /// Creates the install below `root`.
+    pub fn build(self, root: &Path) -> io::Result<Fixture> {
+        let design_data_path = if self.with_executable {
+            root.join("StarRail_Data/StreamingAssets/DesignData/Windows")
+        } else {
+            root.to_path_buf()
+        };
+        fs::create_dir_all(&design_data_path)?;
+        if self.with_executable {
+            fs::write(root.join("StarRail.exe"), b"MZ")?;
+        }
+
+        // The index hash is stored with every 4 byte chunk reversed
+        let mut m_design_v = vec![0u8; 0x40];
+        for (i, chunk) in self.index_hash.chunks(4).enumerate() {
+            let offset = 0x1C + i * 4;
+            m_design_v[offset..offset + 4]
+                .copy_from_slice(&[chunk[3], chunk[2], chunk[1], chunk[0]]);
+        }
+        fs::write(design_data_path.join("M_DesignV.bytes"), m_design_v)?;
+
+        // Unrelated excels before and after make offset mistakes visible
+        let mut excel = AllowedLanguage::write_rows(&self.rows)?;
+        let excel_size = excel.len() + self.padding;
+        excel.resize(excel_size, 0);
+
+        let leading = vec![0xAA; 32];
+        let trailing = vec![0xBB; 32];
+        let mut container = leading.clone();
+        container.extend_from_slice(&excel);
+        container.extend_from_slice(&trailing);
+
+        let bytes_path = design_data_path.join(format!("{}.bytes", hex::encode(self.file_hash)));
+        fs::write(&bytes_path, &container)?;
+
+        let entries = [
+            (1, leading.len(), 0),
+            (ALLOWED_LANGUAGE_HASH, excel_size, leading.len()),
+            (2, trailing.len(), leading.len() + excel_size),
+        ]
+        .map(|(name_hash, size, offset)| DataEntry {
+            name_hash,
+            size: size as i32,
+            offset: offset as i32,
+        });
+
+        let index = DesignIndex {
+            file_count: 1,
+            files: vec![FileEntry {
+                file_hash: hex::encode(self.file_hash),
+                read_size: container.len() as u64,
+                entry_count: entries.len() as u32,
+                entries: entries.to_vec(),
+                ..Default::default()
+            }],
+            legacy: self.legacy,
+            ..Default::default()
+        };
+
+        let index_path =
+            design_data_path.join(format!("DesignV_{}.bytes", hex::encode(self.index_hash)));
+        fs::write(&index_path, index.serialize()?)?;
+
+        Ok(Fixture {
+            root: root.to_path_buf(),
+            design_data_path,
+            bytes_path,
+            index_path,
+            excel_offset: leading.len() as u64,
+            excel_size,
+            temporary: false,
+        })
+    }
+
+    /// Creates the install in a fresh temporary folder that is removed on drop.
+    pub fn build_temp(self) -> io::Result<Fixture> {
+        static COUNTER: AtomicU32 = AtomicU32::new(0);
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.subsec_nanos());
+        let root = env::temp_dir().join(format!(
+            "hsr-lang-patcher-fixture-{}-{nanos}-{}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let mut fixture = self.build(&root)?;
+        fixture.temporary = true;
+        Ok(fixture)
+    }
+}
+
+/// Paths and layout of a built install.
+pub struct Fixture {
+    /// Game folder, or the DesignData folder itself without an executable.
+    pub root: PathBuf,
+    pub design_data_path: PathBuf,
+    /// The excel container holding the AllowedLanguage rows.
+    pub bytes_path: PathBuf,
+    pub index_path: PathBuf,
+    pub excel_offset: u64,
+    pub excel_size: usize,
+    temporary: bool,
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        if self.temporary {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+}
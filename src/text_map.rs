@@ -0,0 +1,74 @@
+//! Where the text of each language lives. The client keeps one `TextMap` per language in
+//! the DesignData containers, listed in the same index as the excels. A default text
+//! language without its TextMap shows blank UI strings.
+
+use std::fs;
+
+use crate::{Result, design_index::stable_hash, game::Game, language::Language, paths::long_path};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextMapStatus {
+    Present {
+        size: u64,
+    },
+    /// Not in the index, or its container wasn't downloaded.
+    Missing,
+    /// Listed with no data, or its container is cut short.
+    Empty,
+    /// No TextMap of any language is listed, so this version keeps them elsewhere and
+    /// nothing can be said.
+    Unknown,
+}
+
+pub fn text_map_suffix(lang: Language) -> &'static str {
+    match lang {
+        Language::Cn => "CHS",
+        Language::En => "EN",
+        Language::Kr => "KR",
+        Language::Jp => "JP",
+        Language::Cht => "CHT",
+        Language::De => "DE",
+        Language::Es => "ES",
+        Language::Fr => "FR",
+        Language::Id => "ID",
+        Language::Pt => "PT",
+        Language::Ru => "RU",
+        Language::Th => "TH",
+        Language::Vi => "VI",
+    }
+}
+
+/// Name hash of a language's TextMap in the index.
+pub fn text_map_hash(lang: Language) -> i32 {
+    stable_hash(&format!(
+        "BakedConfig/TextMap/TextMap{}.bytes",
+        text_map_suffix(lang)
+    ))
+}
+
+impl Game {
+    pub fn text_map_status(&self, lang: Language) -> Result<TextMapStatus> {
+        let index = self.design_index()?;
+        let listed = |lang: Language| index.find_by_hash(text_map_hash(lang));
+        if Language::ALL.into_iter().all(|lang| listed(lang).is_none()) {
+            return Ok(TextMapStatus::Unknown);
+        }
+
+        let Some((entry, file)) = listed(lang) else {
+            return Ok(TextMapStatus::Missing);
+        };
+        let container = self
+            .design_data_path
+            .join(format!("{}.bytes", file.file_hash));
+        let Ok(metadata) = fs::metadata(long_path(&container)) else {
+            return Ok(TextMapStatus::Missing);
+        };
+        let size = u64::try_from(entry.size).unwrap_or_default();
+        let end = u64::try_from(entry.offset).unwrap_or_default() + size;
+        Ok(if size == 0 || metadata.len() < end {
+            TextMapStatus::Empty
+        } else {
+            TextMapStatus::Present { size }
+        })
+    }
+}
@@ -0,0 +1,38 @@
+//! Durations of the pipeline stages for `--timings`, to see where a slow run spends its
+//! time. Nothing is recorded until [`enable`] is called.
+
+use std::{
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STAGES: Mutex<Vec<(&'static str, Duration)>> = Mutex::new(Vec::new());
+
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Runs `f` and records how long it took under `name`.
+pub fn stage<T>(name: &'static str, f: impl FnOnce() -> T) -> T {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed();
+    STAGES
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .push((name, elapsed));
+    result
+}
+
+/// The recorded stages in the order they finished, clearing them.
+pub fn take() -> Vec<(&'static str, Duration)> {
+    std::mem::take(&mut *STAGES.lock().unwrap_or_else(PoisonError::into_inner))
+}
@@ -0,0 +1,160 @@
+//! Steps between the bytes in a container and the bytes a [`Schema`] decodes. Excels are
+//! stored plain today, but other HoYo titles compress or encrypt theirs, so a schema can
+//! list [`TransformConfig`]s that are undone before decoding and redone after encoding.
+
+use std::io;
+
+use crate::{
+    compression::Compression,
+    design_index::invalid_data,
+    schema::{Row, Schema, TransformConfig},
+};
+
+pub trait Transform: Send + Sync {
+    fn name(&self) -> &'static str;
+    /// Container bytes to the bytes the codec reads.
+    fn decode(&self, stored: &[u8]) -> io::Result<Vec<u8>>;
+    /// The codec's bytes back to how the container holds them.
+    fn encode(&self, data: &[u8]) -> io::Result<Vec<u8>>;
+}
+
+/// Leaves the data as it is, what every excel uses so far.
+pub struct Identity;
+
+impl Transform for Identity {
+    fn name(&self) -> &'static str {
+        "identity"
+    }
+
+    fn decode(&self, stored: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(stored.to_vec())
+    }
+
+    fn encode(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(data.to_vec())
+    }
+}
+
+pub struct Decompress(pub Compression);
+
+impl Transform for Decompress {
+    fn name(&self) -> &'static str {
+        self.0.name()
+    }
+
+    fn decode(&self, stored: &[u8]) -> io::Result<Vec<u8>> {
+        self.0.decompress(stored)
+    }
+
+    fn encode(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        self.0.compress(data)
+    }
+}
+
+/// XOR with a repeating key. Trailing zeros are taken as the padding of the region and
+/// kept, since the stored length isn't known otherwise.
+pub struct Decrypt {
+    key: Vec<u8>,
+}
+
+impl Decrypt {
+    pub fn new(key: Vec<u8>) -> io::Result<Self> {
+        if key.is_empty() {
+            return Err(invalid_data("XOR key is empty".to_string()));
+        }
+        Ok(Self { key })
+    }
+
+    fn xor(&self, data: &[u8]) -> Vec<u8> {
+        data.iter()
+            .zip(self.key.iter().cycle())
+            .map(|(byte, key)| byte ^ key)
+            .collect()
+    }
+}
+
+impl Transform for Decrypt {
+    fn name(&self) -> &'static str {
+        "xor"
+    }
+
+    fn decode(&self, stored: &[u8]) -> io::Result<Vec<u8>> {
+        let end = stored
+            .iter()
+            .rposition(|&byte| byte != 0)
+            .map_or(0, |i| i + 1);
+        let mut data = self.xor(&stored[..end]);
+        data.resize(stored.len(), 0);
+        Ok(data)
+    }
+
+    fn encode(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        Ok(self.xor(data))
+    }
+}
+
+impl TransformConfig {
+    pub fn build(&self) -> io::Result<Box<dyn Transform>> {
+        Ok(match self {
+            Self::Zstd => Box::new(Decompress(Compression::Zstd)),
+            Self::Gzip => Box::new(Decompress(Compression::Gzip)),
+            Self::Lz4 => Box::new(Decompress(Compression::Lz4)),
+            Self::Xor { key } => Box::new(Decrypt::new(
+                hex::decode(key).map_err(|e| invalid_data(format!("XOR key: {e}")))?,
+            )?),
+        })
+    }
+}
+
+/// Transforms applied one after another, encoding runs them in reverse.
+pub struct Chain(pub Vec<Box<dyn Transform>>);
+
+impl Chain {
+    pub fn new(configs: &[TransformConfig]) -> io::Result<Self> {
+        Ok(Self(
+            configs
+                .iter()
+                .map(TransformConfig::build)
+                .collect::<io::Result<_>>()?,
+        ))
+    }
+}
+
+impl Transform for Chain {
+    fn name(&self) -> &'static str {
+        "chain"
+    }
+
+    fn decode(&self, stored: &[u8]) -> io::Result<Vec<u8>> {
+        self.0
+            .iter()
+            .try_fold(stored.to_vec(), |data, transform| transform.decode(&data))
+    }
+
+    fn encode(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        self.0
+            .iter()
+            .rev()
+            .try_fold(data.to_vec(), |data, transform| transform.encode(&data))
+    }
+}
+
+impl Schema {
+    /// Decodes the rows from the bytes in the container, undoing the schema's
+    /// transforms first.
+    pub fn decode_stored(&self, stored: &[u8]) -> io::Result<Vec<Row>> {
+        if self.transforms.is_empty() {
+            return self.decode(stored);
+        }
+        self.decode(&Chain::new(&self.transforms)?.decode(stored)?)
+    }
+
+    /// Encodes the rows into the bytes the container holds.
+    pub fn encode_stored(&self, rows: &[Row]) -> io::Result<Vec<u8>> {
+        let data = self.encode(rows)?;
+        if self.transforms.is_empty() {
+            return Ok(data);
+        }
+        Chain::new(&self.transforms)?.encode(&data)
+    }
+}
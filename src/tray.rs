@@ -0,0 +1,360 @@
+#[cfg(not(windows))]
+use std::thread;
+use std::{path::PathBuf, time::Duration};
+
+use hsr_lang_patcher::{
+    PatcherError,
+    allowed_language::ListMode,
+    detect::{default_game_path, update_activity},
+    game::{Game, get_platform_design_data_path},
+    language::Language,
+    state::PatchStatus,
+};
+
+use crate::{
+    Result,
+    config::Config,
+    i18n::{self, Msg},
+};
+
+/// What [`reapply_profile`] wrote over.
+pub(crate) struct Reapplied {
+    /// How the patch was lost.
+    pub status: PatchStatus,
+    pub game_version: Option<String>,
+}
+
+impl Reapplied {
+    /// Tells a game update apart from other ways the patch got lost.
+    pub fn message(&self) -> String {
+        let message = match self.status {
+            PatchStatus::GameUpdated => i18n::tr(Msg::ReappliedAfterUpdate),
+            _ => i18n::tr(Msg::Reapplied),
+        };
+        match &self.game_version {
+            Some(version) => format!("{message} (v{version})"),
+            None => message.to_string(),
+        }
+    }
+}
+
+/// Re-applies the saved profile if the game files no longer match it,
+/// e.g. after the launcher installed an update. Returns how the patch was lost if a
+/// write happened.
+pub(crate) fn reapply_profile() -> Result<Option<Reapplied>> {
+    let config = Config::load()?;
+    let (text_lang, voices) = config.profile()?;
+    // The files are about to be replaced, the next check after the update patches them
+    if update_activity(&profile_game_path(&config)?).is_some() {
+        return Ok(None);
+    }
+
+    let mut game = Game::load(profile_design_data_path(&config)?)?;
+    game.full_backups = config.full_backups;
+    game.all_regions = config.all_regions;
+    game.strict = config.strict;
+    game.backup_root = config.backup_dir.clone();
+    // A run from the command line is changing the install, the next check looks again
+    let _lock = match game.try_lock() {
+        Err(PatcherError::InstallLocked { .. }) => return Ok(None),
+        lock => lock?,
+    };
+
+    // The state file answers the common case without parsing the excel
+    let status = game.patch_status()?;
+    let state = game.patch_state()?;
+    if status == PatchStatus::Intact
+        && state.is_some_and(|state| {
+            state.text_language == text_lang.code()
+                && state.voice_language == voices.os.code()
+                && state.cn_voice_language.as_deref() == voices.cn_override().map(Language::code)
+        })
+    {
+        return Ok(None);
+    }
+
+    let mode = if config.keep_lists {
+        ListMode::Keep
+    } else {
+        ListMode::Replace
+    };
+    let report = game.patch_with(text_lang, voices, mode)?;
+    Ok(report.changed().then_some(Reapplied {
+        status,
+        game_version: report.game_version,
+    }))
+}
+
+/// The saved game path, or the detected install.
+pub(crate) fn profile_game_path(config: &Config) -> Result<PathBuf> {
+    Ok(match &config.game_path {
+        Some(path) => path.clone(),
+        None => default_game_path()?,
+    })
+}
+
+/// DesignData folder of [`profile_game_path`].
+pub(crate) fn profile_design_data_path(config: &Config) -> Result<PathBuf> {
+    Ok(get_platform_design_data_path(
+        &profile_game_path(config)?,
+        config.platform,
+    )?)
+}
+
+pub(crate) const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+pub fn run() -> Result<()> {
+    // Fail early in the console instead of sitting in the tray with a broken profile
+    if let Some(reapplied) = reapply_profile()? {
+        println!("{}", reapplied.message());
+    }
+
+    #[cfg(windows)]
+    return windows::run();
+
+    #[cfg(not(windows))]
+    {
+        println!("Tray icons are only available on Windows, watching from the console instead");
+        loop {
+            thread::sleep(CHECK_INTERVAL);
+            match reapply_profile() {
+                Ok(Some(reapplied)) => println!("{}", reapplied.message()),
+                Ok(None) => {}
+                Err(e) => eprintln!("{}: {e:#}", i18n::tr(Msg::Error)),
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+mod windows {
+    use std::{cell::RefCell, io, mem, path::PathBuf, ptr};
+
+    use windows_sys::Win32::{
+        Foundation::{HWND, LPARAM, LRESULT, POINT, WPARAM},
+        System::LibraryLoader::GetModuleHandleW,
+        UI::{
+            Shell::{
+                NIF_ICON, NIF_INFO, NIF_MESSAGE, NIF_TIP, NIIF_ERROR, NIIF_INFO, NIM_ADD,
+                NIM_DELETE, NIM_MODIFY, NOTIFYICONDATAW, Shell_NotifyIconW,
+            },
+            WindowsAndMessaging::{
+                AppendMenuW, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyMenu,
+                DispatchMessageW, GetCursorPos, GetMessageW, HWND_MESSAGE, IDI_APPLICATION,
+                LoadIconW, MF_STRING, MSG, PostQuitMessage, RegisterClassW, SetForegroundWindow,
+                SetTimer, TPM_NONOTIFY, TPM_RETURNCMD, TrackPopupMenu, TranslateMessage, WM_APP,
+                WM_COMMAND, WM_LBUTTONDBLCLK, WM_RBUTTONUP, WM_TIMER, WNDCLASSW,
+            },
+        },
+    };
+
+    use super::{CHECK_INTERVAL, reapply_profile};
+    use crate::{
+        Result,
+        config::Config,
+        i18n::{self, Msg},
+        notifications,
+    };
+
+    const WM_TRAY: u32 = WM_APP + 1;
+    const CHECK_TIMER: usize = 1;
+    const MENU_REAPPLY: usize = 1;
+    const MENU_EXIT: usize = 2;
+
+    struct Tray {
+        icon: NOTIFYICONDATAW,
+        last_error: Option<String>,
+    }
+
+    thread_local! {
+        static TRAY: RefCell<Option<Tray>> = const { RefCell::new(None) };
+    }
+
+    fn game_path_label() -> PathBuf {
+        Config::load()
+            .ok()
+            .and_then(|config| config.game_path)
+            .unwrap_or_default()
+    }
+
+    fn wide(s: &str) -> Vec<u16> {
+        s.encode_utf16().chain(Some(0)).collect()
+    }
+
+    fn copy_wide(dst: &mut [u16], s: &str) {
+        let len = dst.len() - 1;
+        for (d, c) in dst
+            .iter_mut()
+            .zip(s.encode_utf16().take(len).chain(Some(0)))
+        {
+            *d = c;
+        }
+    }
+
+    pub fn run() -> Result<()> {
+        let class_name = wide("hsr-lang-patcher-tray");
+
+        unsafe {
+            let instance = GetModuleHandleW(ptr::null());
+            let class = WNDCLASSW {
+                lpfnWndProc: Some(window_proc),
+                hInstance: instance,
+                lpszClassName: class_name.as_ptr(),
+                ..mem::zeroed()
+            };
+            if RegisterClassW(&class) == 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+
+            let hwnd = CreateWindowExW(
+                0,
+                class_name.as_ptr(),
+                class_name.as_ptr(),
+                0,
+                0,
+                0,
+                0,
+                0,
+                HWND_MESSAGE,
+                ptr::null_mut(),
+                instance,
+                ptr::null(),
+            );
+            if hwnd.is_null() {
+                return Err(io::Error::last_os_error().into());
+            }
+
+            let mut icon: NOTIFYICONDATAW = mem::zeroed();
+            icon.cbSize = mem::size_of::<NOTIFYICONDATAW>() as u32;
+            icon.hWnd = hwnd;
+            icon.uID = 1;
+            icon.uFlags = NIF_ICON | NIF_MESSAGE | NIF_TIP;
+            icon.uCallbackMessage = WM_TRAY;
+            icon.hIcon = LoadIconW(ptr::null_mut(), IDI_APPLICATION);
+            copy_wide(
+                &mut icon.szTip,
+                &format!(
+                    "{}\n{}",
+                    env!("CARGO_PKG_NAME"),
+                    game_path_label().display()
+                ),
+            );
+            if Shell_NotifyIconW(NIM_ADD, &icon) == 0 {
+                return Err(io::Error::last_os_error().into());
+            }
+
+            SetTimer(hwnd, CHECK_TIMER, CHECK_INTERVAL.as_millis() as u32, None);
+            TRAY.set(Some(Tray {
+                icon,
+                last_error: None,
+            }));
+
+            crate::serve::spawn_pipe_server();
+            println!("{}", i18n::tr(Msg::TrayRunning));
+
+            let mut msg: MSG = mem::zeroed();
+            while GetMessageW(&mut msg, ptr::null_mut(), 0, 0) > 0 {
+                TranslateMessage(&msg);
+                DispatchMessageW(&msg);
+            }
+
+            if let Some(tray) = TRAY.take() {
+                Shell_NotifyIconW(NIM_DELETE, &tray.icon);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check() {
+        let result = reapply_profile();
+
+        TRAY.with_borrow_mut(|tray| {
+            let Some(tray) = tray else { return };
+
+            match result {
+                Ok(Some(reapplied)) => {
+                    tray.last_error = None;
+                    notify(tray, &reapplied.message(), NIIF_INFO);
+                }
+                Ok(None) => tray.last_error = None,
+                Err(e) => {
+                    // Locked files while the game runs would otherwise notify every tick
+                    let message = format!("{e:#}");
+                    if tray.last_error.as_ref() != Some(&message) {
+                        notify(tray, &message, NIIF_ERROR);
+                        tray.last_error = Some(message);
+                    }
+                }
+            }
+        });
+    }
+
+    /// Shows a toast, or a balloon on the tray icon where toasts aren't available.
+    fn notify(tray: &mut Tray, message: &str, flags: u32) {
+        if notifications::show(message, flags == NIIF_ERROR).is_ok() {
+            return;
+        }
+
+        tray.icon.uFlags = NIF_INFO;
+        tray.icon.dwInfoFlags = flags;
+        copy_wide(&mut tray.icon.szInfoTitle, env!("CARGO_PKG_NAME"));
+        copy_wide(&mut tray.icon.szInfo, message);
+
+        unsafe {
+            Shell_NotifyIconW(NIM_MODIFY, &tray.icon);
+        }
+    }
+
+    unsafe fn show_menu(hwnd: HWND) {
+        unsafe {
+            let menu = CreatePopupMenu();
+            let reapply = wide(i18n::tr(Msg::TrayReapply));
+            let exit = wide(i18n::tr(Msg::TrayExit));
+            AppendMenuW(menu, MF_STRING, MENU_REAPPLY, reapply.as_ptr());
+            AppendMenuW(menu, MF_STRING, MENU_EXIT, exit.as_ptr());
+
+            let mut cursor: POINT = mem::zeroed();
+            GetCursorPos(&mut cursor);
+            // Required so the menu closes when clicking elsewhere
+            SetForegroundWindow(hwnd);
+
+            let command = TrackPopupMenu(
+                menu,
+                TPM_RETURNCMD | TPM_NONOTIFY,
+                cursor.x,
+                cursor.y,
+                0,
+                hwnd,
+                ptr::null(),
+            );
+            DestroyMenu(menu);
+
+            match command as usize {
+                MENU_REAPPLY => check(),
+                MENU_EXIT => PostQuitMessage(0),
+                _ => {}
+            }
+        }
+    }
+
+    unsafe extern "system" fn window_proc(
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        match msg {
+            WM_TIMER if wparam == CHECK_TIMER => check(),
+            WM_TRAY => match lparam as u32 {
+                WM_RBUTTONUP => unsafe { show_menu(hwnd) },
+                WM_LBUTTONDBLCLK => check(),
+                _ => {}
+            },
+            WM_COMMAND => {}
+            _ => return unsafe { DefWindowProcW(hwnd, msg, wparam, lparam) },
+        }
+
+        0
+    }
+}
@@ -0,0 +1,358 @@
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::{
+    DefaultTerminal, Frame,
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Cell, Paragraph, Row, Table},
+};
+
+use hsr_lang_patcher::{
+    allowed_language::{AllowedLanguageRow, ListMode, check_combination, patch_languages_with},
+    game::Game,
+    language::Language,
+    pipeline::PatchPipeline,
+};
+
+use crate::{
+    Result,
+    i18n::{self, Msg},
+    output::{self, CurrentStage},
+};
+
+#[derive(Clone, Copy, PartialEq)]
+enum Focus {
+    Text,
+    Voice,
+}
+
+struct App<'a> {
+    game: &'a Game,
+    rows: Vec<AllowedLanguageRow>,
+    text: usize,
+    voice: usize,
+    focus: Focus,
+    status: Option<Line<'static>>,
+    /// `--force`, applies combinations the game doesn't ship without asking.
+    force: bool,
+    /// A combination the game doesn't ship that the next Enter applies.
+    confirming: Option<(Language, Language)>,
+}
+
+pub fn run(game: &Game, force: bool) -> Result<()> {
+    let rows = game.allowed_language().parse()?;
+
+    let mut app = App {
+        text: current_index(&rows, false),
+        voice: current_index(&rows, true),
+        game,
+        rows,
+        focus: Focus::Text,
+        status: None,
+        force,
+        confirming: None,
+    };
+
+    let mut terminal = ratatui::init();
+    let result = app.event_loop(&mut terminal);
+    ratatui::restore();
+
+    result
+}
+
+/// Foreground color, or no style at all when colors are disabled.
+fn color(fg: Color) -> Style {
+    if output::color() {
+        Style::new().fg(fg)
+    } else {
+        Style::new()
+    }
+}
+
+/// What the text or voice selector cycles through.
+fn choices(voice: bool) -> Vec<Language> {
+    if voice {
+        Language::voice_languages().collect()
+    } else {
+        Language::ALL.to_vec()
+    }
+}
+
+fn current_index(rows: &[AllowedLanguageRow], voice: bool) -> usize {
+    rows.iter()
+        .find(|row| row.area() == Some("os") && if voice { row.is_voice() } else { row.is_text() })
+        .and_then(|row| row.language())
+        .and_then(|lang| choices(voice).iter().position(|&l| l == lang))
+        .unwrap_or_default()
+}
+
+impl App<'_> {
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Up | KeyCode::Down | KeyCode::Tab | KeyCode::BackTab => {
+                    self.focus = match self.focus {
+                        Focus::Text => Focus::Voice,
+                        Focus::Voice => Focus::Text,
+                    }
+                }
+                KeyCode::Left => self.cycle(false),
+                KeyCode::Right => self.cycle(true),
+                KeyCode::Enter => self.apply(),
+                _ => {}
+            }
+        }
+    }
+
+    fn cycle(&mut self, forward: bool) {
+        let (index, len) = match self.focus {
+            Focus::Text => (&mut self.text, choices(false).len()),
+            Focus::Voice => (&mut self.voice, choices(true).len()),
+        };
+        let step = if forward { 1 } else { len - 1 };
+        *index = (*index + step) % len;
+        self.confirming = None;
+    }
+
+    fn languages(&self) -> (Language, Language) {
+        (choices(false)[self.text], choices(true)[self.voice])
+    }
+
+    fn preview(&self) -> Result<Vec<AllowedLanguageRow>> {
+        let (text, voice) = self.languages();
+        let mut rows = self.rows.clone();
+        patch_languages_with(
+            &mut rows,
+            text,
+            voice,
+            ListMode::Replace,
+            self.game.patch_areas(),
+            self.game.strict,
+        )?;
+        Ok(rows)
+    }
+
+    fn apply(&mut self) {
+        let (text, voice) = self.languages();
+        // Like the CLI, a combination the game doesn't ship needs --force or a second Enter
+        if !self.force && self.confirming != Some((text, voice)) {
+            match self.unshipped(text, voice) {
+                Ok(None) => {}
+                Ok(Some(warnings)) => {
+                    self.confirming = Some((text, voice));
+                    self.status = Some(
+                        Line::from(i18n::tr(Msg::TuiUnshipped).replace("{warnings}", &warnings))
+                            .style(color(Color::Yellow)),
+                    );
+                    return;
+                }
+                Err(e) => {
+                    self.status = Some(
+                        Line::from(format!("{}: {e:#}", i18n::tr(Msg::Error)))
+                            .style(color(Color::Red)),
+                    );
+                    return;
+                }
+            }
+        }
+        self.confirming = None;
+
+        // The footer names the stage a failed patch stopped in
+        let stage = CurrentStage::new();
+        let result = PatchPipeline::new(&stage)
+            .patch(self.game, text, voice, ListMode::Replace)
+            .map(|report| report.rows_after);
+
+        self.status = Some(match result {
+            Ok(rows) => {
+                self.rows = rows;
+                Line::from(i18n::tr(Msg::Done))
+                    .bold()
+                    .style(color(Color::Green))
+            }
+            Err(e) => {
+                let error = match stage.get() {
+                    Some(stage) => format!("{} ({}): {e:#}", i18n::tr(Msg::Error), stage.name()),
+                    None => format!("{}: {e:#}", i18n::tr(Msg::Error)),
+                };
+                Line::from(error).style(color(Color::Red))
+            }
+        });
+    }
+
+    /// The reasons the game doesn't ship the combination, `None` if it does.
+    fn unshipped(&self, text: Language, voice: Language) -> Result<Option<String>> {
+        let warnings = check_combination(
+            &self.game.original_rows()?,
+            self.game.client_region(),
+            text,
+            voice,
+        );
+        Ok((!warnings.is_empty()).then(|| {
+            warnings
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("; ")
+        }))
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [info, table, selector, footer] = Layout::vertical([
+            Constraint::Length(6),
+            Constraint::Min(5),
+            Constraint::Length(3),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let game = self.game;
+        let info_lines = vec![
+            Line::from(vec![
+                "DesignData: ".bold(),
+                game.design_data_path.display().to_string().into(),
+            ]),
+            Line::from(vec!["Index hash: ".bold(), game.index_hash.as_str().into()]),
+            Line::from(vec![
+                "Container:  ".bold(),
+                format!("{}.bytes", game.file_hash).into(),
+            ]),
+            Line::from(vec![
+                "Entry:      ".bold(),
+                format!(
+                    "offset 0x{:X}, size {} bytes",
+                    game.data_entry.offset, game.data_entry.size
+                )
+                .into(),
+            ]),
+        ];
+        frame.render_widget(
+            Paragraph::new(info_lines).block(Block::bordered().title(format!(
+                " {} v{} ",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            ))),
+            info,
+        );
+
+        self.draw_rows(frame, table);
+
+        let (text, voice) = self.languages();
+        let selector_line = Line::from(vec![
+            Self::selector_span(i18n::tr(Msg::TuiText), text, self.focus == Focus::Text),
+            "   ".into(),
+            Self::selector_span(i18n::tr(Msg::TuiVoice), voice, self.focus == Focus::Voice),
+        ]);
+        frame.render_widget(
+            Paragraph::new(selector_line)
+                .block(Block::bordered().title(format!(" {} ", i18n::tr(Msg::TuiLanguages)))),
+            selector,
+        );
+
+        let footer_line = self.status.clone().unwrap_or_else(|| {
+            Line::from(
+                i18n::tr(Msg::TuiHelp)
+                    .replace("{updown}", output::glyph("↑/↓", "Up/Down"))
+                    .replace("{leftright}", output::glyph("←/→", "Left/Right")),
+            )
+            .dim()
+        });
+        frame.render_widget(Paragraph::new(footer_line), footer);
+    }
+
+    fn draw_rows(&self, frame: &mut Frame, area: Rect) {
+        let preview = self.preview();
+        let header = Row::new(["Area", "Type", "Default", "Languages"]).bold();
+
+        let rows = self.rows.iter().enumerate().map(|(i, row)| {
+            let new = preview.as_ref().ok().and_then(|rows| rows.get(i));
+            let changed = new.is_some_and(|new| new != row);
+
+            let default = Self::diff_cell(
+                row.default_language().unwrap_or("-"),
+                new.and_then(|new| new.default_language()).unwrap_or("-"),
+            );
+            let list = Self::diff_cell(
+                &Self::format_list(row),
+                &new.map(Self::format_list).unwrap_or_default(),
+            );
+
+            let table_row = Row::new(vec![
+                Cell::from(row.area().unwrap_or("-").to_string()),
+                Cell::from(match row.row_type() {
+                    None => "text".to_string(),
+                    Some(1) => "voice".to_string(),
+                    Some(other) => other.to_string(),
+                }),
+                default,
+                list,
+            ]);
+
+            if changed {
+                table_row.style(color(Color::Yellow))
+            } else {
+                table_row
+            }
+        });
+
+        let title = match &preview {
+            Ok(_) => format!(
+                " AllowedLanguage (current {} preview) ",
+                output::glyph("→", "->")
+            ),
+            Err(e) => format!(" AllowedLanguage ({e}) "),
+        };
+
+        frame.render_widget(
+            Table::new(
+                rows,
+                [
+                    Constraint::Length(6),
+                    Constraint::Length(6),
+                    Constraint::Length(10),
+                    Constraint::Min(20),
+                ],
+            )
+            .header(header)
+            .block(Block::bordered().title(title)),
+            area,
+        );
+    }
+
+    fn selector_span(label: &str, lang: Language, focused: bool) -> Span<'static> {
+        let span = Span::raw(format!(
+            "{label}: {} {lang} {}",
+            output::glyph("◀", "<"),
+            output::glyph("▶", ">")
+        ));
+        if focused {
+            span.style(Style::new().reversed())
+        } else {
+            span
+        }
+    }
+
+    fn diff_cell(current: &str, new: &str) -> Cell<'static> {
+        if current == new || new.is_empty() {
+            Cell::from(current.to_string())
+        } else {
+            Cell::from(format!("{current} {} {new}", output::glyph("→", "->")))
+        }
+    }
+
+    fn format_list(row: &AllowedLanguageRow) -> String {
+        row.language_list()
+            .map(|list| list.join(","))
+            .unwrap_or_else(|| "-".to_string())
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use serde::Deserialize;
+use ureq::{
+    Agent,
+    tls::{TlsConfig, TlsProvider},
+};
+
+use crate::Result;
+
+const LATEST_RELEASE_URL: &str =
+    "https://api.github.com/repos/nie4/hsr-lang-patcher/releases/latest";
+
+#[derive(Deserialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub html_url: String,
+    #[serde(default)]
+    pub body: String,
+}
+
+impl Release {
+    /// The changelog line stating which game versions the release supports, if any.
+    pub fn supported_versions(&self) -> Option<&str> {
+        self.body
+            .lines()
+            .map(|line| line.trim_start_matches(['-', '*', '#', ' ']).trim())
+            .find(|line| line.to_lowercase().contains("support"))
+    }
+}
+
+/// Checks GitHub for a newer release in the background so startup isn't delayed.
+pub fn spawn_check() -> JoinHandle<Option<Release>> {
+    thread::spawn(|| {
+        fetch_latest_release()
+            .ok()
+            .filter(|release| is_newer(&release.tag_name, env!("CARGO_PKG_VERSION")))
+    })
+}
+
+/// HTTP client shared by everything that talks to the network.
+pub fn agent(timeout: Duration) -> Agent {
+    Agent::config_builder()
+        .timeout_global(Some(timeout))
+        .tls_config(
+            TlsConfig::builder()
+                .provider(TlsProvider::NativeTls)
+                .build(),
+        )
+        .build()
+        .into()
+}
+
+fn fetch_latest_release() -> Result<Release> {
+    let body = agent(Duration::from_secs(3))
+        .get(LATEST_RELEASE_URL)
+        .header("User-Agent", env!("CARGO_PKG_NAME"))
+        .header("Accept", "application/vnd.github+json")
+        .call()?
+        .body_mut()
+        .read_to_string()?;
+
+    Ok(serde_json::from_str(&body)?)
+}
+
+fn is_newer(tag: &str, current: &str) -> bool {
+    let parse = |version: &str| -> Vec<u32> {
+        version
+            .trim_start_matches('v')
+            .split('.')
+            .map_while(|part| part.parse().ok())
+            .collect()
+    };
+
+    parse(tag) > parse(current)
+}
@@ -0,0 +1,87 @@
+//! Findings that don't stop a run but should be seen, e.g. rows the patcher doesn't know
+//! or a language the game doesn't ship. They are collected while patching, so a front
+//! end can show them together after the result and include them in its JSON output.
+
+use std::fmt;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// Something off in the rows before the patch, see
+    /// [`RowAnomaly`](crate::allowed_language::RowAnomaly).
+    RowAnomaly,
+    /// A row the patch left alone or didn't find.
+    SkippedRow,
+    /// Parts of the excel this version doesn't understand.
+    FormatDrift,
+    /// The TextMap of the text language is missing or empty.
+    TextMap,
+    /// A language the game doesn't offer for its rows, applied anyway.
+    Unshipped,
+    /// Little room is left in the excel's region.
+    SizeSlack,
+    /// Backups couldn't be pruned.
+    Backups,
+    /// A command run after the patch failed.
+    Hook,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Warning {
+    pub kind: WarningKind,
+    pub message: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Warnings in the order they were found.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct Warnings(Vec<Warning>);
+
+impl Warnings {
+    pub fn push(&mut self, kind: WarningKind, message: impl fmt::Display) {
+        self.0.push(Warning {
+            kind,
+            message: message.to_string(),
+        });
+    }
+
+    /// Adds one warning of `kind` per finding.
+    pub fn extend<T: fmt::Display>(
+        &mut self,
+        kind: WarningKind,
+        findings: impl IntoIterator<Item = T>,
+    ) {
+        for finding in findings {
+            self.push(kind, finding);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Warning> {
+        self.0.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Warnings {
+    type Item = &'a Warning;
+    type IntoIter = std::slice::Iter<'a, Warning>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
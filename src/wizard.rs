@@ -0,0 +1,196 @@
+//! First-run setup. On the first interactive start without a config, asks where the game
+//! is, which languages to use and how to back up, and saves the answers, instead of
+//! failing when the patcher was started from e.g. the Downloads folder.
+
+use std::{
+    env,
+    io::{IsTerminal, stdin},
+    path::{Path, PathBuf},
+};
+
+use inquire::{Confirm, InquireError, Select, Text, validator::Validation};
+
+use hsr_lang_patcher::{
+    ErrorKind,
+    detect::{find_installs, running_game_path, scan_install_roots},
+    game::{Platform, get_platform_design_data_path},
+    paths::canonical_path,
+};
+
+use crate::{
+    Result,
+    args::{Args, Command, Languages},
+    config::Config,
+    error_kind,
+    i18n::{self, Msg},
+    output,
+};
+
+/// Whether prompts can be shown: a terminal on both ends and no `--yes` or `--json`.
+pub fn is_interactive(args: &Args) -> bool {
+    !args.yes && !args.json && stdin().is_terminal() && output::is_terminal()
+}
+
+/// Runs `prompts` until they succeed. After an invalid answer or a cancelled prompt the
+/// problem is shown and the user can try again or quit, instead of the window closing on
+/// an error. Other errors and Ctrl+C end it right away.
+pub fn retry<T>(mut prompts: impl FnMut() -> Result<T>) -> Result<T> {
+    loop {
+        let error = match prompts() {
+            Ok(value) => return Ok(value),
+            Err(error) => error,
+        };
+        let interrupted = error.chain().any(|cause| {
+            matches!(
+                cause.downcast_ref::<InquireError>(),
+                Some(InquireError::OperationInterrupted)
+            )
+        });
+        let recoverable = matches!(
+            error_kind(&error),
+            ErrorKind::InvalidArguments | ErrorKind::Cancelled
+        );
+        if interrupted || !recoverable {
+            return Err(error);
+        }
+
+        eprintln!("{}: {error:#}\n", output::warning());
+        let options = vec![i18n::tr(Msg::RetryAgain), i18n::tr(Msg::RetryQuit)];
+        let again = Select::new(i18n::tr(Msg::RetryPrompt), options)
+            .raw_prompt()
+            .is_ok_and(|choice| choice.index == 0);
+        if !again {
+            return Err(error);
+        }
+        println!();
+    }
+}
+
+/// Only a plain patch from a terminal, with nothing saved yet.
+pub fn should_run(args: &Args) -> bool {
+    args.command == Command::Patch
+        && !args.last
+        && !args.serve
+        && !args.tui
+        && is_interactive(args)
+        && Config::path().is_ok_and(|path| !path.exists())
+}
+
+/// Asks for what the command line left out and saves it as the profile.
+pub fn run(args: &mut Args) -> Result<()> {
+    println!("{}\n", i18n::tr(Msg::WizardWelcome));
+    let platform = args.platform.unwrap_or_default();
+
+    let game_path = match args.game_path.take() {
+        Some(path) if get_platform_design_data_path(&path, platform).is_ok() => path,
+        Some(path) => {
+            println!(
+                "{}: '{}'\n",
+                i18n::tr(Msg::WizardNotGameFolder),
+                path.display()
+            );
+            prompt_game_path(&detected_installs(platform), platform)?
+        }
+        None => prompt_game_path(&detected_installs(platform), platform)?,
+    };
+    let languages = match args.languages.take() {
+        Some(languages) => languages,
+        None => {
+            let (text, voice) = args.get_or_prompt_languages()?;
+            Languages { text, voice }
+        }
+    };
+    let full_backups = args.full_backup
+        || Confirm::new(i18n::tr(Msg::WizardFullBackups))
+            .with_help_message(i18n::tr(Msg::WizardFullBackupsHelp))
+            .with_default(false)
+            .prompt()?;
+
+    let result = Config::load().and_then(|mut config| {
+        config.game_path = Some(canonical_path(&game_path));
+        config.text_language = Some(languages.text.to_string());
+        config.voice_language = Some(languages.voice.to_string());
+        config.platform = platform;
+        config.full_backups = full_backups;
+        config.save()?;
+        Config::path()
+    });
+    match result {
+        Ok(path) => println!(
+            "{}\n",
+            i18n::tr(Msg::WizardSaved).replace("{path}", &path.display().to_string())
+        ),
+        Err(e) => eprintln!(
+            "{}: {}: {e:#}",
+            output::warning(),
+            i18n::tr(Msg::SaveProfileFailed)
+        ),
+    }
+
+    // The task runs `--last`, so it needs the profile saved above
+    #[cfg(windows)]
+    if Confirm::new(i18n::tr(Msg::WizardTask))
+        .with_default(false)
+        .prompt()?
+        && let Err(e) = crate::task::install(None, false, false)
+    {
+        eprintln!("{}: {e:#}", output::warning());
+    }
+
+    args.game_path = Some(game_path);
+    args.languages = Some(languages);
+    Ok(())
+}
+
+/// Installs in the current directory and the usual places, scanning the common install
+/// folders of every drive when there are none.
+pub fn detected_installs(platform: Platform) -> Vec<PathBuf> {
+    let is_game = |path: &PathBuf| get_platform_design_data_path(path, platform).is_ok();
+
+    let mut found: Vec<PathBuf> = Vec::new();
+    let candidates = env::current_dir()
+        .into_iter()
+        .chain(find_installs())
+        .chain(running_game_path());
+    for path in candidates {
+        if is_game(&path) && !found.contains(&path) {
+            found.push(path);
+        }
+    }
+
+    if found.is_empty() {
+        println!("{}", i18n::tr(Msg::SearchingInstalls));
+        found = scan_install_roots().into_iter().filter(is_game).collect();
+    }
+    found
+}
+
+/// Offers the `found` installs, or asks for a folder until one holds the game.
+pub fn prompt_game_path(found: &[PathBuf], platform: Platform) -> Result<PathBuf> {
+    let is_game = move |path: &Path| get_platform_design_data_path(path, platform).is_ok();
+
+    if !found.is_empty() {
+        let mut options: Vec<String> = found
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        options.push(i18n::tr(Msg::WizardOtherFolder).to_string());
+        let choice = Select::new(i18n::tr(Msg::WizardGameFolder), options).raw_prompt()?;
+        if let Some(path) = found.get(choice.index) {
+            return Ok(path.clone());
+        }
+    }
+
+    // Paths copied from Explorer come with quotes
+    let clean = |input: &str| PathBuf::from(input.trim().trim_matches('"'));
+    let input = Text::new(i18n::tr(Msg::WizardEnterFolder))
+        .with_validator(move |input: &str| {
+            Ok(if is_game(&clean(input)) {
+                Validation::Valid
+            } else {
+                Validation::Invalid(i18n::tr(Msg::WizardNotGameFolder).into())
+            })
+        })
+        .prompt()?;
+    Ok(clean(&input))
+}
@@ -0,0 +1,198 @@
+//! Patches synthetic installs from the `testkit` feature end to end.
+
+use std::fs;
+
+use hsr_lang_patcher::{
+    allowed_language::{
+        AllowedLanguage, AllowedLanguageRow, Areas, ClientRegion, ListMode, Voices,
+        current_language, patch_languages_with,
+    },
+    design_index::DesignIndex,
+    game::Game,
+    language::Language,
+    state::PatchStatus,
+    testkit::{Fixture, FixtureBuilder},
+};
+
+fn load(fixture: &Fixture) -> Game {
+    Game::load(fixture.design_data_path.clone()).unwrap()
+}
+
+fn rows(game: &Game) -> Vec<AllowedLanguageRow> {
+    game.allowed_language().parse().unwrap()
+}
+
+#[test]
+fn patch_verify_revert() {
+    let fixture = FixtureBuilder::new().build_temp().unwrap();
+    let original = fs::read(&fixture.bytes_path).unwrap();
+    let game = load(&fixture);
+    assert_eq!(game.patch_status().unwrap(), PatchStatus::NotPatched);
+
+    let report = game.patch(Language::Jp, Language::Kr).unwrap();
+    assert!(report.changed());
+    assert_eq!(game.patch_status().unwrap(), PatchStatus::Intact);
+
+    let patched = rows(&game);
+    assert_eq!(patched, report.rows_after);
+    assert_eq!(current_language(&patched, false), Some("jp"));
+    assert_eq!(current_language(&patched, true), Some("kr"));
+
+    // Only the excel's region was written
+    let container = fs::read(&fixture.bytes_path).unwrap();
+    let excel = fixture.excel_offset as usize..fixture.excel_offset as usize + fixture.excel_size;
+    assert_eq!(container.len(), original.len());
+    assert_eq!(container[..excel.start], original[..excel.start]);
+    assert_eq!(container[excel.end..], original[excel.end..]);
+
+    game.restore_backup(report.backup_id.as_deref().unwrap())
+        .unwrap();
+    assert_eq!(fs::read(&fixture.bytes_path).unwrap(), original);
+    assert_eq!(game.patch_status().unwrap(), PatchStatus::NotPatched);
+}
+
+#[test]
+fn patch_twice_writes_once() {
+    let fixture = FixtureBuilder::new().build_temp().unwrap();
+    let game = load(&fixture);
+
+    assert!(game.apply(Language::En, Language::Jp).unwrap());
+    assert!(!game.apply(Language::En, Language::Jp).unwrap());
+}
+
+#[test]
+fn restore_original_undoes_every_patch() {
+    let fixture = FixtureBuilder::new().build_temp().unwrap();
+    let original = fs::read(&fixture.bytes_path).unwrap();
+    let game = load(&fixture);
+
+    game.apply(Language::Jp, Language::Jp).unwrap();
+    game.apply(Language::Kr, Language::En).unwrap();
+    assert!(game.restore_original().unwrap());
+    assert_eq!(fs::read(&fixture.bytes_path).unwrap(), original);
+    assert!(!game.restore_original().unwrap());
+}
+
+#[test]
+fn externally_modified_region_is_not_restored() {
+    let fixture = FixtureBuilder::new().build_temp().unwrap();
+    let game = load(&fixture);
+    let report = game.patch(Language::Jp, Language::Jp).unwrap();
+
+    let mut container = fs::read(&fixture.bytes_path).unwrap();
+    container[fixture.excel_offset as usize + 1] ^= 0xFF;
+    fs::write(&fixture.bytes_path, &container).unwrap();
+
+    assert_eq!(game.patch_status().unwrap(), PatchStatus::Modified);
+    assert!(
+        game.restore_backup(report.backup_id.as_deref().unwrap())
+            .is_err()
+    );
+}
+
+#[test]
+fn legacy_index_and_bare_design_data() {
+    let fixture = FixtureBuilder::new()
+        .legacy(true)
+        .with_executable(false)
+        .build_temp()
+        .unwrap();
+    let game = load(&fixture);
+
+    game.apply(Language::Cn, Language::Cn).unwrap();
+    assert_eq!(current_language(&rows(&game), false), Some("cn"));
+}
+
+#[test]
+fn truncated_index_fails_to_parse() {
+    let fixture = FixtureBuilder::new().build_temp().unwrap();
+    let index = fs::read(&fixture.index_path).unwrap();
+
+    assert!(DesignIndex::parse(&index).is_ok());
+    // Up to two bytes less still read as the legacy format, four cut into the last entry
+    for len in [0, 1, index.len() / 2, index.len() - 4] {
+        assert!(DesignIndex::parse(&index[..len]).is_err(), "length {len}");
+    }
+}
+
+#[test]
+fn entry_count_past_the_end_is_rejected() {
+    let fixture = FixtureBuilder::new().build_temp().unwrap();
+    let mut index = fs::read(&fixture.index_path).unwrap();
+
+    // Header, then the file's name hash, file hash and read_size before its entry count
+    let count = 16 + 4 + 0x10 + 8;
+    index[count..count + 4].copy_from_slice(&1000u32.to_be_bytes());
+    assert!(DesignIndex::parse(&index).is_err());
+}
+
+#[test]
+fn unmodified_index_serializes_byte_exact() {
+    for legacy in [false, true] {
+        let fixture = FixtureBuilder::new().legacy(legacy).build_temp().unwrap();
+        let mut index = fs::read(&fixture.index_path).unwrap();
+        let parsed = DesignIndex::parse(&index).unwrap();
+        assert_eq!(parsed.legacy, legacy);
+        assert_eq!(parsed.serialize().unwrap(), index, "legacy {legacy}");
+
+        // Bytes after the last file record are kept as they are. A legacy index with them
+        // reads as the newer format, whose wider field takes the first two
+        index.extend_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x01]);
+        let parsed = DesignIndex::parse(&index).unwrap();
+        if !legacy {
+            assert_eq!(parsed.trailing, [0xDE, 0xAD, 0xBE, 0xEF, 0x00, 0x01]);
+        }
+        assert_eq!(
+            parsed.serialize().unwrap(),
+            index,
+            "legacy {legacy}, trailing"
+        );
+    }
+}
+
+#[test]
+fn long_lists_round_trip_as_varints() {
+    // Past 127 entries the count takes a second byte
+    let languages = vec!["en"; 300];
+    let rows = vec![AllowedLanguageRow::new(
+        Some("os"),
+        None,
+        Some(&languages),
+        Some("en"),
+    )];
+
+    let data = AllowedLanguage::write_rows(&rows).unwrap();
+    assert_eq!(AllowedLanguage::decode(&data).unwrap(), rows);
+}
+
+#[test]
+fn patch_only_the_chosen_client() {
+    let fixture = FixtureBuilder::new().build_temp().unwrap();
+    let mut rows = rows(&load(&fixture));
+    let before = rows.clone();
+
+    let voices = Voices {
+        os: Language::Jp,
+        cn: Language::Cn,
+    };
+    let skipped = patch_languages_with(
+        &mut rows,
+        Language::En,
+        voices,
+        ListMode::Keep,
+        Areas::Only(ClientRegion::Os),
+        true,
+    )
+    .unwrap();
+
+    assert!(skipped.is_empty());
+    for (row, before) in rows.iter().zip(&before) {
+        if row.area() == Some("cn") {
+            assert_eq!(row, before);
+        } else {
+            // Keep leaves the lists alone
+            assert_eq!(row.language_list(), before.language_list());
+        }
+    }
+    assert_eq!(current_language(&rows, true), Some("jp"));
+}